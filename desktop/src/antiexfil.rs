@@ -0,0 +1,42 @@
+//! The host's side of anti-exfil signing: the device has to sign with a
+//! nonce the host's random data went into, so it can't leak anything
+//! through the nonce. Every signature is checked before it is returned.
+use crate::{
+    exchange,
+    s2c::{tagged_hash, verify},
+};
+use protocol::{AntiExfilPayload, Request, Response};
+use serialport::SerialPort;
+use std::io::Read;
+
+/// Has the device sign `payload` with anti-exfil, returning the checked
+/// 65 byte recoverable signature
+pub fn sign(port: &mut dyn SerialPort, payload: AntiExfilPayload) -> Result<Vec<u8>, String> {
+    let mut buf = vec![0; 2048];
+    let pubkey = match exchange(port, &Request::PubKey, &mut buf)? {
+        Response::PubKey(pubkey) => pubkey.to_vec(),
+        other => return Err(other.to_string()),
+    };
+
+    let mut host_data = [0u8; 32];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut host_data))
+        .map_err(|e| format!("/dev/urandom: {}", e))?;
+    let commitment = tagged_hash("s2c/ecdsa/data", &[&host_data]);
+    let request = Request::AntiExfilCommit((payload, &commitment));
+    let r0 = match exchange(port, &request, &mut buf)? {
+        Response::SignerCommitment(r0) => r0.to_vec(),
+        other => return Err(other.to_string()),
+    };
+    // Only now that the device is committed to its nonce is the data revealed
+    let sig = match exchange(port, &Request::AntiExfilSign(&host_data), &mut buf)? {
+        Response::Sig(sig) => sig.to_vec(),
+        other => return Err(other.to_string()),
+    };
+
+    let data = match payload {
+        AntiExfilPayload::Message(data) | AntiExfilPayload::Tx(data) => data,
+    };
+    verify(&pubkey, data, &host_data, &r0, &sig)?;
+    Ok(sig)
+}
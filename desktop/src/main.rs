@@ -1,8 +1,55 @@
+mod antiexfil;
+mod message;
+mod nostr;
+mod s2c;
+mod seed;
+
 use core::time::Duration;
 use io::ErrorKind;
 use postcard::{from_bytes, to_stdvec};
-use protocol::{Request, Response};
-use std::io::{self, Write};
+use protocol::{
+    AntiExfilPayload, Bip85App, BtcMessageFormat, BtcNetwork, BtcScript, CosmosSignMode,
+    EciesScheme, Request, Response, SeedEntropy, Settings, PSBT_CHUNK_SIZE,
+};
+use serialport::SerialPort;
+use std::{io, time::Instant};
+
+const USAGE: &str = "usage: novus_wallet [COMMAND]
+
+Without a command, cycles through demo requests.
+
+Commands:
+    btc-address [--testnet|--regtest] [--taproot] ACCOUNT CHANGE INDEX
+    btc-xpub [--testnet|--regtest] [--taproot] ACCOUNT
+    sign-psbt [--testnet|--regtest] IN.psbt OUT.psbt
+    register-multisig DESCRIPTOR
+    multisig-address [--testnet|--regtest] CHANGE INDEX
+    sign-message [--testnet|--regtest] [--bip322|--legacy] ACCOUNT CHANGE INDEX MESSAGE
+    verify-message ADDRESS SIGNATURE MESSAGE (offline, no device needed)
+    cosmos-address [--prefix PREFIX] ACCOUNT INDEX
+    cosmos-sign [--prefix PREFIX] [--amino] ACCOUNT INDEX SIGNDOC
+    solana-address ACCOUNT
+    sign-solana ACCOUNT MESSAGE
+    nostr-pubkey ACCOUNT
+    nostr-sign ACCOUNT EVENT.json
+    nostr-signer [--socket PATH] ACCOUNT
+    ecdh PATH PEER_PUBKEY
+    decrypt [--eip5630] PATH CIPHERTEXT
+    bip85 [--to-host] (mnemonic WORDS|hex BYTES|password LENGTH) INDEX
+    slip39-split GROUP_THRESHOLD MEMBER_THRESHOLDofCOUNT...
+    slip39-recover (shares typed on the device)
+    verify-backup [--slip39] (mnemonic or shares typed on the device)
+    generate-seed [--dice ROLLS|--dice-only] WORDS
+    sign-anti-exfil (MESSAGE|--tx TX_HEX)
+    wipe
+    settings [--confirm-timeout SECONDS] [--confirm-exports on|off] [--auto-lock MINUTES]
+        [--brightness 1-5] [--blind-signing on|off] [--label LABEL]
+    dice-seed WORDS ROLLS (offline, the seed generate-seed --dice-only makes)
+    show-address [PATH]    Ethereum address to verify on the device,
+                           m/44'/60'/0'/0/0 by default";
+
+/// Path `show-address` uses without one given
+const DEFAULT_ETH_PATH: &str = "m/44'/60'/0'/0/0";
 
 // // use clap::{App, AppSettings, Arg};
 
@@ -26,6 +73,38 @@ fn main() {
     //     .get_matches();
     // let port_name = matches.value_of("port").unwrap();
     // let baud_rate = matches.value_of("baud").unwrap().parse::<u32>().unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("verify-message") {
+        match &args[1..] {
+            [address, signature, msg] => {
+                match message::verify(address, signature, msg.as_bytes()) {
+                    Ok(()) => println!("Signature is valid"),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        ::std::process::exit(1);
+                    }
+                }
+            }
+            _ => eprintln!("{}", USAGE),
+        }
+        return;
+    }
+
+    if args.first().map(String::as_str) == Some("dice-seed") {
+        let words = args.get(1).and_then(|w| w.parse().ok());
+        match (words, args.get(2)) {
+            (Some(words), Some(rolls)) => match seed::from_dice(words, rolls) {
+                Ok(mnemonic) => println!("{}", mnemonic),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ::std::process::exit(1);
+                }
+            },
+            _ => eprintln!("{}", USAGE),
+        }
+        return;
+    }
+
     let port_name = "/dev/ttyACM0";
     let baud_rate = 1_000_000;
 
@@ -34,56 +113,36 @@ fn main() {
         .open();
 
     match port {
+        Ok(mut port) if !args.is_empty() => {
+            if let Err(e) = run_command(&mut *port, &args) {
+                eprintln!("{}", e);
+                ::std::process::exit(1);
+            }
+        }
         Ok(mut port) => {
-            let mut serial_buf: Vec<u8> = vec![0; 2048];
+            let mut buf = vec![0; 2048];
             println!("Receiving data on {} at {} baud:", &port_name, &baud_rate);
-            let mut i = 0u32;
-            let mut is_waiting = false;
-            loop {
-                if !is_waiting {
-                    let res = i % 5;
-                    let request = if res == 0 {
-                        Request::Serial
-                    } else if res == 1 {
-                        Request::Info
-                    // Request::Ping
-                    } else if res == 2 {
-                        Request::Address(i - res)
-                    } else if res == 3 {
-                        Request::AddressList(i - res)
-                    } else {
-                        Request::Sig(&[0x41, 0x42, 0x43, 0x44])
-                    };
-                    // let request = Request::AddressList(i * 5);
-                    let data = to_stdvec(&request).unwrap();
-                    match port.write(&data) {
-                        Ok(count) => println!("Sent ({}): {:?}", count, &request),
-                        Err(e) => eprintln!("err'd with {:?}", e),
-                    };
-                }
-                // Wait for the response
-                match port.read(serial_buf.as_mut_slice()) {
-                    Ok(t) => {
-                        is_waiting = false;
-                        // println!("Parsed ({}): {}", t, hex::encode(&serial_buf[..]));
-                        // Final byte is the version, assert it's the same
-                        let _ver = &serial_buf[t - 1..t][0];
-                        // assert_eq!(*ver, protocol::version());
-                        if let Ok(response) = from_bytes::<Response>(&serial_buf[..t - 1]) {
-                            println!("Rcvd({}): {}", t, response);
-                            i += 1;
-                        } else {
-                            println!("Failed to parse ({}): {}", t, hex::encode(&serial_buf[..]));
-                        }
-                        // println!("Rcvd({}): {}", t, hex::encode(&serial_buf[..]));
-                    }
-                    Err(e) if e.kind() == ErrorKind::TimedOut => {
-                        // eprint!("timed out, waiting...");
-                        // std::thread::sleep(Duration::from_secs(5))
-                        is_waiting = true;
-                    }
+            for i in 0u32.. {
+                let res = i % 6;
+                let request = if res == 0 {
+                    Request::Serial
+                } else if res == 1 {
+                    Request::Info
+                // Request::Ping
+                } else if res == 2 {
+                    Request::Address(i - res)
+                } else if res == 3 {
+                    Request::AddressList(i - res)
+                } else if res == 4 {
+                    Request::Accounts
+                } else {
+                    Request::Sig(&[0x41, 0x42, 0x43, 0x44])
+                };
+                println!("Sent: {:?}", &request);
+                match exchange(&mut *port, &request, &mut buf) {
+                    Ok(response) => println!("Rcvd: {}", response),
                     Err(e) => {
-                        eprintln!("errored while receiving with: {:?}", e);
+                        eprintln!("errored while receiving with: {}", e);
                         break;
                     }
                 }
@@ -95,3 +154,508 @@ fn main() {
         }
     }
 }
+
+/// Takes `--testnet`/`--regtest` out of `args`, defaulting to mainnet
+fn btc_network(args: &mut Vec<&str>) -> BtcNetwork {
+    let mut network = BtcNetwork::Mainnet;
+    args.retain(|a| match *a {
+        "--testnet" => {
+            network = BtcNetwork::Testnet;
+            false
+        }
+        "--regtest" => {
+            network = BtcNetwork::Regtest;
+            false
+        }
+        _ => true,
+    });
+    network
+}
+
+/// Takes `--taproot` out of `args`, defaulting to native SegWit
+fn btc_script(args: &mut Vec<&str>) -> BtcScript {
+    let len = args.len();
+    args.retain(|a| *a != "--taproot");
+    if args.len() < len {
+        BtcScript::P2tr
+    } else {
+        BtcScript::P2wpkh
+    }
+}
+
+/// Takes `option VALUE` out of `args`
+fn option_value<'a>(args: &mut Vec<&'a str>, option: &str) -> Result<Option<&'a str>, String> {
+    match args.iter().position(|a| *a == option) {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Ok(Some(value))
+        }
+        Some(_) => Err(USAGE.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Takes `--prefix PREFIX` out of `args`, defaulting to the Cosmos Hub's
+fn cosmos_prefix<'a>(args: &mut Vec<&'a str>) -> Result<&'a str, String> {
+    Ok(option_value(args, "--prefix")?.unwrap_or("cosmos"))
+}
+
+fn parse_u32(arg: Option<&&str>) -> Result<u32, String> {
+    let arg = arg.ok_or_else(|| USAGE.to_string())?;
+    arg.parse()
+        .map_err(|_| format!("expected a number, got \"{}\"", arg))
+}
+
+/// Hex with or without a `0x` prefix
+fn parse_hex(arg: Option<&&str>) -> Result<Vec<u8>, String> {
+    let arg = arg.ok_or_else(|| USAGE.to_string())?;
+    hex::decode(arg.trim_start_matches("0x")).map_err(|_| format!("expected hex, got \"{}\"", arg))
+}
+
+/// Sends the request described by `args` and prints the response
+fn run_command(port: &mut dyn SerialPort, args: &[String]) -> Result<(), String> {
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let command = args.remove(0);
+    let request = match command {
+        "btc-address" => {
+            let network = btc_network(&mut args);
+            let script = btc_script(&mut args);
+            Request::BtcAddress((
+                network,
+                script,
+                parse_u32(args.first())?,
+                parse_u32(args.get(1))?,
+                parse_u32(args.get(2))?,
+            ))
+        }
+        "btc-xpub" => {
+            let network = btc_network(&mut args);
+            let script = btc_script(&mut args);
+            Request::BtcXpub((network, script, parse_u32(args.first())?))
+        }
+        "sign-psbt" => {
+            let network = btc_network(&mut args);
+            return match (args.first(), args.get(1)) {
+                (Some(input), Some(output)) => sign_psbt(port, network, input, output),
+                _ => Err(USAGE.to_string()),
+            };
+        }
+        "register-multisig" => match args.first() {
+            Some(descriptor) => Request::RegisterMultisig(descriptor),
+            None => return Err(USAGE.to_string()),
+        },
+        "multisig-address" => {
+            let network = btc_network(&mut args);
+            Request::MultisigAddress((network, parse_u32(args.first())?, parse_u32(args.get(1))?))
+        }
+        "sign-message" => {
+            let network = btc_network(&mut args);
+            let len = args.len();
+            args.retain(|a| *a != "--bip322");
+            let bip322 = args.len() < len;
+            let len = args.len();
+            args.retain(|a| *a != "--legacy");
+            let format = match (bip322, args.len() < len) {
+                (true, false) => BtcMessageFormat::Bip322Simple,
+                (false, true) => BtcMessageFormat::Bip137P2pkh,
+                (false, false) => BtcMessageFormat::Bip137,
+                (true, true) => {
+                    return Err("legacy addresses have no BIP322 signatures".to_string())
+                }
+            };
+            let msg = args.get(3).ok_or_else(|| USAGE.to_string())?;
+            let request = Request::SignBtcMessage((
+                network,
+                format,
+                parse_u32(args.first())?,
+                parse_u32(args.get(1))?,
+                parse_u32(args.get(2))?,
+                msg.as_bytes(),
+            ));
+            let mut buf = vec![0; 2048];
+            return match exchange(port, &request, &mut buf)? {
+                Response::BtcMessageSig((address, sig)) => {
+                    println!("Address: {}", address);
+                    println!("Signature: {}", message::base64_encode(sig));
+                    Ok(())
+                }
+                other => Err(other.to_string()),
+            };
+        }
+        "cosmos-address" => {
+            let prefix = cosmos_prefix(&mut args)?;
+            Request::CosmosAddress((prefix, parse_u32(args.first())?, parse_u32(args.get(1))?))
+        }
+        "cosmos-sign" => {
+            let prefix = cosmos_prefix(&mut args)?;
+            let len = args.len();
+            args.retain(|a| *a != "--amino");
+            let mode = if args.len() < len {
+                CosmosSignMode::Amino
+            } else {
+                CosmosSignMode::Direct
+            };
+            let path = args.get(2).ok_or_else(|| USAGE.to_string())?;
+            let doc = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            let request = Request::CosmosSign((
+                prefix,
+                mode,
+                parse_u32(args.first())?,
+                parse_u32(args.get(1))?,
+                &doc,
+            ));
+            let mut buf = vec![0; 2048];
+            return match exchange(port, &request, &mut buf)? {
+                Response::Sig(sig) => {
+                    println!("Signature: {}", message::base64_encode(sig));
+                    Ok(())
+                }
+                other => Err(other.to_string()),
+            };
+        }
+        "solana-address" => Request::SolanaAddress(parse_u32(args.first())?),
+        "sign-solana" => {
+            let path = args.get(1).ok_or_else(|| USAGE.to_string())?;
+            let msg = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            let request = Request::SignSolanaTx((parse_u32(args.first())?, &msg));
+            let mut buf = vec![0; 2048];
+            let response = exchange(port, &request, &mut buf)?;
+            println!("{}", response);
+            return Ok(());
+        }
+        "nostr-pubkey" => Request::NostrPubkey(parse_u32(args.first())?),
+        "nostr-sign" => {
+            let account = parse_u32(args.first())?;
+            let path = args.get(1).ok_or_else(|| USAGE.to_string())?;
+            let event = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let event = serde_json::from_str(&event).map_err(|e| format!("{}: {}", path, e))?;
+            let pubkey = nostr::pubkey(port, account)?;
+            println!("{}", nostr::sign_event(port, account, &pubkey, event)?);
+            return Ok(());
+        }
+        "nostr-signer" => {
+            let socket =
+                option_value(&mut args, "--socket")?.unwrap_or("/tmp/novus_wallet_nostr.sock");
+            return nostr::serve(port, parse_u32(args.first())?, socket);
+        }
+        "ecdh" => {
+            let peer = parse_hex(args.get(1))?;
+            let path = args.first().ok_or_else(|| USAGE.to_string())?;
+            let mut buf = vec![0; 2048];
+            let response = exchange(port, &Request::Ecdh((&peer, path)), &mut buf)?;
+            println!("{}", response);
+            return Ok(());
+        }
+        "decrypt" => {
+            let len = args.len();
+            args.retain(|a| *a != "--eip5630");
+            let scheme = if args.len() < len {
+                EciesScheme::Eip5630
+            } else {
+                EciesScheme::EthCrypto
+            };
+            let msg = parse_hex(args.get(1))?;
+            let path = args.first().ok_or_else(|| USAGE.to_string())?;
+            let mut buf = vec![0; 2048];
+            let response = exchange(port, &Request::Decrypt((scheme, path, &msg)), &mut buf)?;
+            println!("{}", response);
+            return Ok(());
+        }
+        "bip85" => {
+            let len = args.len();
+            args.retain(|a| *a != "--to-host");
+            let to_host = args.len() < len;
+            let app = match (args.first(), parse_u32(args.get(1))?) {
+                (Some(&"mnemonic"), words) => Bip85App::Mnemonic(words),
+                (Some(&"hex"), bytes) => Bip85App::Hex(bytes),
+                (Some(&"password"), len) => Bip85App::Password(len),
+                _ => return Err(USAGE.to_string()),
+            };
+            Request::Bip85((app, parse_u32(args.get(2))?, to_host))
+        }
+        "slip39-split" => {
+            let group_threshold = parse_u32(args.first())?;
+            return slip39_split(port, group_threshold, &args[1..]);
+        }
+        "slip39-recover" => return slip39_recover(port),
+        "generate-seed" => {
+            let len = args.len();
+            args.retain(|a| *a != "--dice-only");
+            let dice_only = args.len() < len;
+            let dice = option_value(&mut args, "--dice")?;
+            let words = parse_u32(args.first())?;
+            return match (dice, dice_only) {
+                (Some(_), true) => Err(USAGE.to_string()),
+                (dice, _) => seed::generate(port, words, dice.unwrap_or_default(), dice_only),
+            };
+        }
+        "sign-anti-exfil" => {
+            let tx = option_value(&mut args, "--tx")?.map(|tx| parse_hex(Some(&tx)));
+            let sig = match (tx, args.first()) {
+                (Some(tx), None) => antiexfil::sign(port, AntiExfilPayload::Tx(&tx?))?,
+                (None, Some(msg)) => {
+                    antiexfil::sign(port, AntiExfilPayload::Message(msg.as_bytes()))?
+                }
+                _ => return Err(USAGE.to_string()),
+            };
+            println!("Sig: 0x{} (nonce verified)", hex::encode(sig));
+            return Ok(());
+        }
+        "verify-backup" => return verify_backup(port, args.contains(&"--slip39")),
+        "settings" => return settings(port, args),
+        "wipe" => {
+            println!("Confirm on the device to erase its seed and all stored data");
+            Request::Wipe
+        }
+        "show-address" => Request::ShowAddress(args.first().copied().unwrap_or(DEFAULT_ETH_PATH)),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let mut buf = vec![0; 2048];
+    let response = exchange(port, &request, &mut buf)?;
+    println!("{}", response);
+    Ok(())
+}
+
+fn parse_switch(arg: &str) -> Result<bool, String> {
+    match arg {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, got \"{}\"", arg)),
+    }
+}
+
+fn parse_u16(arg: &str) -> Result<u16, String> {
+    arg.parse()
+        .map_err(|_| format!("expected a number up to 65535, got \"{}\"", arg))
+}
+
+/// Shows the device's settings, or changes the ones given in `args`. The
+/// device checks the new settings and has the user confirm them.
+fn settings(port: &mut dyn SerialPort, mut args: Vec<&str>) -> Result<(), String> {
+    let mut buf = vec![0; 2048];
+    let current = match exchange(port, &Request::GetSettings, &mut buf)? {
+        Response::Settings(current) => current,
+        other => return Err(other.to_string()),
+    };
+    let mut new = current;
+    if let Some(s) = option_value(&mut args, "--confirm-timeout")? {
+        new.confirm_timeout_s = parse_u16(s)?;
+    }
+    if let Some(s) = option_value(&mut args, "--confirm-exports")? {
+        new.confirm_exports = parse_switch(s)?;
+    }
+    if let Some(s) = option_value(&mut args, "--auto-lock")? {
+        new.auto_lock_min = parse_u16(s)?;
+    }
+    if let Some(s) = option_value(&mut args, "--brightness")? {
+        new.brightness = s
+            .parse()
+            .map_err(|_| format!("expected a brightness of 1 to 5, got \"{}\"", s))?;
+    }
+    if let Some(s) = option_value(&mut args, "--blind-signing")? {
+        new.blind_signing = parse_switch(s)?;
+    }
+    let label = option_value(&mut args, "--label")?;
+    if !args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    if new == current && label.is_none() {
+        println!("{}", Response::Settings(current));
+        return Ok(());
+    }
+
+    let new = Settings {
+        label: label.unwrap_or(current.label),
+        ..new
+    };
+    println!("Confirm the new settings on the device");
+    let mut reply = vec![0; 2048];
+    println!(
+        "{}",
+        exchange(port, &Request::ApplySettings(new), &mut reply)?
+    );
+    Ok(())
+}
+
+/// Has the device show a new SLIP-39 share set of groups written `2of3`
+fn slip39_split(
+    port: &mut dyn SerialPort,
+    group_threshold: u32,
+    groups: &[&str],
+) -> Result<(), String> {
+    let mut pairs = Vec::new();
+    for group in groups {
+        let (threshold, count) = group
+            .split_once("of")
+            .and_then(|(t, n)| Some((t.parse::<u8>().ok()?, n.parse::<u8>().ok()?)))
+            .ok_or_else(|| format!("expected a group like 2of3, got \"{}\"", group))?;
+        pairs.extend_from_slice(&[threshold, count]);
+    }
+    if pairs.is_empty() || group_threshold > u8::MAX as u32 {
+        return Err(USAGE.to_string());
+    }
+    // Mixed into the shares, so they don't depend on the seed alone
+    let mut entropy = [0u8; 32];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut f| io::Read::read_exact(&mut f, &mut entropy))
+        .map_err(|e| format!("/dev/urandom: {}", e))?;
+    println!("Write down the shares shown on the device");
+    let mut buf = vec![0; 2048];
+    let request = Request::Slip39Split((group_threshold as u8, &pairs, &entropy));
+    println!("{}", exchange(port, &request, &mut buf)?);
+    Ok(())
+}
+
+/// Has the user type SLIP-39 shares on the device until it has enough
+fn slip39_recover(port: &mut dyn SerialPort) -> Result<(), String> {
+    let mut buf = vec![0; 2048];
+    loop {
+        println!("Enter a share on the device");
+        match exchange(port, &Request::Slip39Share, &mut buf)? {
+            Response::Slip39Progress((done, needed)) if done >= needed => {
+                println!("Seed restored");
+                return Ok(());
+            }
+            Response::Slip39Progress((done, needed)) => {
+                println!("{} of {} groups complete", done, needed)
+            }
+            // A mistyped share can be entered again
+            Response::Err(e)
+                if e != protocol::ERR_USER_REJECTED && e != protocol::ERR_USER_TIMEOUT =>
+            {
+                eprintln!("{}", e)
+            }
+            other => return Err(other.to_string()),
+        }
+    }
+}
+
+/// Has the user type the mnemonic, or SLIP-39 shares, on the device to
+/// check them against its seed. Only whether they match is ever reported.
+fn verify_backup(port: &mut dyn SerialPort, slip39: bool) -> Result<(), String> {
+    let mut buf = vec![0; 2048];
+    let response = if slip39 {
+        loop {
+            println!("Enter a share on the device");
+            match exchange(port, &Request::VerifySlip39Share, &mut buf)? {
+                Response::Slip39Progress((done, needed)) => {
+                    println!("{} of {} groups complete", done, needed)
+                }
+                Response::BackupMatches(matches) => break matches,
+                // A mistyped share can be entered again
+                Response::Err(e)
+                    if e != protocol::ERR_USER_REJECTED && e != protocol::ERR_USER_TIMEOUT =>
+                {
+                    eprintln!("{}", e)
+                }
+                other => return Err(other.to_string()),
+            }
+        }
+    } else {
+        println!("Enter the mnemonic on the device");
+        match exchange(port, &Request::VerifyMnemonic, &mut buf)? {
+            Response::BackupMatches(matches) => matches,
+            other => return Err(other.to_string()),
+        }
+    };
+    if response {
+        println!("Backup matches the device's seed");
+        Ok(())
+    } else {
+        Err("Backup does NOT match the device's seed".to_string())
+    }
+}
+
+/// Has the device sign the binary PSBT file `input`, writing the result to `output`
+fn sign_psbt(
+    port: &mut dyn SerialPort,
+    network: BtcNetwork,
+    input: &str,
+    output: &str,
+) -> Result<(), String> {
+    let psbt = std::fs::read(input).map_err(|e| format!("{}: {}", input, e))?;
+    let mut buf = vec![0; 2048];
+    let mut signed = Vec::new();
+    let mut total = 0;
+    for (i, chunk) in psbt.chunks(PSBT_CHUNK_SIZE).enumerate() {
+        let request = Request::SignPsbt((
+            network,
+            psbt.len() as u32,
+            (i * PSBT_CHUNK_SIZE) as u32,
+            chunk,
+        ));
+        match exchange(port, &request, &mut buf)? {
+            Response::Ok => {}
+            Response::Psbt((len, _, bytes)) => {
+                total = len as usize;
+                signed.extend_from_slice(bytes);
+            }
+            other => return Err(other.to_string()),
+        }
+    }
+    while signed.len() < total {
+        match exchange(port, &Request::PsbtChunk(signed.len() as u32), &mut buf)? {
+            Response::Psbt((_, _, bytes)) if !bytes.is_empty() => signed.extend_from_slice(bytes),
+            other => return Err(other.to_string()),
+        }
+    }
+    std::fs::write(output, &signed).map_err(|e| format!("{}: {}", output, e))?;
+    println!("Signed PSBT written to {}", output);
+    Ok(())
+}
+
+/// How long to wait for an answer. The device gives the user at most ten
+/// minutes per confirmation, and a request rarely needs more than one.
+const RESPONSE_DEADLINE: Duration = Duration::from_secs(15 * 60);
+/// How long to wait while the user types a backup or dice rolls on the
+/// device, which takes a button press or more per letter or roll
+const ENTRY_DEADLINE: Duration = Duration::from_secs(3 * 60 * 60);
+
+/// Sends `request` and waits for the device's answer, which may take a
+/// while if the user has to confirm it first
+fn exchange<'a>(
+    port: &mut dyn SerialPort,
+    request: &Request,
+    buf: &'a mut [u8],
+) -> Result<Response<'a>, String> {
+    let data = to_stdvec(request).map_err(|e| e.to_string())?;
+    let header = protocol::frame_header(data.len()).ok_or("request too long")?;
+    port.write_all(&header)
+        .and_then(|_| port.write_all(&data))
+        .map_err(|e| e.to_string())?;
+
+    // The response arrives in USB packets, read until its frame is complete
+    let deadline = Instant::now()
+        + match request {
+            Request::Slip39Share
+            | Request::VerifyMnemonic
+            | Request::VerifySlip39Share
+            | Request::GenerateSeed((_, SeedEntropy::Dice)) => ENTRY_DEADLINE,
+            _ => RESPONSE_DEADLINE,
+        };
+    let mut len = 0;
+    let msg_len = loop {
+        if let Some(msg) = protocol::frame_message(&buf[..len])? {
+            break msg.len();
+        }
+        if len == buf.len() {
+            return Err("response too long".to_string());
+        }
+        if Instant::now() >= deadline {
+            return Err("no response from the device".to_string());
+        }
+        match port.read(&mut buf[len..]) {
+            Ok(n) => len += n,
+            Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+    let buf: &'a [u8] = buf;
+    // The final byte is the protocol version
+    match buf[protocol::FRAME_HEADER_LEN..protocol::FRAME_HEADER_LEN + msg_len].split_last() {
+        Some((_version, response)) => from_bytes::<Response>(response).map_err(|e| e.to_string()),
+        None => Err("empty response".to_string()),
+    }
+}
@@ -0,0 +1,278 @@
+//! Offline verification of the Bitcoin signed messages `sign-message`
+//! produces: BIP137 signatures of legacy and native SegWit addresses and
+//! BIP322 simple signatures of native SegWit ones.
+use k256::ecdsa::{
+    recoverable::{self, Id},
+    signature::DigestVerifier,
+    Signature, VerifyingKey,
+};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use ripemd160::Ripemd160;
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+const MAGIC: &[u8] = b"\x18Bitcoin Signed Message:\n";
+const BASE58: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BECH32: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let mut b = [0u8; 3];
+        b[..chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn base64_decode(s: &str) -> Result<Vec<u8>, String> {
+    let s = s.trim_end_matches('=');
+    let mut out = Vec::new();
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let v = BASE64
+            .iter()
+            .position(|b| *b == c)
+            .ok_or_else(|| "invalid base64".to_string())?;
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn hash160(data: &[u8]) -> Vec<u8> {
+    Ripemd160::digest(&Sha256::digest(data)).to_vec()
+}
+
+fn double_sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(&Sha256::digest(data)).to_vec()
+}
+
+/// Base58 of `payload` followed by the first four bytes of its double SHA256
+fn base58_check(payload: &[u8]) -> String {
+    let mut bytes = payload.to_vec();
+    bytes.extend_from_slice(&double_sha256(payload)[..4]);
+    // Little endian base58 digits, converted one byte at a time
+    let mut digits: Vec<u8> = Vec::new();
+    for b in &bytes {
+        let mut carry = *b as u32;
+        for d in digits.iter_mut() {
+            carry += (*d as u32) << 8;
+            *d = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = bytes.iter().take_while(|b| **b == 0).count();
+    let mut out = "1".repeat(zeros);
+    out.extend(digits.iter().rev().map(|d| BASE58[*d as usize] as char));
+    out
+}
+
+/// Base58check P2PKH address of `pubkey`, compressed or not
+fn p2pkh_address(version: u8, pubkey: &[u8]) -> String {
+    let mut payload = vec![version];
+    payload.extend(hash160(pubkey));
+    base58_check(&payload)
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    values.iter().fold(1, |chk, v| {
+        let top = chk >> 25;
+        let chk = ((chk & 0x01ff_ffff) << 5) ^ *v as u32;
+        (0..5)
+            .filter(|i| (top >> i) & 1 == 1)
+            .fold(chk, |chk, i| chk ^ GENERATOR[i])
+    })
+}
+
+/// Bech32 P2WPKH address of `pubkey`
+fn p2wpkh_address(hrp: &str, pubkey: &[u8]) -> String {
+    // Witness version 0, then the 160 bit key hash as exactly 32 groups of 5 bits
+    let mut data = vec![0u8];
+    let (mut acc, mut bits) = (0u32, 0);
+    for b in hash160(pubkey) {
+        acc = (acc << 8) | b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            data.push(((acc >> bits) & 0x1f) as u8);
+        }
+    }
+
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 0x1f));
+    values.extend(&data);
+    values.extend(&[0; 6]);
+    let checksum = polymod(&values) ^ 1;
+    data.extend((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 0x1f) as u8));
+
+    let mut out = format!("{}1", hrp);
+    out.extend(data.iter().map(|d| BECH32[*d as usize] as char));
+    out
+}
+
+/// A hasher finishing to the BIP137 hash of `msg`
+fn message_hash(msg: &[u8]) -> Sha256 {
+    let mut preimage = MAGIC.to_vec();
+    compact_size(&mut preimage, msg.len());
+    preimage.extend_from_slice(msg);
+    Sha256::new().chain(Sha256::digest(&preimage))
+}
+
+fn compact_size(out: &mut Vec<u8>, n: usize) {
+    if n < 0xfd {
+        out.push(n as u8);
+    } else {
+        out.push(0xfd);
+        out.extend_from_slice(&(n as u16).to_le_bytes());
+    }
+}
+
+/// A hasher finishing to the BIP143 sighash of BIP322's `to_sign`
+/// transaction for `msg`, spending the P2WPKH output of `pubkey`
+fn bip322_sighash(pubkey: &[u8], msg: &[u8]) -> Sha256 {
+    let tag = Sha256::digest(b"BIP0322-signed-message");
+    let msg_hash = Sha256::new().chain(tag).chain(tag).chain(msg).finalize();
+
+    let mut to_spend = Vec::new();
+    to_spend.extend_from_slice(&[0, 0, 0, 0, 1]);
+    to_spend.extend_from_slice(&[0; 32]);
+    to_spend.extend_from_slice(&[0xff; 4]);
+    to_spend.extend_from_slice(&[34, 0x00, 0x20]);
+    to_spend.extend_from_slice(&msg_hash);
+    to_spend.extend_from_slice(&[0, 0, 0, 0, 1]);
+    to_spend.extend_from_slice(&[0; 8]);
+    to_spend.extend_from_slice(&[22, 0x00, 0x14]);
+    to_spend.extend_from_slice(&hash160(pubkey));
+    to_spend.extend_from_slice(&[0; 4]);
+
+    let mut outpoint = double_sha256(&to_spend);
+    outpoint.extend_from_slice(&[0; 4]);
+    let mut preimage = vec![0; 4];
+    preimage.extend(double_sha256(&outpoint));
+    preimage.extend(double_sha256(&[0; 4]));
+    preimage.extend(&outpoint);
+    preimage.extend_from_slice(&[0x19, 0x76, 0xa9, 0x14]);
+    preimage.extend(hash160(pubkey));
+    preimage.extend_from_slice(&[0x88, 0xac]);
+    preimage.extend_from_slice(&[0; 12]);
+    preimage.extend(double_sha256(&[0, 0, 0, 0, 0, 0, 0, 0, 1, 0x6a]));
+    preimage.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0]);
+    Sha256::new().chain(Sha256::digest(&preimage))
+}
+
+/// The items of a serialized witness
+fn witness_items(mut witness: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let malformed = || "malformed BIP322 signature".to_string();
+    let (count, rest) = witness.split_first().ok_or_else(malformed)?;
+    witness = rest;
+    let mut items = Vec::new();
+    for _ in 0..*count {
+        let (len, rest) = witness.split_first().ok_or_else(malformed)?;
+        if rest.len() < *len as usize {
+            return Err(malformed());
+        }
+        let (item, rest) = rest.split_at(*len as usize);
+        items.push(item);
+        witness = rest;
+    }
+    if !witness.is_empty() {
+        return Err(malformed());
+    }
+    Ok(items)
+}
+
+/// Recovers the key of the 65 byte BIP137 signature `sig` of `msg`, checking
+/// it verifies
+fn recover(sig: &[u8], msg: &[u8]) -> Result<VerifyingKey, String> {
+    let invalid = || "invalid signature".to_string();
+    let id = Id::new((sig[0] - 27) % 4).map_err(|_| invalid())?;
+    let rs = Signature::try_from(&sig[1..]).map_err(|_| invalid())?;
+    let sig = recoverable::Signature::new(&rs, id).map_err(|_| invalid())?;
+    let key = sig
+        .recover_verify_key_from_digest(message_hash(msg))
+        .map_err(|_| invalid())?;
+    key.verify_digest(message_hash(msg), &rs)
+        .map_err(|_| invalid())?;
+    Ok(key)
+}
+
+/// Checks the BIP137 `sig` of `msg` by the legacy P2PKH `address`
+fn verify_p2pkh(address: &str, sig: &[u8], msg: &[u8]) -> Result<(), String> {
+    let version = match address.as_bytes().first() {
+        Some(b'1') => 0x00,
+        Some(b'm') | Some(b'n') => 0x6f,
+        _ => return Err("only legacy and native SegWit addresses are supported".to_string()),
+    };
+    // Headers 27 to 30 are for the uncompressed key, 31 to 34 the compressed
+    let compressed = match sig.first() {
+        Some(27..=34) if sig.len() == 65 => sig[0] >= 31,
+        _ => return Err("invalid signature".to_string()),
+    };
+    let pubkey = recover(sig, msg)?.to_encoded_point(compressed);
+    if p2pkh_address(version, pubkey.as_bytes()) == address {
+        Ok(())
+    } else {
+        Err("signature is by another address".to_string())
+    }
+}
+
+/// Checks the base64 `signature` of `msg` by the native SegWit or legacy
+/// `address`
+pub fn verify(address: &str, signature: &str, msg: &[u8]) -> Result<(), String> {
+    let sig = base64_decode(signature)?;
+    let lower = address.to_lowercase();
+    let hrp = match lower.rfind('1') {
+        Some(pos) if ["bc", "tb", "bcrt"].contains(&&lower[..pos]) => &lower[..pos],
+        _ => return verify_p2pkh(address, &sig, msg),
+    };
+    let invalid = || "invalid signature".to_string();
+
+    let pubkey = match sig.first() {
+        Some(27..=30) if sig.len() == 65 => {
+            return Err("uncompressed keys have no SegWit address".to_string());
+        }
+        // BIP137, also accepting the compressed P2PKH headers some wallets use
+        Some(31..=42) if sig.len() == 65 => recover(&sig, msg)?.to_bytes().to_vec(),
+        _ => match witness_items(&sig)?[..] {
+            [der, pubkey] if der.last() == Some(&0x01) => {
+                let rs = Signature::from_asn1(&der[..der.len() - 1]).map_err(|_| invalid())?;
+                let key = VerifyingKey::from_sec1_bytes(pubkey).map_err(|_| invalid())?;
+                key.verify_digest(bip322_sighash(pubkey, msg), &rs)
+                    .map_err(|_| invalid())?;
+                pubkey.to_vec()
+            }
+            _ => return Err("unsupported BIP322 signature".to_string()),
+        },
+    };
+    if p2wpkh_address(hrp, &pubkey) == lower {
+        Ok(())
+    } else {
+        Err("signature is by another address".to_string())
+    }
+}
@@ -0,0 +1,139 @@
+//! Nostr events signed by the device, one at a time from the command line or
+//! for other programs through a NIP-46 style signer listening on a Unix
+//! socket. Requests are one JSON object per line,
+//! `{"id":..,"method":..,"params":[..]}`, answered with
+//! `{"id":..,"result":..}` or `{"id":..,"error":..}`.
+use crate::exchange;
+use protocol::{Request, Response};
+use serde_json::{json, Value};
+use serialport::SerialPort;
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::{fs::FileTypeExt, net::UnixListener};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Hex public key of `account`, which the device also shows
+pub fn pubkey(port: &mut dyn SerialPort, account: u32) -> Result<String, String> {
+    let mut buf = vec![0; 2048];
+    match exchange(port, &Request::NostrPubkey(account), &mut buf)? {
+        Response::NostrPubkey((_, pubkey)) => Ok(hex::encode(pubkey)),
+        other => Err(other.to_string()),
+    }
+}
+
+/// Fills in what `event` leaves out, `pubkey`, `created_at` and `tags`, and
+/// returns its NIP-01 serialization, whose SHA256 is the event id
+fn serialize(event: &mut Value, pubkey: &str) -> Result<String, String> {
+    let fields = event
+        .as_object_mut()
+        .ok_or_else(|| "event must be a JSON object".to_string())?;
+    match fields.get("pubkey") {
+        None => {
+            fields.insert("pubkey".to_string(), json!(pubkey));
+        }
+        Some(p) if p == pubkey => {}
+        Some(_) => return Err("event is by another key".to_string()),
+    }
+    if !fields.contains_key("created_at") {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?;
+        fields.insert("created_at".to_string(), json!(now.as_secs()));
+    }
+    fields.entry("tags").or_insert_with(|| json!([]));
+
+    let created_at = fields["created_at"].as_u64();
+    let kind = fields.get("kind").and_then(Value::as_u64);
+    let tags = fields["tags"].as_array();
+    let content = fields.get("content").and_then(Value::as_str);
+    match (created_at, kind, tags, content) {
+        (Some(created_at), Some(kind), Some(tags), Some(content)) => {
+            Ok(json!([0, pubkey, created_at, kind, tags, content]).to_string())
+        }
+        _ => Err("event needs a kind and content".to_string()),
+    }
+}
+
+/// Has the device sign `event`, adding its `id` and `sig`
+pub fn sign_event(
+    port: &mut dyn SerialPort,
+    account: u32,
+    pubkey: &str,
+    mut event: Value,
+) -> Result<Value, String> {
+    let serialized = serialize(&mut event, pubkey)?;
+    let mut buf = vec![0; 2048];
+    let request = Request::SignNostrEvent((account, serialized.as_bytes()));
+    match exchange(port, &request, &mut buf)? {
+        Response::NostrEvent((id, sig)) if id == &Sha256::digest(serialized.as_bytes())[..] => {
+            event["id"] = json!(hex::encode(id));
+            event["sig"] = json!(hex::encode(sig));
+            Ok(event)
+        }
+        Response::NostrEvent(_) => Err("device signed a different event".to_string()),
+        other => Err(other.to_string()),
+    }
+}
+
+/// The result of the signer request `method`
+fn call(
+    port: &mut dyn SerialPort,
+    account: u32,
+    pubkey: &str,
+    method: &str,
+    params: &[Value],
+) -> Result<String, String> {
+    match method {
+        "connect" => Ok("ack".to_string()),
+        "ping" => Ok("pong".to_string()),
+        "get_public_key" => Ok(pubkey.to_string()),
+        "sign_event" => {
+            let event = params
+                .first()
+                .and_then(Value::as_str)
+                .ok_or_else(|| "sign_event takes the event as a JSON string".to_string())?;
+            let event = serde_json::from_str(event).map_err(|e| e.to_string())?;
+            Ok(sign_event(port, account, pubkey, event)?.to_string())
+        }
+        _ => Err(format!("unsupported method \"{}\"", method)),
+    }
+}
+
+/// Answers signer requests on the Unix socket at `path` until interrupted
+pub fn serve(port: &mut dyn SerialPort, account: u32, path: &str) -> Result<(), String> {
+    let pubkey = pubkey(port, account)?;
+    // A socket left behind by an earlier run, but nothing else, is replaced
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            std::fs::remove_file(path).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+    let listener = UnixListener::bind(path).map_err(|e| format!("{}: {}", path, e))?;
+    println!("Signing Nostr events for {} on {}", pubkey, path);
+
+    for stream in listener.incoming() {
+        let stream = stream.map_err(|e| e.to_string())?;
+        let mut out = stream.try_clone().map_err(|e| e.to_string())?;
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let reply = match serde_json::from_str::<Value>(&line) {
+                Ok(req) => {
+                    let method = req["method"].as_str().unwrap_or_default();
+                    let params = req["params"].as_array().map_or(&[][..], Vec::as_slice);
+                    match call(port, account, &pubkey, method, params) {
+                        Ok(result) => json!({"id": req["id"], "result": result}),
+                        Err(e) => json!({"id": req["id"], "error": e}),
+                    }
+                }
+                Err(e) => json!({"id": null, "error": e.to_string()}),
+            };
+            if writeln!(out, "{}", reply).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
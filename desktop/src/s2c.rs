@@ -0,0 +1,54 @@
+//! The host's check of an anti-exfil signature, apart from the serial link
+//! so that the simulator's tests can run it against the firmware's signer.
+use k256::{
+    ecdsa::{recoverable, VerifyingKey},
+    elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint},
+    AffinePoint, EncodedPoint, FieldBytes, ProjectivePoint, Scalar,
+};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+
+/// SHA256(SHA256(tag) || SHA256(tag) || parts...)
+pub fn tagged_hash(tag: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let tag = Sha256::digest(tag.as_bytes());
+    let mut h = Sha256::new().chain(tag).chain(tag);
+    for p in parts {
+        h.update(p);
+    }
+    h.finalize().into()
+}
+
+/// Checks that `sig` signs `data` with `pubkey` using the nonce `R0 + t·G`,
+/// `t` being the tweak committing to the device's `r0` and `host_data`
+pub fn verify(
+    pubkey: &[u8],
+    data: &[u8],
+    host_data: &[u8],
+    r0: &[u8],
+    sig: &[u8],
+) -> Result<(), String> {
+    let pubkey = VerifyingKey::from_sec1_bytes(pubkey).map_err(|e| e.to_string())?;
+    let sig = recoverable::Signature::try_from(sig).map_err(|e| e.to_string())?;
+    if sig.recover_verify_key(data).map_err(|e| e.to_string())? != pubkey {
+        return Err("signature is not by the device's key".to_string());
+    }
+
+    let r0 = EncodedPoint::from_bytes(r0)
+        .ok()
+        .and_then(|p| AffinePoint::from_encoded_point(&p))
+        .ok_or_else(|| "invalid nonce commitment".to_string())?;
+    let t = tagged_hash(
+        "s2c/ecdsa/point",
+        &[r0.to_encoded_point(true).as_bytes(), host_data],
+    );
+    let t = Scalar::from_bytes_reduced(&FieldBytes::from(t));
+    let nonce = (ProjectivePoint::from(r0) + ProjectivePoint::generator() * t).to_affine();
+    // r is the nonce's x coordinate reduced mod n
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&nonce.to_encoded_point(true).as_bytes()[1..]);
+    let x = Scalar::from_bytes_reduced(&FieldBytes::from(x));
+    if *sig.r() != x {
+        return Err("device didn't sign with the agreed nonce".to_string());
+    }
+    Ok(())
+}
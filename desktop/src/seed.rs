@@ -0,0 +1,142 @@
+//! New seeds created on the device: the host's side of the commit-reveal
+//! that mixes its entropy into the seed and of the cut and choose that
+//! checks the device's side of it, and the derivation of dice-only seeds,
+//! which can be redone here without the device to check it.
+use crate::exchange;
+use hmac::{Hmac, Mac, NewMac};
+use protocol::{Request, Response, SeedEntropy};
+use serialport::SerialPort;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+fn entropy_len(words: u32) -> Result<usize, String> {
+    match words {
+        12 | 18 | 24 => Ok(words as usize * 4 / 3),
+        _ => Err("seeds are 12, 18 or 24 words".to_string()),
+    }
+}
+
+/// The mnemonic the device creates from `rolls` alone: the SHA256 of the
+/// rolls as ASCII digits, cut to length, as BIP39 entropy
+pub fn from_dice(words: u32, rolls: &str) -> Result<String, String> {
+    let len = entropy_len(words)?;
+    if !rolls.bytes().all(|r| (b'1'..=b'6').contains(&r)) {
+        return Err("dice rolls must be digits 1 to 6".to_string());
+    }
+    // log2(6) is a little over 2.585 bits per roll
+    if rolls.len() * 2585 < len * 8 * 1000 {
+        return Err(format!(
+            "{} words need at least {} rolls",
+            words,
+            (len * 8 * 1000).div_ceil(2585)
+        ));
+    }
+    let entropy = Sha256::digest(rolls.as_bytes());
+    bip39::Mnemonic::from_entropy(&entropy[..len])
+        .map(|m| m.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Each mixed seed is kept with a chance of one in `KEEP_ODDS`, the others
+/// have their device entropy opened and checked. A device that doesn't mix
+/// the entropy as it should is caught with every seed it isn't left to keep,
+/// and can't tell in advance which one that will be.
+const KEEP_ODDS: u8 = 4;
+
+fn urandom(out: &mut [u8]) -> Result<(), String> {
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(out))
+        .map_err(|e| format!("/dev/urandom: {}", e))
+}
+
+/// Checks that the seed the device hashed to `hash` is the one the `device`
+/// entropy it committed to with `commitment` makes with `host` and `dice`
+fn check_opening(
+    commitment: &[u8],
+    device: &[u8],
+    host: &[u8],
+    dice: &str,
+    len: usize,
+    hash: &[u8],
+) -> Result<(), String> {
+    if Sha256::digest(device)[..] != commitment[..] {
+        return Err("device entropy doesn't match its commitment".to_string());
+    }
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_varkey(device).unwrap();
+    mac.update(b"novus seed");
+    mac.update(&Sha256::digest(host));
+    mac.update(&Sha256::digest(dice.as_bytes()));
+    let entropy = mac.finalize().into_bytes();
+    if Sha256::digest(&entropy[..len])[..] != hash[..] {
+        return Err("device didn't make the seed from the committed entropy".to_string());
+    }
+    Ok(())
+}
+
+/// Has the device create and store a seed of `words` words. Unless `dice_only`,
+/// the device's entropy is mixed with 32 bytes from /dev/urandom and `dice`.
+/// Dice-only seeds are rolled on the device, `dice` is then unused.
+pub fn generate(
+    port: &mut dyn SerialPort,
+    words: u32,
+    dice: &str,
+    dice_only: bool,
+) -> Result<(), String> {
+    let mut buf = vec![0; 2048];
+    if dice_only {
+        println!(
+            "Enter the dice rolls on the device, then check the seed with: \
+             novus_wallet dice-seed {} ROLLS",
+            words
+        );
+        println!("Write down the seed shown on the device");
+        let request = Request::GenerateSeed((words, SeedEntropy::Dice));
+        return match exchange(port, &request, &mut buf)? {
+            Response::Ok => {
+                println!("Seed stored");
+                Ok(())
+            }
+            other => Err(other.to_string()),
+        };
+    }
+    let len = entropy_len(words)?;
+    loop {
+        let mut host = [0u8; 32];
+        urandom(&mut host)?;
+        let commitment = Sha256::digest(&host);
+        let device_commitment = match exchange(port, &Request::SeedCommit(&commitment), &mut buf)? {
+            Response::EntropyCommitment(device) => device.to_vec(),
+            other => return Err(other.to_string()),
+        };
+        println!(
+            "Device committed to entropy {}",
+            hex::encode(&device_commitment)
+        );
+        // Only now that the device is committed is the host's entropy revealed
+        let request = Request::GenerateSeed((words, SeedEntropy::Mixed((&host, dice))));
+        let hash = match exchange(port, &request, &mut buf)? {
+            Response::NewSeedHash(hash) => hash.to_vec(),
+            other => return Err(other.to_string()),
+        };
+        let mut choice = [0u8];
+        urandom(&mut choice)?;
+        if choice[0].is_multiple_of(KEEP_ODDS) {
+            println!("Write down the seed shown on the device");
+            return match exchange(port, &Request::KeepSeed, &mut buf)? {
+                Response::Ok => {
+                    println!("Seed stored");
+                    Ok(())
+                }
+                other => Err(other.to_string()),
+            };
+        }
+        match exchange(port, &Request::OpenEntropy, &mut buf)? {
+            Response::DeviceEntropy(device) => {
+                check_opening(&device_commitment, device, &host, dice, len, &hash)?
+            }
+            other => return Err(other.to_string()),
+        }
+        println!("Device entropy checked, trying another seed");
+    }
+}
@@ -2,6 +2,143 @@
 
 use serde::{Deserialize, Serialize};
 
+/// Number of `account'` levels the wallet can derive from (`m/44'/60'/0'..`)
+pub const MAX_ACCOUNTS: usize = 8;
+
+/// Longest label, of an account or of the device, in bytes
+pub const LABEL_LEN: usize = 16;
+
+/// `Response::Err` sent when the user declines on the device
+pub const ERR_USER_REJECTED: &str = "UserRejected";
+/// `Response::Err` sent when the user doesn't answer on the device in time
+pub const ERR_USER_TIMEOUT: &str = "UserTimeout";
+
+/// What `Request::ShowQr` renders as a QR code
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum QrContent {
+    Address,
+    /// An `ethereum:` URI of the address
+    Uri,
+    /// The account's extended public key
+    Xpub,
+}
+
+/// Most PSBT bytes in one `Request::SignPsbt` or `Response::Psbt`
+pub const PSBT_CHUNK_SIZE: usize = 512;
+
+/// Which Bitcoin chain an address or extended key is for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BtcNetwork {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+/// The kind of Bitcoin output, each with its own derivation path
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BtcScript {
+    /// BIP84 native SegWit, `m/84'/..`
+    P2wpkh,
+    /// BIP86 Taproot key path, `m/86'/..`
+    P2tr,
+}
+
+/// How a Bitcoin message signature is encoded
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BtcMessageFormat {
+    /// BIP137 compact signature: header byte || r || s, with the "Bitcoin
+    /// Signed Message" prefix
+    Bip137,
+    /// BIP322 simple signature: the serialized witness of the `to_sign`
+    /// transaction
+    Bip322Simple,
+    /// BIP137 compact signature by the key of a legacy (P2PKH) address,
+    /// `m/44'/coin'/account'/change/index` as in BIP44
+    Bip137P2pkh,
+}
+
+/// How a Cosmos SDK transaction is encoded for signing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CosmosSignMode {
+    /// `SIGN_MODE_DIRECT`: a protobuf `SignDoc`
+    Direct,
+    /// `SIGN_MODE_LEGACY_AMINO_JSON`: a canonical JSON `StdSignDoc`
+    Amino,
+}
+
+/// How a message for `Request::Decrypt` was encrypted
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum EciesScheme {
+    /// eth-crypto's, as `EthCrypto.cipher.stringify` serializes it
+    EthCrypto,
+    /// EIP-5630's, the ECIES of geth's `crypto/ecies`
+    Eip5630,
+}
+
+/// A BIP85 application and its length
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Bip85App {
+    /// English BIP39 mnemonic of 12, 18 or 24 words
+    Mnemonic(u32),
+    /// 16 to 64 bytes as hex
+    Hex(u32),
+    /// Base64 password of 20 to 86 characters
+    Password(u32),
+}
+
+/// ERC-20 metadata for `Request::ProvideTokenInfo`. `signature` is a 64 byte
+/// ECDSA signature (r || s) by the firmware's metadata key over the SHA256 of
+/// `chain_id` (8 bytes big endian) || `address` || `decimals` || `symbol`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenInfo<'a> {
+    pub chain_id: u64,
+    pub address: &'a [u8],
+    pub symbol: &'a str,
+    pub decimals: u8,
+    pub signature: &'a [u8],
+}
+
+/// The device's configurable behaviour, kept in its flash
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Settings<'a> {
+    /// Seconds the device waits for the user to approve or reject
+    pub confirm_timeout_s: u16,
+    /// Whether sending public keys and addresses to the host needs approval
+    pub confirm_exports: bool,
+    /// Minutes without use before the device locks until the user approves
+    /// on it, 0 for never
+    pub auto_lock_min: u16,
+    /// Display brightness, 1 (dimmest) to 5 (brightest)
+    pub brightness: u8,
+    /// Whether what the device can't show readably may be signed: binary
+    /// messages, unknown contract calls, Solana programs and Cosmos messages,
+    /// and Bitcoin outputs without a standard address
+    pub blind_signing: bool,
+    /// Shown on the device while idle
+    pub label: &'a str,
+}
+
+/// What an anti-exfil signature is of, hashed with Keccak256 either way
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AntiExfilPayload<'a> {
+    /// A message, as `Sig` signs it
+    Message(&'a [u8]),
+    /// An Ethereum transaction, as `SignTx` signs it
+    Tx(&'a [u8]),
+}
+
+/// Where the entropy of a new seed comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SeedEntropy<'a> {
+    /// The device's noise sources, the host's entropy committed to by the
+    /// last `SeedCommit` and dice rolls, which may be empty
+    Mixed(#[serde(borrow)] (&'a [u8], &'a str)),
+    /// Dice rolls alone, entered on the device so the host never sees them.
+    /// The entropy is the SHA256 of the rolls as ASCII digits 1 to 6, cut to
+    /// length, so anyone can check the seed.
+    Dice,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Request<'a> {
     Ping,
@@ -11,6 +148,108 @@ pub enum Request<'a> {
     PubKey,
     Address(u32),
     AddressList(u32),
+    Accounts,
+    SelectAccount(u32),
+    /// Enable an account with the given label, or disable it with `None`
+    SetAccount(#[serde(borrow)] (u32, Option<&'a str>)),
+    /// Show the Ethereum address at a derivation path such as
+    /// `m/44'/60'/0'/0/0` on the device for the user to verify. The selected
+    /// account and index stay as they are.
+    ShowAddress(&'a str),
+    /// Show a QR code of the content for (account, index) on the device. The
+    /// selected account and index stay as they are.
+    ShowQr((QrContent, u32, u32)),
+    /// Sign an unsigned EIP-155 legacy or EIP-1559 transaction, as hashed
+    /// for signing, after the user reviewed its contents
+    SignTx(&'a [u8]),
+    /// Teach the device a token to show in `SignTx`, until it is reset
+    ProvideTokenInfo(#[serde(borrow)] TokenInfo<'a>),
+    /// Address at (network, script, account, change, index), also shown on
+    /// the device
+    BtcAddress((BtcNetwork, BtcScript, u32, u32, u32)),
+    /// Account extended public key for (network, script, account)
+    BtcXpub((BtcNetwork, BtcScript, u32)),
+    /// (network, total length, offset, bytes) of a PSBT, sent in order in
+    /// chunks of at most `PSBT_CHUNK_SIZE`. Once complete the user reviews it
+    /// and the first chunk of the signed PSBT is returned.
+    SignPsbt((BtcNetwork, u32, u32, &'a [u8])),
+    /// The chunk of the signed PSBT starting at an offset
+    PsbtChunk(u32),
+    /// `wsh(sortedmulti(...))` output descriptor of a multisig wallet this
+    /// device is a co-signer of, registered once the user approves it
+    RegisterMultisig(&'a str),
+    /// Address of the registered multisig wallet at (network, change, index),
+    /// also shown on the device
+    MultisigAddress((BtcNetwork, u32, u32)),
+    /// Sign a message with the native SegWit key at (network, format,
+    /// account, change, index, message), once the user approves it
+    SignBtcMessage((BtcNetwork, BtcMessageFormat, u32, u32, u32, &'a [u8])),
+    /// Address and public key at (bech32 prefix, account, index) of a Cosmos
+    /// SDK chain, the address also shown on the device
+    CosmosAddress((&'a str, u32, u32)),
+    /// Sign a Cosmos SDK sign doc with the key at (bech32 prefix, mode,
+    /// account, index, sign doc), once the user approves it
+    CosmosSign((&'a str, CosmosSignMode, u32, u32, &'a [u8])),
+    /// Address of a Solana account, also shown on the device
+    SolanaAddress(u32),
+    /// Sign a Solana transaction message with (account, message), once the
+    /// user approves it
+    SignSolanaTx((u32, &'a [u8])),
+    /// `npub` and public key of a Nostr account, also shown on the device
+    NostrPubkey(u32),
+    /// Sign a Nostr event with (account, NIP-01 serialization
+    /// `[0,pubkey,created_at,kind,tags,content]`), once the user approves it
+    SignNostrEvent((u32, &'a [u8])),
+    /// ECDH of (peer SEC1 public key, derivation path such as
+    /// `m/44'/60'/0'/0/0`), once the user approves it
+    Ecdh((&'a [u8], &'a str)),
+    /// Decrypt an ECIES message with (scheme, derivation path, ciphertext),
+    /// once the user approves it
+    Decrypt((EciesScheme, &'a str, &'a [u8])),
+    /// Derive the BIP85 child secret at (application, index) and show it on
+    /// the device. It is sent to the host only if the flag is set and the
+    /// user approves that separately.
+    Bip85((Bip85App, u32, bool)),
+    /// Split the seed into a SLIP-39 share set shown on the device:
+    /// (group threshold, (member threshold, member count) per group, host
+    /// entropy mixed into the share polynomials)
+    Slip39Split((u8, &'a [u8], &'a [u8])),
+    /// Have the user type one SLIP-39 share on the device towards restoring
+    /// a seed. The host never sees the words.
+    Slip39Share,
+    /// Have the user type a written down BIP39 mnemonic on the device to
+    /// check that it is the stored seed, without storing anything
+    VerifyMnemonic,
+    /// One SLIP-39 share, typed on the device, towards checking a backup
+    /// the same way
+    VerifySlip39Share,
+    /// Have the device gather its own entropy for a new seed against the
+    /// SHA256 of the host's, answered by the SHA256 of the device's
+    SeedCommit(&'a [u8]),
+    /// Create a seed of 12, 18 or 24 words. Dice-only seeds are shown and
+    /// stored at once. Mixed seeds are answered by `NewSeedHash` and wait
+    /// for `KeepSeed` or `OpenEntropy`.
+    GenerateSeed((u32, SeedEntropy<'a>)),
+    /// Discard the mixed seed waiting to be kept and answer with the device
+    /// entropy it was made from, for the host to check against the device's
+    /// commitment
+    OpenEntropy,
+    /// Show the mixed seed waiting to be kept on the device and store it
+    KeepSeed,
+    /// Start an anti-exfil signature of what `Sig` or `SignTx` would sign,
+    /// given the host's commitment to its nonce data. Answered with the
+    /// device's nonce commitment.
+    AntiExfilCommit((AntiExfilPayload<'a>, &'a [u8])),
+    /// Finish the anti-exfil signature with the host's nonce data, answered
+    /// with `Sig`
+    AntiExfilSign(&'a [u8]),
+    /// Erase the seed and everything else stored on the device, once the
+    /// user confirms it. The device is left without a seed.
+    Wipe,
+    GetSettings,
+    /// Replace the settings, once the user confirms them. Answered with the
+    /// settings now in effect.
+    ApplySettings(#[serde(borrow)] Settings<'a>),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -23,12 +262,80 @@ pub enum Response<'a> {
     Address(&'a [u8]),
     AddressList(&'a [u8]),
     Err(&'a str),
+    /// Labels of the enabled accounts, `None` for disabled ones
+    Accounts(#[serde(borrow)] [Option<&'a str>; MAX_ACCOUNTS]),
+    Account((u32, &'a str)),
+    /// The text encoded in the QR code the user confirmed
+    Qr(&'a str),
+    Ok,
+    BtcAddress(&'a str),
+    Xpub(&'a str),
+    /// (total length, offset, bytes) of a signed PSBT
+    Psbt((u32, u32, &'a [u8])),
+    /// (address, signature) of a signed Bitcoin message
+    BtcMessageSig((&'a str, &'a [u8])),
+    /// (bech32 address, compressed public key) of a Cosmos SDK account
+    CosmosAddress((&'a str, &'a [u8])),
+    SolanaAddress(&'a str),
+    /// (npub, x-only public key) of a Nostr account
+    NostrPubkey((&'a str, &'a [u8])),
+    /// (event id, BIP340 signature) of a signed Nostr event
+    NostrEvent((&'a [u8], &'a [u8])),
+    /// The compressed shared point of an ECDH, whose x coordinate is the
+    /// usual shared secret
+    SharedSecret(&'a [u8]),
+    Plaintext(&'a [u8]),
+    /// A BIP85 child secret the user chose to send to the host
+    Bip85(&'a str),
+    /// SLIP-39 groups complete and groups needed
+    Slip39Progress((u8, u8)),
+    /// Whether the backup checked is the stored seed
+    BackupMatches(bool),
+    /// The device's commitment to its entropy for a new seed
+    EntropyCommitment(&'a [u8]),
+    /// The SHA256 of a new seed's entropy
+    NewSeedHash(&'a [u8]),
+    /// The device entropy behind an opened commitment
+    DeviceEntropy(&'a [u8]),
+    /// The device's anti-exfil nonce commitment `R0`, compressed
+    SignerCommitment(&'a [u8]),
+    Settings(#[serde(borrow)] Settings<'a>),
 }
 
 pub fn version() -> u8 {
     0x0
 }
 
+/// Requests and responses cross the serial link as frames: the length of
+/// the message as 2 big endian bytes, then the message. USB delivers them in
+/// packets of 64 bytes, so a read can hold part of a frame.
+pub const FRAME_HEADER_LEN: usize = 2;
+/// Longest frame, header included, either side accepts
+pub const MAX_FRAME_LEN: usize = 2048;
+/// `Response::Err` sent when a frame claims to be longer than `MAX_FRAME_LEN`
+pub const ERR_FRAME_TOO_LONG: &str = "frame too long";
+
+/// The header of a frame holding `len` bytes, `None` if they don't fit
+pub fn frame_header(len: usize) -> Option<[u8; FRAME_HEADER_LEN]> {
+    if len > MAX_FRAME_LEN - FRAME_HEADER_LEN {
+        return None;
+    }
+    Some((len as u16).to_be_bytes())
+}
+
+/// The message of the frame at the start of `buf`, `Ok(None)` until all of
+/// it arrived. Bytes past the end of the frame are ignored.
+pub fn frame_message(buf: &[u8]) -> Result<Option<&[u8]>, &'static str> {
+    if buf.len() < FRAME_HEADER_LEN {
+        return Ok(None);
+    }
+    let len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+    if len > MAX_FRAME_LEN - FRAME_HEADER_LEN {
+        return Err(ERR_FRAME_TOO_LONG);
+    }
+    Ok(buf.get(FRAME_HEADER_LEN..FRAME_HEADER_LEN + len))
+}
+
 #[cfg(feature = "std")]
 impl std::fmt::Display for Response<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -48,6 +355,67 @@ impl std::fmt::Display for Response<'_> {
             Self::Serial(b) => write!(f, "Serial: 0x{}", hex::encode(b)),
             Self::Info(b) => write!(f, "Info: {}, 0x{:X} {}", b.0, b.1, hex::encode(b.2)),
             Self::Err(s) => write!(f, "Err: {}", s),
+            Self::Accounts(accts) => {
+                let mut acct_str = String::new();
+                for (idx, label) in accts.iter().enumerate() {
+                    if let Some(label) = label {
+                        acct_str += &format!("\t{}': {}\n", idx, label);
+                    }
+                }
+                acct_str.pop();
+                write!(f, "Accounts: \n{}", acct_str)
+            }
+            Self::Account((idx, label)) => write!(f, "Account: {}' {}", idx, label),
+            Self::Qr(s) => write!(f, "Qr: {}", s),
+            Self::Ok => write!(f, "Ok"),
+            Self::BtcAddress(s) => write!(f, "BtcAddress: {}", s),
+            Self::Xpub(s) => write!(f, "Xpub: {}", s),
+            Self::Psbt((total, offset, b)) => {
+                write!(f, "Psbt ({}/{}): {}", offset, total, hex::encode(b))
+            }
+            Self::BtcMessageSig((addr, sig)) => {
+                write!(f, "BtcMessageSig: {} 0x{}", addr, hex::encode(sig))
+            }
+            Self::CosmosAddress((addr, pubkey)) => {
+                write!(f, "CosmosAddress: {} 0x{}", addr, hex::encode(pubkey))
+            }
+            Self::SolanaAddress(s) => write!(f, "SolanaAddress: {}", s),
+            Self::NostrPubkey((npub, pubkey)) => {
+                write!(f, "NostrPubkey: {} {}", npub, hex::encode(pubkey))
+            }
+            Self::SharedSecret(b) => write!(f, "SharedSecret: 0x{}", hex::encode(b)),
+            Self::Plaintext(b) => match core::str::from_utf8(b) {
+                Ok(text) => write!(f, "Plaintext: {}", text),
+                Err(_) => write!(f, "Plaintext: 0x{}", hex::encode(b)),
+            },
+            Self::Bip85(secret) => write!(f, "Bip85: {}", secret),
+            Self::Slip39Progress((done, needed)) => {
+                write!(f, "Slip39Progress: {} of {} groups", done, needed)
+            }
+            Self::SignerCommitment(b) => write!(f, "SignerCommitment: 0x{}", hex::encode(b)),
+            Self::EntropyCommitment(b) => write!(f, "EntropyCommitment: 0x{}", hex::encode(b)),
+            Self::NewSeedHash(b) => write!(f, "NewSeedHash: 0x{}", hex::encode(b)),
+            Self::DeviceEntropy(b) => write!(f, "DeviceEntropy: 0x{}", hex::encode(b)),
+            Self::BackupMatches(true) => write!(f, "BackupMatches: backup is the stored seed"),
+            Self::BackupMatches(false) => write!(f, "BackupMatches: backup is NOT the stored seed"),
+            Self::Settings(s) => write!(
+                f,
+                "Settings:\n\tconfirm-timeout: {}s\n\tconfirm-exports: {}\n\tauto-lock: {}min\n\tbrightness: {}\n\tblind-signing: {}\n\tlabel: {}",
+                s.confirm_timeout_s,
+                s.confirm_exports,
+                s.auto_lock_min,
+                s.brightness,
+                s.blind_signing,
+                s.label
+            ),
+            Self::NostrEvent((id, sig)) => {
+                write!(
+                    f,
+                    "NostrEvent: id {} sig {}",
+                    hex::encode(id),
+                    hex::encode(sig)
+                )
+            }
         }
     }
 }
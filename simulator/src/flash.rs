@@ -0,0 +1,53 @@
+use crate::{error::WalletErr, kvstore::Flash, layout::Sector, Result};
+
+/// Flash kept in memory, which like the real thing reads 0xFF once erased
+/// and can only have bits cleared by programming. It can be made to lose
+/// power after a number of programming operations, which then fail.
+pub struct RamFlash {
+    pub bytes: Vec<u8>,
+    /// Programming operations left before the power goes, `None` for ever
+    pub programs_left: Option<usize>,
+    /// Sectors erased so far
+    pub erases: usize,
+}
+
+impl RamFlash {
+    /// Erased flash of `size` bytes
+    pub fn new(size: usize) -> Self {
+        RamFlash {
+            bytes: vec![0xFF; size],
+            programs_left: None,
+            erases: 0,
+        }
+    }
+}
+
+impl Flash for RamFlash {
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        let offset = offset as usize;
+        buf.copy_from_slice(&self.bytes[offset..offset + buf.len()]);
+    }
+
+    fn program(&mut self, offset: u32, bytes: &[u8]) -> Result<()> {
+        match self.programs_left {
+            Some(0) => return Err(WalletErr::from("power lost")),
+            Some(ref mut left) => *left -= 1,
+            None => (),
+        }
+        let offset = offset as usize;
+        for (cell, b) in self.bytes[offset..offset + bytes.len()]
+            .iter_mut()
+            .zip(bytes)
+        {
+            *cell &= *b;
+        }
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, sector: &Sector) -> Result<()> {
+        let (start, size) = (sector.offset as usize, sector.size as usize);
+        self.bytes[start..start + size].fill(0xFF);
+        self.erases += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+//! The firmware's modules that don't touch the hardware, built for the host
+//! from the wallet's own sources, and stand-ins for the hardware they talk
+//! to: a framebuffer for the display, a scripted user for the buttons and
+//! flash kept in memory. The tests in tests/ drive them.
+#![allow(dead_code)]
+// The firmware is built with an older toolchain, which has none of
+// `div_ceil`, `is_multiple_of` and `Option::is_some_and`
+#![allow(
+    clippy::manual_div_ceil,
+    clippy::manual_is_multiple_of,
+    clippy::unnecessary_map_or
+)]
+
+#[path = "../../wallet/src/accounts.rs"]
+pub mod accounts;
+#[path = "../../wallet/src/antiexfil.rs"]
+pub mod antiexfil;
+#[path = "../../wallet/src/base58.rs"]
+pub mod base58;
+#[path = "../../wallet/src/bech32.rs"]
+pub mod bech32;
+#[path = "../../wallet/src/bip32.rs"]
+pub mod bip32;
+#[path = "../../wallet/src/bip85.rs"]
+pub mod bip85;
+#[path = "../../wallet/src/btc.rs"]
+pub mod btc;
+#[path = "../../wallet/src/cosmos.rs"]
+pub mod cosmos;
+#[path = "../../wallet/src/display.rs"]
+pub mod display;
+#[path = "../../wallet/src/ecies.rs"]
+pub mod ecies;
+#[path = "../../wallet/src/entropy.rs"]
+pub mod entropy;
+#[path = "../../wallet/src/entry.rs"]
+pub mod entry;
+#[path = "../../wallet/src/error.rs"]
+pub mod error;
+#[path = "../../wallet/src/eth.rs"]
+pub mod eth;
+#[path = "../../wallet/src/json.rs"]
+pub mod json;
+#[path = "../../wallet/src/kvstore.rs"]
+pub mod kvstore;
+#[path = "../../wallet/src/layout.rs"]
+pub mod layout;
+#[path = "../../wallet/src/link.rs"]
+pub mod link;
+#[path = "../../wallet/src/message.rs"]
+pub mod message;
+#[path = "../../wallet/src/multisig.rs"]
+pub mod multisig;
+#[path = "../../wallet/src/nostr.rs"]
+pub mod nostr;
+#[path = "../../wallet/src/presence.rs"]
+pub mod presence;
+#[path = "../../wallet/src/psbt.rs"]
+pub mod psbt;
+#[path = "../../wallet/src/qr.rs"]
+pub mod qr;
+#[path = "../../wallet/src/safemem.rs"]
+pub mod safemem;
+#[path = "../../wallet/src/schnorr.rs"]
+pub mod schnorr;
+#[path = "../../wallet/src/settings.rs"]
+pub mod settings;
+#[path = "../../wallet/src/slip10.rs"]
+pub mod slip10;
+#[path = "../../wallet/src/slip39.rs"]
+pub mod slip39;
+#[path = "../../wallet/src/solana.rs"]
+pub mod solana;
+#[path = "../../wallet/src/ui.rs"]
+pub mod ui;
+
+mod flash;
+mod screen;
+mod user;
+
+pub use flash::RamFlash;
+pub use screen::Framebuffer;
+pub use user::ScriptedUser;
+
+pub type Result<T> = core::result::Result<T, error::WalletErr>;
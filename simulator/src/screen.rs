@@ -0,0 +1,83 @@
+use crate::{
+    display::{Screen, HEIGHT, WIDTH},
+    Result,
+};
+
+use core::convert::Infallible;
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, DrawTarget};
+
+const W: usize = WIDTH as usize;
+const H: usize = HEIGHT as usize;
+
+/// A display in memory. What was drawn only counts once it is shown, as on
+/// the OLED.
+pub struct Framebuffer {
+    drawing: [[bool; W]; H],
+    shown: [[bool; W]; H],
+    /// How many times the screen was shown
+    pub shows: usize,
+    /// As last set, 1 to 5
+    pub brightness: u8,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Framebuffer {
+            drawing: [[false; W]; H],
+            shown: [[false; W]; H],
+            shows: 0,
+            brightness: 5,
+        }
+    }
+
+    /// Whether the pixel at (`x`, `y`) is lit on the screen shown last
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.shown[y][x]
+    }
+
+    /// The screen shown last, a line of text per row: `#` lit, `.` dark
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity((W + 1) * H);
+        for row in self.shown.iter() {
+            text.extend(row.iter().map(|lit| if *lit { '#' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DrawTarget<BinaryColor> for Framebuffer {
+    type Error = Infallible;
+
+    fn draw_pixel(&mut self, pixel: Pixel<BinaryColor>) -> core::result::Result<(), Infallible> {
+        let Pixel(Point { x, y }, color) = pixel;
+        // Like the SSD1306 driver, ignore what falls off the screen
+        if (0..WIDTH).contains(&x) && (0..HEIGHT).contains(&y) {
+            self.drawing[y as usize][x as usize] = color == BinaryColor::On;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Size {
+        Size::new(WIDTH as u32, HEIGHT as u32)
+    }
+}
+
+impl Screen for Framebuffer {
+    fn show(&mut self) -> Result<()> {
+        self.shown = self.drawing;
+        self.shows += 1;
+        Ok(())
+    }
+
+    fn set_brightness(&mut self, level: u8) -> Result<()> {
+        self.brightness = level;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use crate::presence::{Decision, UserPresence};
+
+use std::collections::VecDeque;
+
+/// A user who follows a script. Each poll of the buttons is a millisecond,
+/// as on the device, so `UserPresence::confirm` times out as it would there.
+/// Once the script runs out, nobody presses anything.
+#[derive(Default)]
+pub struct ScriptedUser {
+    /// Milliseconds to wait, then the button to press, if any
+    script: VecDeque<(u32, Option<Decision>)>,
+    waited: u32,
+    /// Milliseconds polled so far
+    pub elapsed_ms: u64,
+}
+
+impl ScriptedUser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Presses `decision`'s button after `after_ms`
+    pub fn press(mut self, after_ms: u32, decision: Decision) -> Self {
+        assert!(decision != Decision::TimedOut, "there is no timeout button");
+        self.script.push_back((after_ms, Some(decision)));
+        self
+    }
+
+    pub fn approve(self) -> Self {
+        self.press(0, Decision::Approved)
+    }
+
+    pub fn reject(self) -> Self {
+        self.press(0, Decision::Rejected)
+    }
+
+    /// Presses nothing for `ms`
+    pub fn idle(mut self, ms: u32) -> Self {
+        self.script.push_back((ms, None));
+        self
+    }
+
+    /// Whether every step of the script was played
+    pub fn is_done(&self) -> bool {
+        self.script.is_empty()
+    }
+}
+
+impl UserPresence for ScriptedUser {
+    fn poll(&mut self) -> Option<Decision> {
+        self.elapsed_ms += 1;
+        let (wait, decision) = *self.script.front()?;
+        if self.waited < wait {
+            self.waited += 1;
+            return None;
+        }
+        self.script.pop_front();
+        self.waited = 0;
+        decision
+    }
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::err_msg;
+use simulator::{
+    accounts::{self, LABEL_LEN, MAX_ACCOUNTS},
+    kvstore::Store,
+    layout::Sector,
+    RamFlash,
+};
+
+fn store_on(flash: RamFlash) -> Store<RamFlash> {
+    let sectors = [
+        Sector {
+            number: 1,
+            offset: 0,
+            size: 0x400,
+        },
+        Sector {
+            number: 2,
+            offset: 0x400,
+            size: 0x400,
+        },
+    ];
+    Store::new(flash, sectors)
+}
+
+fn store() -> Store<RamFlash> {
+    store_on(RamFlash::new(0x800))
+}
+
+#[test]
+fn only_account_0_enabled_at_first() {
+    let kv = store();
+    let accts = accounts::accounts(&kv);
+    assert_eq!(accts[0].as_deref(), Some(""));
+    assert!(accts[1..].iter().all(|a| a.is_none()));
+    assert!(accounts::check(&kv, 0).is_ok());
+    assert_eq!(err_msg(accounts::check(&kv, 1)), "account not enabled");
+    assert_eq!(
+        err_msg(accounts::check(&kv, MAX_ACCOUNTS as u32)),
+        "account not enabled"
+    );
+}
+
+#[test]
+fn enabled_and_disabled() {
+    let mut kv = store();
+    accounts::set_account(&mut kv, 2, Some("savings")).unwrap();
+    accounts::set_account(&mut kv, 0, Some("main")).unwrap();
+    assert_eq!(
+        accounts::labels(&accounts::accounts(&kv))[..3],
+        [Some("main"), None, Some("savings")]
+    );
+    assert!(accounts::check(&kv, 2).is_ok());
+
+    accounts::set_account(&mut kv, 2, None).unwrap();
+    assert_eq!(err_msg(accounts::check(&kv, 2)), "account not enabled");
+    assert_eq!(
+        err_msg(accounts::set_account(&mut kv, 0, None)),
+        "account 0 cannot be disabled"
+    );
+    assert_eq!(
+        err_msg(accounts::set_account(
+            &mut kv,
+            MAX_ACCOUNTS as u32,
+            Some("")
+        )),
+        "account out of range"
+    );
+}
+
+#[test]
+fn label_length() {
+    let mut kv = store();
+    let longest = "a".repeat(LABEL_LEN);
+    accounts::set_account(&mut kv, 1, Some(&longest)).unwrap();
+    assert_eq!(accounts::accounts(&kv)[1].as_deref(), Some(&longest[..]));
+    assert_eq!(
+        err_msg(accounts::set_account(
+            &mut kv,
+            1,
+            Some(&format!("{}a", longest))
+        )),
+        "account label too long"
+    );
+    assert_eq!(accounts::accounts(&kv)[1].as_deref(), Some(&longest[..]));
+}
+
+#[test]
+fn kept_in_storage() {
+    let mut kv = store();
+    accounts::set_account(&mut kv, 3, Some("cold")).unwrap();
+    // What a restart reads back from the same flash
+    let mut flash = RamFlash::new(0x800);
+    flash.bytes.copy_from_slice(&kv.flash().bytes);
+    let kv = store_on(flash);
+    assert_eq!(accounts::accounts(&kv)[3].as_deref(), Some("cold"));
+    assert!(accounts::is_enabled(&kv, 3));
+}
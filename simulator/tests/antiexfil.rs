@@ -0,0 +1,104 @@
+mod common;
+
+#[path = "../../desktop/src/s2c.rs"]
+mod s2c;
+
+use common::err_msg;
+use simulator::antiexfil;
+
+use k256::{
+    ecdsa::{
+        recoverable,
+        signature::{Signature as _, Signer},
+        SigningKey, VerifyingKey,
+    },
+    elliptic_curve::sec1::ToEncodedPoint,
+};
+use tiny_keccak::{Hasher, Keccak};
+
+const SECRET: [u8; 32] = [0x42; 32];
+const MESSAGE: &[u8] = b"anti-exfil";
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut out = [0u8; 32];
+    hasher.finalize(&mut out);
+    out
+}
+
+fn pubkey() -> Vec<u8> {
+    let key = VerifyingKey::from(&SigningKey::from_bytes(&SECRET).unwrap());
+    key.to_encoded_point(true).as_bytes().to_vec()
+}
+
+/// The device's side of one signature: (R0, signature)
+fn device(host_commitment: &[u8], host_data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let pending = antiexfil::commit(&SECRET, &keccak256(MESSAGE), host_commitment).unwrap();
+    let r0 = pending.signer_commitment().to_vec();
+    let sig = pending.sign(host_data).unwrap();
+    (r0, sig.as_bytes().to_vec())
+}
+
+#[test]
+fn round_trip() {
+    let host_data = [7; 32];
+    let commitment = s2c::tagged_hash("s2c/ecdsa/data", &[&host_data]);
+    let (r0, sig) = device(&commitment, &host_data);
+    assert_eq!(
+        s2c::verify(&pubkey(), MESSAGE, &host_data, &r0, &sig),
+        Ok(())
+    );
+    assert_eq!(
+        s2c::verify(&pubkey(), b"another message", &host_data, &r0, &sig),
+        Err("signature is not by the device's key".to_string())
+    );
+}
+
+#[test]
+fn nonce_not_from_the_committed_tweak_refused() {
+    let host_data = [7; 32];
+    let commitment = s2c::tagged_hash("s2c/ecdsa/data", &[&host_data]);
+    let (r0, sig) = device(&commitment, &host_data);
+    // R0 of another signature
+    let other = s2c::tagged_hash("s2c/ecdsa/data", &[&[8; 32]]);
+    let (other_r0, _) = device(&other, &[8; 32]);
+    assert_eq!(
+        s2c::verify(&pubkey(), MESSAGE, &host_data, &other_r0, &sig),
+        Err("device didn't sign with the agreed nonce".to_string())
+    );
+    // An ordinary signature, whose nonce the host's data didn't go into
+    let plain: recoverable::Signature = SigningKey::from_bytes(&SECRET).unwrap().sign(MESSAGE);
+    assert_eq!(
+        s2c::verify(&pubkey(), MESSAGE, &host_data, &r0, plain.as_bytes()),
+        Err("device didn't sign with the agreed nonce".to_string())
+    );
+}
+
+#[test]
+fn host_data_reused_or_missing_refused() {
+    let old_data = [7; 32];
+    let old = s2c::tagged_hash("s2c/ecdsa/data", &[&old_data]);
+    let (old_r0, old_sig) = device(&old, &old_data);
+    // A device replaying an earlier signature for new host data
+    let new_data = [9; 32];
+    assert_eq!(
+        s2c::verify(&pubkey(), MESSAGE, &new_data, &old_r0, &old_sig),
+        Err("device didn't sign with the agreed nonce".to_string())
+    );
+    // The device won't sign without the data it was committed to
+    let pending =
+        |commitment: &[u8]| antiexfil::commit(&SECRET, &keccak256(MESSAGE), commitment).unwrap();
+    assert_eq!(
+        err_msg(pending(&old).sign(&new_data)),
+        "host data doesn't match its commitment"
+    );
+    assert_eq!(
+        err_msg(pending(&old).sign(&[])),
+        "host data doesn't match its commitment"
+    );
+    assert_eq!(
+        err_msg(antiexfil::commit(&SECRET, &keccak256(MESSAGE), &[])),
+        "host commitment must be 32 bytes"
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::err_msg;
+use simulator::{base58, bip32::ExtendedKey, bip85};
+
+use protocol::Bip85App;
+
+/// The master key all of BIP85's test vectors derive from
+const XPRV: &str = "xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb";
+
+fn derive(app: Bip85App, index: u32) -> String {
+    let mut raw = [0u8; 78];
+    assert_eq!(base58::decode_check(XPRV, &mut raw), Some(raw.len()));
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&raw[13..45]);
+    let master = ExtendedKey::root(&raw[46..], chain_code).unwrap();
+    bip85::from_master(&master, app, index).unwrap().to_string()
+}
+
+// The test vectors from BIP85
+
+#[test]
+fn bip39_12_words() {
+    assert_eq!(
+        derive(Bip85App::Mnemonic(12), 0),
+        "girl mad pet galaxy egg matter matrix prison refuse sense ordinary nose"
+    );
+}
+
+#[test]
+fn bip39_18_words() {
+    assert_eq!(
+        derive(Bip85App::Mnemonic(18), 0),
+        "near account window bike charge season chef number sketch tomorrow excuse sniff \
+         circle vital hockey outdoor supply token"
+    );
+}
+
+#[test]
+fn bip39_24_words() {
+    assert_eq!(
+        derive(Bip85App::Mnemonic(24), 0),
+        "puppy ocean match cereal symbol another shed magic wrap hammer bulb intact gadget \
+         divorce twin tonight reason outdoor destroy simple truth cigar social volcano"
+    );
+}
+
+#[test]
+fn hex() {
+    assert_eq!(
+        derive(Bip85App::Hex(64), 0),
+        "492db4698cf3b73a5a24998aa3e9d7fa96275d85724a91e71aa2d645442f8785\
+         55d078fd1f1f67e368976f04137b1f7a0d19232136ca50c44614af72b5582a5c"
+    );
+}
+
+#[test]
+fn password() {
+    assert_eq!(derive(Bip85App::Password(21), 0), "dKLoepugzdVJvdL56ogNV");
+}
+
+#[test]
+fn paths() {
+    let path = |app| simulator::bip32::path_str(&bip85::path(app, 1).unwrap());
+    assert_eq!(
+        path(Bip85App::Mnemonic(12)).as_str(),
+        "m/83696968'/39'/0'/12'/1'"
+    );
+    assert_eq!(
+        path(Bip85App::Hex(32)).as_str(),
+        "m/83696968'/128169'/32'/1'"
+    );
+    assert_eq!(
+        path(Bip85App::Password(20)).as_str(),
+        "m/83696968'/707764'/20'/1'"
+    );
+}
+
+#[test]
+fn unsupported_lengths_refused() {
+    for app in &[
+        Bip85App::Mnemonic(15),
+        Bip85App::Hex(15),
+        Bip85App::Hex(65),
+        Bip85App::Password(19),
+        Bip85App::Password(87),
+    ] {
+        assert_eq!(err_msg(bip85::path(*app, 0)), "unsupported BIP85 length");
+    }
+    assert_eq!(
+        err_msg(bip85::path(Bip85App::Hex(16), 1 << 31)),
+        "index out of range"
+    );
+}
+
+#[test]
+fn words_shown_in_fours() {
+    let secret = bip85::derive(&[1; 64], Bip85App::Mnemonic(12), 0).unwrap();
+    let fields = bip85::fields(&secret);
+    let labels: Vec<&str> = fields.iter().map(|f| f.label).collect();
+    assert_eq!(labels, ["1-4", "5-8", "9-12"]);
+    let shown: Vec<&str> = fields.iter().map(|f| f.value.as_str()).collect();
+    assert_eq!(shown.join(" "), &*secret);
+}
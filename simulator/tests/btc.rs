@@ -0,0 +1,96 @@
+use hex_literal::hex;
+use simulator::{
+    bip32::ExtendedKey,
+    btc::{self, BtcNetwork, BtcScript},
+    schnorr,
+};
+
+/// BIP39 seed of "abandon abandon ... about" with an empty passphrase
+const SEED: [u8; 64] = hex!(
+    "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1"
+    "9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+);
+
+fn address(script: BtcScript, change: u32, index: u32) -> String {
+    btc::address(&SEED, BtcNetwork::Mainnet, script, 0, change, index)
+        .unwrap()
+        .as_str()
+        .to_string()
+}
+
+// The test vectors from BIP84
+
+#[test]
+fn bip84_account_xpub() {
+    let xpub = btc::account_xpub(&SEED, BtcNetwork::Mainnet, BtcScript::P2wpkh, 0).unwrap();
+    assert_eq!(
+        xpub.as_str(),
+        "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs"
+    );
+}
+
+#[test]
+fn bip84_addresses() {
+    assert_eq!(
+        address(BtcScript::P2wpkh, 0, 0),
+        "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+    );
+    assert_eq!(
+        address(BtcScript::P2wpkh, 0, 1),
+        "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"
+    );
+    assert_eq!(
+        address(BtcScript::P2wpkh, 1, 0),
+        "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"
+    );
+}
+
+#[test]
+fn paths_out_of_range() {
+    let net = BtcNetwork::Mainnet;
+    assert!(btc::path(net, BtcScript::P2wpkh, 0x8000_0000, 0, 0).is_err());
+    assert!(btc::path(net, BtcScript::P2wpkh, 0, 2, 0).is_err());
+    assert!(btc::path(net, BtcScript::P2wpkh, 0, 0, 0x8000_0000).is_err());
+}
+
+// The test vectors from BIP86
+
+#[test]
+fn bip86_account_xpub() {
+    let xpub = btc::account_xpub(&SEED, BtcNetwork::Mainnet, BtcScript::P2tr, 0).unwrap();
+    assert_eq!(
+        xpub.as_str(),
+        "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ"
+    );
+}
+
+#[test]
+fn bip86_output_key() {
+    let path = btc::path(BtcNetwork::Mainnet, BtcScript::P2tr, 0, 0, 0).unwrap();
+    let pubkey = ExtendedKey::derive(&SEED, &path).unwrap().public_key();
+    let internal = btc::internal_key(&pubkey);
+    assert_eq!(
+        internal,
+        hex!("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115")
+    );
+    assert_eq!(
+        schnorr::taproot_output_key(&internal).unwrap(),
+        hex!("a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c")
+    );
+}
+
+#[test]
+fn bip86_addresses() {
+    assert_eq!(
+        address(BtcScript::P2tr, 0, 0),
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+    );
+    assert_eq!(
+        address(BtcScript::P2tr, 0, 1),
+        "bc1p4qhjn9zdvkux4e44uhx8tc55attvtyu358kutcqkudyccelu0was9fqzwh"
+    );
+    assert_eq!(
+        address(BtcScript::P2tr, 1, 0),
+        "bc1p3qkhfews2uk44qtvauqyr2ttdsw7svhkl9nkm9s9c3x4ax5h60wqwruhk7"
+    );
+}
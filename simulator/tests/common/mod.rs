@@ -0,0 +1,42 @@
+// Each test binary uses only some of these
+#![allow(dead_code)]
+
+use simulator::error::WalletErr;
+use std::{env, fs, path::PathBuf};
+
+/// Compares `actual` with the golden file tests/snapshots/`name`.txt.
+/// Run with `UPDATE_SNAPSHOTS=1` to write the files instead, then review
+/// the diff.
+pub fn assert_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{}.txt", name));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {}, run with UPDATE_SNAPSHOTS=1", path.display(), e));
+    assert!(
+        expected == actual,
+        "{} differs from the screen drawn:\n{}",
+        path.display(),
+        actual
+    );
+}
+
+/// The message of an error the device would send back
+pub fn err_msg<T>(res: Result<T, WalletErr>) -> String {
+    match res {
+        Err(WalletErr::StringErr(msg)) => msg.as_str().to_string(),
+        Err(WalletErr::NoMsg) => panic!("no message"),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+/// The BIP39 seed of `words` with an empty passphrase
+pub fn bip39_seed(words: &str) -> [u8; 64] {
+    let mut seed = [0u8; 64];
+    pbkdf2::pbkdf2::<hmac::Hmac<sha2::Sha512>>(words.as_bytes(), b"mnemonic", 2048, &mut seed);
+    seed
+}
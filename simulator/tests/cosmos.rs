@@ -0,0 +1,156 @@
+mod common;
+
+use common::err_msg;
+use hex_literal::hex;
+use simulator::{
+    bip32::ExtendedKey,
+    cosmos::{self, CosmosSignMode},
+};
+
+/// BIP39 seed of "abandon abandon ... about" with an empty passphrase
+const SEED: [u8; 64] = hex!(
+    "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1"
+    "9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+);
+const OURS: &str = "cosmos19rl4cm2hmr8afy4kldpxz3fka4jguq0auqdal4";
+const THEIRS: &str = "cosmos1zt50azupanqlfam5afhv3hexwyutnukeh4c573";
+
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn bytes_field(out: &mut Vec<u8>, number: u64, value: &[u8]) {
+    varint(out, number << 3 | 2);
+    varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+fn uint_field(out: &mut Vec<u8>, number: u64, value: u64) {
+    varint(out, number << 3);
+    varint(out, value);
+}
+
+fn coin(denom: &str, amount: &str) -> Vec<u8> {
+    let mut coin = Vec::new();
+    bytes_field(&mut coin, 1, denom.as_bytes());
+    bytes_field(&mut coin, 2, amount.as_bytes());
+    coin
+}
+
+/// A `SignDoc` sending 1000uatom from us, with `extra` appended to its body
+fn sign_doc(extra: &[u8]) -> Vec<u8> {
+    let mut send = Vec::new();
+    bytes_field(&mut send, 1, OURS.as_bytes());
+    bytes_field(&mut send, 2, THEIRS.as_bytes());
+    bytes_field(&mut send, 3, &coin("uatom", "1000"));
+    let mut any = Vec::new();
+    bytes_field(&mut any, 1, b"/cosmos.bank.v1beta1.MsgSend");
+    bytes_field(&mut any, 2, &send);
+    let mut body = Vec::new();
+    bytes_field(&mut body, 1, &any);
+    body.extend_from_slice(extra);
+
+    let mut signer_info = Vec::new();
+    uint_field(&mut signer_info, 3, 7);
+    let mut fee = Vec::new();
+    bytes_field(&mut fee, 1, &coin("uatom", "500"));
+    uint_field(&mut fee, 2, 200_000);
+    let mut auth_info = Vec::new();
+    bytes_field(&mut auth_info, 1, &signer_info);
+    bytes_field(&mut auth_info, 2, &fee);
+
+    let mut doc = Vec::new();
+    bytes_field(&mut doc, 1, &body);
+    bytes_field(&mut doc, 2, &auth_info);
+    bytes_field(&mut doc, 3, b"cosmoshub-4");
+    uint_field(&mut doc, 4, 42);
+    doc
+}
+
+fn shown(mode: CosmosSignMode, doc: &[u8]) -> Vec<(&'static str, String)> {
+    let (fields, blind) = cosmos::summary(mode, doc, OURS).unwrap();
+    assert!(!blind);
+    fields
+        .iter()
+        .map(|f| (f.label, f.value.as_str().to_string()))
+        .collect()
+}
+
+fn expected() -> Vec<(&'static str, String)> {
+    [
+        ("Chain", "cosmoshub-4"),
+        ("Account number", "42"),
+        ("Sequence", "7"),
+        ("Send", "1000 uatom"),
+        ("To", THEIRS),
+        ("Fee", "500 uatom"),
+        ("Gas", "200000"),
+    ]
+    .iter()
+    .map(|(label, value)| (*label, value.to_string()))
+    .collect()
+}
+
+#[test]
+fn address_at_path() {
+    let key = ExtendedKey::derive(&SEED, &cosmos::path(0, 0).unwrap()).unwrap();
+    assert_eq!(
+        cosmos::address("cosmos", &key.public_key())
+            .unwrap()
+            .as_str(),
+        OURS
+    );
+}
+
+#[test]
+fn direct_sign_doc_shown() {
+    assert_eq!(shown(CosmosSignMode::Direct, &sign_doc(&[])), expected());
+}
+
+#[test]
+fn other_messages_blind() {
+    let mut any = Vec::new();
+    bytes_field(&mut any, 1, b"/cosmos.gov.v1beta1.MsgVote");
+    bytes_field(&mut any, 2, &[]);
+    let mut msg = Vec::new();
+    bytes_field(&mut msg, 1, &any);
+    let (fields, blind) = cosmos::summary(CosmosSignMode::Direct, &sign_doc(&msg), OURS).unwrap();
+    assert!(blind);
+    assert!(fields
+        .iter()
+        .any(|f| f.label == "Message" && f.value.as_str() == "/cosmos.gov.v1beta1.MsgVote"));
+}
+
+#[test]
+fn extension_options_rejected() {
+    for number in [1023, 2047].iter() {
+        let mut extension = Vec::new();
+        bytes_field(&mut extension, *number, &[]);
+        assert_eq!(
+            err_msg(cosmos::summary(
+                CosmosSignMode::Direct,
+                &sign_doc(&extension),
+                OURS
+            )),
+            "unsupported extension options"
+        );
+    }
+}
+
+#[test]
+fn amino_sign_doc_shown() {
+    let doc = format!(
+        concat!(
+            r#"{{"account_number":"42","chain_id":"cosmoshub-4","#,
+            r#""fee":{{"amount":[{{"amount":"500","denom":"uatom"}}],"gas":"200000"}},"memo":"","#,
+            r#""msgs":[{{"type":"cosmos-sdk/MsgSend","value":{{"amount":[{{"amount":"1000","denom":"uatom"}}],"#,
+            r#""from_address":"{}","to_address":"{}"}}}}],"sequence":"7"}}"#
+        ),
+        OURS, THEIRS
+    );
+    assert_eq!(shown(CosmosSignMode::Amino, doc.as_bytes()), expected());
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::assert_snapshot;
+use simulator::{
+    display::{self, Field, FieldValue},
+    Framebuffer,
+};
+
+fn field(label: &'static str, value: &str) -> Field {
+    Field {
+        label,
+        value: FieldValue::from(value),
+    }
+}
+
+#[test]
+fn boot() {
+    let mut fb = Framebuffer::new();
+    display::boot(&mut fb).unwrap();
+    assert_snapshot("boot", &fb.to_text());
+}
+
+#[test]
+fn status() {
+    let mut fb = Framebuffer::new();
+    display::status(&mut fb, "Ready").unwrap();
+    assert_eq!(fb.shows, 1);
+    assert_snapshot("status", &fb.to_text());
+}
+
+#[test]
+fn status_wraps_and_cuts_off() {
+    let mut fb = Framebuffer::new();
+    let long = "The quick brown fox jumps over the lazy dog. ".repeat(5);
+    display::status(&mut fb, &long).unwrap();
+    assert_snapshot("status_cut_off", &fb.to_text());
+}
+
+#[test]
+fn choice() {
+    let mut fb = Framebuffer::new();
+    display::choice(&mut fb, "Word 3 of 20", "Spell: ab", "s").unwrap();
+    assert_snapshot("choice", &fb.to_text());
+}
+
+#[test]
+fn address() {
+    let mut fb = Framebuffer::new();
+    display::address(
+        &mut fb,
+        "Verify address",
+        "m/44'/60'/0'/0/0",
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+    )
+    .unwrap();
+    assert_snapshot("address", &fb.to_text());
+}
+
+#[test]
+fn sign_printable_message() {
+    let mut fb = Framebuffer::new();
+    display::sign_message(&mut fb, b"Hello, world!").unwrap();
+    assert_snapshot("sign_message_text", &fb.to_text());
+}
+
+#[test]
+fn sign_binary_message_as_hex() {
+    let mut fb = Framebuffer::new();
+    display::sign_message(&mut fb, &[0x00, 0xde, 0xad, 0xbe, 0xef, 0xff]).unwrap();
+    assert_snapshot("sign_message_hex", &fb.to_text());
+}
+
+#[test]
+fn nothing_counts_until_shown() {
+    let mut fb = Framebuffer::new();
+    display::status(&mut fb, "Ready").unwrap();
+    let before = fb.to_text();
+    // Draw without showing, as a screen that fails half way would
+    use embedded_graphics::{pixelcolor::BinaryColor, DrawTarget};
+    fb.clear(BinaryColor::On).unwrap();
+    assert_eq!(fb.to_text(), before);
+}
+
+#[test]
+fn word_fields() {
+    let fields = display::word_fields("a b c d e f g h i j k l");
+    let values: Vec<_> = fields.iter().map(|f| (f.label, f.value.as_str())).collect();
+    assert_eq!(
+        values,
+        [("1-4", "a b c d"), ("5-8", "e f g h"), ("9-12", "i j k l")]
+    );
+}
+
+#[test]
+fn printable() {
+    assert_eq!(display::printable(b"ok to show"), Some("ok to show"));
+    assert_eq!(display::printable(b"tab\there"), None);
+    assert_eq!(display::printable("caf\u{e9}".as_bytes()), None);
+}
+
+#[test]
+fn hex_str() {
+    let s: heapless::String<heapless::consts::U8> = display::hex_str(&[0xab, 0xcd, 0xef]);
+    // Cut off where it no longer fits
+    assert_eq!(s.as_str(), "0xabcdef");
+    let s: heapless::String<heapless::consts::U6> = display::hex_str(&[0xab, 0xcd, 0xef]);
+    assert_eq!(s.as_str(), "0xabcd");
+}
+
+#[test]
+fn fields_page() {
+    let mut fb = Framebuffer::new();
+    let fields = [
+        field("To", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+        field("Value", "1.5 ETH"),
+        field("Fee", "0.00042 ETH"),
+        field("Nonce", "7"),
+    ];
+    let shown = display::fields(&mut fb, "Sign transaction?", &fields).unwrap();
+    // The address takes four lines, the next two fit below it
+    assert_eq!(shown, 3);
+    assert_snapshot("fields", &fb.to_text());
+
+    display::fields(&mut fb, "Sign transaction?", &fields[shown..]).unwrap();
+    assert_snapshot("fields_second_page", &fb.to_text());
+}
+
+#[test]
+fn field_too_long_for_a_page_is_still_shown() {
+    let mut fb = Framebuffer::new();
+    let fields = [field("Data", &"ab".repeat(48))];
+    assert_eq!(display::fields(&mut fb, "Data", &fields).unwrap(), 1);
+}
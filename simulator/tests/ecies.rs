@@ -0,0 +1,61 @@
+mod common;
+
+use common::err_msg;
+use hex_literal::hex;
+use protocol::EciesScheme;
+use simulator::ecies;
+
+// Known answers made with Python's `cryptography` package following each
+// construction: the recipient's secret key is 0x11.., the ephemeral one
+// 0x22.. and the IV 00 01 .. 0f
+const SECRET: [u8; 32] = [0x11; 32];
+const PLAINTEXT: &[u8] = b"The quick brown fox jumps over the lazy dog";
+const ETH_CRYPTO: [u8; 129] = hex!(
+    "000102030405060708090a0b0c0d0e0f02466d7fcae563e5cb09a0d1870bb58034"
+    "4804617879a14949cf22285f1bae3f27413ed453a22301a5915fb467921390fc66"
+    "08b9c42bac423b923e99d2659b49b5e72037726f00dc04678a02190ad6de5295f8"
+    "4aa113c5d1a1a9f9fcd6e44350aaa32a6539eb6ad7eb1774ac1ccb993fb4"
+);
+const EIP5630: [u8; 156] = hex!(
+    "04466d7fcae563e5cb09a0d1870bb580344804617879a14949cf22285f1bae3f27"
+    "6728176c3c6431f8eeda4538dc37c865e2784f3a9e77d044f33e407797e1278a00"
+    "0102030405060708090a0b0c0d0e0f73e6fb2ff0185be31b7a5bda0f7d44c2dec9"
+    "63a8d1e85e420edce7fb8615ffb2ab3872a7b8168c9246752449153dc7cfe80a8a"
+    "84ae1f92b7c25254811c04ddddc87cb8e982e4956cdb658d"
+);
+
+fn vectors() -> [(EciesScheme, &'static [u8]); 2] {
+    [
+        (EciesScheme::EthCrypto, &ETH_CRYPTO),
+        (EciesScheme::Eip5630, &EIP5630),
+    ]
+}
+
+#[test]
+fn known_answers() {
+    for (scheme, msg) in vectors().iter() {
+        let verified = ecies::verify(*scheme, &SECRET, msg).unwrap();
+        assert_eq!(&verified.decrypt().unwrap()[..], PLAINTEXT, "{:?}", scheme);
+    }
+}
+
+#[test]
+fn other_key_refused_before_decrypting() {
+    for (scheme, msg) in vectors().iter() {
+        assert_eq!(
+            err_msg(ecies::verify(*scheme, &[0x33; 32], msg)),
+            "wrong key or corrupted message"
+        );
+    }
+}
+
+#[test]
+fn tampering_detected() {
+    for (scheme, msg) in vectors().iter() {
+        for pos in [0, msg.len() / 2, msg.len() - 1].iter() {
+            let mut tampered = msg.to_vec();
+            tampered[*pos] ^= 1;
+            assert!(ecies::verify(*scheme, &SECRET, &tampered).is_err());
+        }
+    }
+}
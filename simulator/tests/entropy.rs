@@ -0,0 +1,137 @@
+mod common;
+
+use common::err_msg;
+use simulator::entropy::{
+    self, Commit, HealthTests, PROPORTION_CUTOFF, PROPORTION_WINDOW, REPETITION_CUTOFF,
+};
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+
+/// Feeds `samples` to fresh health tests, whether they all passed
+fn healthy(samples: &[u8]) -> bool {
+    let mut tests = HealthTests::default();
+    match samples.iter().try_for_each(|s| tests.feed(*s)) {
+        Ok(()) => true,
+        res => {
+            assert_eq!(err_msg(res), "entropy source failed health test");
+            false
+        }
+    }
+}
+
+/// A window of samples in which the first, 0, comes back `matches` times
+/// counting itself, in runs too short for the repetition count test
+fn biased_window(matches: u16) -> Vec<u8> {
+    let mut window = Vec::new();
+    for n in 1..=matches {
+        window.push(0);
+        if n % 10 == 0 {
+            window.push(1);
+        }
+    }
+    while window.len() < PROPORTION_WINDOW as usize {
+        window.push(2 + window.len() as u8 % 2);
+    }
+    window
+}
+
+#[test]
+fn repetition_count_cutoff() {
+    let n = REPETITION_CUTOFF as usize;
+    assert!(healthy(&vec![7; n - 1]));
+    assert!(!healthy(&vec![7; n]));
+    // A different sample starts the count over
+    let mut broken = vec![7; 2 * n - 1];
+    broken[n - 1] = 8;
+    assert!(healthy(&broken));
+}
+
+#[test]
+fn adaptive_proportion_cutoff() {
+    assert!(!healthy(&biased_window(PROPORTION_CUTOFF)));
+    // One match fewer passes, and the next window counts afresh
+    let below = biased_window(PROPORTION_CUTOFF - 1);
+    assert!(healthy(&below));
+    assert!(healthy(&[&below[..], &below[..]].concat()));
+}
+
+#[test]
+fn dice_parsed() {
+    let rolls = "1234563216543215".repeat(4);
+    let entropy = entropy::dice_entropy(&rolls, 16).unwrap();
+    assert_eq!(entropy[..16], Sha256::digest(rolls.as_bytes())[..16]);
+    assert_eq!(entropy[16..], [0; 16]);
+
+    assert_eq!(
+        err_msg(entropy::dice_entropy(&rolls.replace('6', "7"), 16)),
+        "dice rolls must be digits 1 to 6"
+    );
+    assert_eq!(
+        err_msg(entropy::dice_entropy(&format!("{} ", rolls), 16)),
+        "dice rolls must be digits 1 to 6"
+    );
+    assert_eq!(
+        err_msg(entropy::dice_entropy(&format!("{}444444444", rolls), 16)),
+        "too many repeated dice rolls"
+    );
+    assert!(entropy::dice_entropy(&format!("{}44444444", rolls), 16).is_ok());
+    assert_eq!(
+        err_msg(entropy::dice_entropy(&rolls[..49], 16)),
+        "not enough dice rolls"
+    );
+}
+
+#[test]
+fn dice_needed() {
+    assert_eq!(entropy::dice_needed(16), 50);
+    assert_eq!(entropy::dice_needed(24), 75);
+    assert_eq!(entropy::dice_needed(32), 100);
+}
+
+#[test]
+fn entropy_len() {
+    assert_eq!(entropy::entropy_len(12).unwrap(), 16);
+    assert_eq!(entropy::entropy_len(18).unwrap(), 24);
+    assert_eq!(entropy::entropy_len(24).unwrap(), 32);
+    assert_eq!(
+        err_msg(entropy::entropy_len(15)),
+        "seeds are 12, 18 or 24 words"
+    );
+}
+
+#[test]
+fn commitment_opens_to_the_seed() {
+    let (device, host, dice) = ([1; 32], [2; 32], "123456");
+    let commit = Commit::with_entropy(device, &entropy::commitment(&host)).unwrap();
+    assert_eq!(commit.device_commitment(), entropy::commitment(&device));
+    let new_seed = commit.reveal(&host, dice, 16).unwrap();
+
+    // What the host recomputes from the opening
+    let mut mac = Hmac::<Sha256>::new_varkey(&device).unwrap();
+    mac.update(b"novus seed");
+    mac.update(&Sha256::digest(&host));
+    mac.update(&Sha256::digest(dice.as_bytes()));
+    let expected = mac.finalize().into_bytes();
+    assert_eq!(new_seed.entropy(), &expected[..16]);
+    assert_eq!(new_seed.hash(), entropy::commitment(&expected[..16]));
+    assert_eq!(new_seed.open(), device);
+}
+
+#[test]
+fn host_reveal_checked() {
+    let commit = |host: &[u8]| Commit::with_entropy([1; 32], host).unwrap();
+    let host = [2; 32];
+    assert_eq!(
+        err_msg(commit(&entropy::commitment(&host)).reveal(&[3; 32], "", 16)),
+        "host entropy doesn't match its commitment"
+    );
+    assert_eq!(
+        err_msg(commit(&entropy::commitment(&host)).reveal(&host, "7", 16)),
+        "dice rolls must be digits 1 to 6"
+    );
+    assert_eq!(
+        err_msg(Commit::with_entropy([1; 32], &host[..31])),
+        "host commitment must be 32 bytes"
+    );
+}
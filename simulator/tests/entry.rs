@@ -0,0 +1,163 @@
+mod common;
+
+use common::err_msg;
+use simulator::{entry, slip39, ui::Ui, Framebuffer, ScriptedUser};
+
+fn ui(user: ScriptedUser) -> Ui<Framebuffer, ScriptedUser> {
+    Ui {
+        disp: Framebuffer::new(),
+        user,
+    }
+}
+
+/// Presses reject `n` times, then confirm
+fn pick(mut user: ScriptedUser, n: usize) -> ScriptedUser {
+    for _ in 0..n {
+        user = user.reject();
+    }
+    user.approve()
+}
+
+/// The presses that spell `word` of `wordlist`, letter by letter until
+/// only `word` is left
+fn spell(mut user: ScriptedUser, wordlist: &str, word: &str) -> ScriptedUser {
+    let mut prefix = String::new();
+    loop {
+        let matches: Vec<&str> = wordlist
+            .lines()
+            .filter(|w| w.starts_with(prefix.as_str()))
+            .collect();
+        if matches.len() == 1 {
+            return user.approve();
+        }
+        let mut options: Vec<&str> = Vec::new();
+        for w in matches {
+            let next = w.get(prefix.len()..prefix.len() + 1).unwrap_or(w);
+            if options.last() != Some(&next) {
+                options.push(next);
+            }
+        }
+        if let Some(i) = options.iter().position(|o| *o == word) {
+            return pick(user, i);
+        }
+        let letter = &word[prefix.len()..prefix.len() + 1];
+        user = pick(user, options.iter().position(|o| *o == letter).unwrap());
+        prefix.push_str(letter);
+    }
+}
+
+/// The only share of a 1-of-1 backup of `secret`
+fn share_words(secret: &[u8]) -> String {
+    let groups = slip39::groups(&[1, 1]).unwrap();
+    let mut words = String::new();
+    slip39::split(secret, 1, &groups, &[9; 32], &[7; 32], |share| {
+        words = share.words()?.as_str().to_string();
+        Ok(())
+    })
+    .unwrap();
+    words
+}
+
+/// Picks the share length, then spells every word of `words`
+fn type_share(words: &str) -> ScriptedUser {
+    let length = match words.split(' ').count() {
+        20 => 0,
+        27 => 1,
+        _ => 2,
+    };
+    words
+        .split(' ')
+        .fold(pick(ScriptedUser::new(), length), |user, w| {
+            spell(user, slip39::WORDLIST, w)
+        })
+}
+
+#[test]
+fn words_spelled_letter_by_letter() {
+    let wordlist = "add\naddress\nadjust\nbeach\n";
+    // "a", "d" and "d" leave "add" and "address"; "add" is offered first
+    let user = pick(pick(pick(pick(ScriptedUser::new(), 0), 0), 0), 0);
+    // "b" is the only word left once the first letter is picked
+    let user = pick(pick(user, 1), 0);
+    let mut ui = ui(user);
+    let phrase = entry::words(&mut ui, "Word", wordlist, 2, 1000).unwrap();
+    assert_eq!(phrase.as_str(), "add beach");
+    assert!(ui.user.is_done());
+}
+
+#[test]
+fn back_takes_back_letters_and_words() {
+    let wordlist = "add\naddress\nadjust\nbeach\n";
+    // "b", then at the second word "<" goes back to the first
+    let user = pick(pick(ScriptedUser::new(), 1), 0);
+    let user = pick(user, 2);
+    // "a" then "<" takes the letter back, and "b" gives "beach"
+    let user = pick(pick(user, 0), 1);
+    let user = pick(pick(user, 1), 0);
+    // Second word: "a", "d", "d", then "r" after "add" leaves only "address"
+    let user = pick(pick(pick(pick(pick(user, 0), 0), 0), 1), 0);
+    let mut ui = ui(user);
+    let phrase = entry::words(&mut ui, "Word", wordlist, 2, 1000).unwrap();
+    assert_eq!(phrase.as_str(), "beach address");
+    assert!(ui.user.is_done());
+}
+
+#[test]
+fn mnemonic_typed_on_device() {
+    let phrase = "abandon abandon abandon abandon abandon abandon \
+                  abandon abandon abandon abandon abandon about";
+    let user = phrase.split(' ').fold(ScriptedUser::new(), |user, w| {
+        spell(user, entry::BIP39_WORDLIST, w)
+    });
+    let mut ui = ui(user);
+    let typed = entry::words(&mut ui, "Word", entry::BIP39_WORDLIST, 12, 1000).unwrap();
+    assert_eq!(typed.as_str(), phrase);
+    assert!(ui.user.is_done());
+}
+
+#[test]
+fn dice_rolled_on_device() {
+    // 3, then "<" takes it back, then 1 and 6
+    let user = pick(pick(ScriptedUser::new(), 2), 6);
+    let user = pick(pick(user, 0), 5);
+    let mut ui = ui(user);
+    let rolls = entry::dice(&mut ui, 2, 1000).unwrap();
+    assert_eq!(rolls.as_str(), "16");
+    assert!(ui.user.is_done());
+}
+
+#[test]
+fn share_typed_on_device() {
+    for secret in [&[0x42u8; 16][..], &[0x42; 24], &[0x42; 32]].iter() {
+        let words = share_words(secret);
+        let mut ui = ui(type_share(&words));
+        let share = entry::slip39_share(&mut ui, 1000).unwrap();
+        assert_eq!(share.words().unwrap().as_str(), words);
+        assert!(ui.user.is_done());
+    }
+}
+
+#[test]
+fn mistyped_share_caught_by_checksum() {
+    let words = share_words(&[0x42; 16]);
+    let mut typed: Vec<&str> = words.split(' ').collect();
+    typed[10] = if typed[10] == "academic" {
+        "acid"
+    } else {
+        "academic"
+    };
+    let mut ui = ui(type_share(&typed.join(" ")));
+    assert_eq!(
+        err_msg(entry::slip39_share(&mut ui, 1000)),
+        "share checksum mismatch"
+    );
+}
+
+#[test]
+fn entry_times_out() {
+    let mut ui = ui(ScriptedUser::new());
+    assert_eq!(
+        err_msg(entry::slip39_share(&mut ui, 1000)),
+        protocol::ERR_USER_TIMEOUT
+    );
+}
@@ -0,0 +1,307 @@
+mod common;
+
+use common::err_msg;
+use hex_literal::hex;
+use k256::ecdsa::{signature::DigestSigner, Signature, SigningKey, VerifyingKey};
+use protocol::TokenInfo;
+use sha2::{Digest, Sha256};
+use simulator::{bip32, eth};
+use tiny_keccak::{Hasher, Keccak};
+
+/// BIP39 seed of "abandon abandon ... about" with an empty passphrase
+const SEED: [u8; 64] = hex!(
+    "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1"
+    "9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+);
+
+/// The signing data of the EIP-155 example: nonce 9, 20 gwei, 21000 gas,
+/// 1 ETH to 0x3535...35 on chain 1
+const EIP155_TX: [u8; 45] = hex!(
+    "ec098504a817c800825208943535353535353535353535353535353535353535"
+    "880de0b6b3a764000080018080"
+);
+/// 0.015 ETH to 0x5aAe...eAed, nonce 3, 2 gwei tip, 40 gwei max fee
+const EIP1559_TX: [u8; 49] = hex!(
+    "02ef010384773594008509502f9000825208945aaeb6053f3e94c9b9a09f33669435"
+    "e7ef1beaed87354a6ba7a1800080c0"
+);
+/// 1.5 USDC to 0x5aAe...eAed, nonce 4, 1 gwei tip, 30 gwei max fee
+const USDC_TRANSFER: [u8; 112] = hex!(
+    "02f86d0104843b9aca008506fc23ac0082fde894a0b86991c6218b36c1d19d4a2e9e"
+    "b0ce3606eb4880b844a9059cbb0000000000000000000000005aaeb6053f3e94c9b9"
+    "a09f33669435e7ef1beaed000000000000000000000000000000000000000000000000"
+    "000000000016e360c0"
+);
+
+fn shown(tx: &[u8]) -> Vec<(&'static str, String)> {
+    shown_with(tx, &eth::TokenRegistry::new())
+}
+
+fn shown_with(tx: &[u8], tokens: &eth::TokenRegistry) -> Vec<(&'static str, String)> {
+    eth::Tx::parse(tx)
+        .unwrap()
+        .summary(tokens)
+        .unwrap()
+        .iter()
+        .map(|f| (f.label, f.value.as_str().to_string()))
+        .collect()
+}
+
+fn fields(expected: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+    expected.iter().map(|(l, v)| (*l, v.to_string())).collect()
+}
+
+/// Signs token metadata with the development metadata key
+fn sign_token_info(chain_id: u64, address: &[u8], decimals: u8, symbol: &str) -> [u8; 64] {
+    let secret = Sha256::digest(b"novus wallet metadata development key");
+    let digest = Sha256::new()
+        .chain(chain_id.to_be_bytes())
+        .chain(address)
+        .chain([decimals])
+        .chain(symbol.as_bytes());
+    let sig: Signature = SigningKey::from_bytes(&secret).unwrap().sign_digest(digest);
+    let mut out = [0u8; 64];
+    out.copy_from_slice(sig.as_ref());
+    out
+}
+
+fn from_hex(s: &str) -> [u8; eth::ADDR_SIZE] {
+    let mut addr = [0u8; eth::ADDR_SIZE];
+    for (i, byte) in addr.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[2 + 2 * i..4 + 2 * i], 16).unwrap();
+    }
+    addr
+}
+
+#[test]
+fn eip55_checksums() {
+    // The test vectors from EIP-55
+    let vectors = [
+        "0x52908400098527886E0F7030069857D2E4169EE7",
+        "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+        "0xde709f2102306220921060314715629080e2fb77",
+        "0x27b1fdb04752bbc536007a920d24acb045561c26",
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+    for expected in vectors.iter() {
+        let addr = from_hex(&expected.to_lowercase());
+        assert_eq!(eth::checksum_address(&addr).as_str(), *expected);
+    }
+}
+
+#[test]
+fn address_at_path() {
+    use bip32::HARDENED;
+    let path = [44 | HARDENED, 60 | HARDENED, HARDENED, 0, 0];
+    let key = bip32::ExtendedKey::derive(&SEED, &path).unwrap();
+    let pubkey = VerifyingKey::from_sec1_bytes(&key.public_key()).unwrap();
+    let addr = eth::address(&pubkey);
+    assert_eq!(
+        eth::checksum_address(&addr).as_str(),
+        "0x9858EfFD232B4033E47d90003D41EC34EcaEda94"
+    );
+}
+
+#[test]
+fn rlp_lengths_past_the_end() {
+    // A list claiming 2^32 + 1 bytes, which is 1 byte once cut to 32 bits
+    let tx = [0xfc, 0x01, 0x00, 0x00, 0x00, 0x01, 0x80];
+    assert!(eth::Tx::parse(&tx).is_err());
+    // A string claiming 2^64 - 1 bytes
+    let tx = [0xc9, 0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+    assert!(eth::Tx::parse(&tx).is_err());
+}
+
+#[test]
+fn eip155_tx_shown() {
+    // The signing hash EIP-155 gives for its example
+    let mut hash = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(&EIP155_TX);
+    keccak.finalize(&mut hash);
+    assert_eq!(
+        hash,
+        hex!("daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53")
+    );
+
+    let tx = eth::Tx::parse(&EIP155_TX).unwrap();
+    assert_eq!(tx.chain_id, 1);
+    assert_eq!(tx.max_priority_fee, None);
+    assert_eq!(
+        shown(&EIP155_TX),
+        fields(&[
+            ("Value", "1 ETH"),
+            ("To", "0x3535353535353535353535353535353535353535"),
+            ("Chain", "Ethereum"),
+            ("Gas price", "20 gwei"),
+            ("Gas limit", "21000"),
+            ("Nonce", "9"),
+        ])
+    );
+}
+
+#[test]
+fn eip1559_tx_shown() {
+    assert_eq!(
+        shown(&EIP1559_TX),
+        fields(&[
+            ("Value", "0.015 ETH"),
+            ("To", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            ("Chain", "Ethereum"),
+            ("Max fee", "40 gwei"),
+            ("Priority fee", "2 gwei"),
+            ("Gas limit", "21000"),
+            ("Nonce", "3"),
+        ])
+    );
+}
+
+#[test]
+fn erc20_transfer_shown() {
+    assert_eq!(
+        shown(&USDC_TRANSFER),
+        fields(&[
+            ("Send", "1.5 USDC"),
+            ("To", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            ("Chain", "Ethereum"),
+            ("Max fee", "30 gwei"),
+            ("Priority fee", "1 gwei"),
+            ("Gas limit", "65000"),
+            ("Nonce", "4"),
+        ])
+    );
+
+    // The same call on Optimism, where that isn't the USDC contract
+    let mut tx = USDC_TRANSFER;
+    tx[3] = 10;
+    assert_eq!(
+        shown(&tx)[..3],
+        fields(&[
+            ("Send", "1500000 units"),
+            ("To", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            (
+                "Unknown token",
+                "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+            ),
+        ])[..]
+    );
+}
+
+#[test]
+fn rlp_lists_where_bytes_expected() {
+    // The EIP-155 example with the recipient wrapped in a list
+    let tx = hex!(
+        "ed098504a817c800825208d5943535353535353535353535353535353535353535"
+        "880de0b6b3a764000080018080"
+    );
+    assert!(eth::Tx::parse(&tx).is_err());
+    // An empty list, not an empty string, must not read as contract creation
+    let tx = hex!("d8098504a817c80082520880880de0b6b3a764000080018080");
+    assert!(eth::Tx::parse(&tx).unwrap().to.is_none());
+    let tx = hex!("d8098504a817c800825208c0880de0b6b3a764000080018080");
+    assert!(eth::Tx::parse(&tx).is_err());
+
+    // An access list that is a string
+    let mut tx = EIP1559_TX;
+    tx[48] = 0x80;
+    assert!(eth::Tx::parse(&tx).is_err());
+}
+
+#[test]
+fn format_units_scales() {
+    assert_eq!(
+        eth::format_units(&[0x0f, 0x42, 0x40], 6, "USDC").as_str(),
+        "1 USDC"
+    );
+    assert_eq!(eth::format_units(&[0x0f], 1, "X").as_str(), "1.5 X");
+    assert_eq!(eth::format_units(&[], 18, "ETH").as_str(), "0 ETH");
+}
+
+#[test]
+fn format_units_past_max_decimals() {
+    let max = [0xff; 32];
+    let out = eth::format_units(&max, eth::MAX_DECIMALS, "X");
+    assert!(out.starts_with("1.15792089237316195423570985"));
+    for decimals in [eth::MAX_DECIMALS + 1, 255].iter() {
+        assert_eq!(
+            eth::format_units(&max, *decimals, "X").as_str(),
+            "(overflow)"
+        );
+    }
+}
+
+#[test]
+fn registry_rejects_too_many_decimals() {
+    let info = TokenInfo {
+        chain_id: 1,
+        address: &[0x11; eth::ADDR_SIZE],
+        symbol: "TKN",
+        decimals: eth::MAX_DECIMALS + 1,
+        signature: &[0; 64],
+    };
+    assert_eq!(
+        err_msg(eth::TokenRegistry::new().add(&info)),
+        "invalid token decimals"
+    );
+}
+
+#[test]
+fn signed_token_info_accepted() {
+    const USDC_OPTIMISM: [u8; 20] = hex!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+    let signature = sign_token_info(10, &USDC_OPTIMISM, 6, "USDC");
+    let mut tokens = eth::TokenRegistry::new();
+    tokens
+        .add(&TokenInfo {
+            chain_id: 10,
+            address: &USDC_OPTIMISM,
+            symbol: "USDC",
+            decimals: 6,
+            signature: &signature,
+        })
+        .unwrap();
+
+    let mut tx = USDC_TRANSFER;
+    tx[3] = 10;
+    assert_eq!(
+        shown_with(&tx, &tokens)[..4],
+        fields(&[
+            ("Send", "1.5 USDC"),
+            ("To", "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            ("Token info", "dev-signed"),
+            ("Chain", "Optimism"),
+        ])[..]
+    );
+    // Built-in tokens don't carry the marker
+    assert_eq!(shown_with(&USDC_TRANSFER, &tokens)[2].0, "Chain");
+    // Nor does the registration leak to other chains
+    tx[3] = 56;
+    assert_eq!(shown_with(&tx, &tokens)[2].0, "Unknown token");
+}
+
+#[test]
+fn token_info_bound_to_chain_and_contract() {
+    let address = [0x11; eth::ADDR_SIZE];
+    let signature = sign_token_info(10, &address, 18, "TKN");
+    let info = |chain_id, address| TokenInfo {
+        chain_id,
+        address,
+        symbol: "TKN",
+        decimals: 18,
+        signature: &signature,
+    };
+    let mut tokens = eth::TokenRegistry::new();
+    assert_eq!(
+        err_msg(tokens.add(&info(1, &address))),
+        "invalid token signature"
+    );
+    assert_eq!(
+        err_msg(tokens.add(&info(10, &[0x22; eth::ADDR_SIZE]))),
+        "invalid token signature"
+    );
+    assert!(tokens.find(1, &address).is_none());
+    tokens.add(&info(10, &address)).unwrap();
+    assert_eq!(tokens.find(10, &address).unwrap().symbol, "TKN");
+}
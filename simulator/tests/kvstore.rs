@@ -0,0 +1,187 @@
+mod common;
+
+use common::err_msg;
+use simulator::{kvstore::Store, layout::Sector, RamFlash};
+
+const SECTOR_SIZE: u32 = 256;
+
+/// A store over two small sectors, so that it fills up quickly
+fn store() -> Store<RamFlash> {
+    let sectors = [
+        Sector {
+            number: 1,
+            offset: 0,
+            size: SECTOR_SIZE,
+        },
+        Sector {
+            number: 2,
+            offset: SECTOR_SIZE,
+            size: SECTOR_SIZE,
+        },
+    ];
+    Store::new(RamFlash::new(2 * SECTOR_SIZE as usize), sectors)
+}
+
+fn get(kv: &Store<RamFlash>, key: u16) -> Option<Vec<u8>> {
+    kv.get(key).map(|v| v.to_vec())
+}
+
+/// The generation written at the start of the sector at `offset`
+fn generation(kv: &Store<RamFlash>, offset: usize) -> u32 {
+    let bytes = &kv.flash().bytes[offset..offset + 4];
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[test]
+fn empty() {
+    assert_eq!(get(&store(), 1), None);
+}
+
+#[test]
+fn overwrite() {
+    let mut kv = store();
+    kv.set(1, b"one").unwrap();
+    kv.set(2, b"two").unwrap();
+    kv.set(1, b"uno").unwrap();
+    assert_eq!(get(&kv, 1), Some(b"uno".to_vec()));
+    assert_eq!(get(&kv, 2), Some(b"two".to_vec()));
+    // The same value again programs nothing
+    let before = kv.flash().bytes.clone();
+    kv.set(1, b"uno").unwrap();
+    assert_eq!(kv.flash().bytes, before);
+}
+
+#[test]
+fn delete() {
+    let mut kv = store();
+    kv.set(1, b"one").unwrap();
+    kv.set(2, b"two").unwrap();
+    kv.remove(1).unwrap();
+    assert_eq!(get(&kv, 1), None);
+    assert_eq!(get(&kv, 2), Some(b"two".to_vec()));
+    kv.set(1, b"back").unwrap();
+    assert_eq!(get(&kv, 1), Some(b"back".to_vec()));
+}
+
+#[test]
+fn full_sector_compacted() {
+    let mut kv = store();
+    kv.set(1, b"kept").unwrap();
+    kv.set(2, b"deleted").unwrap();
+    kv.remove(2).unwrap();
+    // Only the first write erased a sector
+    assert_eq!(kv.flash().erases, 1);
+    for i in 0..20u8 {
+        kv.set(3, &[i; 12]).unwrap();
+    }
+    assert_eq!(kv.flash().erases, 2);
+    assert_eq!(generation(&kv, SECTOR_SIZE as usize), 1);
+    assert_eq!(get(&kv, 1), Some(b"kept".to_vec()));
+    assert_eq!(get(&kv, 2), None);
+    assert_eq!(get(&kv, 3), Some(vec![19; 12]));
+
+    // And back into the first sector
+    for i in 0..2u8 {
+        kv.set(3, &[i; 12]).unwrap();
+    }
+    assert_eq!(kv.flash().erases, 3);
+    assert_eq!(generation(&kv, 0), 2);
+    assert_eq!(get(&kv, 1), Some(b"kept".to_vec()));
+    assert_eq!(get(&kv, 3), Some(vec![1; 12]));
+}
+
+#[test]
+fn too_long_refused() {
+    let mut kv = store();
+    assert_eq!(err_msg(kv.set(1, &[0; 1025])), "value too long to store");
+    assert_eq!(err_msg(kv.set(1, &[0; 256])), "storage full");
+    assert_eq!(err_msg(kv.set(0xFFFF, b"")), "invalid storage key");
+}
+
+#[test]
+fn torn_record_ignored() {
+    let mut kv = store();
+    kv.set(1, b"one").unwrap();
+    // Power lost after the header of the next record, before its value
+    kv.flash_mut().programs_left = Some(1);
+    assert_eq!(err_msg(kv.set(1, b"uno")), "power lost");
+    kv.flash_mut().programs_left = None;
+    assert_eq!(get(&kv, 1), Some(b"one".to_vec()));
+
+    // A header cut short leaves the log unreadable past it
+    let mut kv = store();
+    kv.set(1, b"one").unwrap();
+    let end = 8 + 12;
+    kv.flash_mut().bytes[end] = 0x02;
+    kv.flash_mut().bytes[end + 1] = 0x00;
+    assert_eq!(get(&kv, 1), Some(b"one".to_vec()));
+    // Writing past it compacts into the other sector first
+    kv.set(2, b"two").unwrap();
+    assert_eq!(generation(&kv, SECTOR_SIZE as usize), 1);
+    assert_eq!(get(&kv, 1), Some(b"one".to_vec()));
+    assert_eq!(get(&kv, 2), Some(b"two".to_vec()));
+}
+
+#[test]
+fn corrupt_crc_ignored() {
+    let mut kv = store();
+    kv.set(1, b"one").unwrap();
+    kv.set(1, b"uno").unwrap();
+    // Flip a bit of the newer value, the older one is read instead
+    let newer = 8 + 12 + 8;
+    kv.flash_mut().bytes[newer] &= !1;
+    assert_eq!(get(&kv, 1), Some(b"one".to_vec()));
+    // Records after it still count
+    kv.set(2, b"two").unwrap();
+    assert_eq!(get(&kv, 2), Some(b"two".to_vec()));
+}
+
+#[test]
+fn interrupted_compaction_keeps_the_old_sector() {
+    let mut kv = store();
+    kv.set(1, b"kept").unwrap();
+    // Fill the first sector up to the compaction
+    let mut i = 0u8;
+    while kv.flash().erases == 1 {
+        kv.flash_mut().programs_left = Some(2);
+        match kv.set(2, &[i; 12]) {
+            Ok(()) => i += 1,
+            // The compaction ran out of power part way
+            Err(_) => break,
+        }
+    }
+    kv.flash_mut().programs_left = None;
+    assert_eq!(kv.flash().erases, 2);
+    // The new sector never got its magic, the old one stays active
+    assert_eq!(get(&kv, 1), Some(b"kept".to_vec()));
+    assert_eq!(get(&kv, 2), Some(vec![i - 1; 12]));
+
+    // The next write that doesn't fit compacts again and completes
+    kv.set(2, &[99; 12]).unwrap();
+    assert_eq!(kv.flash().erases, 3);
+    assert_eq!(generation(&kv, SECTOR_SIZE as usize), 1);
+    assert_eq!(get(&kv, 1), Some(b"kept".to_vec()));
+    assert_eq!(get(&kv, 2), Some(vec![99; 12]));
+}
+
+#[test]
+fn newest_generation_active() {
+    let mut kv = store();
+    kv.set(1, b"old").unwrap();
+    // A complete sector of a later generation, as a finished compaction
+    // leaves it, wins over the first
+    let mut other = store();
+    other.set(1, b"new").unwrap();
+    let sector = SECTOR_SIZE as usize;
+    let mut copy = other.flash().bytes[..sector].to_vec();
+    copy[..4].copy_from_slice(&1u32.to_le_bytes());
+    kv.flash_mut().bytes[sector..].copy_from_slice(&copy);
+    assert_eq!(get(&kv, 1), Some(b"new".to_vec()));
+
+    // Generations wrap around
+    kv.flash_mut().bytes[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+    kv.flash_mut().bytes[sector..sector + 4].copy_from_slice(&0u32.to_le_bytes());
+    assert_eq!(get(&kv, 1), Some(b"new".to_vec()));
+    kv.flash_mut().bytes[sector..sector + 4].copy_from_slice(&[0xFE, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(get(&kv, 1), Some(b"old".to_vec()));
+}
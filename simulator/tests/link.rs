@@ -0,0 +1,142 @@
+mod common;
+
+use common::err_msg;
+use protocol::{Request, Response, FRAME_HEADER_LEN, MAX_FRAME_LEN};
+use simulator::{
+    link::{self, Receiver, Serial},
+    Result,
+};
+use std::collections::VecDeque;
+
+/// USB full speed bulk packets
+const PACKET: usize = 64;
+
+/// A USB CDC port: bytes come and go a packet at a time, and writes find no
+/// room every other poll
+#[derive(Default)]
+struct Pipe {
+    incoming: VecDeque<u8>,
+    sent: Vec<u8>,
+    /// Whether the host reads what is written
+    host_reading: bool,
+    busy: bool,
+}
+
+impl Serial for Pipe {
+    fn poll(&mut self) -> bool {
+        !self.incoming.is_empty()
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(PACKET).min(self.incoming.len());
+        for (b, byte) in buf.iter_mut().zip(self.incoming.drain(..n)) {
+            *b = byte;
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        self.busy = !self.busy;
+        if !self.host_reading || self.busy {
+            return Ok(0);
+        }
+        let n = data.len().min(PACKET);
+        self.sent.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+}
+
+fn framed(msg: &[u8]) -> Vec<u8> {
+    let mut frame = protocol::frame_header(msg.len()).unwrap().to_vec();
+    frame.extend_from_slice(msg);
+    frame
+}
+
+#[test]
+fn request_over_several_packets() {
+    let psbt = [0x70; 512];
+    let request = postcard::to_stdvec(&Request::SignPsbt((
+        protocol::BtcNetwork::Testnet,
+        512,
+        0,
+        &psbt,
+    )))
+    .unwrap();
+    let mut pipe = Pipe::default();
+    pipe.incoming.extend(framed(&request));
+
+    let mut receiver = Receiver::new();
+    let mut reads = 1;
+    while receiver.receive(&mut pipe).unwrap().is_none() {
+        assert!(receiver.is_partial());
+        reads += 1;
+    }
+    assert_eq!(reads, (request.len() + FRAME_HEADER_LEN).div_ceil(PACKET));
+    // Gets the message once, then starts on the next frame
+    pipe.incoming.extend(framed(&[]));
+    let msg = receiver.receive(&mut pipe).unwrap().unwrap();
+    assert!(msg.is_empty());
+}
+
+#[test]
+fn frame_past_the_limit() {
+    let mut pipe = Pipe::default();
+    pipe.incoming.extend(&[0xff, 0xff, 0x00]);
+    let mut receiver = Receiver::new();
+    assert_eq!(
+        err_msg(receiver.receive(&mut pipe)),
+        protocol::ERR_FRAME_TOO_LONG
+    );
+    assert!(!receiver.is_partial());
+}
+
+#[test]
+fn abandoned_frame_dropped() {
+    let mut pipe = Pipe::default();
+    pipe.incoming.extend(&[0x00, 0x10, 0x01]);
+    let mut receiver = Receiver::new();
+    assert!(receiver.receive(&mut pipe).unwrap().is_none());
+    assert!(receiver.is_partial());
+    receiver.reset();
+    pipe.incoming.extend(framed(&[0x02]));
+    assert_eq!(receiver.receive(&mut pipe).unwrap().unwrap(), &[0x02]);
+}
+
+#[test]
+fn response_sent_whole() {
+    let addrs = [0x42; 100];
+    let mut response = postcard::to_stdvec(&Response::AddressList(&addrs)).unwrap();
+    response.push(protocol::version());
+    let mut pipe = Pipe {
+        host_reading: true,
+        ..Pipe::default()
+    };
+    link::send(&mut pipe, &response).unwrap();
+    assert_eq!(pipe.sent, framed(&response));
+    assert_eq!(
+        protocol::frame_message(&pipe.sent).unwrap().unwrap(),
+        &response[..]
+    );
+}
+
+#[test]
+fn response_to_a_host_not_reading() {
+    let mut pipe = Pipe::default();
+    assert_eq!(
+        err_msg(link::send(&mut pipe, &[0; 10])),
+        "host stopped reading"
+    );
+}
+
+#[test]
+fn frame_limits() {
+    let max = MAX_FRAME_LEN - FRAME_HEADER_LEN;
+    assert_eq!(protocol::frame_header(max), Some([0x07, 0xfe]));
+    assert_eq!(protocol::frame_header(max + 1), None);
+    // Incomplete until the last byte
+    let frame = framed(&[1, 2, 3]);
+    for end in 0..frame.len() {
+        assert_eq!(protocol::frame_message(&frame[..end]), Ok(None));
+    }
+    assert_eq!(protocol::frame_message(&frame), Ok(Some(&[1, 2, 3][..])));
+}
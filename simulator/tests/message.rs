@@ -0,0 +1,124 @@
+//! Signatures made on the device checked with the desktop's offline
+//! verifier, and the verifier checked against published vectors
+#[path = "../../desktop/src/message.rs"]
+mod verifier;
+
+use hex_literal::hex;
+use simulator::{
+    btc::BtcNetwork,
+    message::{self, BtcMessageFormat},
+};
+
+/// BIP39 seed of "abandon abandon ... about" with an empty passphrase
+const SEED: [u8; 64] = hex!(
+    "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1"
+    "9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+);
+const MSG: &[u8] = b"Hello World";
+
+/// Signs `MSG` with the key at account 0, receive `index`, returning the
+/// address and the base64 signature
+fn sign(network: BtcNetwork, format: BtcMessageFormat, index: u32) -> (String, String) {
+    let path = message::path(network, format, 0, 0, index).unwrap();
+    let addr = message::address(&SEED, network, format, &path).unwrap();
+    let sig = message::sign(&SEED, network, format, 0, 0, index, MSG).unwrap();
+    (addr.as_str().to_string(), verifier::base64_encode(&sig))
+}
+
+#[test]
+fn addresses_of_formats() {
+    // BIP84 and BIP44 test vectors of the "abandon ... about" seed
+    let (addr, _) = sign(BtcNetwork::Mainnet, BtcMessageFormat::Bip137, 0);
+    assert_eq!(addr, "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+    let (addr, _) = sign(BtcNetwork::Mainnet, BtcMessageFormat::Bip137P2pkh, 0);
+    assert_eq!(addr, "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA");
+}
+
+#[test]
+fn signatures_verify() {
+    let formats = [
+        BtcMessageFormat::Bip137,
+        BtcMessageFormat::Bip322Simple,
+        BtcMessageFormat::Bip137P2pkh,
+    ];
+    for network in [BtcNetwork::Mainnet, BtcNetwork::Testnet].iter() {
+        for format in formats.iter() {
+            let (addr, sig) = sign(*network, *format, 0);
+            assert_eq!(verifier::verify(&addr, &sig, MSG), Ok(()), "{:?}", format);
+            assert!(verifier::verify(&addr, &sig, b"Hello World!").is_err());
+        }
+    }
+}
+
+#[test]
+fn signature_by_another_address() {
+    let formats = [
+        BtcMessageFormat::Bip137,
+        BtcMessageFormat::Bip322Simple,
+        BtcMessageFormat::Bip137P2pkh,
+    ];
+    for format in formats.iter() {
+        let (_, sig) = sign(BtcNetwork::Mainnet, *format, 0);
+        let (addr, _) = sign(BtcNetwork::Mainnet, *format, 1);
+        assert_eq!(
+            verifier::verify(&addr, &sig, MSG),
+            Err("signature is by another address".to_string())
+        );
+    }
+}
+
+#[test]
+fn bip322_vectors() {
+    // From BIP322, by the key L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k
+    let addr = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    let vectors: [(&[u8], &str); 3] = [
+        (
+            b"",
+            "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=",
+        ),
+        (
+            b"Hello World",
+            "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=",
+        ),
+        (
+            b"Hello World",
+            "AkgwRQIhAOzyynlqt93lOKJr+wmmxIens//zPzl9tqIOua93wO6MAiBi5n5EyAcPScOjf1lAqIUIQtr3zKNeavYabHyR8eGhowEhAsfxIAMZZEKUPYWI4BruhAQjzFT8FSFSajuFwrDL1Yhy",
+        ),
+    ];
+    for (msg, sig) in vectors.iter() {
+        assert_eq!(verifier::verify(addr, sig, msg), Ok(()), "{}", sig);
+    }
+    assert!(verifier::verify(addr, vectors[0].1, b"Hello World").is_err());
+}
+
+#[test]
+fn bip137_legacy_vector() {
+    // From bitcoinjs-message, by the uncompressed key
+    // 5KYZdUEo39z3FPrtuX2QbbwGnNP5zTd7yyr2SC1j299sBCnWjss
+    let sig =
+        "G9L5yLFjti0QTHhPyFrZCT1V/MMnBtXKmoiKDZ78NDBjERki6ZTQZdSMCtkgoNmp17By9ItJr8o7ChX0XxY91nk=";
+    let msg = b"This is an example of a signed message.";
+    assert_eq!(
+        verifier::verify("1HZwkjkeaoZfTSaJxDw6aKkxp45agDiEzN", sig, msg),
+        Ok(())
+    );
+}
+
+#[test]
+fn base64_round_trips() {
+    let data = b"any carnal pleasure.";
+    for len in 0..data.len() {
+        let encoded = verifier::base64_encode(&data[..len]);
+        assert_eq!(encoded.len() % 4, 0);
+        assert_eq!(verifier::base64_decode(&encoded).unwrap(), &data[..len]);
+    }
+    assert_eq!(
+        verifier::base64_encode(b"any carnal pleas"),
+        "YW55IGNhcm5hbCBwbGVhcw=="
+    );
+    assert_eq!(
+        verifier::base64_encode(b"any carnal pleasu"),
+        "YW55IGNhcm5hbCBwbGVhc3U="
+    );
+    assert!(verifier::base64_decode("YW55*").is_err());
+}
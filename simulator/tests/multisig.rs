@@ -0,0 +1,103 @@
+mod common;
+
+use common::err_msg;
+use hex_literal::hex;
+use simulator::{
+    bip32::{ExtendedKey, HARDENED, TPUB},
+    btc::BtcNetwork,
+    multisig::Multisig,
+};
+
+/// BIP39 seed of "abandon abandon ... about" with an empty passphrase
+const SEED: [u8; 64] = hex!(
+    "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1"
+    "9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+);
+/// BIP48 P2WSH account path on the test networks
+const ORIGIN: [u32; 4] = [48 | HARDENED, 1 | HARDENED, HARDENED, 2 | HARDENED];
+
+/// `[fingerprint/48h/1h/0h/2h]tpub` of `seed`, followed by `suffix`
+fn key(seed: &[u8], suffix: &str) -> String {
+    let fingerprint = ExtendedKey::master(seed).unwrap().fingerprint();
+    let xpub = ExtendedKey::derive(seed, &ORIGIN).unwrap().xpub(TPUB);
+    let hex: String = fingerprint.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("[{}/48h/1h/0h/2h]{}{}", hex, xpub.as_str(), suffix)
+}
+
+/// A 2 of 2 of this device and another seed, each key followed by its suffix
+fn descriptor(ours: &str, theirs: &str) -> String {
+    format!(
+        "wsh(sortedmulti(2,{},{}))",
+        key(&SEED, ours),
+        key(&[0x42; 64], theirs)
+    )
+}
+
+fn address(descriptor: &str, change: u32) -> String {
+    let ms = Multisig::parse(descriptor).unwrap();
+    ms.address(BtcNetwork::Testnet, change, 3)
+        .unwrap()
+        .as_str()
+        .to_string()
+}
+
+#[test]
+fn branches_of_multipath_keys() {
+    let both = descriptor("/<0;1>/*", "/<0;1>/*");
+    assert_eq!(address(&both, 0), address(&descriptor("/0/*", "/0/*"), 0));
+    assert_eq!(address(&both, 1), address(&descriptor("/1/*", "/1/*"), 0));
+    assert_ne!(address(&both, 0), address(&both, 1));
+}
+
+#[test]
+fn branches_kept_per_key() {
+    let custom = descriptor("/<2;3>/*", "/<0;1>/*");
+    assert_eq!(address(&custom, 0), address(&descriptor("/2/*", "/0/*"), 0));
+    assert_eq!(address(&custom, 1), address(&descriptor("/3/*", "/1/*"), 0));
+
+    let ms = Multisig::parse(&custom).unwrap();
+    let ours = ms.ours(&SEED).unwrap();
+    assert_eq!(ours.is_change(2), Some(false));
+    assert_eq!(ours.is_change(3), Some(true));
+    assert_eq!(ours.is_change(0), None);
+}
+
+#[test]
+fn receive_only_has_no_change() {
+    let ms = Multisig::parse(&descriptor("/1/*", "/1/*")).unwrap();
+    assert_eq!(
+        err_msg(ms.address(BtcNetwork::Testnet, 1, 0)),
+        "multisig wallet has no change addresses"
+    );
+}
+
+#[test]
+fn unsupported_derivations_rejected() {
+    for suffix in [
+        "",
+        "/**",
+        "/*",
+        "/0/*'",
+        "/0h/*",
+        "/0/1/*",
+        "/<0;1;2>/*",
+        "/<0;0>/*",
+    ]
+    .iter()
+    {
+        assert_eq!(
+            err_msg(Multisig::parse(&descriptor(suffix, "/<0;1>/*"))),
+            "unsupported key derivation",
+            "{}",
+            suffix
+        );
+    }
+}
+
+#[test]
+fn keys_agree_on_change() {
+    assert_eq!(
+        err_msg(Multisig::parse(&descriptor("/<0;1>/*", "/0/*"))),
+        "keys disagree on change addresses"
+    );
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::{bip39_seed, err_msg};
+use hex_literal::hex;
+use simulator::{nostr, schnorr};
+
+// The test vectors from NIP-06
+
+#[test]
+fn nip06_first_vector() {
+    let seed = bip39_seed(
+        "leader monkey parrot ring guide accident before fence cannon height naive bean",
+    );
+    let (secret, pubkey) = nostr::keys(&seed, 0).unwrap();
+    assert_eq!(
+        secret,
+        hex!("7f7ff03d123792d6ac594bfa67bf6d0c0ab55b6b1fdb6249303fe861f1ccba9a")
+    );
+    assert_eq!(
+        pubkey,
+        hex!("17162c921dc4d2518f9a101db33695df1afb56ab82f5ff3e5da6eec3ca5cd917")
+    );
+    assert_eq!(
+        nostr::npub(&pubkey).unwrap().as_str(),
+        "npub1zutzeysacnf9rru6zqwmxd54mud0k44tst6l70ja5mhv8jjumytsd2x7nu"
+    );
+}
+
+#[test]
+fn nip06_second_vector() {
+    let seed = bip39_seed(
+        "what bleak badge arrange retreat wolf trade produce cricket blur garlic valid \
+         proud rude strong choose busy staff weather area salt hollow arm fade",
+    );
+    let (secret, pubkey) = nostr::keys(&seed, 0).unwrap();
+    assert_eq!(
+        secret,
+        hex!("c15d739894c81a2fcfd3a2df85a0d2c0dbc47a280d092799f144d73d7ae78add")
+    );
+    assert_eq!(
+        pubkey,
+        hex!("d41b22899549e1f3d335a31002cfd382174006e166d3e658e3a5eecdb6463573")
+    );
+    assert_eq!(
+        nostr::npub(&pubkey).unwrap().as_str(),
+        "npub16sdj9zv4f8sl85e45vgq9n7nsgt5qphpvmf7vk8r5hhvmdjxx4es8rq74h"
+    );
+}
+
+fn keys() -> ([u8; 32], schnorr::XOnly) {
+    nostr::keys(&bip39_seed("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about"), 0).unwrap()
+}
+
+fn hex_str(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn event(pubkey: &[u8], kind: u16, tags: &str, content: &str) -> Vec<u8> {
+    format!(
+        r#"[0,"{}",1700000000,{},{},"{}"]"#,
+        hex_str(pubkey),
+        kind,
+        tags,
+        content
+    )
+    .into_bytes()
+}
+
+fn shown(event: &[u8], ours: &schnorr::XOnly) -> Vec<(&'static str, String)> {
+    nostr::summary(event, ours)
+        .unwrap()
+        .iter()
+        .map(|f| (f.label, f.value.as_str().to_string()))
+        .collect()
+}
+
+#[test]
+fn note_shown() {
+    let (_, pubkey) = keys();
+    let event = event(&pubkey, 1, r#"[["t","nostr"]]"#, r#"gm \"world\"\n"#);
+    assert_eq!(
+        shown(&event, &pubkey),
+        [
+            ("Kind", "1 (Note)".to_string()),
+            ("Tags", "1".to_string()),
+            ("Content", "gm \"world\" ".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn event_by_another_key_refused() {
+    let (_, pubkey) = keys();
+    let mut other = pubkey;
+    other[31] ^= 1;
+    assert_eq!(
+        err_msg(nostr::summary(&event(&other, 1, "[]", "gm"), &pubkey)),
+        "event is by another key"
+    );
+}
+
+#[test]
+fn malformed_events_refused() {
+    let (_, pubkey) = keys();
+    let key = hex_str(&pubkey);
+    for event in &[
+        format!(r#"[1,"{}",1700000000,1,[],"gm"]"#, key),
+        format!(r#"[0,"{}",-1,1,[],"gm"]"#, key),
+        format!(r#"[0,"{}",1700000000,70000,[],"gm"]"#, key),
+        format!(r#"[0,"{}",1700000000,1,[],"gm",0]"#, key),
+        format!(r#"[0,"{}",1700000000,1,[]]"#, key),
+    ] {
+        assert!(
+            nostr::summary(event.as_bytes(), &pubkey).is_err(),
+            "{}",
+            event
+        );
+    }
+}
+
+#[test]
+fn signature_verifies() {
+    let (secret, pubkey) = keys();
+    let event = event(&pubkey, 1, "[]", "gm");
+    let id = nostr::event_id(&event);
+    let sig = nostr::sign(&secret, &id, &[7; 32]).unwrap();
+    schnorr::verify(&pubkey, &id, &sig).unwrap();
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use common::err_msg;
+use embedded_hal::{blocking::delay::DelayMs, digital::v2::InputPin};
+use simulator::{
+    display::{Field, FieldValue},
+    presence::{Buttons, Debouncer, Decision, UserPresence},
+    ui::Ui,
+    Framebuffer, ScriptedUser,
+};
+use std::{cell::RefCell, collections::VecDeque, convert::Infallible};
+
+fn ui(user: ScriptedUser) -> Ui<Framebuffer, ScriptedUser> {
+    Ui {
+        disp: Framebuffer::new(),
+        user,
+    }
+}
+
+#[test]
+fn debouncer_ignores_bounce() {
+    let mut d = Debouncer::new();
+    // Contact bounce: never 20 identical samples in a row
+    let bounce = [true, false, true, true, false, true, false, true, false];
+    assert!(bounce.iter().all(|raw| !d.update(*raw)));
+    // Then held down: one press, after 20 samples
+    let presses: Vec<bool> = (0..40).map(|_| d.update(true)).collect();
+    assert_eq!(presses.iter().filter(|p| **p).count(), 1);
+    assert_eq!(presses.iter().position(|p| *p), Some(19));
+}
+
+#[test]
+fn debouncer_reports_release_as_no_press() {
+    let mut d = Debouncer::new();
+    assert!((0..20).any(|_| d.update(true)));
+    // Releasing isn't a press, and a held button isn't pressed again
+    assert!((0..100).all(|_| !d.update(false)));
+    assert!((0..19).all(|_| !d.update(true)));
+    assert!(d.update(true));
+}
+
+/// A pin that plays back raw samples, low meaning pressed, then stays high
+struct ScriptedPin(RefCell<VecDeque<bool>>);
+
+impl InputPin for ScriptedPin {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        self.is_low().map(|low| !low)
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(self.0.borrow_mut().pop_front().unwrap_or(false))
+    }
+}
+
+struct NoDelay;
+
+impl DelayMs<u32> for NoDelay {
+    fn delay_ms(&mut self, _: u32) {}
+}
+
+/// Held down for `ms`
+fn held(ms: usize) -> ScriptedPin {
+    ScriptedPin(RefCell::new(VecDeque::from(vec![true; ms])))
+}
+
+#[test]
+fn buttons_approve_after_debouncing() {
+    let mut buttons = Buttons::new(held(50), held(0), NoDelay);
+    assert_eq!(buttons.confirm(1000), Decision::Approved);
+}
+
+#[test]
+fn buttons_reject_wins_when_both_pressed() {
+    let mut buttons = Buttons::new(held(50), held(50), NoDelay);
+    assert_eq!(buttons.confirm(1000), Decision::Rejected);
+}
+
+#[test]
+fn buttons_time_out_on_a_short_blip() {
+    let mut buttons = Buttons::new(held(5), held(0), NoDelay);
+    assert_eq!(buttons.confirm(1000), Decision::TimedOut);
+}
+
+#[test]
+fn confirm_times_out_after_the_timeout() {
+    let mut user = ScriptedUser::new().press(1000, Decision::Approved);
+    assert_eq!(user.confirm(999), Decision::TimedOut);
+    assert_eq!(user.elapsed_ms, 999);
+    // The press comes in time for the next question
+    assert_eq!(user.confirm(999), Decision::Approved);
+}
+
+#[test]
+fn ui_confirm_approved() {
+    let mut ui = ui(ScriptedUser::new().approve());
+    assert!(ui.confirm(30_000).is_ok());
+    assert!(ui.user.is_done());
+    let mut expected = Framebuffer::new();
+    simulator::display::status(&mut expected, "Approved").unwrap();
+    assert_eq!(ui.disp.to_text(), expected.to_text());
+}
+
+#[test]
+fn ui_confirm_rejected() {
+    let mut ui = ui(ScriptedUser::new().reject());
+    assert_eq!(err_msg(ui.confirm(30_000)), protocol::ERR_USER_REJECTED);
+    let mut expected = Framebuffer::new();
+    simulator::display::status(&mut expected, "Rejected").unwrap();
+    assert_eq!(ui.disp.to_text(), expected.to_text());
+}
+
+#[test]
+fn ui_confirm_timed_out() {
+    let mut ui = ui(ScriptedUser::new().idle(30_000));
+    assert_eq!(err_msg(ui.confirm(30_000)), protocol::ERR_USER_TIMEOUT);
+    assert_eq!(ui.user.elapsed_ms, 30_000);
+    let mut expected = Framebuffer::new();
+    simulator::display::status(&mut expected, "Timed out").unwrap();
+    assert_eq!(ui.disp.to_text(), expected.to_text());
+}
+
+fn pages() -> Vec<Field> {
+    (0..4)
+        .map(|_| Field {
+            label: "Long",
+            value: FieldValue::from("x".repeat(30).as_str()),
+        })
+        .collect()
+}
+
+#[test]
+fn ui_confirm_fields_needs_every_page() {
+    // Two fields of three lines each fit on a page
+    let mut ui = ui(ScriptedUser::new().approve().approve());
+    assert!(ui.confirm_fields("Sign?", &pages(), 1000).is_ok());
+    assert_eq!(ui.disp.shows, 4);
+    assert!(ui.user.is_done());
+}
+
+#[test]
+fn ui_confirm_fields_stops_at_a_rejected_page() {
+    let mut ui = ui(ScriptedUser::new().approve().reject().approve());
+    assert_eq!(
+        err_msg(ui.confirm_fields("Sign?", &pages(), 1000)),
+        protocol::ERR_USER_REJECTED
+    );
+    // The approval meant for a third page was never asked for
+    assert!(!ui.user.is_done());
+}
+
+#[test]
+fn ui_confirm_fields_times_out_on_a_later_page() {
+    let mut ui = ui(ScriptedUser::new().approve());
+    assert_eq!(
+        err_msg(ui.confirm_fields("Sign?", &pages(), 1000)),
+        protocol::ERR_USER_TIMEOUT
+    );
+}
@@ -0,0 +1,451 @@
+mod common;
+
+use common::err_msg;
+use hex_literal::hex;
+use k256::ecdsa::{signature::DigestVerifier, Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use simulator::{
+    bip32::{hash160, ExtendedKey, HARDENED},
+    btc::BtcNetwork,
+    psbt::{self, Psbt, Wallet},
+    schnorr,
+};
+
+/// BIP39 seed of "abandon abandon ... about" with an empty passphrase
+const SEED: [u8; 64] = hex!(
+    "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1"
+    "9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+);
+const PATH: [u32; 5] = [84 | HARDENED, 1 | HARDENED, HARDENED, 0, 0];
+const AMOUNT: u64 = 100_000;
+const SEND: u64 = 90_000;
+
+fn wallet() -> Wallet<'static> {
+    Wallet {
+        seed: &SEED,
+        network: BtcNetwork::Testnet,
+        multisig: None,
+        is_enabled: &|_| true,
+    }
+}
+
+fn var_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.len() < 0xfd {
+        out.push(bytes.len() as u8);
+    } else {
+        out.push(0xfd);
+        out.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+    }
+    out.extend_from_slice(bytes);
+}
+
+fn pair(out: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    var_bytes(out, key);
+    var_bytes(out, value);
+}
+
+/// A serialized output paying `amount` to `script`, as in a witness UTXO
+fn output(amount: u64, script: &[u8]) -> Vec<u8> {
+    let mut out = amount.to_le_bytes().to_vec();
+    var_bytes(&mut out, script);
+    out
+}
+
+/// P2WPKH of our key
+fn our_script() -> Vec<u8> {
+    let key = ExtendedKey::derive(&SEED, &PATH).unwrap();
+    let mut script = vec![0x00, 0x14];
+    script.extend_from_slice(&hash160(&key.public_key()));
+    script
+}
+
+/// The P2PKH script of `pubkey`, P2WPKH's BIP143 scriptCode
+fn p2pkh_script(pubkey: &[u8; 33]) -> Vec<u8> {
+    let mut script = vec![0x76, 0xa9, 0x14];
+    script.extend_from_slice(&hash160(pubkey));
+    script.extend_from_slice(&[0x88, 0xac]);
+    script
+}
+
+/// A transaction paying `AMOUNT` to our key in its output 1, with witness
+/// data if `segwit`
+fn prev_tx(segwit: bool) -> Vec<u8> {
+    let mut tx = 2u32.to_le_bytes().to_vec();
+    if segwit {
+        tx.extend_from_slice(&[0x00, 0x01]);
+    }
+    tx.push(1);
+    tx.extend_from_slice(&[0x11; 36]);
+    var_bytes(&mut tx, &[]);
+    tx.extend_from_slice(&u32::MAX.to_le_bytes());
+    tx.push(2);
+    tx.extend_from_slice(&output(5_000, &[0x51, 0x20, 0x22]));
+    tx.extend_from_slice(&output(AMOUNT, &our_script()));
+    if segwit {
+        tx.push(1);
+        var_bytes(&mut tx, &[0x33; 64]);
+    }
+    tx.extend_from_slice(&0u32.to_le_bytes());
+    tx
+}
+
+fn txid(tx: &[u8]) -> [u8; 32] {
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&Sha256::digest(&Sha256::digest(tx)));
+    id
+}
+
+/// A PSBT spending output 1 of the transaction `prev_txid`, which paid
+/// `utxo_amount` to us, with `non_witness_utxo` if given
+fn build(prev_txid: [u8; 32], utxo_amount: u64, non_witness_utxo: Option<&[u8]>) -> Vec<u8> {
+    let mut unsigned = 2u32.to_le_bytes().to_vec();
+    unsigned.push(1);
+    unsigned.extend_from_slice(&prev_txid);
+    unsigned.extend_from_slice(&1u32.to_le_bytes());
+    var_bytes(&mut unsigned, &[]);
+    unsigned.extend_from_slice(&u32::MAX.to_le_bytes());
+    unsigned.push(1);
+    // To someone else's P2WPKH
+    let mut theirs = vec![0x00, 0x14];
+    theirs.extend_from_slice(&[0x55; 20]);
+    unsigned.extend_from_slice(&output(SEND, &theirs));
+    unsigned.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut raw = b"psbt\xff".to_vec();
+    pair(&mut raw, &[0x00], &unsigned);
+    raw.push(0);
+
+    let key = ExtendedKey::derive(&SEED, &PATH).unwrap();
+    let mut derivation_key = vec![0x06];
+    derivation_key.extend_from_slice(&key.public_key());
+    let mut origin = ExtendedKey::master(&SEED).unwrap().fingerprint().to_vec();
+    for i in PATH.iter() {
+        origin.extend_from_slice(&i.to_le_bytes());
+    }
+    if let Some(tx) = non_witness_utxo {
+        pair(&mut raw, &[0x00], tx);
+    }
+    pair(&mut raw, &[0x01], &output(utxo_amount, &our_script()));
+    pair(&mut raw, &derivation_key, &origin);
+    raw.push(0);
+    // The output map
+    raw.push(0);
+    raw
+}
+
+fn labels(raw: &[u8]) -> Vec<&'static str> {
+    let psbt = Psbt::parse(raw).unwrap();
+    let summary = psbt.summary(&wallet()).unwrap();
+    summary.iter().map(|f| f.label).collect()
+}
+
+#[test]
+fn unverified_amounts_warned_about() {
+    let prev = prev_tx(false);
+    let raw = build(txid(&prev), AMOUNT, None);
+    assert_eq!(labels(&raw), ["Warning", "Send", "To", "Fee"]);
+}
+
+#[test]
+fn previous_transaction_checked() {
+    let prev = prev_tx(false);
+    let raw = build(txid(&prev), AMOUNT, Some(&prev));
+    assert_eq!(labels(&raw), ["Send", "To", "Fee"]);
+}
+
+#[test]
+fn txid_leaves_out_witnesses() {
+    let with_witness = prev_tx(true);
+    let raw = build(txid(&prev_tx(false)), AMOUNT, Some(&with_witness));
+    assert_eq!(labels(&raw), ["Send", "To", "Fee"]);
+}
+
+#[test]
+fn previous_transaction_of_another_input() {
+    let prev = prev_tx(false);
+    let raw = build([0x44; 32], AMOUNT, Some(&prev));
+    let psbt = Psbt::parse(&raw).unwrap();
+    assert_eq!(
+        err_msg(psbt.summary(&wallet())),
+        "previous transaction does not match input"
+    );
+}
+
+#[test]
+fn witness_utxo_amount_lied_about() {
+    let prev = prev_tx(false);
+    // Claims less than was paid, to hide a fee
+    let raw = build(txid(&prev), AMOUNT / 2, Some(&prev));
+    let psbt = Psbt::parse(&raw).unwrap();
+    assert_eq!(
+        err_msg(psbt.summary(&wallet())),
+        "witness UTXO does not match previous transaction"
+    );
+}
+
+#[test]
+fn signed_once() {
+    let prev = prev_tx(false);
+    let raw = build(txid(&prev), AMOUNT, Some(&prev));
+    let mut buf = psbt::Buffer::new();
+    buf.extend_from_slice(&raw).unwrap();
+
+    let sigs = Psbt::parse(&raw)
+        .unwrap()
+        .sign(&wallet(), &[7; 32])
+        .unwrap();
+    assert_eq!(sigs.len(), 1);
+    psbt::add_signatures(&mut buf, &sigs).unwrap();
+
+    let signed = Psbt::parse(&buf).unwrap();
+    assert_eq!(
+        err_msg(signed.sign(&wallet(), &[7; 32])),
+        "PSBT already signed"
+    );
+}
+
+#[test]
+fn signature_matches_sighash() {
+    let prev = prev_tx(false);
+    let raw = build(txid(&prev), AMOUNT, Some(&prev));
+    let mut buf = psbt::Buffer::new();
+    buf.extend_from_slice(&raw).unwrap();
+    let sigs = Psbt::parse(&raw)
+        .unwrap()
+        .sign(&wallet(), &[7; 32])
+        .unwrap();
+    psbt::add_signatures(&mut buf, &sigs).unwrap();
+
+    // The partial signature entry: key type and pubkey, then the DER
+    // signature followed by the sighash type
+    let pubkey = ExtendedKey::derive(&SEED, &PATH).unwrap().public_key();
+    let mut key = vec![0x22, 0x02];
+    key.extend_from_slice(&pubkey);
+    let at = buf.windows(key.len()).position(|w| w == &key[..]).unwrap() + key.len();
+    let sig = &buf[at + 1..at + 1 + buf[at] as usize];
+    assert_eq!(sig.last(), Some(&0x01));
+    let sig = Signature::from_asn1(&sig[..sig.len() - 1]).unwrap();
+
+    let sighash = Psbt::parse(&raw)
+        .unwrap()
+        .input_sighash(0, &p2pkh_script(&pubkey))
+        .unwrap();
+    VerifyingKey::from_sec1_bytes(&pubkey)
+        .unwrap()
+        .verify_digest(sighash, &sig)
+        .unwrap();
+}
+
+#[test]
+fn bip143_native_p2wpkh() {
+    // The native P2WPKH example from BIP143, spending its second input
+    let unsigned = hex!(
+        "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f"
+        "0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57"
+        "b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85"
+        "c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2"
+        "f0167faa815988ac11000000"
+    );
+    let pubkey = hex!("025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee6357");
+    let mut raw = b"psbt\xff".to_vec();
+    pair(&mut raw, &[0x00], &unsigned);
+    raw.push(0);
+    raw.push(0);
+    pair(
+        &mut raw,
+        &[0x01],
+        &output(
+            600_000_000,
+            &hex!("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1"),
+        ),
+    );
+    raw.extend_from_slice(&[0, 0, 0]);
+
+    let sighash = Psbt::parse(&raw)
+        .unwrap()
+        .input_sighash(
+            1,
+            &hex!("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac"),
+        )
+        .unwrap();
+    assert_eq!(
+        sighash.clone().finalize()[..],
+        hex!("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670")
+    );
+    let sig = Signature::from_asn1(&hex!(
+        "304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a"
+        "0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee"
+    ))
+    .unwrap();
+    VerifyingKey::from_sec1_bytes(&pubkey)
+        .unwrap()
+        .verify_digest(sighash, &sig)
+        .unwrap();
+}
+
+#[test]
+fn nonstandard_output_blind() {
+    let prev = prev_tx(false);
+    let mut raw = build(txid(&prev), AMOUNT, Some(&prev));
+    let is_blind = |raw: &[u8]| {
+        let psbt = Psbt::parse(raw).unwrap();
+        psbt.is_blind(BtcNetwork::Testnet).unwrap()
+    };
+    assert!(!is_blind(&raw));
+
+    // Turn the payment into OP_RETURN data of the same length
+    let mut theirs = vec![0x00, 0x14];
+    theirs.extend_from_slice(&[0x55; 20]);
+    let at = raw.windows(22).position(|w| w == &theirs[..]).unwrap();
+    raw[at] = 0x6a;
+    assert!(is_blind(&raw));
+    assert_eq!(labels(&raw), ["Send", "To", "Fee"]);
+}
+
+#[test]
+fn taproot_signature_matches_sighash() {
+    let path = [86 | HARDENED, 1 | HARDENED, HARDENED, 0, 0];
+    let key = ExtendedKey::derive(&SEED, &path).unwrap();
+    let mut internal = [0u8; 32];
+    internal.copy_from_slice(&key.public_key()[1..]);
+    let output_key = schnorr::taproot_output_key(&internal).unwrap();
+    let mut script = vec![0x51, 0x20];
+    script.extend_from_slice(&output_key);
+
+    let mut unsigned = 2u32.to_le_bytes().to_vec();
+    unsigned.push(1);
+    unsigned.extend_from_slice(&[0x11; 36]);
+    var_bytes(&mut unsigned, &[]);
+    unsigned.extend_from_slice(&u32::MAX.to_le_bytes());
+    unsigned.push(1);
+    // To someone else's P2WPKH
+    let mut theirs = vec![0x00, 0x14];
+    theirs.extend_from_slice(&[0x55; 20]);
+    unsigned.extend_from_slice(&output(SEND, &theirs));
+    unsigned.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut raw = b"psbt\xff".to_vec();
+    pair(&mut raw, &[0x00], &unsigned);
+    raw.push(0);
+    pair(&mut raw, &[0x01], &output(AMOUNT, &script));
+    let mut derivation_key = vec![0x16];
+    derivation_key.extend_from_slice(&internal);
+    // No leaf hashes, then the origin
+    let mut origin = vec![0x00];
+    origin.extend_from_slice(&ExtendedKey::master(&SEED).unwrap().fingerprint());
+    for i in path.iter() {
+        origin.extend_from_slice(&i.to_le_bytes());
+    }
+    pair(&mut raw, &derivation_key, &origin);
+    raw.extend_from_slice(&[0, 0]);
+
+    let psbt = Psbt::parse(&raw).unwrap();
+    let mut buf = psbt::Buffer::new();
+    buf.extend_from_slice(&raw).unwrap();
+    psbt::add_signatures(&mut buf, &psbt.sign(&wallet(), &[7; 32]).unwrap()).unwrap();
+
+    // The taproot key signature entry: key type, then the 64 byte signature
+    let at = buf
+        .windows(3)
+        .position(|w| w == [0x01, 0x13, 0x40])
+        .unwrap()
+        + 3;
+    let mut sig = [0u8; 64];
+    sig.copy_from_slice(&buf[at..at + 64]);
+    let sighash = psbt.taproot_sighash(0).unwrap();
+    schnorr::verify(&output_key, &sighash, &sig).unwrap();
+}
+
+#[test]
+fn bip341_key_path_sighash() {
+    // The keyPathSpending example from BIP341's wallet-test-vectors.json.
+    // Input 4 is the one spent with SIGHASH_DEFAULT.
+    let unsigned = hex!(
+        "02000000097de20cbff686da83a54981d2b9bab3586f4ca7e48f57f5b55963115f3b334e9c"
+        "010000000000000000d7b7cab57b1393ace2d064f4d4a2cb8af6def61273e127517d44759b"
+        "6dafdd990000000000fffffffff8e1f583384333689228c5d28eac13366be082dc57441760"
+        "d957275419a418420000000000fffffffff0689180aa63b30cb162a73c6d2a38b7eeda2a83"
+        "ece74310fda0843ad604853b0100000000feffffffaa5202bdf6d8ccd2ee0f0202afbbb746"
+        "1d9264a25e5bfd3c5a52ee1239e0ba6c0000000000feffffff956149bdc66faa968eb2be2d"
+        "2faa29718acbfe3941215893a2a3446d32acd050000000000000000000e664b9773b88c09c"
+        "32cb70a2a3e4da0ced63b7ba3b22f848531bbb1d5d5f4c94010000000000000000e9aa6b8e"
+        "6c9de67619e6a3924ae25696bb7b694bb677a632a74ef7eadfd4eabf0000000000ffffffff"
+        "a778eb6a263dc090464cd125c466b5a99667720b1c110468831d058aa1b82af10100000000"
+        "ffffffff0200ca9a3b000000001976a91406afd46bcdfd22ef94ac122aa11f241244a37ecc"
+        "88ac807840cb0000000020ac9a87f5594be208f8532db38cff670c450ed2fea8fcdefcc9a6"
+        "63f78bab962b0065cd1d"
+    );
+    let spent: [(u64, &[u8]); 9] = [
+        (
+            420_000_000,
+            &hex!("512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343"),
+        ),
+        (
+            462_000_000,
+            &hex!("5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3"),
+        ),
+        (
+            294_000_000,
+            &hex!("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac"),
+        ),
+        (
+            504_000_000,
+            &hex!("5120e4d810fd50586274face62b8a807eb9719cef49c04177cc6b76a9a4251d5450e"),
+        ),
+        (
+            630_000_000,
+            &hex!("512091b64d5324723a985170e4dc5a0f84c041804f2cd12660fa5dec09fc21783605"),
+        ),
+        (
+            378_000_000,
+            &hex!("00147dd65592d0ab2fe0d0257d571abf032cd9db93dc"),
+        ),
+        (
+            672_000_000,
+            &hex!("512075169f4001aa68f15bbed28b218df1d0a62cbbcf1188c6665110c293c907b831"),
+        ),
+        (
+            546_000_000,
+            &hex!("5120712447206d7a5238acc7ff53fbe94a3b64539ad291c7cdbc490b7577e4b17df5"),
+        ),
+        (
+            588_000_000,
+            &hex!("512077e30a5522dd9f894c3f8b8bd4c4b2cf82ca7da8a3ea6a239655c39c050ab220"),
+        ),
+    ];
+    let mut raw = b"psbt\xff".to_vec();
+    pair(&mut raw, &[0x00], &unsigned);
+    raw.push(0);
+    for (amount, script) in spent.iter() {
+        pair(&mut raw, &[0x01], &output(*amount, script));
+        raw.push(0);
+    }
+    raw.extend_from_slice(&[0, 0]);
+
+    let sighash = Psbt::parse(&raw).unwrap().taproot_sighash(4).unwrap();
+    assert_eq!(
+        sighash,
+        hex!("4f900a0bae3f1446fd48490c2958b5a023228f01661cda3496a11da502a7f7ef")
+    );
+    let output_key = hex!("91b64d5324723a985170e4dc5a0f84c041804f2cd12660fa5dec09fc21783605");
+    let sig = hex!(
+        "b4010dd48a617db09926f729e79c33ae0b4e94b79f04a1ae93ede6315eb3669d"
+        "e185a17d2b0ac9ee09fd4c64b678a0b61a0a86fa888a273c8511be83bfd6810f"
+    );
+    schnorr::verify(&output_key, &sighash, &sig).unwrap();
+}
+
+#[test]
+fn disabled_account_not_ours() {
+    let prev = prev_tx(false);
+    let raw = build(txid(&prev), AMOUNT, Some(&prev));
+    let wallet = Wallet {
+        is_enabled: &|account| account != 0,
+        ..wallet()
+    };
+    let psbt = Psbt::parse(&raw).unwrap();
+    assert_eq!(
+        err_msg(psbt.summary(&wallet)),
+        "input not owned by this wallet"
+    );
+}
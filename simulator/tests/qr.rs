@@ -0,0 +1,45 @@
+use simulator::{display, qr::QrCode, Framebuffer};
+
+/// Pixels per screen pixel, so the decoder is not working at one pixel per
+/// module for the larger codes
+const ZOOM: usize = 4;
+
+/// Renders `payload` the way `ShowQr` does and reads it back off the screen
+/// like a phone would
+fn scan(payload: &str) -> String {
+    let code = QrCode::encode(payload.as_bytes()).unwrap();
+    let mut disp = Framebuffer::new();
+    display::qr(&mut disp, &code, payload).unwrap();
+
+    // The code takes the left square of the screen, the caption the rest
+    let side = display::HEIGHT as usize;
+    let mut img = rqrr::PreparedImage::prepare_from_greyscale(side * ZOOM, side * ZOOM, |x, y| {
+        if disp.pixel(x / ZOOM, y / ZOOM) {
+            255
+        } else {
+            0
+        }
+    });
+    let grids = img.detect_grids();
+    assert_eq!(grids.len(), 1, "one QR code on the screen");
+    grids[0].decode().unwrap().1
+}
+
+#[test]
+fn address_scans() {
+    let payload = "0x9858EfFD232B4033E47d90003D41EC34EcaEda94";
+    assert_eq!(scan(payload), payload);
+}
+
+#[test]
+fn uri_scans() {
+    let payload = "ethereum:0x9858EfFD232B4033E47d90003D41EC34EcaEda94";
+    assert_eq!(scan(payload), payload);
+}
+
+#[test]
+fn xpub_scans() {
+    // The account xpub of m/44'/60'/0' from the "abandon ... about" seed
+    let payload = "xpub6BosfCnifzxcFwrSzQiqu2DBVTshkCXacvNsWGYJVVhhawA7d4R5WSWGFNbi8Aw6ZRc1brxMyWMzG3DSSSSoekkudhUd9yLb6qx39T9nMdj";
+    assert_eq!(scan(payload), payload);
+}
@@ -0,0 +1,97 @@
+use hex_literal::hex;
+use simulator::schnorr;
+
+/// (secret key, public key, aux_rand, message, signature)
+type Vector = ([u8; 32], [u8; 32], [u8; 32], [u8; 32], [u8; 64]);
+
+/// From BIP340's test-vectors.csv
+const SIGNING: [Vector; 4] = [
+    (
+        hex!("0000000000000000000000000000000000000000000000000000000000000003"),
+        hex!("F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+        hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+        hex!("0000000000000000000000000000000000000000000000000000000000000000"),
+        hex!(
+            "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA8215"
+            "25F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0"
+        ),
+    ),
+    (
+        hex!("B7E151628AED2A6ABF7158809CF4F3C762E7160F38B4DA56A784D9045190CFEF"),
+        hex!("DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659"),
+        hex!("0000000000000000000000000000000000000000000000000000000000000001"),
+        hex!("243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89"),
+        hex!(
+            "6896BD60EEAE296DB48A229FF71DFE071BDE413E6D43F917DC8DCF8C78DE3341"
+            "8906D11AC976ABCCB20B091292BFF4EA897EFCB639EA871CFA95F6DE339E4B0A"
+        ),
+    ),
+    (
+        hex!("C90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B14E5C9"),
+        hex!("DD308AFEC5777E13121FA72B9CC1B7CC0139715309B086C960E18FD969774EB8"),
+        hex!("C87AA53824B4D7AE2EB035A2B5BBBCCC080E76CDC6D1692C4B0B62D798E6D906"),
+        hex!("7E2D58D8B3BCDF1ABADEC7829054F90DDA9805AAB56C77333024B9D0A508B75C"),
+        hex!(
+            "5831AAEED7B44BB74E5EAB94BA9D4294C49BCF2A60728D8B4C200F50DD313C1B"
+            "AB745879A5AD954A72C45A91C3A51D3C7ADEA98D82F8481E0E1E03674A6F3FB7"
+        ),
+    ),
+    (
+        hex!("0B432B2677937381AEF05BB02A66ECD012773062CF3FA2549E44F58ED2401710"),
+        hex!("25D1DFF95105F5253C4022F628A996AD3A0D95FBF21D468A1B33F8C160D8F517"),
+        hex!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"),
+        hex!("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"),
+        hex!(
+            "7EB0509757E246F19449885651611CB965ECC1A187DD51B64FDA1EDC9637D5EC"
+            "97582B9CB13DB3933705B32BA982AF5AF25FD78881EBB32771FC5922EFC66EA3"
+        ),
+    ),
+];
+
+#[test]
+fn bip340_signing() {
+    for (secret, pubkey, aux, msg, sig) in SIGNING.iter() {
+        assert_eq!(schnorr::public_key(secret).unwrap(), *pubkey);
+        assert_eq!(schnorr::sign(secret, msg, aux).unwrap()[..], sig[..]);
+        assert!(schnorr::verify(pubkey, msg, sig).is_ok());
+    }
+}
+
+#[test]
+fn bip340_verification_failures() {
+    let msg = hex!("243F6A8885A308D313198A2E03707344A4093822299F31D0082EFA98EC4E6C89");
+    // Public key not on the curve
+    let off_curve = hex!("EEFDEA4CDB677750A420FEE807EACF21EB9898AE79B9768766E4FAA04A2D4A34");
+    let sig = hex!(
+        "6CFF5C3BA86C69EA4B7376F31A9BCB4F74C1976089B2D9963DA2E5543E177769"
+        "69E89B4C5564D00349106B8497785DD7D1D713A8AE82B32FA79D5F7FC407D39B"
+    );
+    assert!(schnorr::verify(&off_curve, &msg, &sig).is_err());
+    // R has an odd y coordinate
+    let pubkey = hex!("DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659");
+    let sig = hex!(
+        "FFF97BD5755EEEA420453A14355235D382F6472F8568A18B2F057A1460297556"
+        "3CC27944640AC607CD107AE10923D9EF7A73C643E166BE5EBEAFA34B1AC553E2"
+    );
+    assert!(schnorr::verify(&pubkey, &msg, &sig).is_err());
+}
+
+#[test]
+fn aux_changes_the_signature() {
+    let (secret, pubkey, _, msg, _) = SIGNING[1];
+    let a = schnorr::sign(&secret, &msg, &[1; 32]).unwrap();
+    let b = schnorr::sign(&secret, &msg, &[2; 32]).unwrap();
+    assert_ne!(a[..], b[..]);
+    assert!(schnorr::verify(&pubkey, &msg, &a).is_ok());
+    assert!(schnorr::verify(&pubkey, &msg, &b).is_ok());
+}
+
+#[test]
+fn bip341_output_key_without_scripts() {
+    // The first scriptPubKey example of BIP341's wallet-test-vectors.json
+    let internal = hex!("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+    assert_eq!(
+        schnorr::taproot_output_key(&internal).unwrap(),
+        hex!("53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343")
+    );
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use common::err_msg;
+use protocol::Settings;
+use simulator::{
+    kvstore::{self, Store},
+    layout::{KV_SECTORS, STORAGE_SECTOR},
+    settings::{self, DEFAULT},
+    RamFlash,
+};
+
+fn store() -> Store<RamFlash> {
+    Store::new(RamFlash::new(STORAGE_SECTOR.offset as usize), KV_SECTORS)
+}
+
+fn loaded(kv: &Store<RamFlash>) -> Settings<'static> {
+    let mut buf = kvstore::Value::new();
+    let s = settings::load(kv, &mut buf);
+    // Leaked to outlive `buf`, fine for a test
+    Settings {
+        label: Box::leak(s.label.to_string().into_boxed_str()),
+        ..s
+    }
+}
+
+#[test]
+fn defaults() {
+    let fresh = loaded(&store());
+    assert_eq!(fresh, DEFAULT);
+    assert!(!fresh.blind_signing);
+    assert!(settings::validate(&DEFAULT).is_ok());
+}
+
+#[test]
+fn saved_and_loaded() {
+    let mut kv = store();
+    let new = Settings {
+        confirm_timeout_s: 60,
+        confirm_exports: true,
+        auto_lock_min: 5,
+        brightness: 2,
+        blind_signing: true,
+        label: "Cold storage",
+    };
+    settings::save(&mut kv, &new).unwrap();
+    assert_eq!(loaded(&kv), new);
+}
+
+#[test]
+fn unreadable_falls_back_to_defaults() {
+    let mut kv = store();
+    kv.set(kvstore::keys::SETTINGS, &[0xFF; 3]).unwrap();
+    assert_eq!(loaded(&kv), DEFAULT);
+}
+
+#[test]
+fn out_of_range_refused() {
+    let mut kv = store();
+    let check = |s: Settings| err_msg(settings::validate(&s));
+    assert_eq!(
+        check(Settings {
+            confirm_timeout_s: 4,
+            ..DEFAULT
+        }),
+        "confirm timeout must be 5 to 600 seconds"
+    );
+    assert_eq!(
+        check(Settings {
+            confirm_timeout_s: 601,
+            ..DEFAULT
+        }),
+        "confirm timeout must be 5 to 600 seconds"
+    );
+    assert_eq!(
+        check(Settings {
+            auto_lock_min: 24 * 60 + 1,
+            ..DEFAULT
+        }),
+        "auto-lock must be at most a day"
+    );
+    assert_eq!(
+        check(Settings {
+            brightness: 0,
+            ..DEFAULT
+        }),
+        "brightness must be 1 to 5"
+    );
+    assert_eq!(
+        check(Settings {
+            label: "seventeen letters",
+            ..DEFAULT
+        }),
+        "device label too long"
+    );
+    assert_eq!(
+        check(Settings {
+            label: "tab\there",
+            ..DEFAULT
+        }),
+        "device label must be printable ASCII"
+    );
+    // Nothing refused is stored
+    assert_eq!(
+        err_msg(settings::save(
+            &mut kv,
+            &Settings {
+                confirm_timeout_s: 601,
+                ..DEFAULT
+            }
+        )),
+        "confirm timeout must be 5 to 600 seconds"
+    );
+    assert_eq!(loaded(&kv), DEFAULT);
+}
+
+#[test]
+fn limits_accepted() {
+    for s in [
+        Settings {
+            confirm_timeout_s: 5,
+            auto_lock_min: 24 * 60,
+            brightness: 1,
+            label: "sixteen letters!",
+            ..DEFAULT
+        },
+        Settings {
+            confirm_timeout_s: 600,
+            ..DEFAULT
+        },
+    ] {
+        assert!(settings::validate(&s).is_ok());
+    }
+}
+
+#[test]
+fn summary_shown() {
+    let fields = settings::summary(&Settings {
+        auto_lock_min: 15,
+        blind_signing: true,
+        label: "Cold storage",
+        ..DEFAULT
+    })
+    .unwrap();
+    let shown: Vec<_> = fields
+        .iter()
+        .map(|f| (f.label, f.value.as_str().to_string()))
+        .collect();
+    assert_eq!(
+        shown,
+        [
+            ("Confirm within", "30 s".to_string()),
+            ("Confirm exports", "no".to_string()),
+            ("Auto-lock", "15 min".to_string()),
+            ("Brightness", "5 of 5".to_string()),
+            ("Blind signing", "yes".to_string()),
+            ("Label", "Cold storage".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::err_msg;
+use hex_literal::hex;
+use simulator::solana;
+
+/// BIP39 seed of "abandon abandon ... about" with an empty passphrase
+const SEED: [u8; 64] = hex!(
+    "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1"
+    "9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4"
+);
+const OTHER: [u8; 32] = [0x11; 32];
+const PROGRAM: [u8; 32] = [0x22; 32];
+
+fn ours() -> [u8; 32] {
+    solana::key(&SEED, 0).unwrap().public_key().unwrap()
+}
+
+/// A legacy message signed by us alone, calling the System program with
+/// `data` on the accounts `[ours, OTHER]`
+fn system_message(data: &[u8]) -> Vec<u8> {
+    let mut msg = vec![1, 0, 1, 4];
+    msg.extend_from_slice(&ours());
+    msg.extend_from_slice(&OTHER);
+    msg.extend_from_slice(&PROGRAM);
+    msg.extend_from_slice(&[0; 32]);
+    msg.extend_from_slice(&[0x33; 32]);
+    msg.extend_from_slice(&[1, 3, 2, 0, 1, data.len() as u8]);
+    msg.extend_from_slice(data);
+    msg
+}
+
+fn instruction(number: u32, args: &[u8]) -> Vec<u8> {
+    let mut data = number.to_le_bytes().to_vec();
+    data.extend_from_slice(args);
+    data
+}
+
+fn shown(msg: &[u8]) -> Vec<(&'static str, String)> {
+    let (fields, blind) = solana::summary(msg, &ours()).unwrap();
+    assert!(!blind);
+    fields
+        .iter()
+        .map(|f| (f.label, f.value.as_str().to_string()))
+        .collect()
+}
+
+fn addr(key: &[u8]) -> String {
+    solana::address(key).as_str().to_string()
+}
+
+#[test]
+fn address_at_path() {
+    assert_eq!(
+        addr(&ours()),
+        "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk"
+    );
+}
+
+#[test]
+fn transfer_shown() {
+    let msg = system_message(&instruction(2, &1_500_000_000u64.to_le_bytes()));
+    assert_eq!(
+        shown(&msg),
+        [("Send", "1.5 SOL".to_string()), ("To", addr(&OTHER))]
+    );
+}
+
+#[test]
+fn assign_shown() {
+    let msg = system_message(&instruction(1, &PROGRAM));
+    assert_eq!(
+        shown(&msg),
+        [("Assign", addr(&ours())), ("To program", addr(&PROGRAM))]
+    );
+}
+
+#[test]
+fn nonce_authority_change_shown() {
+    let msg = system_message(&instruction(7, &OTHER));
+    assert_eq!(
+        shown(&msg),
+        [
+            ("Nonce account", addr(&ours())),
+            ("New authority", addr(&OTHER))
+        ]
+    );
+}
+
+#[test]
+fn create_account_shown() {
+    let mut args = 1_000_000_000u64.to_le_bytes().to_vec();
+    args.extend_from_slice(&165u64.to_le_bytes());
+    args.extend_from_slice(&PROGRAM);
+    let msg = system_message(&instruction(0, &args));
+    assert_eq!(
+        shown(&msg),
+        [
+            ("Create account", addr(&OTHER)),
+            ("Fund", "1 SOL".to_string()),
+            ("Owner", addr(&PROGRAM))
+        ]
+    );
+}
+
+#[test]
+fn other_programs_blind() {
+    let mut msg = system_message(&[1, 2, 3]);
+    // Calls PROGRAM instead of the System program
+    msg[4 + 4 * 32 + 32 + 1] = 2;
+    let (fields, blind) = solana::summary(&msg, &ours()).unwrap();
+    assert!(blind);
+    assert_eq!(fields[0].label, "Program");
+    assert_eq!(fields[0].value.as_str(), addr(&PROGRAM));
+}
+
+#[test]
+fn other_system_instructions_refused() {
+    // AssignWithSeed, InitializeNonceAccount and one past the last
+    for number in [10, 6, 13].iter() {
+        let msg = system_message(&instruction(*number, &[0; 80]));
+        assert_eq!(
+            err_msg(solana::summary(&msg, &ours())),
+            "unsupported System instruction"
+        );
+    }
+}
+
+#[test]
+fn system_arguments_checked() {
+    let mut data = instruction(2, &1u64.to_le_bytes());
+    data.push(0);
+    assert_eq!(
+        err_msg(solana::summary(&system_message(&data), &ours())),
+        "malformed Solana transaction"
+    );
+    let data = instruction(1, &PROGRAM[..31]);
+    assert_eq!(
+        err_msg(solana::summary(&system_message(&data), &ours())),
+        "malformed Solana transaction"
+    );
+}
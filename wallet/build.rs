@@ -0,0 +1,47 @@
+//! Writes memory.x from src/layout.rs for cortex-m-rt's link.x. The code
+//! starts after the storage sectors, so the linker fails the build rather
+//! than place any of it in them.
+use std::{env, fs, path::PathBuf};
+
+#[allow(dead_code)]
+#[path = "src/layout.rs"]
+mod layout;
+
+use layout::*;
+
+fn main() {
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let memory = format!(
+        "/* Written by build.rs from src/layout.rs */
+MEMORY
+{{
+  FLASH : ORIGIN = {flash:#010X}, LENGTH = {flash_size:#X}
+  RAM : ORIGIN = {ram:#010X}, LENGTH = {ram_size:#X}
+}}
+
+/* Reserved for the key/value store and the serial, seed and multisig records,
+   between the vector table and the code */
+_storage_start = ORIGIN(FLASH) + {storage_start:#X};
+_storage_end = ORIGIN(FLASH) + {storage_end:#X};
+_stext = ORIGIN(FLASH) + {code_start:#X};
+
+/* This is where the call stack will be allocated. */
+/* The stack is of the full descending type. */
+/* NOTE Do NOT modify `_stack_start` unless you know what you are doing */
+_stack_start = ORIGIN(RAM) + LENGTH(RAM);
+
+ASSERT(_stext >= _storage_end, \"the code overlaps storage\");
+",
+        flash = FLASH_START,
+        flash_size = FLASH_SIZE,
+        ram = RAM_START,
+        ram_size = RAM_SIZE,
+        storage_start = KV_SECTORS[0].offset,
+        storage_end = STORAGE_SECTOR.offset + STORAGE_SECTOR.size,
+        code_start = CODE_START,
+    );
+    fs::write(out.join("memory.x"), memory).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/layout.rs");
+}
@@ -0,0 +1,72 @@
+use crate::{
+    error::WalletErr,
+    kvstore::{self, Flash, Store},
+    Result,
+};
+use heapless::{consts::*, String};
+pub use protocol::{LABEL_LEN, MAX_ACCOUNTS};
+
+// Account labels live in the key/value store, one value per enabled account.
+// Account 0 is enabled without one.
+pub type Label = String<U16>;
+pub type Accounts = [Option<Label>; MAX_ACCOUNTS];
+
+fn key(account: usize) -> u16 {
+    kvstore::keys::ACCOUNT_LABELS + account as u16
+}
+
+/// Returns the label of every enabled account, `None` for disabled ones.
+/// Account 0 is always enabled.
+pub fn accounts<F: Flash>(kv: &Store<F>) -> Accounts {
+    let mut accts: Accounts = Default::default();
+    for (acct, label) in accts.iter_mut().enumerate() {
+        *label = kv.get(key(acct)).and_then(|l| {
+            let mut label = Label::new();
+            label.push_str(core::str::from_utf8(&l).ok()?).ok()?;
+            Some(label)
+        });
+    }
+    if accts[0].is_none() {
+        accts[0] = Some(Label::new());
+    }
+    accts
+}
+
+/// `accts` as the host is sent them
+pub fn labels(accts: &Accounts) -> [Option<&str>; MAX_ACCOUNTS] {
+    let mut labels = [None; MAX_ACCOUNTS];
+    for (label, acct) in labels.iter_mut().zip(accts.iter()) {
+        *label = acct.as_ref().map(|l| l.as_str());
+    }
+    labels
+}
+
+pub fn is_enabled<F: Flash>(kv: &Store<F>, account: u32) -> bool {
+    (account as usize) < MAX_ACCOUNTS && accounts(kv)[account as usize].is_some()
+}
+
+/// Keys of disabled accounts are neither shown nor used
+pub fn check<F: Flash>(kv: &Store<F>, account: u32) -> Result<()> {
+    if is_enabled(kv, account) {
+        Ok(())
+    } else {
+        Err(WalletErr::from("account not enabled"))
+    }
+}
+
+/// Enables `account` with the given label, or disables it if `label` is `None`
+pub fn set_account<F: Flash>(kv: &mut Store<F>, account: u32, label: Option<&str>) -> Result<()> {
+    let acct = account as usize;
+    if acct >= MAX_ACCOUNTS {
+        return Err(WalletErr::from("account out of range"));
+    }
+    if acct == 0 && label.is_none() {
+        return Err(WalletErr::from("account 0 cannot be disabled"));
+    }
+
+    match label {
+        Some(l) if l.len() > LABEL_LEN => Err(WalletErr::from("account label too long")),
+        Some(l) => kv.set(key(acct), l.as_bytes()),
+        None => kv.remove(key(acct)),
+    }
+}
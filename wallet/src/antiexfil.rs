@@ -0,0 +1,94 @@
+//! Anti-exfil ECDSA signing, the sign-to-contract protocol of
+//! libsecp256k1-zkp's `ecdsa_s2c` module. Firmware free to pick its nonces
+//! could leak the key through them; here the host makes sure it can't:
+//!
+//! 1. the host commits to 32 random bytes `host_data`, sending
+//!    `tagged_hash("s2c/ecdsa/data", host_data)`
+//! 2. the device commits to its nonce `k0`, sending `R0 = k0·G`
+//! 3. the host reveals `host_data` and the device signs with the nonce
+//!    `k0 + t`, `t = tagged_hash("s2c/ecdsa/point", R0 || host_data)`
+//! 4. the host checks that the signature's `r` is the x coordinate of
+//!    `R0 + t·G`
+//!
+//! The device commits to `k0` before it learns `host_data`, so whatever it
+//! chose, the final nonce is as random as the host's data.
+use crate::{error::WalletErr, safemem::wipe, schnorr::tagged_hash, Result};
+
+use ecdsa::hazmat::RecoverableSignPrimitive;
+use k256::{
+    ecdsa::recoverable::{self, Id},
+    elliptic_curve::{ff::PrimeField, sec1::ToEncodedPoint},
+    FieldBytes, ProjectivePoint, Scalar,
+};
+
+fn reduced(hash: &[u8; 32]) -> Scalar {
+    Scalar::from_bytes_reduced(FieldBytes::from_slice(hash))
+}
+
+/// A signature the device has committed to the nonce of, waiting for the
+/// host's data
+pub struct Pending {
+    secret: [u8; 32],
+    digest: [u8; 32],
+    k0: [u8; 32],
+    r0: [u8; 33],
+    host_commitment: [u8; 32],
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        wipe(&mut self.secret);
+        wipe(&mut self.k0);
+    }
+}
+
+/// Commits to the nonce for signing the 32 byte `digest` with `secret`
+pub fn commit(secret: &[u8; 32], digest: &[u8; 32], host_commitment: &[u8]) -> Result<Pending> {
+    if host_commitment.len() != 32 {
+        return Err(WalletErr::from("host commitment must be 32 bytes"));
+    }
+    // Deterministic, and different for every host commitment
+    let k0 = tagged_hash("novus/s2c/nonce", &[secret, digest, host_commitment]);
+    let k = reduced(&k0);
+    if bool::from(k.is_zero()) {
+        return Err(WalletErr::from("invalid nonce"));
+    }
+    let mut pending = Pending {
+        secret: *secret,
+        digest: *digest,
+        k0,
+        r0: [0; 33],
+        host_commitment: [0; 32],
+    };
+    let r0 = (ProjectivePoint::generator() * k).to_affine();
+    pending
+        .r0
+        .copy_from_slice(r0.to_encoded_point(true).as_bytes());
+    pending.host_commitment.copy_from_slice(host_commitment);
+    Ok(pending)
+}
+
+impl Pending {
+    /// `R0`, compressed
+    pub fn signer_commitment(&self) -> &[u8; 33] {
+        &self.r0
+    }
+
+    /// The recoverable signature, once `host_data` matches its commitment.
+    /// Taking `self` means a nonce is never used twice.
+    pub fn sign(self, host_data: &[u8]) -> Result<recoverable::Signature> {
+        if tagged_hash("s2c/ecdsa/data", &[host_data]) != self.host_commitment {
+            return Err(WalletErr::from("host data doesn't match its commitment"));
+        }
+        let t = reduced(&tagged_hash("s2c/ecdsa/point", &[&self.r0, host_data]));
+        let k = reduced(&self.k0) + t;
+        let d = Scalar::from_repr(*FieldBytes::from_slice(&self.secret))
+            .filter(|d| !bool::from(d.is_zero()))
+            .ok_or_else(|| WalletErr::from("invalid secret key"))?;
+        let (sig, recovery_id) = d.try_sign_recoverable_prehashed(&k, &reduced(&self.digest))?;
+        Ok(recoverable::Signature::new(
+            &sig,
+            Id::new(recovery_id as u8)?,
+        )?)
+    }
+}
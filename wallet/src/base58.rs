@@ -0,0 +1,97 @@
+use heapless::{ArrayLength, String};
+use sha2::{Digest, Sha256};
+
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// Longest input `encode` accepts; base58 needs scratch space ~1.37x the input
+const MAX_INPUT: usize = 82;
+
+/// Base58 (Bitcoin alphabet) encoding of `bytes`. Empty if `N` is too small.
+pub fn encode<N: ArrayLength<u8>>(bytes: &[u8]) -> String<N> {
+    let mut out = String::new();
+    if bytes.len() > MAX_INPUT {
+        return out;
+    }
+
+    // Little endian base58 digits, converted one input byte at a time
+    let mut digits = [0u8; MAX_INPUT * 138 / 100 + 1];
+    let mut len = 0;
+    for b in bytes {
+        let mut carry = *b as u32;
+        for d in digits[..len].iter_mut() {
+            carry += (*d as u32) << 8;
+            *d = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits[len] = (carry % 58) as u8;
+            len += 1;
+            carry /= 58;
+        }
+    }
+
+    // Each leading zero byte is a leading '1'
+    for _ in bytes.iter().take_while(|b| **b == 0) {
+        if out.push('1').is_err() {
+            return String::new();
+        }
+    }
+    for d in digits[..len].iter().rev() {
+        if out.push(ALPHABET[*d as usize] as char).is_err() {
+            return String::new();
+        }
+    }
+    out
+}
+
+/// Base58 of `bytes` followed by the first four bytes of their double SHA256
+pub fn encode_check<N: ArrayLength<u8>>(bytes: &[u8]) -> String<N> {
+    let mut buf = [0u8; MAX_INPUT];
+    if bytes.len() + 4 > MAX_INPUT {
+        return String::new();
+    }
+    let checksum = Sha256::digest(&Sha256::digest(bytes));
+    buf[..bytes.len()].copy_from_slice(bytes);
+    buf[bytes.len()..bytes.len() + 4].copy_from_slice(&checksum[..4]);
+    encode(&buf[..bytes.len() + 4])
+}
+
+/// Decodes base58 `s` into `out`, returning the number of bytes written
+pub fn decode(s: &str, out: &mut [u8]) -> Option<usize> {
+    // Little endian bytes, converted one input digit at a time
+    let mut bytes = [0u8; MAX_INPUT];
+    let mut len = 0;
+    for c in s.bytes() {
+        let mut carry = ALPHABET.iter().position(|a| *a == c)? as u32;
+        for b in bytes[..len].iter_mut() {
+            carry += *b as u32 * 58;
+            *b = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            *bytes.get_mut(len)? = carry as u8;
+            len += 1;
+            carry >>= 8;
+        }
+    }
+
+    let zeros = s.bytes().take_while(|c| *c == b'1').count();
+    let out = out.get_mut(..zeros + len)?;
+    out[..zeros].iter_mut().for_each(|b| *b = 0);
+    for (o, b) in out[zeros..].iter_mut().zip(bytes[..len].iter().rev()) {
+        *o = *b;
+    }
+    Some(zeros + len)
+}
+
+/// Decodes `s` into `out` and checks its trailing double SHA256 checksum,
+/// returning the length of the payload
+pub fn decode_check(s: &str, out: &mut [u8]) -> Option<usize> {
+    let mut buf = [0u8; MAX_INPUT];
+    let len = decode(s, &mut buf)?.checked_sub(4)?;
+    let checksum = Sha256::digest(&Sha256::digest(&buf[..len]));
+    if checksum[..4] != buf[len..len + 4] {
+        return None;
+    }
+    out.get_mut(..len)?.copy_from_slice(&buf[..len]);
+    Some(len)
+}
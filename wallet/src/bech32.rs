@@ -0,0 +1,104 @@
+//! Bech32 (BIP173) and Bech32m (BIP350) encoding, used by SegWit addresses
+use crate::{error::WalletErr, Result};
+
+use heapless::{consts::*, String, Vec};
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+
+/// Which checksum constant is used: Bech32m for witness version 1 and up
+#[derive(Clone, Copy)]
+pub enum Variant {
+    Bech32,
+    Bech32m,
+}
+
+/// Bech32 strings are at most 90 characters
+pub type Bech32String = String<U90>;
+/// 5 bit groups of the data part, without the checksum
+pub type Data = Vec<u8, U84>;
+
+fn polymod(values: impl Iterator<Item = u8>) -> u32 {
+    values.fold(1, |chk, v| {
+        let top = chk >> 25;
+        let chk = ((chk & 0x01ff_ffff) << 5) ^ v as u32;
+        GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold(chk, |chk, (_, g)| chk ^ g)
+    })
+}
+
+fn hrp_expand(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes()
+        .map(|b| b >> 5)
+        .chain(core::iter::once(0))
+        .chain(hrp.bytes().map(|b| b & 0x1f))
+}
+
+/// Regroups 8 bit `bytes` into 5 bit groups, padding the last one with zeros
+pub fn to_base32(bytes: &[u8], out: &mut Data) -> Result<()> {
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let full = || WalletErr::from("bech32 data too long");
+    for b in bytes {
+        acc = (acc << 8) | *b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(((acc >> bits) & 0x1f) as u8).map_err(|_| full())?;
+        }
+    }
+    if bits > 0 {
+        out.push(((acc << (5 - bits)) & 0x1f) as u8)
+            .map_err(|_| full())?;
+    }
+    Ok(())
+}
+
+/// `hrp`, the separator, `data` and a checksum, all lowercase
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> Result<Bech32String> {
+    if hrp.is_empty() || hrp.len() + 1 + data.len() + 6 > 90 {
+        return Err(WalletErr::from("bech32 string too long"));
+    }
+    let checksum = polymod(
+        hrp_expand(hrp)
+            .chain(data.iter().copied())
+            .chain([0u8; 6].iter().copied()),
+    ) ^ match variant {
+        Variant::Bech32 => 1,
+        Variant::Bech32m => 0x2bc8_30a3,
+    };
+
+    let mut out = Bech32String::new();
+    // The length was checked above, so pushing can't fail
+    let _ = out.push_str(hrp);
+    let _ = out.push('1');
+    for d in data {
+        let _ = out.push(CHARSET[*d as usize] as char);
+    }
+    for i in 0..6 {
+        let _ = out.push(CHARSET[((checksum >> (5 * (5 - i))) & 0x1f) as usize] as char);
+    }
+    Ok(out)
+}
+
+/// SegWit address of `program` with witness `version`
+pub fn segwit_address(hrp: &str, version: u8, program: &[u8]) -> Result<Bech32String> {
+    let mut data = Data::new();
+    let _ = data.push(version);
+    to_base32(program, &mut data)?;
+    let variant = if version == 0 {
+        Variant::Bech32
+    } else {
+        Variant::Bech32m
+    };
+    encode(hrp, &data, variant)
+}
@@ -0,0 +1,283 @@
+//! BIP32 extended keys. `tiny_hderive` keeps the chain code to itself, which
+//! is fine for signing but not for exporting extended public keys.
+use crate::{base58, error::WalletErr, Result};
+
+use heapless::{consts::*, String, Vec};
+use hmac::{Hmac, Mac, NewMac};
+use k256::{
+    elliptic_curve::{
+        ff::PrimeField,
+        sec1::{FromEncodedPoint, ToEncodedPoint},
+    },
+    AffinePoint, EncodedPoint, FieldBytes, ProjectivePoint, Scalar, SecretKey,
+};
+use numtoa::NumToA;
+use ripemd160::Ripemd160;
+use sha2::{Digest, Sha256, Sha512};
+
+pub const HARDENED: u32 = 0x8000_0000;
+/// Version bytes of a mainnet `xpub`
+pub const XPUB: [u8; 4] = [0x04, 0x88, 0xB2, 0x1E];
+/// Version bytes of a testnet `tpub`
+pub const TPUB: [u8; 4] = [0x04, 0x35, 0x87, 0xCF];
+
+pub type XpubString = String<U112>;
+pub type PathString = String<U64>;
+pub type Path = Vec<u32, U10>;
+
+pub struct ExtendedKey {
+    secret: SecretKey,
+    pub chain_code: [u8; 32],
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+}
+
+/// An extended public key, e.g. a co-signer's `xpub`
+pub struct ExtendedPubKey {
+    pub version: [u8; 4],
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    pub public_key: [u8; 33],
+}
+
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    let mut out = [0u8; 20];
+    out.copy_from_slice(&Ripemd160::digest(&Sha256::digest(data)));
+    out
+}
+
+/// `path` in the usual `m/84'/0'/0'/0/0` notation
+pub fn path_str(path: &[u32]) -> PathString {
+    let mut out = PathString::new();
+    let mut buf = [0u8; 10];
+    let _ = out.push('m');
+    for idx in path {
+        let _ = out.push('/');
+        let _ = out.push_str((idx & !HARDENED).numtoa_str(10, &mut buf));
+        if idx & HARDENED != 0 {
+            let _ = out.push('\'');
+        }
+    }
+    out
+}
+
+/// A path component such as `48'` or `48h` or `0`
+pub fn parse_index(s: &str) -> Option<u32> {
+    let (num, hardened) = match s.strip_suffix('\'').or_else(|| s.strip_suffix('h')) {
+        Some(num) => (num, HARDENED),
+        None => (s, 0),
+    };
+    let idx: u32 = num.parse().ok()?;
+    if idx >= HARDENED {
+        return None;
+    }
+    Some(idx | hardened)
+}
+
+/// The inverse of `path_str`
+pub fn parse_path(s: &str) -> Result<Path> {
+    let invalid = || WalletErr::from("invalid derivation path");
+    let mut parts = s.split('/');
+    if parts.next() != Some("m") {
+        return Err(invalid());
+    }
+    let mut path = Path::new();
+    for part in parts {
+        path.push(parse_index(part).ok_or_else(invalid)?)
+            .map_err(|_| WalletErr::from("derivation path too deep"))?;
+    }
+    Ok(path)
+}
+
+pub fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha512>::new_varkey(key).unwrap();
+    for p in parts {
+        mac.update(p);
+    }
+    let mut out = [0u8; 64];
+    out.copy_from_slice(&mac.finalize().into_bytes());
+    out
+}
+
+impl ExtendedKey {
+    pub fn master(seed: &[u8]) -> Result<Self> {
+        let i = hmac_sha512(b"Bitcoin seed", &[seed]);
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+        Self::root(&i[..32], chain_code)
+    }
+
+    /// A master key from its secret and chain code, as an `xprv` holds them
+    pub fn root(secret: &[u8], chain_code: [u8; 32]) -> Result<Self> {
+        Ok(ExtendedKey {
+            secret: SecretKey::from_bytes(secret)?,
+            chain_code,
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: 0,
+        })
+    }
+
+    /// Derives along `path`, where indices >= `HARDENED` are hardened
+    pub fn derive(seed: &[u8], path: &[u32]) -> Result<Self> {
+        path.iter()
+            .try_fold(Self::master(seed)?, |key, idx| key.child(*idx))
+    }
+
+    pub fn child(&self, index: u32) -> Result<Self> {
+        let i = if index >= HARDENED {
+            hmac_sha512(
+                &self.chain_code,
+                &[&[0], &self.secret_bytes(), &index.to_be_bytes()],
+            )
+        } else {
+            hmac_sha512(
+                &self.chain_code,
+                &[&self.public_key(), &index.to_be_bytes()],
+            )
+        };
+
+        let tweak = Scalar::from_repr(*FieldBytes::from_slice(&i[..32]))
+            .ok_or_else(|| WalletErr::from("invalid child key"))?;
+        let child = tweak + self.secret.secret_scalar().as_ref();
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+        Ok(ExtendedKey {
+            secret: SecretKey::from_bytes(child.to_bytes())?,
+            chain_code,
+            depth: self.depth + 1,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+        })
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&self.secret.to_bytes());
+        out
+    }
+
+    /// The compressed SEC1 public key
+    pub fn public_key(&self) -> [u8; 33] {
+        let mut out = [0u8; 33];
+        out.copy_from_slice(
+            &k256::ecdsa::SigningKey::from(&self.secret)
+                .verify_key()
+                .to_bytes(),
+        );
+        out
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        let mut out = [0u8; 4];
+        out.copy_from_slice(&hash160(&self.public_key())[..4]);
+        out
+    }
+
+    /// The base58check serialized extended public key, using `version` bytes
+    pub fn xpub(&self, version: [u8; 4]) -> XpubString {
+        self.to_public(version).to_base58()
+    }
+
+    pub fn to_public(&self, version: [u8; 4]) -> ExtendedPubKey {
+        ExtendedPubKey {
+            version,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            public_key: self.public_key(),
+        }
+    }
+}
+
+impl ExtendedPubKey {
+    /// Parses a base58check serialized extended public key of any version
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || WalletErr::from("invalid extended public key");
+        let mut raw = [0u8; 78];
+        if base58::decode_check(s, &mut raw) != Some(raw.len()) {
+            return Err(invalid());
+        }
+        let mut key = ExtendedPubKey {
+            version: [0; 4],
+            depth: raw[4],
+            parent_fingerprint: [0; 4],
+            child_number: u32::from_be_bytes([raw[9], raw[10], raw[11], raw[12]]),
+            chain_code: [0; 32],
+            public_key: [0; 33],
+        };
+        key.version.copy_from_slice(&raw[..4]);
+        key.parent_fingerprint.copy_from_slice(&raw[5..9]);
+        key.chain_code.copy_from_slice(&raw[13..45]);
+        key.public_key.copy_from_slice(&raw[45..]);
+        // Rejects private keys and points not on the curve
+        point(&key.public_key).ok_or_else(invalid)?;
+        Ok(key)
+    }
+
+    /// Derives the non-hardened child `index`
+    pub fn child(&self, index: u32) -> Result<Self> {
+        if index >= HARDENED {
+            return Err(WalletErr::from("hardened derivation needs the private key"));
+        }
+        let i = hmac_sha512(&self.chain_code, &[&self.public_key, &index.to_be_bytes()]);
+        let tweak = Scalar::from_repr(*FieldBytes::from_slice(&i[..32]))
+            .ok_or_else(|| WalletErr::from("invalid child key"))?;
+        let parent =
+            point(&self.public_key).ok_or_else(|| WalletErr::from("invalid public key"))?;
+        let child = (ProjectivePoint::generator() * tweak + parent).to_affine();
+        if bool::from(child.is_identity()) {
+            return Err(WalletErr::from("invalid child key"));
+        }
+
+        let mut public_key = [0u8; 33];
+        public_key.copy_from_slice(child.to_encoded_point(true).as_bytes());
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&i[32..]);
+        Ok(ExtendedPubKey {
+            version: self.version,
+            depth: self.depth.wrapping_add(1),
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            chain_code,
+            public_key,
+        })
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        let mut out = [0u8; 4];
+        out.copy_from_slice(&hash160(&self.public_key)[..4]);
+        out
+    }
+
+    /// Serialized as 78 bytes, without the base58check encoding
+    pub fn to_bytes(&self) -> [u8; 78] {
+        let mut raw = [0u8; 78];
+        raw[..4].copy_from_slice(&self.version);
+        raw[4] = self.depth;
+        raw[5..9].copy_from_slice(&self.parent_fingerprint);
+        raw[9..13].copy_from_slice(&self.child_number.to_be_bytes());
+        raw[13..45].copy_from_slice(&self.chain_code);
+        raw[45..].copy_from_slice(&self.public_key);
+        raw
+    }
+
+    pub fn to_base58(&self) -> XpubString {
+        base58::encode_check(&self.to_bytes())
+    }
+}
+
+/// Decompresses a SEC1 compressed public key
+fn point(public_key: &[u8; 33]) -> Option<AffinePoint> {
+    if public_key[0] != 0x02 && public_key[0] != 0x03 {
+        return None;
+    }
+    EncodedPoint::from_bytes(&public_key[..])
+        .ok()
+        .and_then(|p| AffinePoint::from_encoded_point(&p))
+}
@@ -0,0 +1,175 @@
+//! BIP85 deterministic entropy: child mnemonics, hex and passwords derived
+//! from the master seed, so a hot wallet's backup is this device's backup.
+use crate::{
+    bip32::{self, hmac_sha512, ExtendedKey, Path, HARDENED},
+    display::{self, Field, FieldValue, WordFields},
+    error::WalletErr,
+    safemem::wipe,
+    Result,
+};
+
+use core::ops::Deref;
+use heapless::{consts::*, String};
+use numtoa::NumToA;
+use protocol::Bip85App;
+use sha2::{Digest, Sha256};
+
+const PURPOSE: u32 = 83_696_968;
+const BIP39_APP: u32 = 39;
+const ENGLISH: u32 = 0;
+const HEX_APP: u32 = 128_169;
+const PASSWORD_APP: u32 = 707_764;
+const BIP39_WORDLIST: &str = include_str!("../bip39-english.txt");
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub type Fields = WordFields;
+
+/// A derived secret, wiped when dropped. A 24 word mnemonic is the longest.
+pub struct Secret(String<U256>);
+
+impl Deref for Secret {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        // Zeroes are still valid UTF-8
+        wipe(unsafe { self.0.as_mut_vec() });
+    }
+}
+
+pub fn path(app: Bip85App, index: u32) -> Result<Path> {
+    let (app, params): (u32, &[u32]) = match app {
+        Bip85App::Mnemonic(words @ 12)
+        | Bip85App::Mnemonic(words @ 18)
+        | Bip85App::Mnemonic(words @ 24) => (BIP39_APP, &[ENGLISH, words]),
+        Bip85App::Hex(len @ 16..=64) => (HEX_APP, &[len]),
+        Bip85App::Password(len @ 20..=86) => (PASSWORD_APP, &[len]),
+        _ => return Err(WalletErr::from("unsupported BIP85 length")),
+    };
+    if index >= HARDENED {
+        return Err(WalletErr::from("index out of range"));
+    }
+    let mut path = Path::new();
+    for idx in [PURPOSE, app].iter().chain(params).chain(&[index]) {
+        let _ = path.push(idx | HARDENED);
+    }
+    Ok(path)
+}
+
+/// What the user checks before the secret is derived and shown
+pub fn summary(app: Bip85App, index: u32) -> Result<Fields> {
+    let path = path(app, index)?;
+    let mut buf = [0u8; 10];
+    let (len, unit) = match app {
+        Bip85App::Mnemonic(words) => (words, " words"),
+        Bip85App::Hex(len) => (len, " bytes hex"),
+        Bip85App::Password(len) => (len, " char password"),
+    };
+    let mut value = FieldValue::from(len.numtoa_str(10, &mut buf));
+    let _ = value.push_str(unit);
+    let mut fields = Fields::new();
+    let _ = fields.push(Field {
+        label: "Secret",
+        value,
+    });
+    let _ = fields.push(Field {
+        label: "Index",
+        value: FieldValue::from(index.numtoa_str(10, &mut buf)),
+    });
+    let _ = fields.push(Field {
+        label: "Path",
+        value: FieldValue::from(bip32::path_str(&path).as_str()),
+    });
+    Ok(fields)
+}
+
+/// The BIP39 words of `entropy`, 11 bits each, the last of them ending with
+/// the first bits of its SHA-256
+fn push_words(entropy: &[u8], out: &mut String<U256>) -> Result<()> {
+    let checksum = Sha256::digest(entropy)[0];
+    let bit = |i: usize| entropy.get(i / 8).unwrap_or(&checksum) >> (7 - i % 8) & 1;
+    let words = (entropy.len() * 8 + entropy.len() / 4) / 11;
+    for w in 0..words {
+        let idx = (0..11).fold(0, |idx, i| idx << 1 | bit(w * 11 + i) as usize);
+        let word = BIP39_WORDLIST.lines().nth(idx).unwrap_or_default();
+        if w > 0 {
+            let _ = out.push(' ');
+        }
+        out.push_str(word)
+            .map_err(|_| WalletErr::from("mnemonic too long"))?;
+    }
+    Ok(())
+}
+
+pub fn derive(seed: &[u8], app: Bip85App, index: u32) -> Result<Secret> {
+    from_master(&ExtendedKey::master(seed)?, app, index)
+}
+
+/// The secret of `app` at `index` below `master`, the root key that BIP85's
+/// test vectors start from
+pub fn from_master(master: &ExtendedKey, app: Bip85App, index: u32) -> Result<Secret> {
+    let path = path(app, index)?;
+    let key = path[1..]
+        .iter()
+        .try_fold(master.child(path[0])?, |key, idx| key.child(*idx))?;
+    let mut secret = key.secret_bytes();
+    let mut entropy = hmac_sha512(b"bip-entropy-from-k", &[&secret]);
+    wipe(&mut secret);
+
+    let mut out = Secret(String::new());
+    let written = match app {
+        Bip85App::Mnemonic(words) => {
+            // 12 words from 16 bytes, 18 from 24 and 24 from 32
+            push_words(&entropy[..words as usize * 4 / 3], &mut out.0)
+        }
+        Bip85App::Hex(len) => {
+            const DIGITS: &[u8; 16] = b"0123456789abcdef";
+            for b in &entropy[..len as usize] {
+                let _ = out.0.push(DIGITS[(b >> 4) as usize] as char);
+                let _ = out.0.push(DIGITS[(b & 0xf) as usize] as char);
+            }
+            Ok(())
+        }
+        Bip85App::Password(len) => {
+            // Base64 of all 64 bytes, cut to length before any padding
+            for chunk in entropy.chunks(3) {
+                let mut b = [0u8; 3];
+                b[..chunk.len()].copy_from_slice(chunk);
+                let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+                for i in 0..=chunk.len() {
+                    let _ = out
+                        .0
+                        .push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+                }
+            }
+            while out.0.len() > len as usize {
+                out.0.pop();
+            }
+            Ok(())
+        }
+    };
+    wipe(&mut entropy);
+    written.map(|_| out)
+}
+
+/// `secret` split into fields that fit the display, words in fours
+pub fn fields(secret: &Secret) -> Fields {
+    if secret.contains(' ') {
+        return display::word_fields(secret);
+    }
+    // Hex is up to 128 characters, passwords up to 86
+    let mut fields = Fields::new();
+    for (chunk, label) in secret.as_bytes().chunks(64).zip(&["Secret", "(cont.)"]) {
+        let chunk = core::str::from_utf8(chunk).unwrap_or_default();
+        let _ = fields.push(Field {
+            label,
+            value: FieldValue::from(chunk),
+        });
+    }
+    fields
+}
@@ -0,0 +1,135 @@
+//! Bitcoin keys and addresses. Accounts follow BIP84, native SegWit
+//! (P2WPKH) at `m/84'/coin'/account'/change/index`, or BIP86, Taproot key
+//! path (P2TR) at `m/86'/coin'/account'/change/index`.
+use crate::{
+    base58,
+    bech32::{self, Bech32String},
+    bip32::{hash160, ExtendedKey, XpubString, HARDENED, TPUB, XPUB},
+    error::WalletErr,
+    schnorr::{self, XOnly},
+    Result,
+};
+
+use heapless::{ArrayLength, String};
+
+pub use protocol::{BtcNetwork, BtcScript};
+
+/// Version bytes of a mainnet BIP84 account key
+const ZPUB: [u8; 4] = [0x04, 0xB2, 0x47, 0x46];
+/// Version bytes of a testnet/regtest BIP84 account key
+const VPUB: [u8; 4] = [0x04, 0x5F, 0x1C, 0xF6];
+
+/// SLIP-44 coin type: test networks share coin type 1
+pub fn coin_type(network: BtcNetwork) -> u32 {
+    match network {
+        BtcNetwork::Mainnet => 0,
+        BtcNetwork::Testnet | BtcNetwork::Regtest => 1,
+    }
+}
+
+/// Human readable part of the network's SegWit addresses
+pub fn hrp(network: BtcNetwork) -> &'static str {
+    match network {
+        BtcNetwork::Mainnet => "bc",
+        BtcNetwork::Testnet => "tb",
+        BtcNetwork::Regtest => "bcrt",
+    }
+}
+
+fn purpose(script: BtcScript) -> u32 {
+    match script {
+        BtcScript::P2wpkh => 84,
+        BtcScript::P2tr => 86,
+    }
+}
+
+pub fn account_path(network: BtcNetwork, script: BtcScript, account: u32) -> Result<[u32; 3]> {
+    if account >= HARDENED {
+        return Err(WalletErr::from("account out of range"));
+    }
+    Ok([
+        purpose(script) | HARDENED,
+        coin_type(network) | HARDENED,
+        account | HARDENED,
+    ])
+}
+
+/// Full path; `change` is 0 for receive and 1 for change addresses
+pub fn path(
+    network: BtcNetwork,
+    script: BtcScript,
+    account: u32,
+    change: u32,
+    index: u32,
+) -> Result<[u32; 5]> {
+    if change > 1 {
+        return Err(WalletErr::from("change must be 0 or 1"));
+    }
+    if index >= HARDENED {
+        return Err(WalletErr::from("index out of range"));
+    }
+    let [purpose, coin, account] = account_path(network, script, account)?;
+    Ok([purpose, coin, account, change, index])
+}
+
+/// The x-only internal key of a P2TR output, from the compressed public key
+pub fn internal_key(pubkey: &[u8; 33]) -> XOnly {
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&pubkey[1..]);
+    x
+}
+
+/// P2WPKH address of a compressed public key
+pub fn p2wpkh_address(network: BtcNetwork, pubkey: &[u8; 33]) -> Result<Bech32String> {
+    bech32::segwit_address(hrp(network), 0, &hash160(pubkey))
+}
+
+/// Base58check P2PKH address of a compressed public key
+pub fn p2pkh_address<N: ArrayLength<u8>>(network: BtcNetwork, pubkey: &[u8; 33]) -> String<N> {
+    let mut payload = [0u8; 21];
+    payload[0] = match network {
+        BtcNetwork::Mainnet => 0x00,
+        BtcNetwork::Testnet | BtcNetwork::Regtest => 0x6f,
+    };
+    payload[1..].copy_from_slice(&hash160(pubkey));
+    base58::encode_check(&payload)
+}
+
+/// BIP86 P2TR address of a compressed public key
+pub fn p2tr_address(network: BtcNetwork, pubkey: &[u8; 33]) -> Result<Bech32String> {
+    let output_key = schnorr::taproot_output_key(&internal_key(pubkey))?;
+    bech32::segwit_address(hrp(network), 1, &output_key)
+}
+
+pub fn address(
+    seed: &[u8],
+    network: BtcNetwork,
+    script: BtcScript,
+    account: u32,
+    change: u32,
+    index: u32,
+) -> Result<Bech32String> {
+    let key = ExtendedKey::derive(seed, &path(network, script, account, change, index)?)?;
+    match script {
+        BtcScript::P2wpkh => p2wpkh_address(network, &key.public_key()),
+        BtcScript::P2tr => p2tr_address(network, &key.public_key()),
+    }
+}
+
+/// The account's extended public key: `zpub`/`vpub` for BIP84 and
+/// `xpub`/`tpub` for BIP86, which has no version bytes of its own
+pub fn account_xpub(
+    seed: &[u8],
+    network: BtcNetwork,
+    script: BtcScript,
+    account: u32,
+) -> Result<XpubString> {
+    let key = ExtendedKey::derive(seed, &account_path(network, script, account)?)?;
+    let version = match (script, network) {
+        (BtcScript::P2wpkh, BtcNetwork::Mainnet) => ZPUB,
+        (BtcScript::P2wpkh, _) => VPUB,
+        (BtcScript::P2tr, BtcNetwork::Mainnet) => XPUB,
+        (BtcScript::P2tr, _) => TPUB,
+    };
+    Ok(key.xpub(version))
+}
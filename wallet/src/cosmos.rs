@@ -0,0 +1,480 @@
+//! Cosmos SDK chains: keys at `m/44'/118'/account'/0/index`, bech32
+//! addresses with the chain's own prefix, and transactions signed in direct
+//! (protobuf `SignDoc`) or legacy amino JSON mode.
+use crate::{
+    bech32::{self, Bech32String, Data, Variant},
+    bip32::{hash160, ExtendedKey, HARDENED},
+    display::{self, Field, FieldValue},
+    error::WalletErr,
+    json, Result,
+};
+
+use heapless::{consts::*, Vec};
+use k256::ecdsa::{signature::DigestSigner, Signature, SigningKey};
+use numtoa::NumToA;
+use sha2::{Digest, Sha256};
+
+pub use protocol::CosmosSignMode;
+
+const COIN_TYPE: u32 = 118;
+const MAX_PREFIX_LEN: usize = 16;
+
+pub type Fields = Vec<Field, U16>;
+
+pub fn path(account: u32, index: u32) -> Result<[u32; 5]> {
+    if account >= HARDENED || index >= HARDENED {
+        return Err(WalletErr::from("index out of range"));
+    }
+    Ok([
+        44 | HARDENED,
+        COIN_TYPE | HARDENED,
+        account | HARDENED,
+        0,
+        index,
+    ])
+}
+
+/// Bech32 address of `pubkey` on the chain whose addresses start with `prefix`
+pub fn address(prefix: &str, pubkey: &[u8; 33]) -> Result<Bech32String> {
+    let valid = prefix
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit());
+    if prefix.is_empty() || prefix.len() > MAX_PREFIX_LEN || !valid {
+        return Err(WalletErr::from("invalid bech32 prefix"));
+    }
+    let mut data = Data::new();
+    bech32::to_base32(&hash160(pubkey), &mut data)?;
+    bech32::encode(prefix, &data, Variant::Bech32)
+}
+
+/// Signs the SHA256 of `doc`, as every Cosmos SDK sign mode does
+pub fn sign(key: &ExtendedKey, doc: &[u8]) -> Result<[u8; 64]> {
+    let signer = SigningKey::from_bytes(&key.secret_bytes())?;
+    let sig: Signature = signer.try_sign_digest(Sha256::new().chain(doc))?;
+    let mut out = [0u8; 64];
+    out.copy_from_slice(sig.as_ref());
+    Ok(out)
+}
+
+/// The messages shown before signing; anything else is shown by type only
+enum Msg<'a> {
+    Send {
+        from: &'a str,
+        to: &'a str,
+        amount: FieldValue,
+    },
+    Delegate {
+        delegator: &'a str,
+        validator: &'a str,
+        amount: FieldValue,
+    },
+    Undelegate {
+        delegator: &'a str,
+        validator: &'a str,
+        amount: FieldValue,
+    },
+    Redelegate {
+        delegator: &'a str,
+        src: &'a str,
+        dst: &'a str,
+        amount: FieldValue,
+    },
+    WithdrawRewards {
+        delegator: &'a str,
+        validator: &'a str,
+    },
+    Other(&'a str),
+}
+
+/// Confirmation screens of a sign doc, made by `summary`
+struct Summary<'a> {
+    fields: Fields,
+    /// The signer's address: messages signed by it don't show it again
+    ours: &'a str,
+    /// Set by messages only shown by their type
+    blind: bool,
+}
+
+impl Summary<'_> {
+    fn push(&mut self, label: &'static str, value: &str) -> Result<()> {
+        let text = display::printable(value.as_bytes())
+            .ok_or_else(|| WalletErr::from("unprintable text in sign doc"))?;
+        let too_large = || WalletErr::from("transaction too large to show");
+        let mut value = FieldValue::new();
+        value.push_str(text).map_err(|_| too_large())?;
+        self.fields
+            .push(Field { label, value })
+            .map_err(|_| too_large())
+    }
+
+    /// Shows `signer` if it isn't this device's address
+    fn signer(&mut self, signer: &str) -> Result<()> {
+        if signer != self.ours {
+            self.push("Signer", signer)?;
+        }
+        Ok(())
+    }
+
+    fn msg(&mut self, msg: Msg) -> Result<()> {
+        match msg {
+            Msg::Send { from, to, amount } => {
+                self.signer(from)?;
+                self.push("Send", &amount)?;
+                self.push("To", to)
+            }
+            Msg::Delegate {
+                delegator,
+                validator,
+                amount,
+            } => {
+                self.signer(delegator)?;
+                self.push("Delegate", &amount)?;
+                self.push("Validator", validator)
+            }
+            Msg::Undelegate {
+                delegator,
+                validator,
+                amount,
+            } => {
+                self.signer(delegator)?;
+                self.push("Undelegate", &amount)?;
+                self.push("Validator", validator)
+            }
+            Msg::Redelegate {
+                delegator,
+                src,
+                dst,
+                amount,
+            } => {
+                self.signer(delegator)?;
+                self.push("Redelegate", &amount)?;
+                self.push("From validator", src)?;
+                self.push("To validator", dst)
+            }
+            Msg::WithdrawRewards {
+                delegator,
+                validator,
+            } => {
+                self.signer(delegator)?;
+                self.push("Withdraw rewards", validator)
+            }
+            Msg::Other(type_name) => {
+                self.blind = true;
+                self.push("Message", type_name)
+            }
+        }
+    }
+}
+
+/// Appends "`amount` `denom`" to a list of coins
+fn push_coin(coins: &mut FieldValue, amount: &str, denom: &str) -> Result<()> {
+    let valid = !amount.is_empty() && amount.bytes().all(|b| b.is_ascii_digit());
+    if !valid {
+        return Err(WalletErr::from("invalid coin amount"));
+    }
+    let full = || WalletErr::from("too many coins to show");
+    if !coins.is_empty() {
+        coins.push_str(", ").map_err(|_| full())?;
+    }
+    coins.push_str(amount).map_err(|_| full())?;
+    coins.push(' ').map_err(|_| full())?;
+    coins.push_str(denom).map_err(|_| full())
+}
+
+fn zero_coins(coins: FieldValue) -> FieldValue {
+    if coins.is_empty() {
+        let mut none = FieldValue::new();
+        let _ = none.push('0');
+        none
+    } else {
+        coins
+    }
+}
+
+/// What the user checks before signing `doc` as the owner of `ours`, and
+/// whether it has messages that can only be named, not shown
+pub fn summary(mode: CosmosSignMode, doc: &[u8], ours: &str) -> Result<(Fields, bool)> {
+    let mut summary = Summary {
+        fields: Fields::new(),
+        ours,
+        blind: false,
+    };
+    match mode {
+        CosmosSignMode::Direct => direct::summary(&mut summary, doc)?,
+        CosmosSignMode::Amino => amino::summary(&mut summary, doc)?,
+    }
+    Ok((summary.fields, summary.blind))
+}
+
+/// `SIGN_MODE_DIRECT`, the protobuf encoded `SignDoc`
+mod direct {
+    use super::*;
+
+    const MSG_SEND: &str = "/cosmos.bank.v1beta1.MsgSend";
+    const MSG_DELEGATE: &str = "/cosmos.staking.v1beta1.MsgDelegate";
+    const MSG_UNDELEGATE: &str = "/cosmos.staking.v1beta1.MsgUndelegate";
+    const MSG_REDELEGATE: &str = "/cosmos.staking.v1beta1.MsgBeginRedelegate";
+    const MSG_WITHDRAW: &str = "/cosmos.distribution.v1beta1.MsgWithdrawDelegatorReward";
+
+    enum Value<'a> {
+        Varint(u64),
+        Bytes(&'a [u8]),
+    }
+
+    fn malformed() -> WalletErr {
+        WalletErr::from("malformed sign doc")
+    }
+
+    fn varint(buf: &[u8], pos: &mut usize) -> Result<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = *buf.get(*pos).ok_or_else(malformed)?;
+            *pos += 1;
+            n |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(malformed())
+    }
+
+    fn field<'a>(msg: &'a [u8], pos: &mut usize) -> Result<(u64, Value<'a>)> {
+        let tag = varint(msg, pos)?;
+        let value = match tag & 7 {
+            0 => Value::Varint(varint(msg, pos)?),
+            2 => {
+                let len = varint(msg, pos)? as usize;
+                let end = pos.checked_add(len).ok_or_else(malformed)?;
+                let bytes = msg.get(*pos..end).ok_or_else(malformed)?;
+                *pos = end;
+                Value::Bytes(bytes)
+            }
+            _ => return Err(WalletErr::from("unsupported protobuf field")),
+        };
+        Ok((tag >> 3, value))
+    }
+
+    /// The (number, value) fields of a protobuf message
+    fn fields(msg: &[u8]) -> impl Iterator<Item = Result<(u64, Value<'_>)>> {
+        let mut pos = 0;
+        core::iter::from_fn(move || {
+            if pos >= msg.len() {
+                return None;
+            }
+            let field = field(msg, &mut pos);
+            if field.is_err() {
+                // Nothing after a malformed field can be read
+                pos = msg.len();
+            }
+            Some(field)
+        })
+    }
+
+    /// The only occurrence of field `number`, so what's shown is what a
+    /// node decodes
+    fn single<'a>(msg: &'a [u8], number: u64) -> Result<Option<Value<'a>>> {
+        let mut found = None;
+        for field in fields(msg) {
+            let (n, value) = field?;
+            if n == number {
+                if found.is_some() {
+                    return Err(WalletErr::from("repeated protobuf field"));
+                }
+                found = Some(value);
+            }
+        }
+        Ok(found)
+    }
+
+    fn bytes(msg: &[u8], number: u64) -> Result<&[u8]> {
+        match single(msg, number)? {
+            Some(Value::Bytes(b)) => Ok(b),
+            None => Ok(&[]),
+            Some(Value::Varint(_)) => Err(malformed()),
+        }
+    }
+
+    fn string(msg: &[u8], number: u64) -> Result<&str> {
+        core::str::from_utf8(bytes(msg, number)?).map_err(|_| malformed())
+    }
+
+    fn uint(msg: &[u8], number: u64) -> Result<u64> {
+        match single(msg, number)? {
+            Some(Value::Varint(n)) => Ok(n),
+            None => Ok(0),
+            Some(Value::Bytes(_)) => Err(malformed()),
+        }
+    }
+
+    fn repeated(msg: &[u8], number: u64) -> impl Iterator<Item = Result<&[u8]>> {
+        fields(msg).filter_map(move |field| match field {
+            Ok((n, Value::Bytes(b))) if n == number => Some(Ok(b)),
+            Ok((n, Value::Varint(_))) if n == number => Some(Err(malformed())),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    /// The `Coin`s in field `number`
+    fn coins(msg: &[u8], number: u64) -> Result<FieldValue> {
+        let mut out = FieldValue::new();
+        for coin in repeated(msg, number) {
+            let coin = coin?;
+            push_coin(&mut out, string(coin, 2)?, string(coin, 1)?)?;
+        }
+        Ok(zero_coins(out))
+    }
+
+    fn msg<'a>(type_url: &'a str, v: &'a [u8]) -> Result<Msg<'a>> {
+        Ok(match type_url {
+            MSG_SEND => Msg::Send {
+                from: string(v, 1)?,
+                to: string(v, 2)?,
+                amount: coins(v, 3)?,
+            },
+            MSG_DELEGATE => Msg::Delegate {
+                delegator: string(v, 1)?,
+                validator: string(v, 2)?,
+                amount: coins(v, 3)?,
+            },
+            MSG_UNDELEGATE => Msg::Undelegate {
+                delegator: string(v, 1)?,
+                validator: string(v, 2)?,
+                amount: coins(v, 3)?,
+            },
+            MSG_REDELEGATE => Msg::Redelegate {
+                delegator: string(v, 1)?,
+                src: string(v, 2)?,
+                dst: string(v, 3)?,
+                amount: coins(v, 4)?,
+            },
+            MSG_WITHDRAW => Msg::WithdrawRewards {
+                delegator: string(v, 1)?,
+                validator: string(v, 2)?,
+            },
+            other => Msg::Other(other),
+        })
+    }
+
+    pub fn summary(summary: &mut Summary, doc: &[u8]) -> Result<()> {
+        let body = bytes(doc, 1)?;
+        let auth_info = bytes(doc, 2)?;
+        let mut buf = [0u8; 20];
+        summary.push("Chain", string(doc, 3)?)?;
+        summary.push("Account number", uint(doc, 4)?.numtoa_str(10, &mut buf))?;
+
+        // AuthInfo signer infos: public key, mode, sequence
+        for signer_info in repeated(auth_info, 1) {
+            summary.push("Sequence", uint(signer_info?, 3)?.numtoa_str(10, &mut buf))?;
+        }
+
+        // TxBody: messages, memo, timeout height, extension options and
+        // non-critical ones, which change what the transaction does too
+        for field in fields(body) {
+            let (number, _) = field?;
+            if number == 1023 || number == 2047 {
+                return Err(WalletErr::from("unsupported extension options"));
+            }
+        }
+        for any in repeated(body, 1) {
+            let any = any?;
+            summary.msg(msg(string(any, 1)?, bytes(any, 2)?)?)?;
+        }
+        let memo = string(body, 2)?;
+        if !memo.is_empty() {
+            summary.push("Memo", memo)?;
+        }
+
+        // AuthInfo fee: amount, gas limit, payer, granter
+        let fee = bytes(auth_info, 2)?;
+        summary.push("Fee", &coins(fee, 1)?)?;
+        summary.push("Gas", uint(fee, 2)?.numtoa_str(10, &mut buf))?;
+        let payer = string(fee, 3)?;
+        if !payer.is_empty() {
+            summary.push("Fee payer", payer)?;
+        }
+        Ok(())
+    }
+}
+
+/// `SIGN_MODE_LEGACY_AMINO_JSON`, the canonical JSON `StdSignDoc`
+mod amino {
+    use super::*;
+
+    const MSG_SEND: &str = "cosmos-sdk/MsgSend";
+    const MSG_DELEGATE: &str = "cosmos-sdk/MsgDelegate";
+    const MSG_UNDELEGATE: &str = "cosmos-sdk/MsgUndelegate";
+    const MSG_REDELEGATE: &str = "cosmos-sdk/MsgBeginRedelegate";
+    const MSG_WITHDRAW: &str = "cosmos-sdk/MsgWithdrawDelegationReward";
+
+    fn string<'a>(object: &'a str, key: &str) -> Result<&'a str> {
+        json::string(json::get(object, key)?)
+    }
+
+    fn push_json_coin(out: &mut FieldValue, coin: &str) -> Result<()> {
+        push_coin(out, string(coin, "amount")?, string(coin, "denom")?)
+    }
+
+    /// A list of coins, or a single coin for staking messages
+    fn coins(object: &str, key: &str) -> Result<FieldValue> {
+        let value = json::get(object, key)?;
+        let mut out = FieldValue::new();
+        if value.starts_with('{') {
+            push_json_coin(&mut out, value)?;
+        } else {
+            for coin in json::items(value)? {
+                push_json_coin(&mut out, coin)?;
+            }
+        }
+        Ok(zero_coins(out))
+    }
+
+    fn msg(msg: &str) -> Result<Msg<'_>> {
+        let v = json::get(msg, "value")?;
+        Ok(match string(msg, "type")? {
+            MSG_SEND => Msg::Send {
+                from: string(v, "from_address")?,
+                to: string(v, "to_address")?,
+                amount: coins(v, "amount")?,
+            },
+            MSG_DELEGATE => Msg::Delegate {
+                delegator: string(v, "delegator_address")?,
+                validator: string(v, "validator_address")?,
+                amount: coins(v, "amount")?,
+            },
+            MSG_UNDELEGATE => Msg::Undelegate {
+                delegator: string(v, "delegator_address")?,
+                validator: string(v, "validator_address")?,
+                amount: coins(v, "amount")?,
+            },
+            MSG_REDELEGATE => Msg::Redelegate {
+                delegator: string(v, "delegator_address")?,
+                src: string(v, "validator_src_address")?,
+                dst: string(v, "validator_dst_address")?,
+                amount: coins(v, "amount")?,
+            },
+            MSG_WITHDRAW => Msg::WithdrawRewards {
+                delegator: string(v, "delegator_address")?,
+                validator: string(v, "validator_address")?,
+            },
+            other => Msg::Other(other),
+        })
+    }
+
+    pub fn summary(summary: &mut Summary, doc: &[u8]) -> Result<()> {
+        let doc = json::validate(doc)?;
+        summary.push("Chain", string(doc, "chain_id")?)?;
+        summary.push("Account number", string(doc, "account_number")?)?;
+        summary.push("Sequence", string(doc, "sequence")?)?;
+        for m in json::items(json::get(doc, "msgs")?)? {
+            summary.msg(msg(m)?)?;
+        }
+        let memo = string(doc, "memo")?;
+        if !memo.is_empty() {
+            summary.push("Memo", memo)?;
+        }
+        let fee = json::get(doc, "fee")?;
+        summary.push("Fee", &coins(fee, "amount")?)?;
+        summary.push("Gas", string(fee, "gas")?)
+    }
+}
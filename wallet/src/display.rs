@@ -0,0 +1,260 @@
+//! Screens shown on the SSD1306. Every screen draws onto a generic
+//! `DrawTarget`, so layouts can be rendered into an in-memory framebuffer on
+//! the host as well as onto the OLED. Nothing here depends on the display
+//! driver, see oled.rs for that, so the simulator builds it as it is.
+use crate::{error::WalletErr, qr::QrCode, Result};
+
+use embedded_graphics::{
+    fonts::{Font6x8, Text},
+    image::{Image, ImageRaw},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, Rectangle},
+    style::{PrimitiveStyle, TextStyle},
+};
+use heapless::{consts::*, ArrayLength, String, Vec};
+
+pub const WIDTH: i32 = 128;
+pub const HEIGHT: i32 = 64;
+/// Characters that fit on one line with `Font6x8`
+pub const LINE_LEN: usize = (WIDTH / 6) as usize;
+const LINE_HEIGHT: i32 = 9;
+const BODY_TOP: i32 = 11;
+/// Lines of text that fit below the title bar
+pub const BODY_LINES: usize = ((HEIGHT - BODY_TOP) / LINE_HEIGHT) as usize;
+
+/// A rectangle of the screen text gets wrapped into
+struct Area {
+    x: i32,
+    top: i32,
+    line_len: usize,
+    lines: usize,
+}
+
+/// Everything below the title bar
+const BODY: Area = Area {
+    x: 0,
+    top: BODY_TOP,
+    line_len: LINE_LEN,
+    lines: BODY_LINES,
+};
+
+/// Right of a QR code, which takes up the left `HEIGHT` x `HEIGHT` pixels
+const QR_CAPTION: Area = Area {
+    x: HEIGHT + 2,
+    top: 0,
+    line_len: ((WIDTH - HEIGHT - 2) / 6) as usize,
+    lines: (HEIGHT / LINE_HEIGHT) as usize,
+};
+
+const BOOT_IMAGE: &[u8] = include_bytes!("../ssd1306-image.data");
+
+pub type FieldValue = String<U96>;
+/// Up to 36 words of a mnemonic, four to a field
+pub type WordFields = Vec<Field, U9>;
+
+const WORD_LABELS: [&str; 9] = [
+    "1-4", "5-8", "9-12", "13-16", "17-20", "21-24", "25-28", "29-32", "33-36",
+];
+
+/// A labelled value on a confirmation screen, e.g. "To: 0x.."
+pub struct Field {
+    pub label: &'static str,
+    pub value: FieldValue,
+}
+
+/// Something screens can be drawn onto and then pushed to the user
+pub trait Screen: DrawTarget<BinaryColor> {
+    fn show(&mut self) -> Result<()>;
+    /// `level` from 1 (dimmest) to 5 (brightest)
+    fn set_brightness(&mut self, level: u8) -> Result<()>;
+}
+
+fn draw_err<E>(_: E) -> WalletErr {
+    WalletErr::from("failed to draw to display")
+}
+
+fn text<D: Screen>(d: &mut D, s: &str, at: Point) -> Result<()> {
+    Text::new(s, at)
+        .into_styled(TextStyle::new(Font6x8, BinaryColor::On))
+        .draw(d)
+        .map_err(draw_err)
+}
+
+/// Clears the screen and draws `title` above a divider
+fn title<D: Screen>(d: &mut D, title: &str) -> Result<()> {
+    d.clear(BinaryColor::Off).map_err(draw_err)?;
+    text(d, title, Point::zero())?;
+    Line::new(
+        Point::new(0, LINE_HEIGHT),
+        Point::new(WIDTH - 1, LINE_HEIGHT),
+    )
+    .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+    .draw(d)
+    .map_err(draw_err)
+}
+
+/// Draws `body` wrapped to fit `area`, starting on the area's line `first`.
+/// Text that doesn't fit is cut off with "..". Returns the next free line.
+fn wrapped<D: Screen>(d: &mut D, area: &Area, body: &str, first: usize) -> Result<usize> {
+    let mut line = first;
+    let mut rest = body;
+    while !rest.is_empty() && line < area.lines {
+        let at = Point::new(area.x, area.top + line as i32 * LINE_HEIGHT);
+        let mut end = rest.len().min(area.line_len);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        if !tail.is_empty() && line == area.lines - 1 {
+            let mut cut: String<U32> = String::new();
+            let mut cut_end = chunk.len().saturating_sub(2);
+            while !chunk.is_char_boundary(cut_end) {
+                cut_end -= 1;
+            }
+            let _ = cut.push_str(&chunk[..cut_end]);
+            let _ = cut.push_str("..");
+            text(d, &cut, at)?;
+        } else {
+            text(d, chunk, at)?;
+        }
+        rest = tail;
+        line += 1;
+    }
+    Ok(line)
+}
+
+/// `phrase` as numbered fields of four words each
+pub fn word_fields(phrase: &str) -> WordFields {
+    let mut fields = WordFields::new();
+    let mut words = phrase.split(' ');
+    for label in WORD_LABELS.iter() {
+        let mut value = FieldValue::new();
+        for word in words.by_ref().take(4) {
+            if !value.is_empty() {
+                let _ = value.push(' ');
+            }
+            let _ = value.push_str(word);
+        }
+        if value.is_empty() {
+            break;
+        }
+        let _ = fields.push(Field { label, value });
+    }
+    fields
+}
+
+/// Lower case hex of `bytes`, prefixed with "0x". Truncated if `N` is too small.
+pub fn hex_str<N: ArrayLength<u8>>(bytes: &[u8]) -> String<N> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut s = String::new();
+    let _ = s.push_str("0x");
+    for b in bytes {
+        let _ = s.push(DIGITS[(b >> 4) as usize] as char);
+        let _ = s.push(DIGITS[(b & 0xF) as usize] as char);
+    }
+    s
+}
+
+pub fn boot<D: Screen>(d: &mut D) -> Result<()> {
+    let raw: ImageRaw<BinaryColor> = ImageRaw::new(BOOT_IMAGE, WIDTH as u32, HEIGHT as u32);
+    Image::new(&raw, Point::zero()).draw(d).map_err(draw_err)?;
+    d.show()
+}
+
+/// A title with free text underneath, e.g. "Ready" or an error
+pub fn status<D: Screen>(d: &mut D, msg: &str) -> Result<()> {
+    title(d, "NoviSigner")?;
+    wrapped(d, &BODY, msg, 0)?;
+    d.show()
+}
+
+/// Draws as many of `fields` as fit under `heading`, at least one.
+/// Returns how many were drawn.
+pub fn fields<D: Screen>(d: &mut D, heading: &str, fields: &[Field]) -> Result<usize> {
+    title(d, heading)?;
+    let mut line = 0;
+    let mut shown = 0;
+    for f in fields {
+        let mut text: String<U128> = String::new();
+        let _ = text.push_str(f.label);
+        let _ = text.push_str(": ");
+        let _ = text.push_str(&f.value);
+        let needed = (text.len() + LINE_LEN - 1) / LINE_LEN;
+        if shown > 0 && line + needed > BODY_LINES {
+            break;
+        }
+        line = wrapped(d, &BODY, &text, line)?;
+        shown += 1;
+    }
+    d.show()?;
+    Ok(shown)
+}
+
+/// One of several options to pick from with the buttons, below `prompt`
+pub fn choice<D: Screen>(d: &mut D, heading: &str, prompt: &str, option: &str) -> Result<()> {
+    title(d, heading)?;
+    wrapped(d, &BODY, prompt, 0)?;
+    let mut line: String<U32> = String::new();
+    let _ = line.push_str("> ");
+    let _ = line.push_str(option);
+    wrapped(d, &BODY, &line, 2)?;
+    wrapped(d, &BODY, "Reject: next OK: pick", BODY_LINES - 1)?;
+    d.show()
+}
+
+pub fn address<D: Screen>(d: &mut D, heading: &str, path: &str, addr: &str) -> Result<()> {
+    title(d, heading)?;
+    wrapped(d, &BODY, path, 0)?;
+    wrapped(d, &BODY, addr, 1)?;
+    d.show()
+}
+
+/// `msg` as a `str` if it is printable ASCII, which is all `Font6x8` can draw
+pub fn printable(msg: &[u8]) -> Option<&str> {
+    if msg.iter().all(|b| *b == b' ' || b.is_ascii_graphic()) {
+        core::str::from_utf8(msg).ok()
+    } else {
+        None
+    }
+}
+
+/// Shows the message about to be signed, as text if it is printable and as
+/// hex otherwise
+pub fn sign_message<D: Screen>(d: &mut D, msg: &[u8]) -> Result<()> {
+    title(d, "Sign message?")?;
+    match printable(msg) {
+        Some(s) => wrapped(d, &BODY, s, 0)?,
+        None => wrapped(d, &BODY, &hex_str::<U128>(msg), 0)?,
+    };
+    d.show()
+}
+
+/// Draws `code` as dark modules on a lit square on the left, as large as
+/// fits with at least a one module quiet zone, with `caption` next to it
+pub fn qr<D: Screen>(d: &mut D, code: &QrCode, caption: &str) -> Result<()> {
+    d.clear(BinaryColor::Off).map_err(draw_err)?;
+    Rectangle::new(Point::zero(), Point::new(HEIGHT - 1, HEIGHT - 1))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(d)
+        .map_err(draw_err)?;
+
+    let size = code.size();
+    let scale = (HEIGHT / (size + 2)).max(1);
+    let offset = (HEIGHT - size * scale) / 2;
+    let dark = PrimitiveStyle::with_fill(BinaryColor::Off);
+    for y in 0..size {
+        for x in 0..size {
+            if code.module(x, y) {
+                let top_left = Point::new(offset + x * scale, offset + y * scale);
+                Rectangle::new(top_left, top_left + Point::new(scale - 1, scale - 1))
+                    .into_styled(dark)
+                    .draw(d)
+                    .map_err(draw_err)?;
+            }
+        }
+    }
+
+    wrapped(d, &QR_CAPTION, caption, 0)?;
+    d.show()
+}
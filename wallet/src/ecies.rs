@@ -0,0 +1,221 @@
+//! ECDH with device keys and decryption of ECIES messages encrypted to
+//! them, so apps can encrypt to a user's public key without the secret key
+//! ever leaving the device. Two constructions are understood:
+//!
+//! - eth-crypto's (`eccrypto`): SHA512 of the shared x coordinate keys
+//!   AES-256-CBC and HMAC-SHA256, serialized by `EthCrypto.cipher.stringify`
+//!   as `iv || compressed ephemeral key || mac || ciphertext`
+//! - EIP-5630's, which is geth's `crypto/ecies`: a concat KDF of the shared x
+//!   coordinate keys AES-128-CTR and HMAC-SHA256, serialized as
+//!   `uncompressed ephemeral key || iv || ciphertext || mac`
+use crate::{error::WalletErr, safemem::wipe, Result};
+
+use core::convert::TryInto;
+
+use aes::{cipher::generic_array::GenericArray, Aes128, Aes256, BlockCipher, NewBlockCipher};
+use heapless::{consts::*, Vec};
+use hmac::{Hmac, Mac, NewMac};
+use k256::{
+    elliptic_curve::{
+        ff::PrimeField,
+        sec1::{FromEncodedPoint, ToEncodedPoint},
+    },
+    AffinePoint, EncodedPoint, FieldBytes, ProjectivePoint, Scalar,
+};
+use protocol::EciesScheme;
+use sha2::{Digest, Sha256, Sha512};
+
+/// What fits in a response next to its framing
+pub type Plaintext = Vec<u8, U960>;
+
+const IV_LEN: usize = 16;
+const MAC_LEN: usize = 32;
+
+fn invalid() -> WalletErr {
+    WalletErr::from("invalid ciphertext")
+}
+
+fn point(pubkey: &[u8]) -> Result<AffinePoint> {
+    EncodedPoint::from_bytes(pubkey)
+        .ok()
+        .and_then(|p| AffinePoint::from_encoded_point(&p))
+        .filter(|p| !bool::from(p.is_identity()))
+        .ok_or_else(|| WalletErr::from("invalid public key"))
+}
+
+/// `pubkey`, compressed or not, as a compressed SEC1 public key
+pub fn compressed(pubkey: &[u8]) -> Result<[u8; 33]> {
+    let mut out = [0u8; 33];
+    out.copy_from_slice(point(pubkey)?.to_encoded_point(true).as_bytes());
+    Ok(out)
+}
+
+fn uncompressed(pubkey: &[u8]) -> Result<[u8; 65]> {
+    let mut out = [0u8; 65];
+    out.copy_from_slice(point(pubkey)?.to_encoded_point(false).as_bytes());
+    Ok(out)
+}
+
+/// The compressed point `secret` * `peer`, where `peer` is a compressed or
+/// uncompressed SEC1 public key. Its x coordinate is what most protocols
+/// call the shared secret.
+pub fn ecdh(secret: &[u8; 32], peer: &[u8]) -> Result<[u8; 33]> {
+    let d = Scalar::from_repr(*FieldBytes::from_slice(secret))
+        .filter(|d| !bool::from(d.is_zero()))
+        .ok_or_else(|| WalletErr::from("invalid secret key"))?;
+    // Never the point at infinity, `d` being non-zero and the group of prime order
+    let shared = (ProjectivePoint::from(point(peer)?) * d).to_affine();
+    let mut out = [0u8; 33];
+    out.copy_from_slice(shared.to_encoded_point(true).as_bytes());
+    Ok(out)
+}
+
+/// HMAC-SHA256 of `parts` under `key`, compared in constant time
+fn check_mac(key: &[u8], parts: &[&[u8]], mac: &[u8]) -> Result<()> {
+    // HMAC accepts keys of any length
+    let mut hmac = Hmac::<Sha256>::new_varkey(key).unwrap();
+    for p in parts {
+        hmac.update(p);
+    }
+    hmac.verify(mac)
+        .map_err(|_| WalletErr::from("wrong key or corrupted message"))
+}
+
+fn too_long(_: u8) -> WalletErr {
+    WalletErr::from("message too long")
+}
+
+/// AES-256-CBC decryption with PKCS#7 padding
+fn cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Plaintext> {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    let mut out = Plaintext::new();
+    let mut prev = iv;
+    for chunk in ciphertext.chunks(16) {
+        let mut block = GenericArray::clone_from_slice(chunk);
+        cipher.decrypt_block(&mut block);
+        for (b, p) in block.iter().zip(prev) {
+            out.push(b ^ p).map_err(too_long)?;
+        }
+        prev = chunk;
+    }
+    let pad = *out.last().ok_or_else(invalid)? as usize;
+    if pad == 0 || pad > 16 || !out[out.len() - pad..].iter().all(|b| *b as usize == pad) {
+        return Err(invalid());
+    }
+    for _ in 0..pad {
+        out.pop();
+    }
+    Ok(out)
+}
+
+/// AES-128-CTR, the counter being the whole 16 byte block
+fn ctr_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Plaintext> {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut out = Plaintext::new();
+    let mut counter = u128::from_be_bytes(iv.try_into().map_err(|_| invalid())?);
+    for chunk in ciphertext.chunks(16) {
+        let mut stream = GenericArray::from(counter.to_be_bytes());
+        cipher.encrypt_block(&mut stream);
+        counter = counter.wrapping_add(1);
+        for (c, s) in chunk.iter().zip(stream.iter()) {
+            out.push(c ^ s).map_err(too_long)?;
+        }
+    }
+    Ok(out)
+}
+
+/// A message whose MAC checked out, decrypted once the user agrees to
+pub struct Verified<'a> {
+    scheme: EciesScheme,
+    /// The AES key, of which EIP-5630 uses the first 16 bytes
+    key: [u8; 32],
+    iv: &'a [u8],
+    ciphertext: &'a [u8],
+}
+
+impl Verified<'_> {
+    pub fn decrypt(&self) -> Result<Plaintext> {
+        match self.scheme {
+            EciesScheme::EthCrypto => cbc_decrypt(&self.key, self.iv, self.ciphertext),
+            EciesScheme::Eip5630 => ctr_decrypt(&self.key[..16], self.iv, self.ciphertext),
+        }
+    }
+}
+
+impl Drop for Verified<'_> {
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+fn eth_crypto<'a>(secret: &[u8; 32], msg: &'a [u8]) -> Result<Verified<'a>> {
+    if msg.len() < IV_LEN + 33 + MAC_LEN {
+        return Err(invalid());
+    }
+    let (iv, rest) = msg.split_at(IV_LEN);
+    let (ephemeral, rest) = rest.split_at(33);
+    let (mac, ciphertext) = rest.split_at(MAC_LEN);
+    if ciphertext.is_empty() || ciphertext.len() % 16 != 0 {
+        return Err(invalid());
+    }
+    // The MAC covers the uncompressed ephemeral key, as `eccrypto` has it
+    let ephemeral = uncompressed(ephemeral)?;
+    let mut shared = ecdh(secret, &ephemeral)?;
+    let mut keys = [0u8; 64];
+    keys.copy_from_slice(&Sha512::digest(&shared[1..]));
+    let mut verified = Verified {
+        scheme: EciesScheme::EthCrypto,
+        key: [0; 32],
+        iv,
+        ciphertext,
+    };
+    verified.key.copy_from_slice(&keys[..32]);
+
+    let result = check_mac(&keys[32..], &[iv, &ephemeral, ciphertext], mac);
+    wipe(&mut shared);
+    wipe(&mut keys);
+    result.map(|_| verified)
+}
+
+fn eip5630<'a>(secret: &[u8; 32], msg: &'a [u8]) -> Result<Verified<'a>> {
+    if msg.len() < 65 + IV_LEN + MAC_LEN || msg[0] != 0x04 {
+        return Err(invalid());
+    }
+    let (ephemeral, rest) = msg.split_at(65);
+    let (iv_and_ciphertext, mac) = rest.split_at(rest.len() - MAC_LEN);
+    let (iv, ciphertext) = iv_and_ciphertext.split_at(IV_LEN);
+    let mut shared = ecdh(secret, ephemeral)?;
+    // NIST SP 800-56 concatenation KDF with SHA256, one block being enough
+    // for both keys, and no shared info
+    let mut keys = [0u8; 32];
+    keys.copy_from_slice(
+        &Sha256::new()
+            .chain(1u32.to_be_bytes())
+            .chain(&shared[1..])
+            .finalize(),
+    );
+    let mut mac_key = [0u8; 32];
+    mac_key.copy_from_slice(&Sha256::digest(&keys[16..]));
+    let mut verified = Verified {
+        scheme: EciesScheme::Eip5630,
+        key: [0; 32],
+        iv,
+        ciphertext,
+    };
+    verified.key[..16].copy_from_slice(&keys[..16]);
+
+    let result = check_mac(&mac_key, &[iv_and_ciphertext], mac);
+    wipe(&mut shared);
+    wipe(&mut keys);
+    wipe(&mut mac_key);
+    result.map(|_| verified)
+}
+
+/// Checks that `msg` was encrypted to the public key of `secret` and not
+/// tampered with, before the user is asked to decrypt it
+pub fn verify<'a>(scheme: EciesScheme, secret: &[u8; 32], msg: &'a [u8]) -> Result<Verified<'a>> {
+    match scheme {
+        EciesScheme::EthCrypto => eth_crypto(secret, msg),
+        EciesScheme::Eip5630 => eip5630(secret, msg),
+    }
+}
@@ -0,0 +1,361 @@
+//! Entropy for new seeds. The STM32F401 has no RNG, so the device's own
+//! entropy comes from two noise sources, each checked by the NIST SP 800-90B
+//! health tests while it is sampled:
+//!
+//! - the least significant bits of the internal temperature sensor as read
+//!   by ADC1
+//! - the jitter of the LSI RC oscillator measured against the crystal
+//!   driven timer clock by TIM5 input capture
+//!
+//! Neither is trusted alone. A new seed mixes the device's entropy with the
+//! host's through commit-reveal, the device committing to its entropy before
+//! it sees the host's and the host to its own before it sees the device's
+//! commitment, so neither side can choose its entropy to cancel out the
+//! other's. Dice rolls can be mixed in as well.
+//!
+//! The host checks the device's side by cut and choose: the device answers
+//! the host's reveal with the hash of the new seed's entropy, then the host
+//! either keeps the seed or has the device open its commitment, throwing the
+//! seed away, and recomputes the hash. Opened seeds are never stored, and
+//! the device can't tell which seed will be kept.
+//!
+//! A seed can also come from dice alone, rolled into the device with its
+//! buttons so the host never sees them: its entropy is then the SHA256 of
+//! the rolls as ASCII digits, cut to length, which anyone can recompute, e.g.
+//! `echo -n 3261... | sha256sum`.
+use crate::{error::WalletErr, safemem::wipe, Result};
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+
+#[cfg(target_os = "none")]
+pub use noise::device_entropy;
+
+/// Samples taken from each source. At the assumed 0.5 bits of min-entropy
+/// per sample that is 1024 bits from each.
+const SAMPLES: usize = 2048;
+/// Health test cutoffs for 0.5 bits of min-entropy per sample and a false
+/// alarm rate of 2^-20 (SP 800-90B 4.4.1 and 4.4.2)
+pub const REPETITION_CUTOFF: u16 = 41;
+pub const PROPORTION_WINDOW: u16 = 512;
+pub const PROPORTION_CUTOFF: u16 = 410;
+/// The same repetition count test for fair dice, log2(6) bits per roll
+const DICE_REPETITION_CUTOFF: u16 = 9;
+
+fn failed() -> WalletErr {
+    WalletErr::from("entropy source failed health test")
+}
+
+/// The repetition count and adaptive proportion tests, run continuously on
+/// the raw samples of one noise source
+#[derive(Default)]
+pub struct HealthTests {
+    last: u8,
+    repeated: u16,
+    window_sample: u8,
+    window_seen: u16,
+    window_matches: u16,
+}
+
+impl HealthTests {
+    pub fn feed(&mut self, sample: u8) -> Result<()> {
+        if self.repeated > 0 && sample == self.last {
+            self.repeated += 1;
+            if self.repeated >= REPETITION_CUTOFF {
+                return Err(failed());
+            }
+        } else {
+            self.last = sample;
+            self.repeated = 1;
+        }
+
+        if self.window_seen == 0 {
+            self.window_sample = sample;
+            self.window_matches = 1;
+        } else if sample == self.window_sample {
+            self.window_matches += 1;
+            if self.window_matches >= PROPORTION_CUTOFF {
+                return Err(failed());
+            }
+        }
+        self.window_seen = (self.window_seen + 1) % PROPORTION_WINDOW;
+        Ok(())
+    }
+}
+
+/// The noise sources themselves
+#[cfg(target_os = "none")]
+mod noise {
+    use super::{HealthTests, SAMPLES};
+    use crate::{error::WalletErr, Result};
+
+    use sha2::{Digest, Sha256};
+    use stm32f4xx_hal::{
+        adc::{
+            config::{AdcConfig, SampleTime},
+            Adc, Temperature,
+        },
+        stm32,
+    };
+
+    /// Spins waiting for an LSI edge before the oscillator is taken to be dead
+    const CAPTURE_TIMEOUT: u32 = 100_000;
+
+    /// Samples `source` into `hasher` through the health tests
+    fn sample(hasher: &mut Sha256, mut source: impl FnMut() -> Result<u16>) -> Result<()> {
+        let mut tests = HealthTests::default();
+        for _ in 0..SAMPLES {
+            let raw = source()?;
+            // Only the low bits are noisy, but hashing all of them does no harm
+            tests.feed(raw as u8)?;
+            hasher.update(raw.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Internal temperature sensor readings
+    fn adc_noise(hasher: &mut Sha256) -> Result<()> {
+        let dp = unsafe { stm32::Peripherals::steal() };
+        let mut adc = Adc::adc1(dp.ADC1, true, AdcConfig::default());
+        adc.enable_temperature_and_vref();
+        // The shortest sample time leaves the most noise in the conversions
+        let res = sample(hasher, || {
+            Ok(adc.convert(&Temperature, SampleTime::Cycles_3))
+        });
+        adc.disable_temperature_and_vref();
+        adc.disable();
+        res
+    }
+
+    /// Timer ticks between successive LSI edges
+    fn clock_jitter(hasher: &mut Sha256) -> Result<()> {
+        let dp = unsafe { stm32::Peripherals::steal() };
+        dp.RCC.csr.modify(|_, w| w.lsion().set_bit());
+        let mut spins = 0;
+        while dp.RCC.csr.read().lsirdy().bit_is_clear() {
+            spins += 1;
+            if spins > CAPTURE_TIMEOUT {
+                return Err(WalletErr::from("LSI oscillator not running"));
+            }
+        }
+        dp.RCC.apb1enr.modify(|_, w| w.tim5en().set_bit());
+        let tim = dp.TIM5;
+        // Channel 4 captures the timer count on every LSI rising edge
+        tim.or.write(|w| unsafe { w.it4_rmp().bits(0b01) });
+        tim.ccmr2_input_mut().modify(|_, w| w.cc4s().ti4());
+        tim.ccer.modify(|_, w| w.cc4e().set_bit());
+        tim.cr1.modify(|_, w| w.cen().set_bit());
+
+        let mut capture = || {
+            let mut spins = 0;
+            while tim.sr.read().cc4if().bit_is_clear() {
+                spins += 1;
+                if spins > CAPTURE_TIMEOUT {
+                    return Err(WalletErr::from("LSI oscillator not running"));
+                }
+            }
+            // Reading the capture clears the flag
+            Ok(tim.ccr4.read().ccr().bits())
+        };
+        let mut prev = capture()?;
+        let res = sample(hasher, || {
+            let now = capture()?;
+            let ticks = now.wrapping_sub(prev);
+            prev = now;
+            Ok(ticks as u16)
+        });
+
+        tim.cr1.modify(|_, w| w.cen().clear_bit());
+        tim.ccer.modify(|_, w| w.cc4e().clear_bit());
+        dp.RCC.apb1enr.modify(|_, w| w.tim5en().clear_bit());
+        res
+    }
+
+    /// 32 bytes from the device's noise sources, conditioned by SHA256
+    pub fn device_entropy() -> Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        adc_noise(&mut hasher)?;
+        clock_jitter(&mut hasher)?;
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&hasher.finalize());
+        Ok(out)
+    }
+}
+
+pub fn commitment(entropy: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&Sha256::digest(entropy));
+    out
+}
+
+/// Auxiliary randomness for BIP340 signatures. The noise sources take a while
+/// to sample, so they are sampled once and the result is ratcheted forward
+/// with SHA256 for each signature. An output tells nothing of the others.
+#[cfg(target_os = "none")]
+pub struct AuxRand {
+    state: Option<[u8; 32]>,
+}
+
+#[cfg(target_os = "none")]
+impl AuxRand {
+    pub const fn new() -> Self {
+        AuxRand { state: None }
+    }
+
+    pub fn next(&mut self) -> Result<[u8; 32]> {
+        let mut state = match self.state {
+            Some(state) => state,
+            None => device_entropy()?,
+        };
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&Sha256::new().chain(b"aux").chain(state).finalize());
+        let next = Sha256::new().chain(b"next").chain(state).finalize();
+        wipe(&mut state);
+        let mut next_state = [0u8; 32];
+        next_state.copy_from_slice(&next);
+        self.state = Some(next_state);
+        Ok(out)
+    }
+}
+
+#[cfg(target_os = "none")]
+impl Drop for AuxRand {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.as_mut() {
+            wipe(state);
+        }
+    }
+}
+
+/// The device's entropy, kept between its commitment and the host's reveal
+pub struct Commit {
+    device: [u8; 32],
+    host_commitment: [u8; 32],
+}
+
+impl Commit {
+    /// Gathers the device's entropy against the host's `host_commitment`
+    #[cfg(target_os = "none")]
+    pub fn new(host_commitment: &[u8]) -> Result<Self> {
+        Self::with_entropy(device_entropy()?, host_commitment)
+    }
+
+    /// Commits to `device` as the device's entropy
+    pub fn with_entropy(device: [u8; 32], host_commitment: &[u8]) -> Result<Self> {
+        if host_commitment.len() != 32 {
+            return Err(WalletErr::from("host commitment must be 32 bytes"));
+        }
+        let mut commit = Commit {
+            device,
+            host_commitment: [0; 32],
+        };
+        commit.host_commitment.copy_from_slice(host_commitment);
+        Ok(commit)
+    }
+
+    /// What the device shows the host before the host reveals its entropy
+    pub fn device_commitment(&self) -> [u8; 32] {
+        commitment(&self.device)
+    }
+
+    /// `len` bytes of seed entropy from the device's entropy, the host's
+    /// revealed entropy and `dice`
+    pub fn reveal(self, host: &[u8], dice: &str, len: usize) -> Result<NewSeed> {
+        if commitment(host) != self.host_commitment {
+            return Err(WalletErr::from("host entropy doesn't match its commitment"));
+        }
+        check_dice(dice)?;
+        // HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.device).unwrap();
+        mac.update(b"novus seed");
+        mac.update(&commitment(host));
+        mac.update(&commitment(dice.as_bytes()));
+        let mut entropy = [0u8; 32];
+        entropy.copy_from_slice(&mac.finalize().into_bytes());
+        wipe(&mut entropy[len..]);
+        Ok(NewSeed {
+            commit: self,
+            entropy,
+            len,
+        })
+    }
+}
+
+impl Drop for Commit {
+    fn drop(&mut self) {
+        wipe(&mut self.device);
+    }
+}
+
+/// The entropy of a new seed, waiting for the host to keep it or to have the
+/// commitment it came from opened
+pub struct NewSeed {
+    commit: Commit,
+    entropy: [u8; 32],
+    len: usize,
+}
+
+impl NewSeed {
+    /// What the host recomputes from an opened commitment
+    pub fn hash(&self) -> [u8; 32] {
+        commitment(self.entropy())
+    }
+
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy[..self.len]
+    }
+
+    /// The device's entropy behind the commitment, for the host to check.
+    /// The seed is gone with it.
+    pub fn open(self) -> [u8; 32] {
+        self.commit.device
+    }
+}
+
+impl Drop for NewSeed {
+    fn drop(&mut self) {
+        wipe(&mut self.entropy);
+    }
+}
+
+/// Rolls of a six sided die as the digits 1 to 6
+fn check_dice(dice: &str) -> Result<()> {
+    // Only the repetition count test makes sense for so few samples
+    let (mut last, mut repeated) = (0, 0);
+    for roll in dice.bytes() {
+        if !(b'1'..=b'6').contains(&roll) {
+            return Err(WalletErr::from("dice rolls must be digits 1 to 6"));
+        }
+        repeated = if roll == last { repeated + 1 } else { 1 };
+        last = roll;
+        if repeated >= DICE_REPETITION_CUTOFF {
+            return Err(WalletErr::from("too many repeated dice rolls"));
+        }
+    }
+    Ok(())
+}
+
+/// Rolls needed for `len` bytes of entropy
+pub fn dice_needed(len: usize) -> usize {
+    // log2(6) is a little over 2.585 bits per roll
+    (len * 8 * 1000 + 2584) / 2585
+}
+
+/// `len` bytes of seed entropy from dice alone, the SHA256 of the rolls
+pub fn dice_entropy(dice: &str, len: usize) -> Result<[u8; 32]> {
+    check_dice(dice)?;
+    if dice.len() < dice_needed(len) {
+        return Err(WalletErr::from("not enough dice rolls"));
+    }
+    let mut out = commitment(dice.as_bytes());
+    wipe(&mut out[len..]);
+    Ok(out)
+}
+
+/// Entropy bytes for a mnemonic of `words` words
+pub fn entropy_len(words: u32) -> Result<usize> {
+    match words {
+        12 | 18 | 24 => Ok(words as usize * 4 / 3),
+        _ => Err(WalletErr::from("seeds are 12, 18 or 24 words")),
+    }
+}
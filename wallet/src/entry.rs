@@ -0,0 +1,187 @@
+//! Words entered on the device with its two buttons, for secrets the
+//! computer must never see: backups being restored or checked.
+//!
+//! A word is spelled a letter at a time, offering only letters that lead to
+//! a word of the list, until a single word is left. "<" takes back a letter,
+//! or at the start of a word goes back to the previous one.
+use crate::{
+    display::{self, Screen},
+    error::WalletErr,
+    presence::UserPresence,
+    safemem::wipe,
+    slip39,
+    ui::Ui,
+    Result,
+};
+
+use heapless::{consts::*, String, Vec};
+use numtoa::NumToA;
+
+pub const BIP39_WORDLIST: &str = include_str!("../bip39-english.txt");
+const BACK: &str = "<";
+const DIE_FACES: [&str; 7] = ["1", "2", "3", "4", "5", "6", BACK];
+/// Latest rolls shown while rolling, as many as fit on a line
+const ROLLS_SHOWN: usize = 13;
+
+/// Words or dice rolls typed on the device, zeroed when dropped
+pub struct Phrase(String<U320>);
+
+impl Phrase {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for Phrase {
+    fn drop(&mut self) {
+        // Only zero bytes are written, which keeps the string valid UTF-8
+        wipe(unsafe { self.0.as_mut_vec() });
+    }
+}
+
+/// "`what` 3 of 20"
+fn counter(what: &str, n: usize, of: usize) -> String<U32> {
+    let mut s = String::new();
+    let mut buf = [0u8; 3];
+    let _ = s.push_str(what);
+    let _ = s.push(' ');
+    let _ = s.push_str((n as u8 + 1).numtoa_str(10, &mut buf));
+    let _ = s.push_str(" of ");
+    let _ = s.push_str((of as u8).numtoa_str(10, &mut buf));
+    s
+}
+
+/// Spells out one word of `wordlist`, which has one word per line in
+/// order. Returns `None` if the user backs out of it, which is only offered
+/// if `can_back` is set.
+fn word<D: Screen, U: UserPresence>(
+    ui: &mut Ui<D, U>,
+    heading: &str,
+    wordlist: &'static str,
+    can_back: bool,
+    timeout_ms: u32,
+) -> Result<Option<&'static str>> {
+    let mut prefix: String<U16> = String::new();
+    loop {
+        let mut matches = wordlist.lines().filter(|w| w.starts_with(prefix.as_str()));
+        let mut options: Vec<&'static str, U32> = Vec::new();
+        if let (Some(only), None) = (matches.next(), matches.next()) {
+            let _ = options.push(only);
+        } else {
+            for w in wordlist.lines().filter(|w| w.starts_with(prefix.as_str())) {
+                let next = match w.get(prefix.len()..prefix.len() + 1) {
+                    Some(letter) => letter,
+                    // The prefix is a word itself, e.g. "add" of "address"
+                    None => w,
+                };
+                if options.last() != Some(&next) {
+                    let _ = options.push(next);
+                }
+            }
+        }
+        if !prefix.is_empty() || can_back {
+            let _ = options.push(BACK);
+        }
+
+        let mut prompt: String<U32> = String::new();
+        let _ = prompt.push_str("Spell: ");
+        let _ = prompt.push_str(&prefix);
+        let picked = options[ui.choose(heading, &prompt, &options, timeout_ms)?];
+        if picked == BACK {
+            if prefix.pop().is_none() {
+                return Ok(None);
+            }
+        } else if picked.len() > 1 {
+            return Ok(Some(picked));
+        } else {
+            let _ = prefix.push_str(picked);
+        }
+    }
+}
+
+/// `count` words of `wordlist`, headed by `heading` and their position
+pub fn words<D: Screen, U: UserPresence>(
+    ui: &mut Ui<D, U>,
+    heading: &str,
+    wordlist: &'static str,
+    count: usize,
+    timeout_ms: u32,
+) -> Result<Phrase> {
+    let mut words: Vec<&'static str, U33> = Vec::new();
+    if count > words.capacity() {
+        return Err(WalletErr::from("too many words"));
+    }
+    while words.len() < count {
+        let heading = counter(heading, words.len(), count);
+        match word(ui, &heading, wordlist, !words.is_empty(), timeout_ms)? {
+            Some(w) => {
+                let _ = words.push(w);
+            }
+            None => {
+                words.pop();
+            }
+        }
+    }
+    let mut phrase = Phrase(String::new());
+    for w in words.iter() {
+        if !phrase.0.is_empty() {
+            let _ = phrase.0.push(' ');
+        }
+        let _ = phrase.0.push_str(w);
+    }
+    Ok(phrase)
+}
+
+/// `count` rolls of a six sided die as the digits 1 to 6. "<" takes back
+/// the last roll.
+pub fn dice<D: Screen, U: UserPresence>(
+    ui: &mut Ui<D, U>,
+    count: usize,
+    timeout_ms: u32,
+) -> Result<Phrase> {
+    let mut rolls = Phrase(String::new());
+    if count > rolls.0.capacity() {
+        return Err(WalletErr::from("too many dice rolls"));
+    }
+    while rolls.0.len() < count {
+        let heading = counter("Roll", rolls.0.len(), count);
+        let mut prompt: String<U32> = String::new();
+        let _ = prompt.push_str("Rolled: ");
+        let _ = prompt.push_str(&rolls.0[rolls.0.len().saturating_sub(ROLLS_SHOWN)..]);
+        let faces = if rolls.0.is_empty() {
+            &DIE_FACES[..6]
+        } else {
+            &DIE_FACES[..]
+        };
+        let picked = faces[ui.choose(&heading, &prompt, faces, timeout_ms)?];
+        if picked == BACK {
+            rolls.0.pop();
+        } else {
+            let _ = rolls.0.push_str(picked);
+        }
+    }
+    Ok(rolls)
+}
+
+/// `res`, with its error shown on the screen too. For mistakes in what
+/// the user typed, which only they can fix.
+pub fn checked<T, D: Screen, U: UserPresence>(ui: &mut Ui<D, U>, res: Result<T>) -> Result<T> {
+    if let Err(WalletErr::StringErr(msg)) = &res {
+        display::status(&mut ui.disp, msg)?;
+    }
+    res
+}
+
+/// A SLIP-39 share typed on the device. Mistyped words are caught by the
+/// share's checksum, which is said on the screen as well as to the host.
+pub fn slip39_share<D: Screen, U: UserPresence>(
+    ui: &mut Ui<D, U>,
+    timeout_ms: u32,
+) -> Result<slip39::Share> {
+    let lengths = ["20 words", "27 words", "33 words"];
+    let length = ui.choose("Enter share", "Share length", &lengths, timeout_ms)?;
+    let count = slip39::SHARE_WORDS[length];
+    let phrase = words(ui, "Word", slip39::WORDLIST, count, timeout_ms)?;
+    let share = slip39::Share::parse(phrase.as_str());
+    checked(ui, share)
+}
@@ -1,10 +1,9 @@
 use core::{array::TryFromSliceError, str::Utf8Error};
 
-use bip39::ErrorKind;
 use heapless::{consts::*, String};
-use usb_device::UsbError;
 
 pub type ErrStringType = String<U80>;
+#[derive(Debug)]
 pub enum WalletErr {
     NoMsg,
     StringErr(ErrStringType),
@@ -49,48 +48,6 @@ impl From<TryFromSliceError> for WalletErr {
     }
 }
 
-impl From<stm32f4xx_hal::flash::Error> for WalletErr {
-    fn from(e: stm32f4xx_hal::flash::Error) -> WalletErr {
-        match e {
-            stm32f4xx_hal::flash::Error::ProgrammingSequence => {
-                WalletErr::from("ProgrammingSequence")
-            }
-            stm32f4xx_hal::flash::Error::ProgrammingParallelism => {
-                WalletErr::from("ProgrammingParallelism")
-            }
-            stm32f4xx_hal::flash::Error::ProgrammingAlignment => {
-                WalletErr::from("ProgrammingAlignment")
-            }
-            stm32f4xx_hal::flash::Error::WriteProtection => WalletErr::from("WriteProtection"),
-            stm32f4xx_hal::flash::Error::Operation => WalletErr::from("Operation"),
-        }
-    }
-}
-
-impl From<aes_ccm::Error> for WalletErr {
-    fn from(e: aes_ccm::Error) -> WalletErr {
-        match e {
-            aes_ccm::Error => WalletErr::from("AES error"),
-        }
-    }
-}
-
-impl From<UsbError> for WalletErr {
-    fn from(e: usb_device::UsbError) -> WalletErr {
-        use usb_device::UsbError::*;
-        match e {
-            WouldBlock => WalletErr::from("UsbError: An operation would block because the device is currently busy or there is no data available."),
-            ParseError => WalletErr::from("UsbError: Parsing failed due to invalid input.,"),
-            BufferOverflow => WalletErr::from("UsbError: A buffer too short for the data to read was passed, or provided data cannot fit within length constraints."),
-            EndpointOverflow => WalletErr::from("UsbError: Classes attempted to allocate more endpoints than the peripheral supports."),
-            EndpointMemoryOverflow => WalletErr::from("UsbError: Classes attempted to allocate more packet buffer memory than the peripheral supports."),
-            InvalidEndpoint => WalletErr::from("UsbError: The endpoint address is invalid or already used."),
-            Unsupported => WalletErr::from("UsbError: Operation is not supported by device or configuration."),
-            InvalidState => WalletErr::from("UsbError: Operation is not valid in the current state of the object."),
-        }
-    }
-}
-
 impl From<postcard::Error> for WalletErr {
     fn from(e: postcard::Error) -> WalletErr {
         use postcard::Error::*;
@@ -119,32 +76,92 @@ impl From<k256::ecdsa::Error> for WalletErr {
     }
 }
 
+impl From<k256::elliptic_curve::Error> for WalletErr {
+    fn from(_: k256::elliptic_curve::Error) -> WalletErr {
+        WalletErr::from("elliptic curve error")
+    }
+}
+
 impl From<&'static str> for WalletErr {
     fn from(s: &'static str) -> WalletErr {
         WalletErr::StringErr(ErrStringType::from(s))
     }
 }
 
-impl From<ErrorKind> for WalletErr {
-    fn from(e: ErrorKind) -> WalletErr {
-        match e {
-            ErrorKind::InvalidChecksum => WalletErr::from("InvalidChecksum"),
-            ErrorKind::InvalidWord(_) => WalletErr::from("InvalidWord"),
-            ErrorKind::InvalidKeysize(_) => WalletErr::from("InvalidKeysize"),
-            ErrorKind::InvalidWordLength(_) => WalletErr::from("InvalidWordLength"),
-            ErrorKind::InvalidEntropyLength(_, _) => WalletErr::from("InvalidEntropyLength"),
+/// Errors of the hardware and of the crates only the firmware uses, which the
+/// simulator builds without
+#[cfg(target_os = "none")]
+mod device {
+    use super::WalletErr;
+
+    use bip39::ErrorKind;
+    use usb_device::UsbError;
+
+    impl From<stm32f4xx_hal::flash::Error> for WalletErr {
+        fn from(e: stm32f4xx_hal::flash::Error) -> WalletErr {
+            match e {
+                stm32f4xx_hal::flash::Error::ProgrammingSequence => {
+                    WalletErr::from("ProgrammingSequence")
+                }
+                stm32f4xx_hal::flash::Error::ProgrammingParallelism => {
+                    WalletErr::from("ProgrammingParallelism")
+                }
+                stm32f4xx_hal::flash::Error::ProgrammingAlignment => {
+                    WalletErr::from("ProgrammingAlignment")
+                }
+                stm32f4xx_hal::flash::Error::WriteProtection => WalletErr::from("WriteProtection"),
+                stm32f4xx_hal::flash::Error::Operation => WalletErr::from("Operation"),
+            }
         }
     }
-}
 
-impl From<tiny_hderive::Error> for WalletErr {
-    fn from(e: tiny_hderive::Error) -> WalletErr {
-        match e {
-            tiny_hderive::Error::Secp256k1 => WalletErr::from("Secp256k1"),
-            tiny_hderive::Error::InvalidChildNumber => WalletErr::from("InvalidChildNumber"),
-            tiny_hderive::Error::InvalidDerivationPath => WalletErr::from("InvalidDerivationPath"),
-            tiny_hderive::Error::InvalidExtendedPrivKey => {
-                WalletErr::from("InvalidExtendedPrivKey")
+    impl From<aes_ccm::Error> for WalletErr {
+        fn from(e: aes_ccm::Error) -> WalletErr {
+            match e {
+                aes_ccm::Error => WalletErr::from("AES error"),
+            }
+        }
+    }
+
+    impl From<UsbError> for WalletErr {
+        fn from(e: usb_device::UsbError) -> WalletErr {
+            use usb_device::UsbError::*;
+            match e {
+                WouldBlock => WalletErr::from("UsbError: An operation would block because the device is currently busy or there is no data available."),
+                ParseError => WalletErr::from("UsbError: Parsing failed due to invalid input.,"),
+                BufferOverflow => WalletErr::from("UsbError: A buffer too short for the data to read was passed, or provided data cannot fit within length constraints."),
+                EndpointOverflow => WalletErr::from("UsbError: Classes attempted to allocate more endpoints than the peripheral supports."),
+                EndpointMemoryOverflow => WalletErr::from("UsbError: Classes attempted to allocate more packet buffer memory than the peripheral supports."),
+                InvalidEndpoint => WalletErr::from("UsbError: The endpoint address is invalid or already used."),
+                Unsupported => WalletErr::from("UsbError: Operation is not supported by device or configuration."),
+                InvalidState => WalletErr::from("UsbError: Operation is not valid in the current state of the object."),
+            }
+        }
+    }
+
+    impl From<ErrorKind> for WalletErr {
+        fn from(e: ErrorKind) -> WalletErr {
+            match e {
+                ErrorKind::InvalidChecksum => WalletErr::from("InvalidChecksum"),
+                ErrorKind::InvalidWord(_) => WalletErr::from("InvalidWord"),
+                ErrorKind::InvalidKeysize(_) => WalletErr::from("InvalidKeysize"),
+                ErrorKind::InvalidWordLength(_) => WalletErr::from("InvalidWordLength"),
+                ErrorKind::InvalidEntropyLength(_, _) => WalletErr::from("InvalidEntropyLength"),
+            }
+        }
+    }
+
+    impl From<tiny_hderive::Error> for WalletErr {
+        fn from(e: tiny_hderive::Error) -> WalletErr {
+            match e {
+                tiny_hderive::Error::Secp256k1 => WalletErr::from("Secp256k1"),
+                tiny_hderive::Error::InvalidChildNumber => WalletErr::from("InvalidChildNumber"),
+                tiny_hderive::Error::InvalidDerivationPath => {
+                    WalletErr::from("InvalidDerivationPath")
+                }
+                tiny_hderive::Error::InvalidExtendedPrivKey => {
+                    WalletErr::from("InvalidExtendedPrivKey")
+                }
             }
         }
     }
@@ -0,0 +1,578 @@
+//! Ethereum transaction parsing, so the user sees what they sign instead of
+//! RLP bytes. Supports EIP-155 legacy and EIP-1559 transactions and
+//! recognises ERC-20 `transfer`/`approve` calldata.
+use crate::{
+    display::{self, Field, FieldValue},
+    error::WalletErr,
+    Result,
+};
+
+use core::convert::TryFrom;
+use heapless::{consts::*, String, Vec};
+use k256::{
+    ecdsa::{signature::DigestVerifier, Signature, VerifyingKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+};
+use numtoa::NumToA;
+use protocol::TokenInfo;
+use sha2::{Digest, Sha256};
+use tiny_keccak::{Hasher, Keccak};
+
+const TRANSFER: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+const APPROVE: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+const EIP1559_TYPE: u8 = 0x02;
+
+pub const ADDR_SIZE: usize = 20;
+pub type Address = [u8; ADDR_SIZE];
+pub type Fields = Vec<Field, U10>;
+
+/// (chain id, name, native currency symbol)
+const CHAINS: &[(u64, &str, &str)] = &[
+    (1, "Ethereum", "ETH"),
+    (10, "Optimism", "ETH"),
+    (56, "BNB Chain", "BNB"),
+    (137, "Polygon", "MATIC"),
+    (42161, "Arbitrum One", "ETH"),
+    (11_155_111, "Sepolia", "ETH"),
+];
+
+/// Only token metadata signed by this key is accepted from the host. Release
+/// builds set `NOVUS_METADATA_KEY` to the metadata service's compressed public
+/// key in hex, otherwise the development key is used.
+const METADATA_KEY: [u8; 33] = match option_env!("NOVUS_METADATA_KEY") {
+    Some(key) => parse_key(key),
+    None => DEV_METADATA_KEY,
+};
+/// Its secret is SHA256("novus wallet metadata development key"), so anyone
+/// can sign with it. Tokens it vouches for are marked as dev-signed.
+const DEV_METADATA_KEY: [u8; 33] =
+    hex_literal::hex!("03bf4639955451917c209fdbc25181d7cd869855e9eb9eb7fd9fbb0a69752b0a7b");
+pub const DEV_SIGNED: bool = option_env!("NOVUS_METADATA_KEY").is_none();
+/// Longest symbol `ProvideTokenInfo` accepts
+const MAX_SYMBOL_LEN: usize = 11;
+/// Most decimals `format_units` handles, a 256 bit amount has up to 78 digits
+pub const MAX_DECIMALS: u8 = 77;
+/// Host provided tokens kept in RAM, the oldest is forgotten first
+const REGISTRY_SIZE: usize = 8;
+
+#[derive(Clone, Copy)]
+pub struct Token<'a> {
+    pub chain_id: u64,
+    pub address: Address,
+    pub symbol: &'a str,
+    pub decimals: u8,
+}
+
+/// Tokens we can show in human units without any help from the host
+const TOKENS: &[Token<'static>] = &[
+    Token {
+        chain_id: 1,
+        address: hex_literal::hex!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"),
+        symbol: "USDC",
+        decimals: 6,
+    },
+    Token {
+        chain_id: 1,
+        address: hex_literal::hex!("dac17f958d2ee523a2206206994597c13d831ec7"),
+        symbol: "USDT",
+        decimals: 6,
+    },
+    Token {
+        chain_id: 1,
+        address: hex_literal::hex!("6b175474e89094c44da98b954eedeac495271d0f"),
+        symbol: "DAI",
+        decimals: 18,
+    },
+    Token {
+        chain_id: 1,
+        address: hex_literal::hex!("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2"),
+        symbol: "WETH",
+        decimals: 18,
+    },
+    Token {
+        chain_id: 1,
+        address: hex_literal::hex!("2260fac5e5542a773aa44fbcfedf7c193bc2c599"),
+        symbol: "WBTC",
+        decimals: 8,
+    },
+    Token {
+        chain_id: 1,
+        address: hex_literal::hex!("514910771af9ca656af840dff83e8264ecf986ca"),
+        symbol: "LINK",
+        decimals: 18,
+    },
+];
+
+struct RegisteredToken {
+    chain_id: u64,
+    address: Address,
+    symbol: String<U11>,
+    decimals: u8,
+}
+
+/// Tokens the host told us about, each signed by `METADATA_KEY`
+pub struct TokenRegistry {
+    tokens: [Option<RegisteredToken>; REGISTRY_SIZE],
+    next: usize,
+}
+
+impl Default for TokenRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenRegistry {
+    pub const fn new() -> Self {
+        TokenRegistry {
+            tokens: [None, None, None, None, None, None, None, None],
+            next: 0,
+        }
+    }
+
+    /// Verifies `info` and remembers it, replacing an older entry for the
+    /// same contract
+    pub fn add(&mut self, info: &TokenInfo) -> Result<()> {
+        if info.address.len() != ADDR_SIZE {
+            return Err(WalletErr::from("invalid token address"));
+        }
+        if info.symbol.is_empty()
+            || info.symbol.len() > MAX_SYMBOL_LEN
+            || display::printable(info.symbol.as_bytes()).is_none()
+        {
+            return Err(WalletErr::from("invalid token symbol"));
+        }
+        if info.decimals > MAX_DECIMALS {
+            return Err(WalletErr::from("invalid token decimals"));
+        }
+        verify_token_info(info)?;
+
+        let mut address = [0u8; ADDR_SIZE];
+        address.copy_from_slice(info.address);
+        let token = RegisteredToken {
+            chain_id: info.chain_id,
+            address,
+            symbol: String::from(info.symbol),
+            decimals: info.decimals,
+        };
+        let slot = self
+            .tokens
+            .iter()
+            .position(|t| match t {
+                Some(t) => t.chain_id == info.chain_id && t.address == address,
+                None => false,
+            })
+            .unwrap_or_else(|| {
+                let slot = self.next;
+                self.next = (self.next + 1) % REGISTRY_SIZE;
+                slot
+            });
+        self.tokens[slot] = Some(token);
+        Ok(())
+    }
+
+    /// Looks `address` up in the built-in tokens, then the registered ones
+    pub fn find(&self, chain_id: u64, address: &[u8]) -> Option<Token<'_>> {
+        let builtin = TOKENS
+            .iter()
+            .find(|t| t.chain_id == chain_id && t.address == address)
+            .copied();
+        builtin.or_else(|| {
+            self.tokens.iter().flatten().find_map(|t| {
+                if t.chain_id == chain_id && t.address == address {
+                    Some(Token {
+                        chain_id: t.chain_id,
+                        address: t.address,
+                        symbol: &t.symbol,
+                        decimals: t.decimals,
+                    })
+                } else {
+                    None
+                }
+            })
+        })
+    }
+}
+
+/// Decodes a 33 byte hex key at compile time
+const fn parse_key(hex: &str) -> [u8; 33] {
+    const fn nibble(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => panic!("NOVUS_METADATA_KEY must be hex"),
+        }
+    }
+    let hex = hex.as_bytes();
+    assert!(
+        hex.len() == 66,
+        "NOVUS_METADATA_KEY must be a compressed key"
+    );
+    let mut key = [0u8; 33];
+    let mut i = 0;
+    while i < key.len() {
+        key[i] = nibble(hex[2 * i]) << 4 | nibble(hex[2 * i + 1]);
+        i += 1;
+    }
+    key
+}
+
+fn is_builtin(chain_id: u64, address: &[u8]) -> bool {
+    TOKENS
+        .iter()
+        .any(|t| t.chain_id == chain_id && t.address == address)
+}
+
+fn verify_token_info(info: &TokenInfo) -> Result<()> {
+    let digest = Sha256::new()
+        .chain(info.chain_id.to_be_bytes())
+        .chain(info.address)
+        .chain([info.decimals])
+        .chain(info.symbol.as_bytes());
+    let sig = Signature::try_from(info.signature)
+        .map_err(|_| WalletErr::from("invalid token signature"))?;
+    VerifyingKey::from_sec1_bytes(&METADATA_KEY)?
+        .verify_digest(digest, &sig)
+        .map_err(|_| WalletErr::from("invalid token signature"))
+}
+
+/// The address of `pubkey`: the last 20 bytes of the Keccak256 of its
+/// uncompressed encoding, without the 0x04 prefix
+pub fn address(pubkey: &VerifyingKey) -> Address {
+    let uncompressed = pubkey.to_encoded_point(false);
+    let mut hasher = Keccak::v256();
+    hasher.update(&uncompressed.as_bytes()[1..]);
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+    let mut address = [0u8; ADDR_SIZE];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+/// EIP-55 mixed-case checksum encoding of `addr`
+pub fn checksum_address(addr: &[u8; ADDR_SIZE]) -> String<U42> {
+    let lower = display::hex_str::<U42>(addr);
+    let mut hasher = Keccak::v256();
+    hasher.update(&lower.as_bytes()[2..]);
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+
+    let mut checksummed = String::new();
+    let _ = checksummed.push_str("0x");
+    for (i, c) in lower[2..].chars().enumerate() {
+        let nibble = (hash[i / 2] >> (4 * (1 - i % 2))) & 0xF;
+        let _ = checksummed.push(if nibble >= 8 {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        });
+    }
+    checksummed
+}
+
+/// `amount` (a big endian integer) as a decimal scaled down by `decimals`,
+/// followed by `symbol`, e.g. "1.5 ETH". Shows "(overflow)" for amounts past
+/// 256 bits or more than `MAX_DECIMALS` decimals.
+pub fn format_units(amount: &[u8], decimals: u8, symbol: &str) -> FieldValue {
+    let mut out = FieldValue::new();
+    let amount = strip_zeros(amount);
+    if amount.len() > 32 || decimals > MAX_DECIMALS {
+        let _ = out.push_str("(overflow)");
+        return out;
+    }
+    let mut n = [0u8; 32];
+    n[32 - amount.len()..].copy_from_slice(amount);
+
+    // Least significant digit first, by repeated division by 10
+    let mut digits = [0u8; 80];
+    let mut len = 0;
+    loop {
+        let mut rem = 0u32;
+        for b in n.iter_mut() {
+            let cur = (rem << 8) | *b as u32;
+            *b = (cur / 10) as u8;
+            rem = cur % 10;
+        }
+        digits[len] = rem as u8;
+        len += 1;
+        if n.iter().all(|b| *b == 0) {
+            break;
+        }
+    }
+    let decimals = decimals as usize;
+    // At least one digit before the decimal point
+    len = len.max(decimals + 1);
+    let frac_start = (0..decimals).find(|i| digits[*i] != 0).unwrap_or(decimals);
+
+    for d in digits[decimals..len].iter().rev() {
+        let _ = out.push((b'0' + d) as char);
+    }
+    if frac_start < decimals {
+        let _ = out.push('.');
+        for d in digits[frac_start..decimals].iter().rev() {
+            let _ = out.push((b'0' + d) as char);
+        }
+    }
+    let _ = out.push(' ');
+    let _ = out.push_str(symbol);
+    out
+}
+
+fn strip_zeros(int: &[u8]) -> &[u8] {
+    let start = int.iter().position(|b| *b != 0).unwrap_or(int.len());
+    &int[start..]
+}
+
+fn to_u64(int: &[u8]) -> Result<u64> {
+    let int = strip_zeros(int);
+    if int.len() > 8 {
+        return Err(WalletErr::from("integer too large"));
+    }
+    Ok(int.iter().fold(0, |acc, b| acc << 8 | *b as u64))
+}
+
+fn decimal(n: u64) -> FieldValue {
+    let mut buf = [0u8; 20];
+    let mut out = FieldValue::new();
+    let _ = out.push_str(n.numtoa_str(10, &mut buf));
+    out
+}
+
+/// One decoded RLP item and whatever follows it
+enum Rlp<'a> {
+    Bytes(&'a [u8]),
+    List(&'a [u8]),
+}
+
+fn rlp_decode(buf: &[u8]) -> Result<(Rlp<'_>, &[u8])> {
+    let err = || WalletErr::from("malformed RLP");
+    let prefix = *buf.first().ok_or_else(err)?;
+    let (offset, len, is_list) = match prefix {
+        0x00..=0x7f => return Ok((Rlp::Bytes(&buf[..1]), &buf[1..])),
+        0x80..=0xb7 => (1, (prefix - 0x80) as usize, false),
+        0xc0..=0xf7 => (1, (prefix - 0xc0) as usize, true),
+        _ => {
+            let is_list = prefix >= 0xf8;
+            let len_of_len = (prefix - if is_list { 0xf7 } else { 0xb7 }) as usize;
+            let len_bytes = buf.get(1..1 + len_of_len).ok_or_else(err)?;
+            // Compared before the cast, a usize is 32 bits on the device
+            let len = to_u64(len_bytes)?;
+            if len > buf.len() as u64 {
+                return Err(err());
+            }
+            (1 + len_of_len, len as usize, is_list)
+        }
+    };
+    let payload = buf.get(offset..offset + len).ok_or_else(err)?;
+    let rest = &buf[offset + len..];
+    Ok((
+        if is_list {
+            Rlp::List(payload)
+        } else {
+            Rlp::Bytes(payload)
+        },
+        rest,
+    ))
+}
+
+/// Splits the payload of an RLP list into its items
+fn rlp_items(mut list: &[u8]) -> Result<Vec<Rlp<'_>, U12>> {
+    let mut items = Vec::new();
+    while !list.is_empty() {
+        let (item, rest) = rlp_decode(list)?;
+        items
+            .push(item)
+            .map_err(|_| WalletErr::from("too many RLP items"))?;
+        list = rest;
+    }
+    Ok(items)
+}
+
+/// The payload of `item`, which must be a byte string and not a list
+fn rlp_bytes<'a>(item: &Rlp<'a>) -> Result<&'a [u8]> {
+    match item {
+        Rlp::Bytes(b) => Ok(b),
+        Rlp::List(_) => Err(WalletErr::from("transaction field must not be a list")),
+    }
+}
+
+/// The fields of an unsigned transaction. Integers are big endian.
+pub struct Tx<'a> {
+    pub chain_id: u64,
+    pub nonce: &'a [u8],
+    /// Gas price for legacy transactions, max fee per gas for EIP-1559
+    pub max_fee: &'a [u8],
+    pub max_priority_fee: Option<&'a [u8]>,
+    pub gas_limit: &'a [u8],
+    /// `None` for contract creation
+    pub to: Option<&'a [u8]>,
+    pub value: &'a [u8],
+    pub data: &'a [u8],
+}
+
+impl<'a> Tx<'a> {
+    /// Parses the exact bytes that get hashed for signing: the EIP-155
+    /// `rlp([nonce, gasPrice, gas, to, value, data, chainId, 0, 0])` or the
+    /// EIP-1559 `0x02 || rlp([chainId, nonce, maxPriorityFee, maxFee, gas,
+    /// to, value, data, accessList])`
+    pub fn parse(raw: &'a [u8]) -> Result<Self> {
+        let is_1559 = raw.first() == Some(&EIP1559_TYPE);
+        let body = if is_1559 { &raw[1..] } else { raw };
+        let list = match rlp_decode(body)? {
+            (Rlp::List(list), []) => list,
+            _ => return Err(WalletErr::from("transaction is not an RLP list")),
+        };
+        let items = rlp_items(list)?;
+        let field = |i: usize| rlp_bytes(&items[i]);
+
+        let tx = if is_1559 {
+            if items.len() != 9 {
+                return Err(WalletErr::from("EIP-1559 transaction needs 9 fields"));
+            }
+            // Not shown, but it has to be what it claims to be
+            if let Rlp::Bytes(_) = items[8] {
+                return Err(WalletErr::from("access list must be a list"));
+            }
+            Tx {
+                chain_id: to_u64(field(0)?)?,
+                nonce: field(1)?,
+                max_priority_fee: Some(field(2)?),
+                max_fee: field(3)?,
+                gas_limit: field(4)?,
+                to: Some(field(5)?),
+                value: field(6)?,
+                data: field(7)?,
+            }
+        } else {
+            // Pre EIP-155 transactions could be replayed on any chain
+            if items.len() != 9 || !field(7)?.is_empty() || !field(8)?.is_empty() {
+                return Err(WalletErr::from("legacy transaction must use EIP-155"));
+            }
+            Tx {
+                chain_id: to_u64(field(6)?)?,
+                nonce: field(0)?,
+                max_priority_fee: None,
+                max_fee: field(1)?,
+                gas_limit: field(2)?,
+                to: Some(field(3)?),
+                value: field(4)?,
+                data: field(5)?,
+            }
+        };
+
+        match tx.to.map(|to| to.len()) {
+            Some(0) => Ok(Tx { to: None, ..tx }),
+            Some(ADDR_SIZE) => Ok(tx),
+            _ => Err(WalletErr::from("invalid recipient address")),
+        }
+    }
+
+    fn native_symbol(&self) -> &'static str {
+        CHAINS
+            .iter()
+            .find(|c| c.0 == self.chain_id)
+            .map_or("ETH", |c| c.2)
+    }
+
+    fn chain(&self) -> FieldValue {
+        match CHAINS.iter().find(|c| c.0 == self.chain_id) {
+            Some(c) => FieldValue::from(c.1),
+            None => decimal(self.chain_id),
+        }
+    }
+
+    /// Whether `data` is a contract call or creation the device can't decode
+    pub fn is_blind(&self) -> bool {
+        !self.data.is_empty() && self.erc20_call().is_none()
+    }
+
+    /// The token contract call in `data` we know how to show, if any:
+    /// (selector, address argument, amount argument)
+    fn erc20_call(&self) -> Option<([u8; 4], &'a [u8], &'a [u8])> {
+        if self.data.len() != 4 + 2 * 32 || self.to.is_none() {
+            return None;
+        }
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&self.data[..4]);
+        // The address is the low 20 bytes of the first 32 byte word
+        if (selector == TRANSFER || selector == APPROVE)
+            && strip_zeros(&self.data[4..16]).is_empty()
+        {
+            Some((selector, &self.data[16..36], &self.data[36..68]))
+        } else {
+            None
+        }
+    }
+
+    /// What the user confirms, in display order
+    pub fn summary(&self, tokens: &TokenRegistry) -> Result<Fields> {
+        let mut fields = Fields::new();
+        let mut push = |label, value| {
+            fields
+                .push(Field { label, value })
+                .map_err(|_| WalletErr::from("too many fields"))
+        };
+
+        let address = |a: &[u8]| {
+            let mut addr = [0u8; ADDR_SIZE];
+            addr.copy_from_slice(a);
+            FieldValue::from(checksum_address(&addr).as_str())
+        };
+
+        match (self.erc20_call(), self.to) {
+            (Some((selector, arg, amount)), Some(contract)) => {
+                let token = tokens.find(self.chain_id, contract);
+                let amount = match token {
+                    _ if selector == APPROVE && amount.iter().all(|b| *b == 0xFF) => {
+                        FieldValue::from("UNLIMITED")
+                    }
+                    Some(t) => format_units(amount, t.decimals, t.symbol),
+                    None => format_units(amount, 0, "units"),
+                };
+                if selector == TRANSFER {
+                    push("Send", amount)?;
+                    push("To", address(arg))?;
+                } else {
+                    push("Approve", amount)?;
+                    push("Spender", address(arg))?;
+                }
+                if token.is_none() {
+                    push("Unknown token", address(contract))?;
+                } else if DEV_SIGNED && !is_builtin(self.chain_id, contract) {
+                    push("Token info", FieldValue::from("dev-signed"))?;
+                }
+                if !strip_zeros(self.value).is_empty() {
+                    push("Value", format_units(self.value, 18, self.native_symbol()))?;
+                }
+            }
+            (_, to) => {
+                push("Value", format_units(self.value, 18, self.native_symbol()))?;
+                match to {
+                    Some(to) => push("To", address(to))?,
+                    None => push("To", FieldValue::from("new contract"))?,
+                }
+                if !self.data.is_empty() {
+                    let mut data = FieldValue::new();
+                    let _ = data.push_str(&decimal(self.data.len() as u64));
+                    let _ = data.push_str(" bytes, ");
+                    let _ = data.push_str(&display::hex_str::<U10>(
+                        &self.data[..4.min(self.data.len())],
+                    ));
+                    push("Data", data)?;
+                }
+            }
+        }
+
+        push("Chain", self.chain())?;
+        if let Some(tip) = self.max_priority_fee {
+            push("Max fee", format_units(self.max_fee, 9, "gwei"))?;
+            push("Priority fee", format_units(tip, 9, "gwei"))?;
+        } else {
+            push("Gas price", format_units(self.max_fee, 9, "gwei"))?;
+        }
+        push("Gas limit", decimal(to_u64(self.gas_limit)?))?;
+        push("Nonce", decimal(to_u64(self.nonce)?))?;
+        Ok(fields)
+    }
+}
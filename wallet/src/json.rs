@@ -0,0 +1,142 @@
+//! Just enough JSON for amino sign docs. Those are canonical: no whitespace
+//! and the keys of every object sorted, which is enforced so that a document
+//! can't be read two ways, say with a key repeated.
+use crate::{error::WalletErr, Result};
+
+fn malformed() -> WalletErr {
+    WalletErr::from("malformed JSON")
+}
+
+/// End of the string starting at `pos`
+fn string_end(s: &[u8], pos: usize) -> Result<usize> {
+    let mut i = pos + 1;
+    loop {
+        match s.get(i).ok_or_else(malformed)? {
+            b'"' => return Ok(i + 1),
+            b'\\' => i += 2,
+            c if *c < 0x20 => return Err(malformed()),
+            _ => i += 1,
+        }
+    }
+}
+
+/// End of the value starting at `pos`, checking it on the way
+fn value_end(s: &[u8], pos: usize, depth: usize) -> Result<usize> {
+    if depth > 16 {
+        return Err(WalletErr::from("JSON nested too deeply"));
+    }
+    match s.get(pos).ok_or_else(malformed)? {
+        b'"' => string_end(s, pos),
+        b'{' => {
+            let mut i = pos + 1;
+            let mut last: Option<&[u8]> = None;
+            if s.get(i) == Some(&b'}') {
+                return Ok(i + 1);
+            }
+            loop {
+                if s.get(i) != Some(&b'"') {
+                    return Err(malformed());
+                }
+                let key_end = string_end(s, i)?;
+                let key = &s[i..key_end];
+                if last.map_or(false, |last| last >= key) {
+                    return Err(WalletErr::from("JSON keys not sorted"));
+                }
+                last = Some(key);
+                if s.get(key_end) != Some(&b':') {
+                    return Err(malformed());
+                }
+                i = value_end(s, key_end + 1, depth + 1)?;
+                match s.get(i) {
+                    Some(b',') => i += 1,
+                    Some(b'}') => return Ok(i + 1),
+                    _ => return Err(malformed()),
+                }
+            }
+        }
+        b'[' => {
+            let mut i = pos + 1;
+            if s.get(i) == Some(&b']') {
+                return Ok(i + 1);
+            }
+            loop {
+                i = value_end(s, i, depth + 1)?;
+                match s.get(i) {
+                    Some(b',') => i += 1,
+                    Some(b']') => return Ok(i + 1),
+                    _ => return Err(malformed()),
+                }
+            }
+        }
+        b'-' | b'0'..=b'9' => {
+            let len = s[pos + 1..]
+                .iter()
+                .position(|c| !matches!(c, b'0'..=b'9' | b'.' | b'e' | b'E' | b'+' | b'-'))
+                .unwrap_or(s.len() - pos - 1);
+            Ok(pos + 1 + len)
+        }
+        _ => ["true", "false", "null"]
+            .iter()
+            .find(|lit| s[pos..].starts_with(lit.as_bytes()))
+            .map(|lit| pos + lit.len())
+            .ok_or_else(malformed),
+    }
+}
+
+/// Checks that `doc` is a single canonical JSON value
+pub fn validate(doc: &[u8]) -> Result<&str> {
+    let doc = core::str::from_utf8(doc).map_err(|_| malformed())?;
+    if value_end(doc.as_bytes(), 0, 0)? != doc.len() {
+        return Err(malformed());
+    }
+    Ok(doc)
+}
+
+/// The values of an array, or the keys and values of an object, of a
+/// validated document
+fn elements(container: &str) -> impl Iterator<Item = (&str, &str)> {
+    let s = container.as_bytes();
+    let mut i = 1;
+    core::iter::from_fn(move || {
+        if i >= s.len() || (i == 1 && s.len() == 2) {
+            return None;
+        }
+        let mut key = "";
+        if s[0] == b'{' {
+            let key_end = string_end(s, i).ok()?;
+            key = &container[i + 1..key_end - 1];
+            i = key_end + 1;
+        }
+        let end = value_end(s, i, 0).ok()?;
+        let value = &container[i..end];
+        i = end + 1;
+        Some((key, value))
+    })
+}
+
+/// The value of `key` in `object`
+pub fn get<'a>(object: &'a str, key: &str) -> Result<&'a str> {
+    if !object.starts_with('{') {
+        return Err(malformed());
+    }
+    elements(object)
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+        .ok_or_else(|| WalletErr::from("missing JSON field"))
+}
+
+pub fn items(array: &str) -> Result<impl Iterator<Item = &str>> {
+    if !array.starts_with('[') {
+        return Err(malformed());
+    }
+    Ok(elements(array).map(|(_, v)| v))
+}
+
+/// The contents of a string value, escapes left as they are
+pub fn string(value: &str) -> Result<&str> {
+    if value.len() >= 2 && value.starts_with('"') {
+        Ok(&value[1..value.len() - 1])
+    } else {
+        Err(malformed())
+    }
+}
@@ -0,0 +1,325 @@
+//! A log-structured key/value store for state that changes, unlike the seed
+//! which is programmed once. Values are appended to the active sector as
+//! CRC-checked records and the newest record for a key wins. When the active
+//! sector is full, its live records are compacted into the next sector,
+//! which then becomes the active one, so wear spreads over all of them.
+//!
+//! A sector starts with its generation followed by a magic number, written
+//! only once compaction into it is complete. The active sector is the one
+//! with the newest generation, so losing power while compacting leaves the
+//! previous sector active. Losing it while appending leaves a record whose
+//! CRC doesn't match, which is ignored.
+use crate::{error::WalletErr, layout::Sector, Result};
+use core::convert::TryInto;
+use heapless::{consts::*, FnvIndexMap, Vec};
+
+/// Keys of the values in the store
+pub mod keys {
+    /// Account labels, one key per account from this one on
+    pub const ACCOUNT_LABELS: u16 = 0x0100;
+    pub const SETTINGS: u16 = 0x0200;
+}
+
+/// What the store needs of the flash it is kept in. Erased bytes read
+/// 0xFF and programming only clears bits.
+pub trait Flash {
+    /// Fills `buf` from `offset` bytes into flash
+    fn read(&self, offset: u32, buf: &mut [u8]);
+    fn program(&mut self, offset: u32, bytes: &[u8]) -> Result<()>;
+    fn erase_sector(&mut self, sector: &Sector) -> Result<()>;
+}
+
+/// "NVKV"
+const MAGIC: u32 = 0x564B_564E;
+const SECTOR_HEADER: u32 = 8; // generation, magic
+const RECORD_HEADER: u32 = 8; // key, length, CRC
+const ERASED_KEY: u16 = 0xFFFF;
+const ERASED_LEN: u16 = 0xFFFF;
+/// The length of a record that deletes its key
+const DELETED: u16 = 0xFFFE;
+pub const MAX_VALUE: usize = 1024;
+
+/// A value read from the store
+pub type Value = Vec<u8, U1024>;
+/// Most keys a compaction can carry over
+type Live = FnvIndexMap<u16, (u32, u16), U64>;
+
+/// CRC-32 as used by zlib and Ethernet
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for b in parts.iter().flat_map(|p| p.iter()) {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Records start on 4 byte boundaries
+fn padded(len: u32) -> u32 {
+    (len + 3) & !3
+}
+
+/// Whether `a` is a later generation than `b`, allowing for wrap around
+fn newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+struct Record {
+    key: u16,
+    /// `None` if the key was deleted
+    value: Option<Value>,
+}
+
+/// The records of a sector in the order they were written, with their
+/// offsets. A record whose CRC doesn't match comes out as `None`.
+struct Records<'a, F> {
+    store: &'a Store<F>,
+    pos: u32,
+    end: u32,
+    /// Whether the log ends in a record too damaged to skip
+    torn: bool,
+}
+
+impl<F> Records<'_, F> {
+    /// Where the next record goes, once all were read. `None` if the log
+    /// is torn and needs compacting first.
+    fn free(&self) -> Option<u32> {
+        if self.torn {
+            None
+        } else {
+            Some(self.pos)
+        }
+    }
+}
+
+impl<F: Flash> Iterator for Records<'_, F> {
+    type Item = (u32, Option<Record>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.torn || self.pos + RECORD_HEADER > self.end {
+            return None;
+        }
+        let mut header = [0u8; RECORD_HEADER as usize];
+        self.store.flash.read(self.pos, &mut header);
+        let key = u16::from_le_bytes([header[0], header[1]]);
+        let len = u16::from_le_bytes([header[2], header[3]]);
+        if header.iter().all(|b| *b == 0xFF) {
+            return None;
+        }
+        let data_len = if len == DELETED { 0 } else { len as u32 };
+        if key == ERASED_KEY
+            || len == ERASED_LEN
+            || data_len as usize > MAX_VALUE
+            || self.pos + RECORD_HEADER + data_len > self.end
+        {
+            self.torn = true;
+            return None;
+        }
+
+        let at = self.pos;
+        let data = self.store.read(at + RECORD_HEADER, data_len);
+        self.pos += padded(RECORD_HEADER + data_len);
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if crc != crc32(&[&header[..4], &data]) {
+            return Some((at, None));
+        }
+        let value = if len == DELETED { None } else { Some(data) };
+        Some((at, Some(Record { key, value })))
+    }
+}
+
+/// The store, kept in `sectors` of `flash`
+pub struct Store<F> {
+    flash: F,
+    sectors: [Sector; 2],
+}
+
+impl<F> Store<F> {
+    pub const fn new(flash: F, sectors: [Sector; 2]) -> Self {
+        Store { flash, sectors }
+    }
+
+    pub fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    pub fn flash_mut(&mut self) -> &mut F {
+        &mut self.flash
+    }
+}
+
+impl<F: Flash> Store<F> {
+    /// `len` bytes from `offset` bytes into flash, at most `MAX_VALUE`
+    fn read(&self, offset: u32, len: u32) -> Value {
+        let mut out = Value::new();
+        let _ = out.resize_default(len as usize);
+        self.flash.read(offset, &mut out);
+        out
+    }
+
+    /// Whether the `len` bytes from `offset` are all erased
+    fn is_erased(&self, offset: u32, len: u32) -> bool {
+        let mut chunk = [0u8; 32];
+        (offset..offset + len).step_by(chunk.len()).all(|at| {
+            let n = (offset + len - at).min(chunk.len() as u32) as usize;
+            self.flash.read(at, &mut chunk[..n]);
+            chunk[..n].iter().all(|b| *b == 0xFF)
+        })
+    }
+
+    fn records(&self, sector: usize) -> Records<'_, F> {
+        let sector = &self.sectors[sector];
+        Records {
+            store: self,
+            pos: sector.offset + SECTOR_HEADER,
+            end: sector.offset + sector.size,
+            torn: false,
+        }
+    }
+
+    fn generation(&self, sector: usize) -> Option<u32> {
+        let mut header = [0u8; SECTOR_HEADER as usize];
+        self.flash.read(self.sectors[sector].offset, &mut header);
+        // The magic is programmed after the generation, so a sector with the
+        // magic has all of its generation
+        if u32::from_le_bytes(header[4..].try_into().ok()?) == MAGIC {
+            Some(u32::from_le_bytes(header[..4].try_into().ok()?))
+        } else {
+            None
+        }
+    }
+
+    /// The index into `sectors` and generation of the active sector
+    fn active(&self) -> Option<(usize, u32)> {
+        (0..self.sectors.len())
+            .filter_map(|i| Some((i, self.generation(i)?)))
+            .fold(None, |newest, (i, gen)| match newest {
+                Some((_, g)) if !newer(gen, g) => newest,
+                _ => Some((i, gen)),
+            })
+    }
+
+    /// Programs the record for `key` at `pos`, returning where the next one
+    /// goes
+    fn append(&mut self, pos: u32, end: u32, key: u16, value: Option<&[u8]>) -> Result<u32> {
+        let data = value.unwrap_or(&[]);
+        let next = pos + padded(RECORD_HEADER + data.len() as u32);
+        if next > end {
+            return Err(WalletErr::from("storage full"));
+        }
+        let len = value.map_or(DELETED, |v| v.len() as u16);
+        let mut header = [0u8; RECORD_HEADER as usize];
+        header[..2].copy_from_slice(&key.to_le_bytes());
+        header[2..4].copy_from_slice(&len.to_le_bytes());
+        let crc = crc32(&[&header[..4], data]);
+        header[4..].copy_from_slice(&crc.to_le_bytes());
+        self.flash.program(pos, &header)?;
+        if !data.is_empty() {
+            self.flash.program(pos + RECORD_HEADER, data)?;
+        }
+        Ok(next)
+    }
+
+    /// Writes the live records of the active sector, if there is one, and
+    /// the new record for `key` to the next sector, then makes that sector
+    /// active
+    fn compact(
+        &mut self,
+        from: Option<(usize, u32)>,
+        key: u16,
+        value: Option<&[u8]>,
+    ) -> Result<()> {
+        // Where the newest record of each key other than `key` is, and its
+        // length, in one pass over the log
+        let mut live = Live::new();
+        if let Some((from, _)) = from {
+            for (at, record) in self.records(from) {
+                match record {
+                    Some(Record { key: k, value: v }) if k != key => match v {
+                        Some(v) => {
+                            live.insert(k, (at, v.len() as u16))
+                                .map_err(|_| WalletErr::from("too many keys in storage"))?;
+                        }
+                        None => {
+                            live.remove(&k);
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        let (to, generation) = match from {
+            Some((i, gen)) => ((i + 1) % self.sectors.len(), gen.wrapping_add(1)),
+            None => (0, 0),
+        };
+        let sector = self.sectors[to];
+        let (offset, end) = (sector.offset, sector.offset + sector.size);
+        self.flash.erase_sector(&sector)?;
+
+        let mut pos = offset + SECTOR_HEADER;
+        for (k, (at, len)) in live.iter() {
+            let v = self.read(at + RECORD_HEADER, *len as u32);
+            pos = self.append(pos, end, *k, Some(&v))?;
+        }
+        if value.is_some() {
+            self.append(pos, end, key, value)?;
+        }
+
+        let mut header = [0u8; SECTOR_HEADER as usize];
+        header[..4].copy_from_slice(&generation.to_le_bytes());
+        header[4..].copy_from_slice(&MAGIC.to_le_bytes());
+        self.flash.program(offset, &header)
+    }
+
+    fn write(&mut self, key: u16, value: Option<&[u8]>) -> Result<()> {
+        if key == ERASED_KEY {
+            return Err(WalletErr::from("invalid storage key"));
+        }
+        if value.map_or(false, |v| v.len() > MAX_VALUE) {
+            return Err(WalletErr::from("value too long to store"));
+        }
+        let active = self.active();
+        if let Some((i, _)) = active {
+            let mut log = self.records(i);
+            let current = (&mut log)
+                .filter_map(|(_, r)| r)
+                .filter(|r| r.key == key)
+                .last();
+            if current.map_or(value.is_none(), |r| r.value.as_deref() == value) {
+                // Spare the flash, nothing changes
+                return Ok(());
+            }
+            let free = log.free();
+            if let Some(pos) = free {
+                let len = padded(RECORD_HEADER + value.map_or(0, |v| v.len() as u32));
+                let end = self.sectors[i].offset + self.sectors[i].size;
+                // Whatever a lost write left behind can't be programmed over
+                if pos + len <= end && self.is_erased(pos, len) {
+                    return self.append(pos, end, key, value).map(|_| ());
+                }
+            }
+        }
+        self.compact(active, key, value)
+    }
+
+    /// The newest value stored for `key`
+    pub fn get(&self, key: u16) -> Option<Value> {
+        let (i, _) = self.active()?;
+        self.records(i)
+            .filter_map(|(_, r)| r)
+            .filter(|r| r.key == key)
+            .last()
+            .and_then(|r| r.value)
+    }
+
+    pub fn set(&mut self, key: u16, value: &[u8]) -> Result<()> {
+        self.write(key, Some(value))
+    }
+
+    pub fn remove(&mut self, key: u16) -> Result<()> {
+        self.write(key, None)
+    }
+}
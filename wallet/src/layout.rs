@@ -0,0 +1,97 @@
+//! Where everything lives in flash. The STM32F4's flash is split into four
+//! 16K sectors, one of 64K and then 128K ones, and a sector can only be
+//! erased whole, so the firmware and each kind of storage get sectors of
+//! their own:
+//!
+//! - sector 0: the vector table
+//! - sectors 1 and 2: the key/value store
+//! - sector 3: the serial, seed and multisig records, and a magic number
+//!   saying the sector is in this layout
+//! - sector 4 to the end: the rest of the firmware
+//!
+//! Storage takes the small sectors at the start, leaving the large ones at
+//! the end to the code. build.rs writes memory.x from this module, so the
+//! linker starts the code after storage and fails the build if it doesn't
+//! fit. The part is picked with a cargo feature, the STM32F401xC by default.
+
+#[cfg(all(feature = "stm32f401xc", feature = "stm32f401xe"))]
+compile_error!("select a single part, build with --no-default-features for the STM32F401xE");
+
+pub const FLASH_START: u32 = 0x0800_0000;
+pub const RAM_START: u32 = 0x2000_0000;
+
+#[cfg(not(feature = "stm32f401xe"))]
+pub const FLASH_SIZE: u32 = 256 * 1024;
+#[cfg(not(feature = "stm32f401xe"))]
+pub const RAM_SIZE: u32 = 64 * 1024;
+
+#[cfg(feature = "stm32f401xe")]
+pub const FLASH_SIZE: u32 = 512 * 1024;
+#[cfg(feature = "stm32f401xe")]
+pub const RAM_SIZE: u32 = 96 * 1024;
+
+/// A flash sector, `offset` bytes into flash
+#[derive(Clone, Copy)]
+pub struct Sector {
+    pub number: u8,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Sector `number` of the STM32F4's flash
+pub const fn sector(number: u8) -> Sector {
+    let n = number as u32;
+    let (offset, size) = if n < 4 {
+        (n * 16 * 1024, 16 * 1024)
+    } else if n == 4 {
+        (64 * 1024, 64 * 1024)
+    } else {
+        ((n - 4) * 128 * 1024, 128 * 1024)
+    };
+    Sector {
+        number,
+        offset,
+        size,
+    }
+}
+
+pub const KV_SECTORS: [Sector; 2] = [sector(1), sector(2)];
+pub const STORAGE_SECTOR: Sector = sector(3);
+/// Where the code after the vector table starts, `_stext` in memory.x
+pub const CODE_START: u32 = sector(4).offset;
+
+/// "NVST", programmed at the start of the storage sector once it is in
+/// this layout, see migrate.rs
+pub const STORAGE_MAGIC: u32 = 0x5453_564E;
+pub const STORAGE_MAGIC_ADDR: u32 = STORAGE_SECTOR.offset;
+
+// The records at the end of the storage sector
+pub const STORAGE_START: u32 = STORAGE_SECTOR.offset + STORAGE_SECTOR.size - 1024;
+pub const SERIAL_ADDR: u32 = STORAGE_START;
+pub const SEED_ADDR: u32 = STORAGE_START + 0xA;
+// The encrypted seed is at most 512 bytes of ciphertext + 8 byte tag + 2 byte size
+// Registered multisig descriptors take the 3K below
+pub const MULTISIG_ADDR: u32 = STORAGE_START - 3 * 1024;
+pub const MULTISIG_SIZE: u32 = STORAGE_START - MULTISIG_ADDR;
+/// Programmed once the factory seed has been installed and kept through
+/// wipes, so it is never installed again
+pub const FACTORY_SEED_USED_ADDR: u32 = STORAGE_SECTOR.offset + STORAGE_SECTOR.size - 4;
+
+const _: () = assert!(
+    KV_SECTORS[0].offset >= sector(1).offset
+        && KV_SECTORS[1].offset + KV_SECTORS[1].size <= STORAGE_SECTOR.offset,
+    "the key/value store must sit between the vector table and the records"
+);
+const _: () = assert!(
+    STORAGE_SECTOR.offset + STORAGE_SECTOR.size <= CODE_START,
+    "the code overlaps the storage sector"
+);
+const _: () = assert!(
+    MULTISIG_ADDR >= STORAGE_MAGIC_ADDR + 4,
+    "the multisig records must be in the storage sector, after its magic"
+);
+const _: () = assert!(
+    SEED_ADDR + 2 + 512 + 8 <= FACTORY_SEED_USED_ADDR,
+    "the seed record overlaps the factory seed flag"
+);
+const _: () = assert!(CODE_START < FLASH_SIZE, "no room left for the code");
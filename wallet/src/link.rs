@@ -0,0 +1,105 @@
+//! Requests and responses over the serial link to the host, each in a frame
+//! (see `protocol::frame_header`) as neither side gets a message in one read.
+//! Nothing here depends on the USB stack, see usb.rs for that.
+use crate::{error::WalletErr, Result};
+
+use protocol::{FRAME_HEADER_LEN, MAX_FRAME_LEN};
+
+/// Bytes to and from the host, in pieces of whatever size the transport
+/// manages at the time
+pub trait Serial {
+    /// Services the transport, true if there may be bytes to read
+    fn poll(&mut self) -> bool;
+    /// Reads what arrived so far, `Ok(0)` if nothing did
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+    /// Writes what fits of `data` for now, `Ok(0)` if nothing does
+    fn write(&mut self, data: &[u8]) -> Result<usize>;
+}
+
+/// Polls in a row without room to write before the host is taken to have
+/// stopped reading
+const SEND_STALL_POLLS: u32 = 100_000;
+
+/// Gathers the frame of a request as its packets arrive. The host sends one
+/// request at a time and waits for the response.
+pub struct Receiver {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    /// Whether `buf` holds a frame already handed out
+    complete: bool,
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Receiver {
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            complete: false,
+        }
+    }
+
+    /// Reads what arrived from `serial`, returning the message once its
+    /// frame is complete
+    pub fn receive<S: Serial>(&mut self, serial: &mut S) -> Result<Option<&[u8]>> {
+        if self.complete {
+            self.reset();
+        }
+        self.len += serial.read(&mut self.buf[self.len..])?;
+        match protocol::frame_message(&self.buf[..self.len]).map(|msg| msg.map(|m| m.len())) {
+            Ok(Some(len)) => {
+                self.complete = true;
+                Ok(Some(&self.buf[FRAME_HEADER_LEN..FRAME_HEADER_LEN + len]))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.reset();
+                Err(WalletErr::from(e))
+            }
+        }
+    }
+
+    /// Whether part of a frame arrived and the rest is still awaited
+    pub fn is_partial(&self) -> bool {
+        !self.complete && self.len > 0
+    }
+
+    /// Drops what was received, for a frame the host gave up on half way
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.complete = false;
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sends `msg` in a frame, all of it or an error
+pub fn send<S: Serial>(serial: &mut S, msg: &[u8]) -> Result<()> {
+    let header =
+        protocol::frame_header(msg.len()).ok_or_else(|| WalletErr::from("response too long"))?;
+    write_all(serial, &header)?;
+    write_all(serial, msg)
+}
+
+fn write_all<S: Serial>(serial: &mut S, mut data: &[u8]) -> Result<()> {
+    let mut stalled = 0;
+    while !data.is_empty() {
+        match serial.write(data)? {
+            0 => {
+                stalled += 1;
+                if stalled > SEND_STALL_POLLS {
+                    return Err(WalletErr::from("host stopped reading"));
+                }
+                serial.poll();
+            }
+            n => {
+                stalled = 0;
+                data = &data[n..];
+            }
+        }
+    }
+    Ok(())
+}
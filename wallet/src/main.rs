@@ -1,46 +1,83 @@
 #![no_main]
 #![no_std]
 
+mod accounts;
+mod antiexfil;
+mod base58;
+mod bech32;
+mod bip32;
+mod bip85;
+mod btc;
+mod cosmos;
+mod display;
+mod ecies;
+mod entropy;
+mod entry;
 pub mod error;
+mod eth;
+mod json;
+mod kvstore;
+mod layout;
+mod link;
+mod message;
+mod migrate;
+mod multisig;
+mod nostr;
+mod oled;
+mod presence;
+mod psbt;
+mod qr;
 mod safemem;
+mod schnorr;
+mod settings;
+mod slip10;
+mod slip39;
+mod solana;
+mod ui;
+mod usb;
 
 use core::convert::TryInto;
 use error::{ErrStringType, WalletErr};
+use eth::ADDR_SIZE;
+use layout::{
+    FACTORY_SEED_USED_ADDR, FLASH_START, KV_SECTORS, SEED_ADDR, SERIAL_ADDR, STORAGE_SECTOR,
+    STORAGE_START,
+};
+use safemem::SeedBytes;
 
 use bip39::{Language, Mnemonic, Seed};
 use hex_literal::hex;
-// use numtoa::NumToA;
+use numtoa::NumToA;
 
 use panic_halt as _; // panic handler
 use stm32f4xx_hal as hal;
 
 use hal::flash::FlashExt;
 
-use k256::{
-    ecdsa::{
-        recoverable,
-        signature::{Signature, Signer},
-        SigningKey, VerifyingKey,
-    },
-    elliptic_curve::sec1::ToEncodedPoint,
+use k256::ecdsa::{
+    recoverable,
+    signature::{Signature, Signer},
+    SigningKey, VerifyingKey,
 };
 
 use cortex_m_rt::entry;
+use stm32f4xx_hal::delay::Delay;
+use stm32f4xx_hal::i2c::I2c;
 use stm32f4xx_hal::otg_fs::{UsbBus, USB};
 use stm32f4xx_hal::{prelude::*, stm32};
-use usb_device::{
-    class_prelude,
-    device::{UsbDeviceBuilder, UsbVidPid},
-    UsbError,
-};
+use usb_device::device::{UsbDeviceBuilder, UsbVidPid};
 
-// use embedded_graphics::{image::Image, image::ImageRaw, pixelcolor::BinaryColor, prelude::*};
-// use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use display::Screen;
+use link::Serial;
+use presence::{Buttons, Decision, UserPresence};
+use ssd1306::{prelude::*, Builder, I2CDIBuilder};
+use ui::Ui;
+use usb::Usb;
 use usbd_serial::{SerialPort, USB_CLASS_CDC};
 
-use heapless::{consts::*, ArrayLength, Vec};
+use heapless::{consts::*, ArrayLength, String, Vec};
 use postcard::{from_bytes, to_vec};
-use protocol::{Request, Response};
+use protocol::{AntiExfilPayload, QrContent, Request, Response, SeedEntropy, Settings};
 use tiny_hderive::{bip32::ExtendedPrivKey, bip44::ChildNumber};
 use tiny_keccak::{Hasher, Keccak};
 
@@ -49,20 +86,95 @@ use aes_ccm::{
     Aes256Ccm,
 };
 
-const FLASH_START: u32 = 0x0800_0000;
-const FLASH_SIZE: u32 = 256 * 1024;
-const STORAGE_START: u32 = FLASH_SIZE - 1024;
-const SERIAL_ADDR: u32 = STORAGE_START;
-const SEED_ADDR: u32 = STORAGE_START + 0xA;
-
 type Result<T> = core::result::Result<T, WalletErr>;
 
 struct Context {
-    seed: Seed,
+    /// None until a seed is created or restored
+    seed: Option<SeedBytes>,
+    pub account: u32,
     pub idx: u32,
+    pub confirm_timeout_ms: u32,
+    /// Whether sending public keys and addresses needs the user's approval
+    pub confirm_exports: bool,
+    /// Idle time before the device locks, 0 for never
+    pub auto_lock_ms: u32,
+    pub blind_signing: bool,
+    /// Set after `auto_lock_ms` idle, until the user approves on the device
+    pub locked: bool,
+    pub tokens: eth::TokenRegistry,
+    /// The PSBT being received or, once signed, sent back
+    pub psbt: psbt::Buffer,
+    /// Whether `psbt` is signed, `PsbtChunk` sends nothing else
+    pub psbt_signed: bool,
+    /// SLIP-39 shares entered so far towards restoring a seed
+    pub slip39: slip39::Recovery,
+    /// SLIP-39 shares entered so far towards checking a backup
+    pub slip39_check: slip39::Recovery,
+    /// Auxiliary randomness for Schnorr signatures
+    pub aux: entropy::AuxRand,
+    /// The device's entropy for a new seed, once committed to
+    pub seed_commit: Option<entropy::Commit>,
+    /// A mixed seed waiting for the host to keep it or open its entropy
+    pub new_seed: Option<entropy::NewSeed>,
+    /// The anti-exfil signature waiting for the host's nonce data
+    pub anti_exfil: Option<antiexfil::Pending>,
 }
 
 impl Context {
+    fn new(seed: Option<SeedBytes>) -> Self {
+        let mut ctx = Context {
+            seed,
+            account: 0,
+            idx: 0,
+            confirm_timeout_ms: 0,
+            confirm_exports: false,
+            auto_lock_ms: 0,
+            blind_signing: false,
+            locked: false,
+            tokens: eth::TokenRegistry::new(),
+            psbt: psbt::Buffer::new(),
+            psbt_signed: false,
+            slip39: slip39::Recovery::new(),
+            slip39_check: slip39::Recovery::new(),
+            aux: entropy::AuxRand::new(),
+            seed_commit: None,
+            new_seed: None,
+            anti_exfil: None,
+        };
+        ctx.apply(&settings::DEFAULT);
+        ctx
+    }
+
+    /// Takes on the behaviour `settings` asks for
+    pub fn apply(&mut self, settings: &Settings) {
+        self.confirm_timeout_ms = settings.confirm_timeout_s as u32 * 1000;
+        self.confirm_exports = settings.confirm_exports;
+        self.auto_lock_ms = settings.auto_lock_min as u32 * 60 * 1000;
+        self.blind_signing = settings.blind_signing;
+    }
+
+    /// Refuses to sign what the user can't read, unless the settings allow it
+    pub fn check_blind(&self, blind: bool) -> Result<()> {
+        if blind && !self.blind_signing {
+            Err(WalletErr::from("blind signing is disabled in the settings"))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn seed(&self) -> Result<&[u8]> {
+        match &self.seed {
+            Some(seed) => Ok(&seed.0),
+            None => Err(WalletErr::from("no seed, create or restore one first")),
+        }
+    }
+
+    pub fn set_account(&mut self, account: u32) -> Result<&mut Self> {
+        accounts::check(&kv(), account)?;
+        self.account = account;
+        Ok(self)
+    }
+
     pub fn set_idx(&mut self, idx: u32) -> &mut Self {
         self.idx = idx;
         self
@@ -72,6 +184,9 @@ impl Context {
 // A specifically sized buffer for the USB driver
 static mut EP_MEMORY: [u32; 1024] = [0; 1024];
 
+/// How long the rest of a request's frame may take to arrive
+const FRAME_TIMEOUT_MS: u32 = 1000;
+
 const MNEMONIC: &str = "panda eyebrow bullet gorilla call smoke muffin taste mesh discover soft ostrich alcohol speed nation flash devote level hobby quick inner drive ghost inside";
 const AES_KEY: &[u8] = &hex!("C0 C1 C2 C3 C4 C5 C6 C7 C8 C9 CA CB CC CD CE CF");
 const NONCE: &[u8] = &hex!("00 00 00 03 02 01 00 A0 A1 A2 A3 A4 A5");
@@ -81,6 +196,7 @@ const NONCE: &[u8] = &hex!("00 00 00 03 02 01 00 A0 A1 A2 A3 A4 A5");
 fn main() -> ! {
     // This unwrap is safe because we're the first/only to take() it
     let dp = stm32::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
     let rcc = dp.RCC.constrain();
 
     let clocks = rcc
@@ -100,24 +216,30 @@ fn main() -> ! {
 
     // Do I2C related things
     // For I2C1, SCL=PB6, SDA=PB7, AF04
-    // let gpiob = dp.GPIOB.split();
-    // let scl = gpiob.pb6.into_alternate_af4_open_drain();
-    // let sda = gpiob.pb7.into_alternate_af4_open_drain();
-    // let i2c = I2c::i2c1(dp.I2C1, (scl, sda), 400.khz(), clocks);
-
-    // let i2c_interface = I2CDIBuilder::new().init(i2c);
-    // let mut disp: GraphicsMode<_, _> = Builder::new().connect(i2c_interface).into();
-    // disp.init().unwrap();
-    // disp.flush().unwrap();
-
-    // // Display the rustacean
-    // let raw_image: ImageRaw<BinaryColor> =
-    //     ImageRaw::new(include_bytes!("../ssd1306-image.data"), 128, 64);
-    // let image: Image<_, BinaryColor> = Image::new(&raw_image, Point::zero());
-    // image.draw(&mut disp).unwrap();
-    // disp.flush().unwrap();
+    let gpiob = dp.GPIOB.split();
+    let scl = gpiob.pb6.into_alternate_af4_open_drain();
+    let sda = gpiob.pb7.into_alternate_af4_open_drain();
+    let i2c = I2c::i2c1(dp.I2C1, (scl, sda), 400.khz(), clocks);
+
+    let i2c_interface = I2CDIBuilder::new().init(i2c);
+    let mut disp: GraphicsMode<_, _> = Builder::new().connect(i2c_interface).into();
+    disp.init().unwrap();
 
+    // Display the rustacean while we boot
+    let _ = display::boot(&mut disp);
+
+    // Confirm on the board's KEY button (PA0), reject on PA1. Both active low.
     let gpioa = dp.GPIOA.split();
+    let buttons = Buttons::new(
+        gpioa.pa0.into_pull_up_input(),
+        gpioa.pa1.into_pull_up_input(),
+        Delay::new(cp.SYST, clocks),
+    );
+    let mut ui = Ui {
+        disp,
+        user: buttons,
+    };
+
     let usb = USB {
         usb_global: dp.OTG_FS_GLOBAL,
         usb_device: dp.OTG_FS_DEVICE,
@@ -127,77 +249,548 @@ fn main() -> ! {
         hclk: clocks.hclk(),
     };
     let usb_bus = UsbBus::new(usb, unsafe { &mut EP_MEMORY });
-    let mut serial = SerialPort::new(&usb_bus);
-    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0xDEAD, 0xBEEF))
+    let serial = SerialPort::new(&usb_bus);
+    let dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0xDEAD, 0xBEEF))
         .manufacturer("noviinc")
         .product("NoviSigner")
         .serial_number("123")
         .device_class(USB_CLASS_CDC)
         .build();
+    let mut usb = Usb { dev, serial };
+    let mut receiver = link::Receiver::new();
 
-    let mut ctx = initialize().map_err(|_| ()).unwrap();
+    // Unreadable storage leaves the device without a seed, but still
+    // answering requests so it can be wiped
+    let (seed, boot_err) = match initialize() {
+        Ok(seed) => (seed, None),
+        Err(e) => (None, Some(e)),
+    };
+    let mut ctx = Context::new(seed);
+    let mut buf = kvstore::Value::new();
+    let stored = settings::load(&kv(), &mut buf);
+    ctx.apply(&stored);
+    let _ = ui.disp.set_brightness(stored.brightness);
+    let _ = match boot_err {
+        Some(WalletErr::StringErr(msg)) => show_boot_error(&mut ui.disp, &msg),
+        Some(WalletErr::NoMsg) => show_boot_error(&mut ui.disp, "unknown error"),
+        None => show_ready(&mut ui.disp),
+    };
+    // The idle menu page being shown, if any
+    let mut page = None;
+    // Roughly, as polling the buttons takes a millisecond
+    let mut idle_ms: u32 = 0;
 
     loop {
-        if !usb_dev.poll(&mut [&mut serial]) {
+        if !usb.poll() {
+            match ui.user.poll() {
+                Some(Decision::Approved) if ctx.locked => {
+                    ctx.locked = false;
+                    idle_ms = 0;
+                    let _ = show_ready(&mut ui.disp);
+                }
+                Some(_) if ctx.locked => {}
+                Some(decision) => {
+                    idle_ms = 0;
+                    page = next_page(page, decision);
+                    let _ = match page {
+                        Some(content) => {
+                            show_qr(&ctx, &mut ui.disp, content, ctx.account, ctx.idx).map(|_| ())
+                        }
+                        None => show_ready(&mut ui.disp),
+                    };
+                }
+                None => {
+                    idle_ms = idle_ms.saturating_add(1);
+                    if receiver.is_partial() && idle_ms >= FRAME_TIMEOUT_MS {
+                        // The host gave up on the request half way
+                        receiver.reset();
+                    }
+                    if ctx.auto_lock_ms > 0 && idle_ms >= ctx.auto_lock_ms && !ctx.locked {
+                        ctx.locked = true;
+                        page = None;
+                        let _ = display::status(&mut ui.disp, "Locked, approve to unlock");
+                    }
+                }
+            }
             continue;
         }
+        idle_ms = 0;
 
-        // Initialize our receive buffer to zeros
-        let mut buf = [0u8; 2048];
-
-        let res = serial
-            .read(&mut buf[..])
-            .map_err(|e| match e {
-                UsbError::WouldBlock => WalletErr::NoMsg,
-                _ => WalletErr::StringErr(ErrStringType::from("failed to read usb")),
-            })
-            .and_then(|count| {
+        let res = receiver
+            .receive(&mut usb)
+            .and_then(|msg| match msg {
                 // Turn the LED on, we've started processing a msg
                 // let _ = led.set_low();
                 // Deserialize the data into a Request
-                Ok(from_bytes::<Request>(&buf[..count])?)
+                Some(msg) => Ok(from_bytes::<Request>(msg)?),
+                // The rest of the frame is still on its way
+                None => Err(WalletErr::NoMsg),
             })
             .and_then(|req| {
                 // We've successfully deserialized into a Request -- process it
-                answer_request(&req, &mut serial, &mut ctx)
+                answer_request(&req, &mut usb, &mut ctx, &mut ui)
             });
 
         // If we have an actual error, send it to the host
         if let Err(WalletErr::StringErr(msg)) = res {
-            respond_with_err(msg, &mut serial)
+            respond_with_err(msg, &mut usb)
         }
         // Turn the LED off, in case it was turned on while processing a message
         // let _ = led.set_high();
     }
 }
 
-fn initialize() -> Result<Context> {
-    if load_seed_plaintext_size()?.is_none() {
-        save_seed_phrase_encr(MNEMONIC)?;
-        erase_seed_phrase()?;
+/// The stored seed, if any, after installing the factory seed on first boot
+fn initialize() -> Result<Option<SeedBytes>> {
+    migrate::run()?;
+    // The factory seed is only installed once: after a wipe the device stays
+    // uninitialized until a seed is created or restored
+    if !is_factory_seed_used() {
+        if let Some(mnemonic) = factory_mnemonic() {
+            if load_seed_plaintext_size()?.is_none() {
+                save_seed_phrase_encr(mnemonic)?;
+            }
+            // Erased before it is marked used, or losing power in between
+            // would leave it in the image for good
+            erase_seed_phrase()?;
+        }
+        mark_factory_seed_used()?;
     }
-    let seed = load_seed()?;
-    let ctx = Context { seed, idx: 0 };
-    Ok(ctx)
+    match load_seed_plaintext_size()? {
+        Some(_) => Ok(Some(load_seed()?)),
+        None => Ok(None),
+    }
+}
+
+/// Shows why the seed couldn't be loaded at boot
+fn show_boot_error<D: Screen>(disp: &mut D, msg: &str) -> Result<()> {
+    let mut status: String<U128> = String::new();
+    let _ = status.push_str("Wipe to recover: ");
+    let _ = status.push_str(msg);
+    display::status(disp, &status)
 }
 
-fn answer_request<T>(r: &Request, s: &mut SerialPort<T>, ctx: &mut Context) -> Result<()>
+fn answer_request<T, D, U>(
+    r: &Request,
+    s: &mut T,
+    ctx: &mut Context,
+    ui: &mut Ui<D, U>,
+) -> Result<()>
 where
-    T: class_prelude::UsbBus,
+    T: Serial,
+    D: Screen,
+    U: UserPresence,
 {
+    if ctx.locked && !matches!(r, Request::Ping) {
+        display::status(&mut ui.disp, "Locked, approve to unlock")?;
+        ui.confirm(ctx.confirm_timeout_ms)?;
+        ctx.locked = false;
+    }
+    if ctx.confirm_exports && is_export(r) {
+        display::status(&mut ui.disp, "Send public key to host?")?;
+        ui.confirm(ctx.confirm_timeout_ms)?;
+    }
+
     match r {
         Request::Ping => transmit_response(Response::Pong, s),
         Request::Sig(msg) => {
+            ctx.check_blind(display::printable(msg).is_none())?;
+            display::sign_message(&mut ui.disp, msg)?;
+            ui.confirm(ctx.confirm_timeout_ms)?;
             let sig = sign_msg(&ctx, &msg)?;
             let sig_bytes = sig.as_bytes();
             transmit_response(Response::Sig(&sig_bytes), s)
         }
+        Request::SignTx(tx) => {
+            let parsed = eth::Tx::parse(tx)?;
+            ctx.check_blind(parsed.is_blind())?;
+            let summary = parsed.summary(&ctx.tokens)?;
+            ui.confirm_fields("Sign transaction?", &summary, ctx.confirm_timeout_ms)?;
+            let sig = sign_msg(&ctx, &tx)?;
+            transmit_response(Response::Sig(&sig.as_bytes()), s)
+        }
+        Request::AntiExfilCommit((payload, host_commitment)) => {
+            let data = match payload {
+                AntiExfilPayload::Message(msg) => {
+                    ctx.check_blind(display::printable(msg).is_none())?;
+                    display::sign_message(&mut ui.disp, msg)?;
+                    ui.confirm(ctx.confirm_timeout_ms)?;
+                    msg
+                }
+                AntiExfilPayload::Tx(tx) => {
+                    let parsed = eth::Tx::parse(tx)?;
+                    ctx.check_blind(parsed.is_blind())?;
+                    let summary = parsed.summary(&ctx.tokens)?;
+                    ui.confirm_fields("Sign transaction?", &summary, ctx.confirm_timeout_ms)?;
+                    tx
+                }
+            };
+            let mut secret = [0u8; 32];
+            secret.copy_from_slice(&secret_key(ctx)?.to_bytes());
+            let pending = antiexfil::commit(&secret, &keccak256(data), host_commitment);
+            safemem::wipe(&mut secret);
+            let pending = pending?;
+            let r0 = *pending.signer_commitment();
+            ctx.anti_exfil = Some(pending);
+            transmit_response(Response::SignerCommitment(&r0), s)
+        }
+        Request::AntiExfilSign(host_data) => {
+            let sig = ctx
+                .anti_exfil
+                .take()
+                .ok_or_else(|| WalletErr::from("no anti-exfil signature started"))?
+                .sign(host_data)?;
+            transmit_response(Response::Sig(&sig.as_bytes()), s)
+        }
+        Request::ProvideTokenInfo(info) => {
+            ctx.tokens.add(info)?;
+            transmit_response(Response::Ok, s)
+        }
+        Request::BtcAddress((network, script, account, change, index)) => {
+            accounts::check(&kv(), *account)?;
+            let path = btc::path(*network, *script, *account, *change, *index)?;
+            let addr = btc::address(ctx.seed()?, *network, *script, *account, *change, *index)?;
+            display::address(
+                &mut ui.disp,
+                "Bitcoin address",
+                &bip32::path_str(&path),
+                &addr,
+            )?;
+            transmit_response(Response::BtcAddress(&addr), s)
+        }
+        Request::BtcXpub((network, script, account)) => {
+            accounts::check(&kv(), *account)?;
+            let xpub = btc::account_xpub(ctx.seed()?, *network, *script, *account)?;
+            transmit_response(Response::Xpub(&xpub), s)
+        }
+        Request::SignPsbt((network, total, offset, chunk)) => {
+            // A new PSBT starts at offset 0, never on top of a signed one
+            if *offset == 0 || ctx.psbt_signed {
+                ctx.psbt.clear();
+                ctx.psbt_signed = false;
+            }
+            if *offset as usize != ctx.psbt.len()
+                || ctx.psbt.extend_from_slice(chunk).is_err()
+                || ctx.psbt.len() > *total as usize
+            {
+                ctx.psbt.clear();
+                return Err(WalletErr::from("unexpected PSBT chunk"));
+            }
+            if ctx.psbt.len() < *total as usize {
+                return transmit_response(Response::Ok, s);
+            }
+            // Don't hand out a half processed PSBT
+            if let Err(e) = sign_psbt(ctx, ui, *network) {
+                ctx.psbt.clear();
+                return Err(e);
+            }
+            ctx.psbt_signed = true;
+            transmit_response(psbt_chunk(ctx, 0)?, s)
+        }
+        Request::PsbtChunk(offset) => transmit_response(psbt_chunk(ctx, *offset)?, s),
+        Request::RegisterMultisig(descriptor) => {
+            let ms = multisig::Multisig::parse(descriptor)?;
+            let summary = ms.summary(ctx.seed()?)?;
+            ui.confirm_fields("Register multisig?", &summary, ctx.confirm_timeout_ms)?;
+            multisig::register(descriptor)?;
+            transmit_response(Response::Ok, s)
+        }
+        Request::MultisigAddress((network, change, index)) => {
+            let ms = multisig::registered()?
+                .ok_or_else(|| WalletErr::from("no multisig wallet registered"))?;
+            let addr = ms.address(*network, *change, *index)?;
+            let ours = ms.ours(ctx.seed()?)?;
+            let mut path: Vec<u32, U10> = Vec::new();
+            let _ = path.extend_from_slice(&ours.origin);
+            let _ = path.extend_from_slice(&[ours.branch(*change)?, *index]);
+            display::address(
+                &mut ui.disp,
+                "Multisig address",
+                &bip32::path_str(&path),
+                &addr,
+            )?;
+            transmit_response(Response::BtcAddress(&addr), s)
+        }
+        Request::SignBtcMessage((network, format, account, change, index, msg)) => {
+            accounts::check(&kv(), *account)?;
+            let path = message::path(*network, *format, *account, *change, *index)?;
+            let seed = ctx.seed()?;
+            let addr = message::address(seed, *network, *format, &path)?;
+            display::address(&mut ui.disp, "Sign as", &bip32::path_str(&path), &addr)?;
+            ui.confirm(ctx.confirm_timeout_ms)?;
+            display::sign_message(&mut ui.disp, msg)?;
+            ui.confirm(ctx.confirm_timeout_ms)?;
+            let sig = message::sign(seed, *network, *format, *account, *change, *index, msg)?;
+            transmit_response(Response::BtcMessageSig((&addr, &sig)), s)
+        }
+        Request::CosmosAddress((prefix, account, index)) => {
+            accounts::check(&kv(), *account)?;
+            let path = cosmos::path(*account, *index)?;
+            let pubkey = bip32::ExtendedKey::derive(ctx.seed()?, &path)?.public_key();
+            let addr = cosmos::address(prefix, &pubkey)?;
+            display::address(
+                &mut ui.disp,
+                "Cosmos address",
+                &bip32::path_str(&path),
+                &addr,
+            )?;
+            transmit_response(Response::CosmosAddress((&addr, &pubkey)), s)
+        }
+        Request::CosmosSign((prefix, mode, account, index, doc)) => {
+            accounts::check(&kv(), *account)?;
+            let path = cosmos::path(*account, *index)?;
+            let key = bip32::ExtendedKey::derive(ctx.seed()?, &path)?;
+            let addr = cosmos::address(prefix, &key.public_key())?;
+            let (summary, blind) = cosmos::summary(*mode, doc, &addr)?;
+            ctx.check_blind(blind)?;
+            ui.confirm_fields("Sign transaction?", &summary, ctx.confirm_timeout_ms)?;
+            transmit_response(Response::Sig(&cosmos::sign(&key, doc)?), s)
+        }
+        Request::SolanaAddress(account) => {
+            accounts::check(&kv(), *account)?;
+            let pubkey = solana::key(ctx.seed()?, *account)?.public_key()?;
+            let addr = solana::address(&pubkey);
+            display::address(
+                &mut ui.disp,
+                "Solana address",
+                &bip32::path_str(&solana::path(*account)?),
+                &addr,
+            )?;
+            transmit_response(Response::SolanaAddress(&addr), s)
+        }
+        Request::SignSolanaTx((account, msg)) => {
+            accounts::check(&kv(), *account)?;
+            let key = solana::key(ctx.seed()?, *account)?;
+            let (summary, blind) = solana::summary(msg, &key.public_key()?)?;
+            ctx.check_blind(blind)?;
+            ui.confirm_fields("Sign transaction?", &summary, ctx.confirm_timeout_ms)?;
+            transmit_response(Response::Sig(&key.sign(msg)?), s)
+        }
+        Request::NostrPubkey(account) => {
+            accounts::check(&kv(), *account)?;
+            let (_, pubkey) = nostr::keys(ctx.seed()?, *account)?;
+            let npub = nostr::npub(&pubkey)?;
+            display::address(
+                &mut ui.disp,
+                "Nostr key",
+                &bip32::path_str(&nostr::path(*account)?),
+                &npub,
+            )?;
+            transmit_response(Response::NostrPubkey((&npub, &pubkey)), s)
+        }
+        Request::SignNostrEvent((account, event)) => {
+            accounts::check(&kv(), *account)?;
+            let (secret, pubkey) = nostr::keys(ctx.seed()?, *account)?;
+            let summary = nostr::summary(event, &pubkey)?;
+            ui.confirm_fields("Sign event?", &summary, ctx.confirm_timeout_ms)?;
+            let id = nostr::event_id(event);
+            let sig = nostr::sign(&secret, &id, &ctx.aux.next()?)?;
+            transmit_response(Response::NostrEvent((&id, &sig)), s)
+        }
+        Request::Ecdh((peer, path)) => {
+            let path = bip32::parse_path(path)?;
+            let key = bip32::ExtendedKey::derive(ctx.seed()?, &path)?;
+            let shared = ecies::ecdh(&key.secret_bytes(), peer)?;
+            let fields = [
+                display::Field {
+                    label: "Key",
+                    value: display::FieldValue::from(bip32::path_str(&path).as_str()),
+                },
+                display::Field {
+                    label: "Peer",
+                    value: display::hex_str(&ecies::compressed(peer)?),
+                },
+            ];
+            ui.confirm_fields("Share ECDH secret?", &fields, ctx.confirm_timeout_ms)?;
+            transmit_response(Response::SharedSecret(&shared), s)
+        }
+        Request::Decrypt((scheme, path, msg)) => {
+            let path = bip32::parse_path(path)?;
+            let key = bip32::ExtendedKey::derive(ctx.seed()?, &path)?;
+            let verified = ecies::verify(*scheme, &key.secret_bytes(), msg)?;
+            let fields = [display::Field {
+                label: "Key",
+                value: display::FieldValue::from(bip32::path_str(&path).as_str()),
+            }];
+            ui.confirm_fields("Decrypt message?", &fields, ctx.confirm_timeout_ms)?;
+            let plaintext = verified.decrypt()?;
+            transmit_response(Response::Plaintext(&plaintext), s)
+        }
+        Request::Bip85((app, index, to_host)) => {
+            let summary = bip85::summary(*app, *index)?;
+            ui.confirm_fields("Derive child secret?", &summary, ctx.confirm_timeout_ms)?;
+            let secret = bip85::derive(ctx.seed()?, *app, *index)?;
+            ui.confirm_fields(
+                "Child secret",
+                &bip85::fields(&secret),
+                ctx.confirm_timeout_ms,
+            )?;
+            if *to_host {
+                let warning = [display::Field {
+                    label: "Warning",
+                    value: display::FieldValue::from("the computer will see the secret"),
+                }];
+                ui.confirm_fields("Send to host?", &warning, ctx.confirm_timeout_ms)?;
+                transmit_response(Response::Bip85(&secret), s)
+            } else {
+                transmit_response(Response::Ok, s)
+            }
+        }
+        Request::Slip39Split((group_threshold, groups, host)) => {
+            let groups = slip39::groups(groups)?;
+            let summary = slip39::summary(*group_threshold, &groups)?;
+            ui.confirm_fields("Create SLIP-39 backup?", &summary, ctx.confirm_timeout_ms)?;
+            let mut device = entropy::device_entropy()?;
+            let mut secret = seed_entropy()?;
+            let timeout_ms = ctx.confirm_timeout_ms;
+            let res = slip39::split(&secret, *group_threshold, &groups, &device, host, |share| {
+                let mut heading: String<U32> = String::new();
+                let mut buf = [0u8; 3];
+                let _ = heading.push_str("Group ");
+                let _ = heading.push_str((share.group_index + 1).numtoa_str(10, &mut buf));
+                let _ = heading.push_str(" share ");
+                let _ = heading.push_str((share.member_index + 1).numtoa_str(10, &mut buf));
+                let words = share.words()?;
+                ui.confirm_fields(&heading, &display::word_fields(&words), timeout_ms)
+            });
+            safemem::wipe(&mut secret);
+            safemem::wipe(&mut device);
+            res?;
+            transmit_response(Response::Ok, s)
+        }
+        Request::Slip39Share => {
+            ensure_no_seed()?;
+            let share = entry::slip39_share(ui, ctx.confirm_timeout_ms)?;
+            let (done, needed) = ctx.slip39.add(share)?;
+            if done < needed {
+                show_slip39_progress(&mut ui.disp, done, needed)?;
+                return transmit_response(Response::Slip39Progress((done, needed)), s);
+            }
+            // Complete, right or wrong: start over next time
+            let recovery = core::mem::replace(&mut ctx.slip39, slip39::Recovery::new());
+            let m = slip39_mnemonic(&recovery)?;
+            let summary = [display::Field {
+                label: "Seed",
+                value: display::FieldValue::from("from SLIP-39 shares"),
+            }];
+            ui.confirm_fields("Restore seed?", &summary, ctx.confirm_timeout_ms)?;
+            save_seed_phrase_encr(m.phrase())?;
+            ctx.seed = Some(load_seed()?);
+            transmit_response(Response::Slip39Progress((done, needed)), s)
+        }
+        Request::VerifyMnemonic => {
+            let count = seed_word_count()?;
+            let phrase = entry::words(
+                ui,
+                "Word",
+                entry::BIP39_WORDLIST,
+                count,
+                ctx.confirm_timeout_ms,
+            )?;
+            let m = Mnemonic::from_phrase(phrase.as_str(), Language::English);
+            let m = entry::checked(ui, m.map_err(WalletErr::from))?;
+            let matches = backup_matches(ctx, &m, &mut ui.disp)?;
+            transmit_response(Response::BackupMatches(matches), s)
+        }
+        Request::VerifySlip39Share => {
+            // Fails before anything is typed if there is no seed
+            ctx.seed()?;
+            let share = entry::slip39_share(ui, ctx.confirm_timeout_ms)?;
+            let (done, needed) = ctx.slip39_check.add(share)?;
+            if done < needed {
+                show_slip39_progress(&mut ui.disp, done, needed)?;
+                return transmit_response(Response::Slip39Progress((done, needed)), s);
+            }
+            let recovery = core::mem::replace(&mut ctx.slip39_check, slip39::Recovery::new());
+            let m = slip39_mnemonic(&recovery)?;
+            let matches = backup_matches(ctx, &m, &mut ui.disp)?;
+            transmit_response(Response::BackupMatches(matches), s)
+        }
+        Request::SeedCommit(host_commitment) => {
+            ensure_no_seed()?;
+            ctx.new_seed = None;
+            display::status(&mut ui.disp, "Gathering entropy")?;
+            let commit = entropy::Commit::new(host_commitment)?;
+            let device_commitment = commit.device_commitment();
+            ctx.seed_commit = Some(commit);
+            transmit_response(Response::EntropyCommitment(&device_commitment), s)
+        }
+        Request::GenerateSeed((words, source)) => {
+            ensure_no_seed()?;
+            let len = entropy::entropy_len(*words)?;
+            match source {
+                SeedEntropy::Mixed((host, dice)) => {
+                    let new_seed = ctx
+                        .seed_commit
+                        .take()
+                        .ok_or_else(|| WalletErr::from("no entropy committed to"))?
+                        .reveal(host, dice, len)?;
+                    let hash = new_seed.hash();
+                    ctx.new_seed = Some(new_seed);
+                    transmit_response(Response::NewSeedHash(&hash), s)
+                }
+                SeedEntropy::Dice => {
+                    let count = entropy::dice_needed(len);
+                    let rolls = entry::dice(ui, count, ctx.confirm_timeout_ms)?;
+                    let dice = entropy::dice_entropy(rolls.as_str(), len);
+                    let mut bytes = entry::checked(ui, dice)?;
+                    let res = store_new_seed(ctx, ui, &bytes[..len]);
+                    safemem::wipe(&mut bytes);
+                    res?;
+                    transmit_response(Response::Ok, s)
+                }
+            }
+        }
+        Request::OpenEntropy => {
+            let new_seed = ctx
+                .new_seed
+                .take()
+                .ok_or_else(|| WalletErr::from("no new seed"))?;
+            let mut device = new_seed.open();
+            let res = transmit_response(Response::DeviceEntropy(&device), s);
+            safemem::wipe(&mut device);
+            res
+        }
+        Request::KeepSeed => {
+            ensure_no_seed()?;
+            let new_seed = ctx
+                .new_seed
+                .take()
+                .ok_or_else(|| WalletErr::from("no new seed"))?;
+            store_new_seed(ctx, ui, new_seed.entropy())?;
+            transmit_response(Response::Ok, s)
+        }
         Request::PubKey => {
             let pubkey_bytes = public_key(&ctx)?.to_bytes();
             transmit_response(Response::PubKey(&pubkey_bytes), s)
         }
         Request::Address(idx) => {
             let addr_bytes = address(ctx.set_idx(*idx))?;
+            display::address(
+                &mut ui.disp,
+                "Address",
+                &path_str(ctx),
+                &eth::checksum_address(&addr_bytes),
+            )?;
+            transmit_response(Response::Address(&addr_bytes), s)
+        }
+        Request::ShowAddress(path) => {
+            // Only shown, the selected account and index stay as they are
+            let path = bip32::parse_path(path)?;
+            if let [purpose, coin, account, ..] = path[..] {
+                if purpose == 44 | bip32::HARDENED && coin == 60 | bip32::HARDENED {
+                    accounts::check(&kv(), account & !bip32::HARDENED)?;
+                }
+            }
+            let key = bip32::ExtendedKey::derive(ctx.seed()?, &path)?;
+            let addr_bytes = eth::address(&VerifyingKey::from_sec1_bytes(&key.public_key())?);
+            display::address(
+                &mut ui.disp,
+                "Verify address",
+                &bip32::path_str(&path),
+                &eth::checksum_address(&addr_bytes),
+            )?;
+            ui.confirm(ctx.confirm_timeout_ms)?;
             transmit_response(Response::Address(&addr_bytes), s)
         }
         Request::AddressList(idx) => {
@@ -210,6 +803,33 @@ where
             };
             transmit_response(Response::Serial(read_serial()), s)
         }
+        Request::ShowQr((content, account, idx)) => {
+            // Only shown, the selected account and index stay as they are
+            accounts::check(&kv(), *account)?;
+            let payload = show_qr(ctx, &mut ui.disp, *content, *account, *idx)?;
+            ui.confirm(ctx.confirm_timeout_ms)?;
+            transmit_response(Response::Qr(&payload), s)
+        }
+        Request::Accounts => {
+            let accts = accounts::accounts(&kv());
+            transmit_response(Response::Accounts(accounts::labels(&accts)), s)
+        }
+        Request::SelectAccount(account) => {
+            let account = ctx.set_account(*account)?.account;
+            let accts = accounts::accounts(&kv());
+            let label = accts[account as usize].as_ref().map_or("", |l| l.as_str());
+            transmit_response(Response::Account((account, label)), s)
+        }
+        Request::SetAccount((account, label)) => {
+            let mut kv = kv();
+            accounts::set_account(&mut kv, *account, *label)?;
+            // Fall back to the default account if the selected one was disabled
+            if !accounts::is_enabled(&kv, ctx.account) {
+                ctx.account = 0;
+            }
+            let accts = accounts::accounts(&kv);
+            transmit_response(Response::Accounts(accounts::labels(&accts)), s)
+        }
         Request::Info => transmit_response(
             Response::Info((
                 load_seed_plaintext_size()?.is_some(),
@@ -218,7 +838,146 @@ where
             )),
             s,
         ),
+        Request::Wipe => {
+            let warning = [display::Field {
+                label: "Erases",
+                value: display::FieldValue::from("seed, accounts, multisig"),
+            }];
+            ui.confirm_fields("Wipe device?", &warning, ctx.confirm_timeout_ms)?;
+            display::status(&mut ui.disp, "Wiping")?;
+            // Whatever happens to the flash, no secret outlives the request
+            *ctx = Context::new(None);
+            wipe_storage()?;
+            ui.disp.set_brightness(settings::DEFAULT.brightness)?;
+            display::status(&mut ui.disp, "Wiped")?;
+            transmit_response(Response::Ok, s)
+        }
+        Request::GetSettings => {
+            let mut buf = kvstore::Value::new();
+            transmit_response(Response::Settings(settings::load(&kv(), &mut buf)), s)
+        }
+        Request::ApplySettings(new) => {
+            settings::validate(new)?;
+            let summary = settings::summary(new)?;
+            ui.confirm_fields("Apply settings?", &summary, ctx.confirm_timeout_ms)?;
+            settings::save(&mut kv(), new)?;
+            ctx.apply(new);
+            ui.disp.set_brightness(new.brightness)?;
+            show_ready(&mut ui.disp)?;
+            let mut buf = kvstore::Value::new();
+            transmit_response(Response::Settings(settings::load(&kv(), &mut buf)), s)
+        }
+    }
+}
+
+/// Signs the PSBT in `ctx.psbt` in place, once the user approved it
+fn sign_psbt<D, U>(ctx: &mut Context, ui: &mut Ui<D, U>, network: btc::BtcNetwork) -> Result<()>
+where
+    D: Screen,
+    U: UserPresence,
+{
+    let aux = ctx.aux.next()?;
+    // A multisig wallet for another network is simply not used
+    let multisig = multisig::registered()?.filter(|ms| ms.check_network(network).is_ok());
+    let wallet = psbt::Wallet {
+        seed: ctx.seed()?,
+        network,
+        multisig: multisig.as_ref(),
+        is_enabled: &|account| accounts::is_enabled(&kv(), account),
+    };
+    let sigs = {
+        let psbt = psbt::Psbt::parse(&ctx.psbt)?;
+        let summary = psbt.summary(&wallet)?;
+        ctx.check_blind(psbt.is_blind(network)?)?;
+        ui.confirm_fields("Sign transaction?", &summary, ctx.confirm_timeout_ms)?;
+        psbt.sign(&wallet, &aux)?
+    };
+    psbt::add_signatures(&mut ctx.psbt, &sigs)
+}
+
+fn psbt_chunk(ctx: &Context, offset: u32) -> Result<Response> {
+    let buf = &ctx.psbt;
+    let start = offset as usize;
+    if !ctx.psbt_signed || start > buf.len() {
+        return Err(WalletErr::from("no signed PSBT at that offset"));
+    }
+    let end = buf.len().min(start + protocol::PSBT_CHUNK_SIZE);
+    Ok(Response::Psbt((buf.len() as u32, offset, &buf[start..end])))
+}
+
+/// Requests that send public keys or addresses to the host, which
+/// `Settings::confirm_exports` has the user approve first
+fn is_export(r: &Request) -> bool {
+    matches!(
+        r,
+        Request::PubKey
+            | Request::Address(_)
+            | Request::AddressList(_)
+            | Request::BtcAddress(_)
+            | Request::BtcXpub(_)
+            | Request::MultisigAddress(_)
+            | Request::CosmosAddress(_)
+            | Request::SolanaAddress(_)
+            | Request::NostrPubkey(_)
+    )
+}
+
+/// Steps through the idle menu: confirm shows the next QR code, reject
+/// (or confirming past the last page) goes back to the status screen
+fn next_page(page: Option<QrContent>, decision: Decision) -> Option<QrContent> {
+    match (decision, page) {
+        (Decision::Approved, None) => Some(QrContent::Address),
+        (Decision::Approved, Some(QrContent::Address)) => Some(QrContent::Uri),
+        (Decision::Approved, Some(QrContent::Uri)) => Some(QrContent::Xpub),
+        _ => None,
+    }
+}
+
+/// The idle screen, showing the device's label if it has one
+fn show_ready<D: Screen>(disp: &mut D) -> Result<()> {
+    let mut buf = kvstore::Value::new();
+    match settings::load(&kv(), &mut buf).label {
+        "" => display::status(disp, "Ready"),
+        label => display::status(disp, label),
+    }
+}
+
+/// Renders `content` for `account` and `idx` as a QR code, returning the
+/// encoded text
+fn show_qr<D: Screen>(
+    ctx: &Context,
+    disp: &mut D,
+    content: QrContent,
+    account: u32,
+    idx: u32,
+) -> Result<String<U112>> {
+    use bip32::HARDENED;
+    let account_key = bip32::ExtendedKey::derive(
+        ctx.seed()?,
+        &[44 | HARDENED, 60 | HARDENED, account | HARDENED],
+    )?;
+    let address = || -> Result<[u8; ADDR_SIZE]> {
+        let key = account_key.child(0)?.child(idx)?;
+        Ok(eth::address(&VerifyingKey::from_sec1_bytes(
+            &key.public_key(),
+        )?))
+    };
+    let mut payload: String<U112> = String::new();
+    match content {
+        QrContent::Address => payload.push_str(&eth::checksum_address(&address()?)),
+        QrContent::Uri => {
+            let addr = eth::checksum_address(&address()?);
+            payload
+                .push_str("ethereum:")
+                .and_then(|_| payload.push_str(&addr))
+        }
+        QrContent::Xpub => payload.push_str(&account_key.xpub(bip32::XPUB)),
     }
+    .map_err(|_| WalletErr::from("QR payload too long"))?;
+
+    let code = qr::QrCode::encode(payload.as_bytes())?;
+    display::qr(disp, &code, &payload)?;
+    Ok(payload)
 }
 
 fn get_uid_raw() -> &'static [u8] {
@@ -235,32 +994,144 @@ fn get_aes_key() -> [u8; 32] {
     buf
 }
 
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut buf = [0u8; 32];
+    hasher.finalize(&mut buf);
+    buf
+}
+
 fn sign_msg(ctx: &Context, msg: &[u8]) -> Result<recoverable::Signature> {
     Ok(secret_key(ctx)?.try_sign(&msg)?)
 }
 
+/// The factory seed, unless `erase_seed_phrase` has zeroed it. Read
+/// volatile, as the compiler would otherwise take the bytes the constant
+/// was built with rather than what is in flash now.
+fn factory_mnemonic() -> Option<&'static str> {
+    let present = MNEMONIC
+        .as_bytes()
+        .iter()
+        .any(|b| unsafe { core::ptr::read_volatile(b) } != 0);
+    if present {
+        Some(MNEMONIC)
+    } else {
+        None
+    }
+}
+
+fn is_factory_seed_used() -> bool {
+    let flag = unsafe {
+        core::slice::from_raw_parts((FLASH_START + FACTORY_SEED_USED_ADDR) as *const u8, 4)
+    };
+    flag.iter().any(|b| *b != 0xFF)
+}
+
+fn mark_factory_seed_used() -> Result<()> {
+    let dp = unsafe { stm32::Peripherals::steal() };
+    let mut flash = dp.FLASH;
+    let mut unlocked = flash.unlocked();
+    unlocked.program(FACTORY_SEED_USED_ADDR as usize, &[0; 4])?;
+    Ok(())
+}
+
 // This is the kind of thing we would do only in the factory
 // This would allow us to load a seed phrase into static memory
 // in a factory image, so all factory-produced wallets would have
 // this same seed stored in memory in a protected way.
+/// Overwrites whatever is left of the factory seed in flash with 0's
 fn erase_seed_phrase() -> Result<()> {
-    // If our mnemonic hasn't been erased yet, and we have it saved
-    // to disk, overwite it with 0's now
-    if !MNEMONIC.is_empty() && load_seed_plaintext_size()?.is_some() {
-        // If the seed has been saved to disk encrypted,
+    if factory_mnemonic().is_none() {
+        return Ok(());
+    }
+    let dp = unsafe { stm32::Peripherals::steal() };
+    let mut flash = dp.FLASH;
+    let mut unlocked = flash.unlocked();
+    let addr = MNEMONIC.as_ptr() as usize - FLASH_START as usize;
+    for a in addr..addr + MNEMONIC.len() {
+        unlocked.program(a, &[0; 1])?;
+    }
+    Ok(())
+}
+
+/// Erases flash sector `number`
+fn erase_sector(number: u8) -> Result<()> {
+    let dp = unsafe { stm32::Peripherals::steal() };
+    let mut flash = dp.FLASH;
+    flash.unlocked().erase(number)?;
+    // The data cache may still hold what was just erased
+    flash.acr.modify(|_, w| w.dcen().clear_bit());
+    flash.acr.modify(|_, w| w.dcrst().set_bit());
+    flash
+        .acr
+        .modify(|_, w| w.dcrst().clear_bit().dcen().set_bit());
+    Ok(())
+}
+
+/// The on-chip flash, as the key/value store sees it
+struct OnChipFlash;
+
+impl kvstore::Flash for OnChipFlash {
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        let bytes =
+            unsafe { core::slice::from_raw_parts((FLASH_START + offset) as *const u8, buf.len()) };
+        buf.copy_from_slice(bytes);
+    }
+
+    fn program(&mut self, offset: u32, bytes: &[u8]) -> Result<()> {
         let dp = unsafe { stm32::Peripherals::steal() };
         let mut flash = dp.FLASH;
         let mut unlocked = flash.unlocked();
+        unlocked.program(offset as usize, bytes)?;
+        Ok(())
+    }
 
-        // Erase the seed from flash
-        let addr = MNEMONIC.as_ptr() as usize - FLASH_START as usize;
-        for a in addr..addr + MNEMONIC.len() {
-            unlocked.program(a, &[0; 1])?;
+    fn erase_sector(&mut self, sector: &layout::Sector) -> Result<()> {
+        erase_sector(sector.number)
+    }
+}
+
+/// The key/value store in `KV_SECTORS`
+fn kv() -> kvstore::Store<OnChipFlash> {
+    kvstore::Store::new(OnChipFlash, KV_SECTORS)
+}
+
+/// Erases the key/value store and the sector holding the serial, seed and
+/// multisig records, keeping only the serial, and zeroes any factory seed
+/// left in the image. The factory seed is then used up for good.
+fn wipe_storage() -> Result<()> {
+    // A factory seed that never got stored, say for losing power half way
+    // through the first boot, mustn't outlive the wipe
+    erase_seed_phrase()?;
+    let keep_serial = is_serial_set();
+    let mut serial = [0u8; SERIAL_LEN];
+    serial.copy_from_slice(read_serial());
+
+    for sector in KV_SECTORS.iter().chain(core::iter::once(&STORAGE_SECTOR)) {
+        erase_sector(sector.number)?;
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                (FLASH_START + sector.offset) as *const u8,
+                sector.size as usize,
+            )
+        };
+        if bytes.iter().any(|b| *b != 0xFF) {
+            return Err(WalletErr::from("storage sector failed to erase"));
         }
-        Ok(())
-    } else {
-        Err(WalletErr::from("failed to erase seed phrase"))
     }
+
+    // Marked before the serial goes back, so that power lost half way leaves
+    // storage in this layout rather than looking left over by old firmware
+    mark_factory_seed_used()?;
+    migrate::mark_current()?;
+    if keep_serial {
+        let dp = unsafe { stm32::Peripherals::steal() };
+        let mut flash = dp.FLASH;
+        let mut unlocked = flash.unlocked();
+        unlocked.program(SERIAL_ADDR as usize, &serial[..])?;
+    }
+    Ok(())
 }
 
 fn save_seed_phrase_encr(s: &str) -> Result<()> {
@@ -272,8 +1143,7 @@ fn save_seed_phrase_encr(s: &str) -> Result<()> {
     // `U8` represents the tag size as a `typenum` unsigned (8-bytes here)
     let mut key = get_aes_key();
     let ccm = Aes256Ccm::<U8>::new((&key).into());
-    // Clear the key from memory
-    key.iter_mut().for_each(|b| *b = 0);
+    safemem::wipe(&mut key);
 
     // Encrypt `buffer` in-place, replacing the plaintext contents with ciphertext
     // Use the UID of the chip as the associated_data
@@ -322,8 +1192,7 @@ where
     // `U8` represents the tag size as a `typenum` unsigned (8-bytes here)
     let mut key = get_aes_key();
     let ccm = Aes256Ccm::<U8>::new((&key).into());
-    // Clear the key from memory
-    key.iter_mut().for_each(|b| *b = 0);
+    safemem::wipe(&mut key);
 
     // Decrypt `buffer` in-place, replacing its ciphertext contents with the original plaintext
     // Use the UID of the chip as the associated_data
@@ -334,19 +1203,128 @@ where
         .map_err(|_| WalletErr::from("failed to decode decrypted seed as utf8; corrupt?"))?)
 }
 
-fn load_seed() -> Result<Seed> {
+/// The seed is programmed once, see `save_seed_phrase_encr`, so a new one
+/// needs a wiped device
+fn ensure_no_seed() -> Result<()> {
+    match load_seed_plaintext_size()? {
+        Some(_) => Err(WalletErr::from("wipe the device before storing a new seed")),
+        None => Ok(()),
+    }
+}
+
+fn show_slip39_progress<D: Screen>(disp: &mut D, done: u8, needed: u8) -> Result<()> {
+    let mut status: String<U32> = String::new();
+    let mut buf = [0u8; 3];
+    let _ = status.push_str("Groups ");
+    let _ = status.push_str(done.numtoa_str(10, &mut buf));
+    let _ = status.push_str(" of ");
+    let _ = status.push_str(needed.numtoa_str(10, &mut buf));
+    display::status(disp, &status)
+}
+
+/// Shows the mnemonic of the new seed `entropy` for the user to write down
+/// and stores it once they confirm
+fn store_new_seed<D, U>(ctx: &mut Context, ui: &mut Ui<D, U>, entropy: &[u8]) -> Result<()>
+where
+    D: Screen,
+    U: UserPresence,
+{
+    let m = Mnemonic::from_entropy(entropy, Language::English)?;
+    let timeout_ms = ctx.confirm_timeout_ms;
+    ui.confirm_fields(
+        "Write down seed",
+        &display::word_fields(m.phrase()),
+        timeout_ms,
+    )?;
+    let mut buf = [0u8; 10];
+    let words = m.phrase().split(' ').count() as u32;
+    let summary = [display::Field {
+        label: "Words",
+        value: display::FieldValue::from(words.numtoa_str(10, &mut buf)),
+    }];
+    ui.confirm_fields("Save new seed?", &summary, timeout_ms)?;
+    save_seed_phrase_encr(m.phrase())?;
+    ctx.seed = Some(load_seed()?);
+    Ok(())
+}
+
+/// The mnemonic whose entropy the complete share set `recovery` holds
+fn slip39_mnemonic(recovery: &slip39::Recovery) -> Result<Mnemonic> {
+    let mut secret = recovery.secret()?;
+    let m = Mnemonic::from_entropy(&secret, Language::English);
+    safemem::wipe(&mut secret);
+    Ok(m?)
+}
+
+/// Whether `backup` is the stored seed, judged by the master key
+/// fingerprints. Nothing but the verdict is shown or returned, and nothing
+/// is stored.
+fn backup_matches<D: Screen>(ctx: &Context, backup: &Mnemonic, disp: &mut D) -> Result<bool> {
+    let seed = Seed::new(backup, "");
+    let theirs = bip32::ExtendedKey::master(seed.as_bytes())?.fingerprint();
+    let ours = bip32::ExtendedKey::master(ctx.seed()?)?.fingerprint();
+    let matches = theirs == ours;
+    display::status(
+        disp,
+        if matches {
+            "Backup matches"
+        } else {
+            "Backup does NOT match"
+        },
+    )?;
+    Ok(matches)
+}
+
+/// How many words the stored mnemonic has, so a backup of it can be typed
+fn seed_word_count() -> Result<usize> {
+    let mut buffer: Vec<u8, U512> = Vec::new();
+    let count = load_seed_phrase(&mut buffer).map(|phrase| phrase.split(' ').count());
+    safemem::wipe(&mut buffer);
+    count
+}
+
+/// The entropy of the stored mnemonic, which SLIP-39 backups split
+fn seed_entropy() -> Result<slip39::Value> {
+    let mut buffer: Vec<u8, U512> = Vec::new();
+    let entropy = load_seed_phrase(&mut buffer)
+        .and_then(|phrase| Ok(Mnemonic::from_phrase(phrase, Language::English)?))
+        .and_then(|m| {
+            slip39::Value::from_slice(m.entropy())
+                .map_err(|_| WalletErr::from("unsupported seed length"))
+        });
+    safemem::wipe(&mut buffer);
+    entropy
+}
+
+fn load_seed() -> Result<SeedBytes> {
     // Decrypt the seed_phrase
     let mut buffer: Vec<u8, U512> = Vec::new();
     let seed_phrase = load_seed_phrase(&mut buffer)?;
     // Generate a mnemonic from it
     let m = Mnemonic::from_phrase(seed_phrase, Language::English)?;
-    Ok(Seed::new(&m, ""))
+    let mut seed = SeedBytes([0; 64]);
+    seed.0.copy_from_slice(Seed::new(&m, "").as_bytes());
+    Ok(seed)
 }
 
 fn secret_key(ctx: &Context) -> Result<SigningKey> {
-    let account = ExtendedPrivKey::derive(ctx.seed.as_bytes(), "m/44'/60'/0'/0")?
+    let key = ExtendedPrivKey::derive(ctx.seed()?, "m/44'/60'")?
+        .child(ChildNumber::hardened_from_u32(ctx.account))?
+        .child(ChildNumber::non_hardened_from_u32(0))?
         .child(ChildNumber::non_hardened_from_u32(ctx.idx))?;
-    Ok(SigningKey::from_bytes(&account.secret())?)
+    Ok(SigningKey::from_bytes(&key.secret())?)
+}
+
+/// The derivation path of the currently selected key, for display
+fn path_str(ctx: &Context) -> bip32::PathString {
+    use bip32::HARDENED;
+    bip32::path_str(&[
+        44 | HARDENED,
+        60 | HARDENED,
+        ctx.account | HARDENED,
+        0,
+        ctx.idx,
+    ])
 }
 
 fn public_key(ctx: &Context) -> Result<VerifyingKey> {
@@ -354,7 +1332,6 @@ fn public_key(ctx: &Context) -> Result<VerifyingKey> {
 }
 
 const NUM_ADDRS: usize = 5;
-const ADDR_SIZE: usize = 20;
 fn addresses(ctx: &mut Context) -> Result<[u8; ADDR_SIZE * NUM_ADDRS]> {
     let idx = ctx.idx as usize;
     let mut buf = [0u8; ADDR_SIZE * NUM_ADDRS];
@@ -367,15 +1344,7 @@ fn addresses(ctx: &mut Context) -> Result<[u8; ADDR_SIZE * NUM_ADDRS]> {
 }
 
 fn address(ctx: &Context) -> Result<[u8; ADDR_SIZE]> {
-    let uncompressed_pubkey = public_key(ctx)?.to_encoded_point(false);
-    let pubkey_bytes = uncompressed_pubkey.as_bytes();
-    let mut hasher = Keccak::v256();
-    hasher.update(&pubkey_bytes[1..]);
-    let mut buf = [0u8; 32];
-    hasher.finalize(&mut buf);
-    let mut address = [0u8; ADDR_SIZE];
-    address.copy_from_slice(&buf[12..]);
-    Ok(address)
+    Ok(eth::address(&public_key(ctx)?))
 }
 
 fn read_serial<'a>() -> &'a [u8] {
@@ -412,24 +1381,18 @@ fn write_serial() -> Result<()> {
     Ok(())
 }
 
-fn transmit_response<T>(r: Response, serial: &mut SerialPort<T>) -> Result<()>
-where
-    T: class_prelude::UsbBus,
-{
+fn transmit_response<T: Serial>(r: Response, serial: &mut T) -> Result<()> {
     let mut data = to_vec::<U1000, _>(&r)?;
-    let _ = data.push(protocol::version());
-    let _ = serial.write(&data)?;
-    Ok(())
+    data.push(protocol::version())
+        .map_err(|_| WalletErr::from("response too long"))?;
+    link::send(serial, &data)
 }
 
-fn respond_with_err<T>(msg: ErrStringType, serial: &mut SerialPort<T>)
-where
-    T: class_prelude::UsbBus,
-{
+fn respond_with_err<T: Serial>(msg: ErrStringType, serial: &mut T) {
     // Create a Response::Err from our msg, silently fail
     let resp = Response::Err(msg.as_str());
     if let Ok(mut data) = to_vec::<U1000, _>(&resp) {
         let _ = data.push(protocol::version());
-        let _ = serial.write(&data);
+        let _ = link::send(serial, &data);
     } // else do nothing
 }
@@ -0,0 +1,160 @@
+//! Bitcoin signed messages, proving ownership of a native SegWit or legacy
+//! address. Signatures are either BIP137 compact signatures or BIP322 simple
+//! ones, the latter for native SegWit only.
+use crate::{
+    bech32::Bech32String,
+    bip32::{hash160, ExtendedKey, HARDENED},
+    btc::{self, BtcNetwork, BtcScript},
+    psbt::{compact_size, der_signature, p2wpkh_script},
+    schnorr::tagged_hash,
+    Result,
+};
+
+use heapless::{consts::*, Vec};
+use k256::ecdsa::{recoverable, signature::DigestSigner, Signature, SigningKey};
+use sha2::{Digest, Sha256};
+
+pub use protocol::BtcMessageFormat;
+
+const MAGIC: &[u8] = b"\x18Bitcoin Signed Message:\n";
+/// BIP137 headers of compressed P2PKH and of P2WPKH signatures, before
+/// adding the recovery id
+const HEADER_P2PKH: u8 = 31;
+const HEADER_P2WPKH: u8 = 39;
+
+/// A BIP137 signature or a serialized BIP322 witness
+pub type MessageSig = Vec<u8, U112>;
+
+fn double_sha256(data: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&Sha256::digest(&Sha256::digest(data)));
+    out
+}
+
+/// A hasher finishing to the BIP137 hash of `msg`
+fn message_hash(msg: &[u8]) -> Sha256 {
+    let inner = Sha256::new()
+        .chain(MAGIC)
+        .chain(compact_size(msg.len()))
+        .chain(msg)
+        .finalize();
+    Sha256::new().chain(inner)
+}
+
+/// A hasher finishing to the BIP143 sighash of BIP322's `to_sign`
+/// transaction for `msg`, spending the P2WPKH output of `pubkey`
+fn bip322_sighash(pubkey: &[u8; 33], msg: &[u8]) -> Sha256 {
+    let msg_hash = tagged_hash("BIP0322-signed-message", &[msg]);
+    let script_pubkey = p2wpkh_script(pubkey);
+
+    // `to_spend` commits to the message in its only input and pays the
+    // address in its only output
+    let to_spend = Sha256::new()
+        .chain(0u32.to_le_bytes())
+        .chain([1])
+        .chain([0u8; 32])
+        .chain(u32::MAX.to_le_bytes())
+        .chain([34, 0x00, 0x20])
+        .chain(msg_hash)
+        .chain(0u32.to_le_bytes())
+        .chain([1])
+        .chain(0u64.to_le_bytes())
+        .chain([script_pubkey.len() as u8])
+        .chain(script_pubkey)
+        .chain(0u32.to_le_bytes())
+        .finalize();
+    let to_spend = Sha256::digest(&to_spend);
+
+    // `to_sign` spends it to a single OP_RETURN output
+    let mut outpoint = [0u8; 36];
+    outpoint[..32].copy_from_slice(&to_spend);
+    let mut outputs = [0u8; 10];
+    outputs[8..].copy_from_slice(&[1, 0x6a]);
+    let inner = Sha256::new()
+        .chain(0u32.to_le_bytes())
+        .chain(double_sha256(&outpoint))
+        .chain(double_sha256(&0u32.to_le_bytes()))
+        .chain(outpoint)
+        .chain([0x19, 0x76, 0xa9, 0x14])
+        .chain(hash160(pubkey))
+        .chain([0x88, 0xac])
+        .chain(0u64.to_le_bytes())
+        .chain(0u32.to_le_bytes())
+        .chain(double_sha256(&outputs))
+        .chain(0u32.to_le_bytes())
+        .chain(1u32.to_le_bytes())
+        .finalize();
+    Sha256::new().chain(inner)
+}
+
+/// Path of the key signing in `format`: BIP44 for legacy addresses, BIP84
+/// for native SegWit ones
+pub fn path(
+    network: BtcNetwork,
+    format: BtcMessageFormat,
+    account: u32,
+    change: u32,
+    index: u32,
+) -> Result<[u32; 5]> {
+    let mut path = btc::path(network, BtcScript::P2wpkh, account, change, index)?;
+    if format == BtcMessageFormat::Bip137P2pkh {
+        path[0] = 44 | HARDENED;
+    }
+    Ok(path)
+}
+
+/// The address a signature in `format` by the key at `path` is for
+pub fn address(
+    seed: &[u8],
+    network: BtcNetwork,
+    format: BtcMessageFormat,
+    path: &[u32],
+) -> Result<Bech32String> {
+    let pubkey = ExtendedKey::derive(seed, path)?.public_key();
+    match format {
+        BtcMessageFormat::Bip137P2pkh => Ok(btc::p2pkh_address(network, &pubkey)),
+        BtcMessageFormat::Bip137 | BtcMessageFormat::Bip322Simple => {
+            btc::p2wpkh_address(network, &pubkey)
+        }
+    }
+}
+
+/// Signs `msg` with the key at `account`/`change`/`index`, see `path`
+pub fn sign(
+    seed: &[u8],
+    network: BtcNetwork,
+    format: BtcMessageFormat,
+    account: u32,
+    change: u32,
+    index: u32,
+    msg: &[u8],
+) -> Result<MessageSig> {
+    let key = ExtendedKey::derive(seed, &path(network, format, account, change, index)?)?;
+    let pubkey = key.public_key();
+    let signer = SigningKey::from_bytes(&key.secret_bytes())?;
+
+    let mut out = MessageSig::new();
+    match format {
+        BtcMessageFormat::Bip137 | BtcMessageFormat::Bip137P2pkh => {
+            let header = match format {
+                BtcMessageFormat::Bip137P2pkh => HEADER_P2PKH,
+                _ => HEADER_P2WPKH,
+            };
+            let sig: recoverable::Signature = signer.try_sign_digest(message_hash(msg))?;
+            let _ = out.push(header + u8::from(sig.recovery_id()));
+            let _ = out.extend_from_slice(&sig.as_ref()[..64]);
+        }
+        BtcMessageFormat::Bip322Simple => {
+            let sig: Signature = signer.try_sign_digest(bip322_sighash(&pubkey, msg))?;
+            let der = der_signature(&sig);
+            // Two witness items: the signature with SIGHASH_ALL and the key
+            let _ = out.push(2);
+            let _ = out.push(der.len() as u8 + 1);
+            let _ = out.extend_from_slice(&der);
+            let _ = out.push(0x01);
+            let _ = out.push(pubkey.len() as u8);
+            let _ = out.extend_from_slice(&pubkey);
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,47 @@
+//! Storage left by firmware from before layout.rs, which kept the serial and
+//! seed in the last 1K of a 256K part. That sector now holds code, so the
+//! flasher erases the old records along with the old image: a device
+//! upgraded from such firmware starts without a seed, which has to be
+//! restored from its backup. What the old image left in sectors 1 to 3 is
+//! erased on the first boot, rather than read as records.
+use crate::{
+    erase_sector,
+    layout::{FLASH_START, KV_SECTORS, STORAGE_MAGIC, STORAGE_MAGIC_ADDR, STORAGE_SECTOR},
+    mark_factory_seed_used, Result,
+};
+use stm32f4xx_hal::{flash::FlashExt, stm32};
+
+fn read(offset: u32, len: u32) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts((FLASH_START + offset) as *const u8, len as usize) }
+}
+
+fn is_current() -> bool {
+    let magic = read(STORAGE_MAGIC_ADDR, 4);
+    u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]) == STORAGE_MAGIC
+}
+
+/// Marks erased storage as being in this layout
+pub fn mark_current() -> Result<()> {
+    let dp = unsafe { stm32::Peripherals::steal() };
+    let mut flash = dp.FLASH;
+    let mut unlocked = flash.unlocked();
+    unlocked.program(STORAGE_MAGIC_ADDR as usize, &STORAGE_MAGIC.to_le_bytes())?;
+    Ok(())
+}
+
+/// Brings storage into this layout, see the module docs
+pub fn run() -> Result<()> {
+    if is_current() {
+        return Ok(());
+    }
+    let sectors = || KV_SECTORS.iter().chain(core::iter::once(&STORAGE_SECTOR));
+    let blank = sectors().all(|s| read(s.offset, s.size).iter().all(|b| *b == 0xFF));
+    if !blank {
+        for sector in sectors() {
+            erase_sector(sector.number)?;
+        }
+        // The device was in use before, the factory seed isn't for it
+        mark_factory_seed_used()?;
+    }
+    mark_current()
+}
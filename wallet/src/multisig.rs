@@ -0,0 +1,446 @@
+//! Multisig wallets, registered as `wsh(sortedmulti(..))` output
+//! descriptors. Once registered the device can show the wallet's receive
+//! addresses, recognise its change and sign its inputs.
+use crate::{
+    bech32::{self, Bech32String},
+    bip32::{self, ExtendedKey, ExtendedPubKey, TPUB, XPUB},
+    btc::{self, BtcNetwork},
+    display::{Field, FieldValue},
+    error::WalletErr,
+    Result,
+};
+
+use heapless::{consts::*, Vec};
+use numtoa::NumToA;
+use sha2::{Digest, Sha256};
+
+pub const MAX_KEYS: usize = 5;
+pub const MAX_DESCRIPTOR_LEN: usize = 1024;
+/// Version bytes of SLIP-132 multisig P2WSH keys, mainnet and test networks
+const ZPUB_MULTISIG: [u8; 4] = [0x02, 0xAA, 0x7E, 0xD3];
+const VPUB_MULTISIG: [u8; 4] = [0x02, 0x57, 0x54, 0x83];
+
+const KEY_LABELS: [&str; MAX_KEYS] = ["Key 1", "Key 2", "Key 3", "Key 4", "Key 5"];
+
+/// `OP_m <key>... OP_n OP_CHECKMULTISIG` for up to `MAX_KEYS` keys
+pub type WitnessScript = Vec<u8, U176>;
+pub type Fields = Vec<Field, U8>;
+
+pub struct Cosigner {
+    pub fingerprint: [u8; 4],
+    /// Path from the co-signer's master key to `xpub`, at most 8 deep
+    pub origin: Vec<u32, U8>,
+    pub xpub: ExtendedPubKey,
+    /// Child of `xpub` the receive addresses are under
+    pub receive: u32,
+    /// Child of `xpub` the change addresses are under, if the key has them
+    pub change: Option<u32>,
+}
+
+impl Cosigner {
+    /// Child of `xpub` the addresses are under, `change` being 0 for
+    /// receive and 1 for change
+    pub fn branch(&self, change: u32) -> Result<u32> {
+        match (change, self.change) {
+            (0, _) => Ok(self.receive),
+            (1, Some(branch)) => Ok(branch),
+            (1, None) => Err(WalletErr::from("multisig wallet has no change addresses")),
+            _ => Err(WalletErr::from("change must be 0 or 1")),
+        }
+    }
+
+    /// Whether `branch` of `xpub` holds change, `None` if it isn't one of
+    /// the key's
+    pub fn is_change(&self, branch: u32) -> Option<bool> {
+        if branch == self.receive {
+            Some(false)
+        } else if Some(branch) == self.change {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Multisig {
+    pub threshold: usize,
+    pub keys: Vec<Cosigner, U5>,
+    mainnet: bool,
+}
+
+fn invalid() -> WalletErr {
+    WalletErr::from("invalid descriptor")
+}
+
+fn parse_hex(s: &str, out: &mut [u8]) -> Result<()> {
+    if s.len() != out.len() * 2 {
+        return Err(invalid());
+    }
+    for (o, pair) in out.iter_mut().zip(s.as_bytes().chunks(2)) {
+        let pair = core::str::from_utf8(pair).map_err(|_| invalid())?;
+        *o = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+    Ok(())
+}
+
+fn parse_branch(s: &str) -> Result<u32> {
+    match s.parse() {
+        Ok(index) if index < bip32::HARDENED => Ok(index),
+        _ => Err(WalletErr::from("unsupported key derivation")),
+    }
+}
+
+/// `[fingerprint/origin/path]xpub` followed by the branches of its addresses,
+/// `/<0;1>/*` for receive and change (BIP389) or `/0/*` for receive only
+fn parse_key(s: &str) -> Result<Cosigner> {
+    let s = s
+        .strip_prefix('[')
+        .ok_or_else(|| WalletErr::from("key origins are required"))?;
+    let end = s.find(']').ok_or_else(invalid)?;
+    let mut origin_parts = s[..end].split('/');
+    let mut fingerprint = [0u8; 4];
+    parse_hex(origin_parts.next().ok_or_else(invalid)?, &mut fingerprint)?;
+    let mut origin = Vec::new();
+    for part in origin_parts {
+        origin
+            .push(bip32::parse_index(part).ok_or_else(invalid)?)
+            .map_err(|_| WalletErr::from("key origin too deep"))?;
+    }
+
+    let key = &s[end + 1..];
+    let (xpub, suffix) = key.split_at(key.find('/').unwrap_or(key.len()));
+    // Only ranged keys one unhardened step below the xpub make addresses
+    let branches = suffix
+        .strip_prefix('/')
+        .and_then(|s| s.strip_suffix("/*"))
+        .ok_or_else(|| WalletErr::from("unsupported key derivation"))?;
+    let (receive, change) = match branches.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+        Some(multipath) => {
+            let mut parts = multipath.split(';');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(receive), Some(change), None) => {
+                    (parse_branch(receive)?, Some(parse_branch(change)?))
+                }
+                _ => return Err(WalletErr::from("unsupported key derivation")),
+            }
+        }
+        None => (parse_branch(branches)?, None),
+    };
+    if Some(receive) == change {
+        return Err(WalletErr::from("unsupported key derivation"));
+    }
+    let xpub = ExtendedPubKey::parse(xpub)?;
+    if xpub.depth as usize != origin.len() {
+        return Err(WalletErr::from("key origin doesn't match key depth"));
+    }
+    Ok(Cosigner {
+        fingerprint,
+        origin,
+        xpub,
+        receive,
+        change,
+    })
+}
+
+/// BIP380 descriptor checksum of `s`
+fn checksum(s: &str) -> Result<[u8; 8]> {
+    const INPUT_CHARSET: &[u8] = b"0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+    const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    const GENERATOR: [u64; 5] = [
+        0xf5_dee5_1989,
+        0xa9_fdca_3312,
+        0x1b_ab10_e32d,
+        0x37_06b1_677a,
+        0x64_4d62_6ffd,
+    ];
+    let polymod = |chk: u64, value: u64| {
+        let top = chk >> 35;
+        let chk = ((chk & 0x7_ffff_ffff) << 5) ^ value;
+        GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold(chk, |chk, (_, g)| chk ^ g)
+    };
+
+    let mut chk = 1;
+    let mut group = 0;
+    let mut group_len = 0;
+    for c in s.bytes() {
+        let v = INPUT_CHARSET
+            .iter()
+            .position(|i| *i == c)
+            .ok_or_else(invalid)? as u64;
+        chk = polymod(chk, v & 31);
+        group = group * 3 + (v >> 5);
+        group_len += 1;
+        if group_len == 3 {
+            chk = polymod(chk, group);
+            group = 0;
+            group_len = 0;
+        }
+    }
+    if group_len > 0 {
+        chk = polymod(chk, group);
+    }
+    let chk = (0..8).fold(chk, |chk, _| polymod(chk, 0)) ^ 1;
+
+    let mut out = [0u8; 8];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = CHECKSUM_CHARSET[((chk >> (5 * (7 - i))) & 31) as usize];
+    }
+    Ok(out)
+}
+
+impl Multisig {
+    pub fn parse(descriptor: &str) -> Result<Self> {
+        let body = match descriptor.find('#') {
+            Some(pos) => {
+                let (body, sum) = descriptor.split_at(pos);
+                if sum.as_bytes()[1..] != checksum(body)? {
+                    return Err(WalletErr::from("descriptor checksum mismatch"));
+                }
+                body
+            }
+            None => descriptor,
+        };
+        let inner = body
+            .strip_prefix("wsh(sortedmulti(")
+            .and_then(|s| s.strip_suffix("))"))
+            .ok_or_else(|| WalletErr::from("only wsh(sortedmulti(..)) is supported"))?;
+
+        let mut parts = inner.split(',');
+        let threshold: usize = parts
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(invalid)?;
+        let mut keys: Vec<Cosigner, U5> = Vec::new();
+        for part in parts {
+            keys.push(parse_key(part)?)
+                .map_err(|_| WalletErr::from("too many keys"))?;
+        }
+        if threshold == 0 || threshold > keys.len() {
+            return Err(WalletErr::from("invalid threshold"));
+        }
+
+        let is_mainnet = |k: &Cosigner| match k.xpub.version {
+            XPUB | ZPUB_MULTISIG => Ok(true),
+            TPUB | VPUB_MULTISIG => Ok(false),
+            _ => Err(WalletErr::from("unsupported key version")),
+        };
+        let mainnet = is_mainnet(&keys[0])?;
+        for (i, k) in keys.iter().enumerate() {
+            if is_mainnet(k)? != mainnet {
+                return Err(WalletErr::from("keys are for different networks"));
+            }
+            if keys[..i]
+                .iter()
+                .any(|o| o.xpub.public_key == k.xpub.public_key)
+            {
+                return Err(WalletErr::from("duplicate key"));
+            }
+            if k.change.is_some() != keys[0].change.is_some() {
+                return Err(WalletErr::from("keys disagree on change addresses"));
+            }
+        }
+        Ok(Multisig {
+            threshold,
+            keys,
+            mainnet,
+        })
+    }
+
+    pub fn check_network(&self, network: BtcNetwork) -> Result<()> {
+        if self.mainnet == (network == BtcNetwork::Mainnet) {
+            Ok(())
+        } else {
+            Err(WalletErr::from("multisig wallet is for another network"))
+        }
+    }
+
+    /// This device's key in the wallet
+    pub fn ours(&self, seed: &[u8]) -> Result<&Cosigner> {
+        let fingerprint = ExtendedKey::master(seed)?.fingerprint();
+        for k in self.keys.iter().filter(|k| k.fingerprint == fingerprint) {
+            let key = ExtendedKey::derive(seed, &k.origin)?;
+            if key.public_key() == k.xpub.public_key && key.chain_code == k.xpub.chain_code {
+                return Ok(k);
+            }
+        }
+        Err(WalletErr::from("this device is not a co-signer"))
+    }
+
+    /// Identifies the wallet the same way on every co-signer, however the
+    /// descriptor was written
+    pub fn id(&self) -> [u8; 4] {
+        let mut keys: Vec<&Cosigner, U5> = self.keys.iter().collect();
+        keys.sort_unstable_by_key(|k| k.xpub.public_key);
+        let mut h = Sha256::new().chain([self.threshold as u8]);
+        for k in keys {
+            h.update(k.fingerprint);
+            for idx in k.origin.iter() {
+                h.update(idx.to_le_bytes());
+            }
+            h.update(k.xpub.chain_code);
+            h.update(k.xpub.public_key);
+            h.update(k.receive.to_le_bytes());
+            if let Some(change) = k.change {
+                h.update(change.to_le_bytes());
+            }
+        }
+        let mut id = [0u8; 4];
+        id.copy_from_slice(&h.finalize()[..4]);
+        id
+    }
+
+    /// The script at `index` of the receive (`change` 0) or change (1)
+    /// addresses, each key at its own branch
+    pub fn witness_script(&self, change: u32, index: u32) -> Result<WitnessScript> {
+        let mut pubkeys: Vec<[u8; 33], U5> = Vec::new();
+        for k in self.keys.iter() {
+            let _ = pubkeys.push(k.xpub.child(k.branch(change)?)?.child(index)?.public_key);
+        }
+        pubkeys.sort_unstable();
+
+        let mut script = WitnessScript::new();
+        // OP_1 is 0x51; the key count is at most MAX_KEYS, so this all fits
+        let _ = script.push(0x50 + self.threshold as u8);
+        for pk in pubkeys.iter() {
+            let _ = script.push(33);
+            let _ = script.extend_from_slice(pk);
+        }
+        let _ = script.push(0x50 + pubkeys.len() as u8);
+        let _ = script.push(0xae);
+        Ok(script)
+    }
+
+    /// The P2WSH script of the wallet at `change`/`index`
+    pub fn script_pubkey(&self, change: u32, index: u32) -> Result<[u8; 34]> {
+        Ok(p2wsh_script(&self.witness_script(change, index)?))
+    }
+
+    pub fn address(&self, network: BtcNetwork, change: u32, index: u32) -> Result<Bech32String> {
+        self.check_network(network)?;
+        let script = self.script_pubkey(change, index)?;
+        bech32::segwit_address(btc::hrp(network), 0, &script[2..])
+    }
+
+    /// What the user checks before registering the wallet
+    pub fn summary(&self, seed: &[u8]) -> Result<Fields> {
+        let ours = self.ours(seed)?.xpub.public_key;
+        let mut fields = Fields::new();
+        let mut policy = FieldValue::new();
+        let mut buf = [0u8; 20];
+        let _ = policy.push_str(self.threshold.numtoa_str(10, &mut buf));
+        let _ = policy.push_str(" of ");
+        let _ = policy.push_str(self.keys.len().numtoa_str(10, &mut buf));
+        let _ = fields.push(Field {
+            label: "Policy",
+            value: policy,
+        });
+        let _ = fields.push(Field {
+            label: "Wallet id",
+            value: hex(&self.id()),
+        });
+        for (label, k) in KEY_LABELS.iter().zip(self.keys.iter()) {
+            let mut value = hex(&k.fingerprint);
+            if k.xpub.public_key == ours {
+                let _ = value.push_str(" (this device)");
+            }
+            let _ = fields.push(Field { label, value });
+        }
+        Ok(fields)
+    }
+}
+
+/// Lowercase hex without a prefix, as fingerprints are usually written
+fn hex(bytes: &[u8]) -> FieldValue {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut out = FieldValue::new();
+    for b in bytes {
+        let _ = out.push(DIGITS[(b >> 4) as usize] as char);
+        let _ = out.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+    out
+}
+
+/// The P2WSH script paying to `witness_script`
+pub fn p2wsh_script(witness_script: &[u8]) -> [u8; 34] {
+    let mut script = [0u8; 34];
+    script[1] = 0x20;
+    script[2..].copy_from_slice(&Sha256::digest(witness_script));
+    script
+}
+
+#[cfg(target_os = "none")]
+pub use storage::{descriptor, register, registered};
+
+/// The registered descriptor in flash
+#[cfg(target_os = "none")]
+mod storage {
+    use super::{Multisig, MAX_DESCRIPTOR_LEN};
+    use crate::{
+        error::WalletErr,
+        layout::{FLASH_START, MULTISIG_ADDR, MULTISIG_SIZE},
+        Result,
+    };
+
+    use stm32f4xx_hal::{flash::FlashExt, stm32};
+
+    // Registered descriptors live in an append-only log of length prefixed
+    // records, as the flash is only ever programmed and never erased. The
+    // newest one is the registered wallet.
+    const EMPTY_LEN: u16 = 0xFFFF;
+
+    fn read(offset: usize, len: usize) -> &'static [u8] {
+        let addr = FLASH_START + MULTISIG_ADDR + offset as u32;
+        unsafe { core::slice::from_raw_parts(addr as *const u8, len) }
+    }
+
+    /// Offsets and lengths of every stored descriptor, oldest first
+    fn records() -> impl Iterator<Item = (usize, usize)> {
+        let mut offset = 0;
+        core::iter::from_fn(move || {
+            if offset + 2 > MULTISIG_SIZE as usize {
+                return None;
+            }
+            let len_bytes = read(offset, 2);
+            let len = u16::from_le_bytes([len_bytes[0], len_bytes[1]]);
+            if len == EMPTY_LEN || offset + 2 + len as usize > MULTISIG_SIZE as usize {
+                return None;
+            }
+            let record = (offset + 2, len as usize);
+            offset += 2 + len as usize;
+            Some(record)
+        })
+    }
+
+    /// The registered descriptor, if any
+    pub fn descriptor() -> Option<&'static str> {
+        let (offset, len) = records().last()?;
+        core::str::from_utf8(read(offset, len)).ok()
+    }
+
+    pub fn registered() -> Result<Option<Multisig>> {
+        descriptor().map(Multisig::parse).transpose()
+    }
+
+    /// Stores `descriptor` as the registered wallet. Parse and confirm it first.
+    pub fn register(descriptor: &str) -> Result<()> {
+        if descriptor.len() > MAX_DESCRIPTOR_LEN {
+            return Err(WalletErr::from("descriptor too long"));
+        }
+        let offset = records().last().map(|(o, l)| o + l).unwrap_or(0);
+        if offset + 2 + descriptor.len() > MULTISIG_SIZE as usize {
+            return Err(WalletErr::from("multisig storage full"));
+        }
+
+        let dp = unsafe { stm32::Peripherals::steal() };
+        let mut flash = dp.FLASH;
+        let mut unlocked = flash.unlocked();
+        let start = (MULTISIG_ADDR as usize) + offset;
+        unlocked.program(start, &(descriptor.len() as u16).to_le_bytes())?;
+        unlocked.program(start + 2, descriptor.as_bytes())?;
+        Ok(())
+    }
+}
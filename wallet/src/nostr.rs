@@ -0,0 +1,166 @@
+//! Nostr keys (NIP-06) at `m/44'/1237'/account'/0/0`, `npub` encoding
+//! (NIP-19) and BIP340 signatures of event ids (NIP-01).
+use crate::{
+    bech32::{self, Bech32String, Data, Variant},
+    bip32::{ExtendedKey, HARDENED},
+    display::{Field, FieldValue},
+    error::WalletErr,
+    json,
+    schnorr::{self, Signature, XOnly},
+    Result,
+};
+
+use heapless::{consts::*, Vec};
+use numtoa::NumToA;
+use sha2::{Digest, Sha256};
+
+const COIN_TYPE: u32 = 1237;
+
+pub type Fields = Vec<Field, U4>;
+
+pub fn path(account: u32) -> Result<[u32; 5]> {
+    if account >= HARDENED {
+        return Err(WalletErr::from("account out of range"));
+    }
+    Ok([
+        44 | HARDENED,
+        COIN_TYPE | HARDENED,
+        account | HARDENED,
+        0,
+        0,
+    ])
+}
+
+/// Secret key and x-only public key of `account`
+pub fn keys(seed: &[u8], account: u32) -> Result<([u8; 32], XOnly)> {
+    let secret = ExtendedKey::derive(seed, &path(account)?)?.secret_bytes();
+    let pubkey = schnorr::public_key(&secret)?;
+    Ok((secret, pubkey))
+}
+
+pub fn npub(pubkey: &XOnly) -> Result<Bech32String> {
+    let mut data = Data::new();
+    bech32::to_base32(pubkey, &mut data)?;
+    bech32::encode("npub", &data, Variant::Bech32)
+}
+
+fn malformed() -> WalletErr {
+    WalletErr::from("malformed Nostr event")
+}
+
+/// Whether `s` is the lower case hex of `bytes`, as NIP-01 writes keys
+fn is_hex_of(s: &str, bytes: &[u8]) -> bool {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    s.len() == 2 * bytes.len()
+        && s.as_bytes()
+            .chunks(2)
+            .zip(bytes)
+            .all(|(c, b)| c == [DIGITS[(b >> 4) as usize], DIGITS[(b & 0xf) as usize]])
+}
+
+fn kind_name(kind: u16) -> Option<&'static str> {
+    Some(match kind {
+        0 => "Profile",
+        1 => "Note",
+        3 => "Follow list",
+        4 => "Encrypted DM",
+        5 => "Deletion",
+        6 => "Repost",
+        7 => "Reaction",
+        9734 => "Zap request",
+        10002 => "Relay list",
+        22242 => "Relay auth",
+        30023 => "Article",
+        _ => return None,
+    })
+}
+
+/// The contents of a JSON string as far as the display can show them:
+/// escapes undone where they are printable, anything else as '?', and cut
+/// off with ".." if too long
+fn shown_text(s: &str) -> FieldValue {
+    let mut out = FieldValue::new();
+    let room = out.capacity() - 2;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if out.len() == room {
+            let _ = out.push_str("..");
+            break;
+        }
+        let c = match c {
+            '\\' => match chars.next() {
+                Some(c @ '"') | Some(c @ '\\') | Some(c @ '/') => c,
+                Some('n') | Some('r') | Some('t') => ' ',
+                Some('u') => {
+                    chars.nth(3);
+                    '?'
+                }
+                _ => '?',
+            },
+            c if c == ' ' || c.is_ascii_graphic() => c,
+            _ => '?',
+        };
+        let _ = out.push(c);
+    }
+    out
+}
+
+/// What the user checks before `ours` signs `event`, the NIP-01
+/// serialization `[0,pubkey,created_at,kind,tags,content]` of an event
+pub fn summary(event: &[u8], ours: &XOnly) -> Result<Fields> {
+    let event = json::validate(event)?;
+    let mut items = json::items(event)?;
+    let mut next = || items.next().ok_or_else(malformed);
+    let (zero, pubkey, created_at, kind, tags, content) =
+        (next()?, next()?, next()?, next()?, next()?, next()?);
+    if items.next().is_some() || zero != "0" {
+        return Err(malformed());
+    }
+    if !is_hex_of(json::string(pubkey)?, ours) {
+        return Err(WalletErr::from("event is by another key"));
+    }
+    if created_at.is_empty() || !created_at.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(malformed());
+    }
+    let kind: u16 = kind.parse().map_err(|_| malformed())?;
+
+    let mut fields = Fields::new();
+    let mut buf = [0u8; 20];
+    let mut value = FieldValue::new();
+    let _ = value.push_str(kind.numtoa_str(10, &mut buf));
+    if let Some(name) = kind_name(kind) {
+        let _ = value.push_str(" (");
+        let _ = value.push_str(name);
+        let _ = value.push(')');
+    }
+    let _ = fields.push(Field {
+        label: "Kind",
+        value,
+    });
+    let tag_count = json::items(tags)?.count();
+    if tag_count > 0 {
+        let mut value = FieldValue::new();
+        let _ = value.push_str(tag_count.numtoa_str(10, &mut buf));
+        let _ = fields.push(Field {
+            label: "Tags",
+            value,
+        });
+    }
+    let _ = fields.push(Field {
+        label: "Content",
+        value: shown_text(json::string(content)?),
+    });
+    Ok(fields)
+}
+
+/// The event id, which is what gets signed
+pub fn event_id(event: &[u8]) -> [u8; 32] {
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&Sha256::digest(event));
+    id
+}
+
+/// Signs the event `id` with fresh `aux` randomness
+pub fn sign(secret: &[u8; 32], id: &[u8; 32], aux: &[u8; 32]) -> Result<Signature> {
+    schnorr::sign(secret, id, aux)
+}
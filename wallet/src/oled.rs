@@ -0,0 +1,27 @@
+//! The SSD1306 OLED behind `display::Screen`
+use crate::{display::Screen, error::WalletErr, Result};
+
+use ssd1306::{mode::GraphicsMode, prelude::*};
+
+impl<DI, DSIZE> Screen for GraphicsMode<DI, DSIZE>
+where
+    DI: WriteOnlyDataCommand,
+    DSIZE: DisplaySize,
+{
+    fn show(&mut self) -> Result<()> {
+        self.flush()
+            .map_err(|_| WalletErr::from("failed to flush display"))
+    }
+
+    fn set_brightness(&mut self, level: u8) -> Result<()> {
+        let brightness = match level {
+            1 => Brightness::DIMMEST,
+            2 => Brightness::DIM,
+            3 => Brightness::NORMAL,
+            4 => Brightness::BRIGHT,
+            _ => Brightness::BRIGHTEST,
+        };
+        GraphicsMode::set_brightness(self, brightness)
+            .map_err(|_| WalletErr::from("failed to set display brightness"))
+    }
+}
@@ -0,0 +1,129 @@
+//! User presence: nothing secret leaves the device until the person holding
+//! it has approved what is on the screen.
+use embedded_hal::{blocking::delay::DelayMs, digital::v2::InputPin};
+
+pub const DEFAULT_TIMEOUT_MS: u32 = 30_000;
+/// Consecutive identical samples (1 ms apart) before a button state is trusted
+const DEBOUNCE_SAMPLES: u8 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Approved,
+    Rejected,
+    TimedOut,
+}
+
+/// Anything able to ask the user for a yes/no, e.g. physical buttons or a
+/// simulator injecting answers
+pub trait UserPresence {
+    /// Checks whether a button was pressed, waiting at most a millisecond.
+    /// Never returns `Decision::TimedOut`.
+    fn poll(&mut self) -> Option<Decision>;
+
+    /// Blocks until the user decides or `timeout_ms` has passed
+    fn confirm(&mut self, timeout_ms: u32) -> Decision {
+        (0..timeout_ms)
+            .find_map(|_| self.poll())
+            .unwrap_or(Decision::TimedOut)
+    }
+}
+
+/// Filters contact bounce out of a stream of raw button samples
+pub struct Debouncer {
+    pressed: bool,
+    count: u8,
+}
+
+impl Debouncer {
+    pub const fn new() -> Self {
+        Debouncer {
+            pressed: false,
+            count: 0,
+        }
+    }
+
+    /// Feeds one raw sample in, returns true once per debounced press
+    pub fn update(&mut self, raw_pressed: bool) -> bool {
+        if raw_pressed == self.pressed {
+            self.count = 0;
+            return false;
+        }
+        self.count += 1;
+        if self.count < DEBOUNCE_SAMPLES {
+            return false;
+        }
+        self.count = 0;
+        self.pressed = raw_pressed;
+        raw_pressed
+    }
+}
+
+impl Default for Debouncer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An active-low push button
+pub struct Button<P> {
+    pin: P,
+    debouncer: Debouncer,
+}
+
+impl<P: InputPin> Button<P> {
+    pub fn new(pin: P) -> Self {
+        Button {
+            pin,
+            debouncer: Debouncer::new(),
+        }
+    }
+
+    /// Samples the pin, returns true once per debounced press
+    pub fn poll(&mut self) -> bool {
+        let raw = self.pin.is_low().unwrap_or(false);
+        self.debouncer.update(raw)
+    }
+}
+
+/// A confirm and a reject button, sampled once per millisecond.
+/// USB is not serviced while waiting, the host sees its reads time out.
+pub struct Buttons<C, R, DL> {
+    confirm: Button<C>,
+    reject: Button<R>,
+    delay: DL,
+}
+
+impl<C, R, DL> Buttons<C, R, DL>
+where
+    C: InputPin,
+    R: InputPin,
+    DL: DelayMs<u32>,
+{
+    pub fn new(confirm: C, reject: R, delay: DL) -> Self {
+        Buttons {
+            confirm: Button::new(confirm),
+            reject: Button::new(reject),
+            delay,
+        }
+    }
+}
+
+impl<C, R, DL> UserPresence for Buttons<C, R, DL>
+where
+    C: InputPin,
+    R: InputPin,
+    DL: DelayMs<u32>,
+{
+    fn poll(&mut self) -> Option<Decision> {
+        // Reject wins if both are pressed at once
+        let decision = if self.reject.poll() {
+            Some(Decision::Rejected)
+        } else if self.confirm.poll() {
+            Some(Decision::Approved)
+        } else {
+            None
+        };
+        self.delay.delay_ms(1);
+        decision
+    }
+}
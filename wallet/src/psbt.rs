@@ -0,0 +1,787 @@
+//! Partially signed Bitcoin transactions (BIP174, version 0). Only inputs
+//! spending this wallet's outputs are signed: BIP84 P2WPKH and registered
+//! multisig P2WSH with BIP143 sighashes and ECDSA, BIP86 P2TR with BIP341
+//! sighashes and Schnorr.
+use crate::{
+    bech32,
+    bip32::{hash160, ExtendedKey, HARDENED},
+    btc::{self, BtcNetwork, BtcScript},
+    display::{Field, FieldValue},
+    error::WalletErr,
+    eth::format_units,
+    multisig::{p2wsh_script, Multisig, WitnessScript},
+    schnorr, Result,
+};
+
+use heapless::{consts::*, Vec};
+use k256::ecdsa::{signature::DigestSigner, Signature, SigningKey};
+use sha2::{Digest, Sha256};
+
+const MAGIC: &[u8] = b"psbt\xff";
+const GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const IN_NON_WITNESS_UTXO: u8 = 0x00;
+const IN_WITNESS_UTXO: u8 = 0x01;
+const IN_PARTIAL_SIG: u8 = 0x02;
+const IN_SIGHASH_TYPE: u8 = 0x03;
+const IN_BIP32_DERIVATION: u8 = 0x06;
+const IN_TAP_KEY_SIG: u8 = 0x13;
+const IN_TAP_BIP32_DERIVATION: u8 = 0x16;
+const OUT_BIP32_DERIVATION: u8 = 0x02;
+const OUT_TAP_BIP32_DERIVATION: u8 = 0x07;
+const SIGHASH_DEFAULT: u32 = 0x00;
+const SIGHASH_ALL: u32 = 0x01;
+
+/// Largest PSBT the device buffers
+pub type Buffer = Vec<u8, U4096>;
+pub type Fields = Vec<Field, U16>;
+type Inputs<'a> = Vec<Input<'a>, U16>;
+type Outputs<'a> = Vec<Output<'a>, U16>;
+
+/// A key-value map with its position in the PSBT, so entries can be added
+struct Map<'a> {
+    pairs: &'a [u8],
+    /// Offset of the map's terminating 0x00
+    end: usize,
+}
+
+struct Input<'a> {
+    prevout: &'a [u8],
+    sequence: u32,
+    map: Map<'a>,
+}
+
+struct Output<'a> {
+    value: u64,
+    script: &'a [u8],
+    /// The serialized output, as hashed for the sighash
+    raw: &'a [u8],
+    map: Map<'a>,
+}
+
+pub struct Psbt<'a> {
+    version: u32,
+    locktime: u32,
+    inputs: Inputs<'a>,
+    outputs: Outputs<'a>,
+}
+
+/// A signature entry to add to the input map ending at `at`
+pub struct PartialSig {
+    at: usize,
+    key: Vec<u8, U34>,
+    value: Vec<u8, U73>,
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+fn malformed() -> WalletErr {
+    WalletErr::from("malformed PSBT")
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).ok_or_else(malformed)?;
+        let out = self.buf.get(self.pos..end).ok_or_else(malformed)?;
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    fn compact_size(&mut self) -> Result<usize> {
+        let n = match self.u8()? {
+            0xfd => {
+                let b = self.take(2)?;
+                u16::from_le_bytes([b[0], b[1]]) as usize
+            }
+            0xfe => self.u32()? as usize,
+            0xff => return Err(malformed()),
+            n => n as usize,
+        };
+        Ok(n)
+    }
+
+    fn var_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.compact_size()?;
+        self.take(len)
+    }
+
+    /// Reads a key-value map, up to and including its terminator
+    fn map(&mut self) -> Result<Map<'a>> {
+        let start = self.pos;
+        loop {
+            let key_start = self.pos;
+            if self.var_bytes()?.is_empty() {
+                return Ok(Map {
+                    pairs: &self.buf[start..key_start],
+                    end: key_start,
+                });
+            }
+            self.var_bytes()?;
+        }
+    }
+}
+
+impl<'a> Map<'a> {
+    /// (key, value) pairs, the key type byte included in the key
+    fn pairs(&self) -> impl Iterator<Item = (&'a [u8], &'a [u8])> {
+        // Already checked to be well formed by `Reader::map`
+        let mut r = Reader {
+            buf: self.pairs,
+            pos: 0,
+        };
+        core::iter::from_fn(move || {
+            if r.pos == r.buf.len() {
+                return None;
+            }
+            let key = r.var_bytes().ok()?;
+            let value = r.var_bytes().ok()?;
+            Some((key, value))
+        })
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&'a [u8]> {
+        self.pairs().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// BIP32 derivations of key type `bip32` (compressed keys) and
+    /// `tap_bip32` (x-only keys): (script, public key, fingerprint || path).
+    /// Taproot leaf hashes are skipped, only key path spends are signed.
+    fn derivations(
+        &self,
+        bip32: u8,
+        tap_bip32: u8,
+    ) -> impl Iterator<Item = (BtcScript, &'a [u8], &'a [u8])> {
+        self.pairs()
+            .filter_map(move |(k, v)| match k.split_first() {
+                Some((t, pubkey)) if *t == bip32 && pubkey.len() == 33 => {
+                    Some((BtcScript::P2wpkh, pubkey, v))
+                }
+                Some((t, pubkey)) if *t == tap_bip32 && pubkey.len() == 32 => {
+                    let mut r = Reader { buf: v, pos: 0 };
+                    let leaves = r.compact_size().ok()?;
+                    r.take(leaves.checked_mul(32)?).ok()?;
+                    Some((BtcScript::P2tr, pubkey, &v[r.pos..]))
+                }
+                _ => None,
+            })
+    }
+}
+
+/// The P2WPKH script paying `pubkey`
+pub fn p2wpkh_script(pubkey: &[u8; 33]) -> [u8; 22] {
+    let mut script = [0u8; 22];
+    script[1] = 0x14;
+    script[2..].copy_from_slice(&hash160(pubkey));
+    script
+}
+
+/// The P2TR script paying the BIP86 output key of `pubkey`
+fn p2tr_script(pubkey: &[u8; 33]) -> Result<[u8; 34]> {
+    let mut script = [0u8; 34];
+    script[0] = 0x51;
+    script[1] = 0x20;
+    script[2..].copy_from_slice(&schnorr::taproot_output_key(&btc::internal_key(pubkey))?);
+    Ok(script)
+}
+
+pub fn compact_size(n: usize) -> Vec<u8, U3> {
+    let mut out = Vec::new();
+    if n < 0xfd {
+        let _ = out.push(n as u8);
+    } else {
+        let _ = out.push(0xfd);
+        let _ = out.extend_from_slice(&(n as u16).to_le_bytes());
+    }
+    out
+}
+
+/// DER encoding of one signature integer
+fn der_int(out: &mut Vec<u8, U73>, int: &[u8]) {
+    let int = &int[int.iter().position(|b| *b != 0).unwrap_or(int.len() - 1)..];
+    let pad = int[0] & 0x80 != 0;
+    // At most 2 + 33 bytes, twice, plus a 3 byte header fits in U73
+    let _ = out.push(0x02);
+    let _ = out.push(int.len() as u8 + pad as u8);
+    if pad {
+        let _ = out.push(0);
+    }
+    let _ = out.extend_from_slice(int);
+}
+
+pub fn der_signature(sig: &Signature) -> Vec<u8, U73> {
+    let (r, s) = sig.as_ref().split_at(32);
+    let mut ints: Vec<u8, U73> = Vec::new();
+    der_int(&mut ints, r);
+    der_int(&mut ints, s);
+    let mut out = Vec::new();
+    let _ = out.push(0x30);
+    let _ = out.push(ints.len() as u8);
+    let _ = out.extend_from_slice(&ints);
+    out
+}
+
+/// The keys the device signs with
+pub struct Wallet<'a> {
+    pub seed: &'a [u8],
+    pub network: BtcNetwork,
+    /// The registered multisig wallet, if it is for `network`
+    pub multisig: Option<&'a Multisig>,
+    /// Whether single key inputs and outputs of an account are ours
+    pub is_enabled: &'a dyn Fn(u32) -> bool,
+}
+
+enum Spend {
+    P2wpkh,
+    P2tr,
+    Multisig(WitnessScript),
+}
+
+/// Why an input or output belongs to this wallet: its key and script
+struct Owned {
+    key: ExtendedKey,
+    spend: Spend,
+    change: bool,
+}
+
+/// How an output paying the key of type `kind` at `path` is spent and
+/// whether it is change, if the path is one of this wallet's
+fn spend(wallet: &Wallet, kind: BtcScript, path: &[u32]) -> Result<Option<(Spend, bool)>> {
+    if let [_, _, account, change, index] = *path {
+        let expected = btc::path(wallet.network, kind, account & !HARDENED, change, index);
+        if account & HARDENED != 0
+            && expected.ok().as_ref().map(|p| &p[..]) == Some(path)
+            && (wallet.is_enabled)(account & !HARDENED)
+        {
+            let spend = match kind {
+                BtcScript::P2wpkh => Spend::P2wpkh,
+                BtcScript::P2tr => Spend::P2tr,
+            };
+            return Ok(Some((spend, change == 1)));
+        }
+    }
+    match (kind, wallet.multisig) {
+        (BtcScript::P2wpkh, Some(ms)) => {
+            let ours = match ms.ours(wallet.seed) {
+                Ok(ours) => ours,
+                Err(_) => return Ok(None),
+            };
+            match path.split_at(ours.origin.len().min(path.len())) {
+                (prefix, [branch, index]) if prefix == &ours.origin[..] && *index < HARDENED => {
+                    match ours.is_change(*branch) {
+                        Some(change) => {
+                            let ws = ms.witness_script(change as u32, *index)?;
+                            Ok(Some((Spend::Multisig(ws), change)))
+                        }
+                        None => Ok(None),
+                    }
+                }
+                _ => Ok(None),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Finds the derivation in `map` of a key of `wallet` whose script is `script`
+fn owned(wallet: &Wallet, map: &Map, key_types: (u8, u8), script: &[u8]) -> Result<Option<Owned>> {
+    let fingerprint = ExtendedKey::master(wallet.seed)?.fingerprint();
+    for (kind, pubkey, origin) in map.derivations(key_types.0, key_types.1) {
+        if origin.len() < 4 || origin.len() % 4 != 0 || origin[..4] != fingerprint {
+            continue;
+        }
+        let mut path: Vec<u32, U10> = Vec::new();
+        for c in origin[4..].chunks_exact(4) {
+            let _ = path.push(u32::from_le_bytes([c[0], c[1], c[2], c[3]]));
+        }
+        let (spend, change) = match spend(wallet, kind, &path)? {
+            Some(spend) if path.len() * 4 == origin.len() - 4 => spend,
+            _ => continue,
+        };
+        let key = ExtendedKey::derive(wallet.seed, &path)?;
+        let ours = key.public_key();
+        let matches = match &spend {
+            Spend::P2wpkh => pubkey == &ours[..] && script == &p2wpkh_script(&ours)[..],
+            Spend::P2tr => {
+                pubkey == &btc::internal_key(&ours)[..] && script == &p2tr_script(&ours)?[..]
+            }
+            Spend::Multisig(ws) => pubkey == &ours[..] && script == &p2wsh_script(ws)[..],
+        };
+        if matches {
+            return Ok(Some(Owned { key, spend, change }));
+        }
+    }
+    Ok(None)
+}
+
+/// The address of `script`, if it is a standard one
+fn standard_address(network: BtcNetwork, script: &[u8]) -> Result<Option<FieldValue>> {
+    let (p2pkh, p2sh) = match network {
+        BtcNetwork::Mainnet => (0x00, 0x05),
+        BtcNetwork::Testnet | BtcNetwork::Regtest => (0x6f, 0xc4),
+    };
+    let mut legacy = [0u8; 21];
+    let addr = match script {
+        [0x00, 0x14, program @ ..] if program.len() == 20 => {
+            bech32::segwit_address(btc::hrp(network), 0, program)?
+        }
+        [0x00, 0x20, program @ ..] if program.len() == 32 => {
+            bech32::segwit_address(btc::hrp(network), 0, program)?
+        }
+        [0x51, 0x20, program @ ..] if program.len() == 32 => {
+            bech32::segwit_address(btc::hrp(network), 1, program)?
+        }
+        [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if hash.len() == 20 => {
+            legacy[0] = p2pkh;
+            legacy[1..].copy_from_slice(hash);
+            crate::base58::encode_check(&legacy)
+        }
+        [0xa9, 0x14, hash @ .., 0x87] if hash.len() == 20 => {
+            legacy[0] = p2sh;
+            legacy[1..].copy_from_slice(hash);
+            crate::base58::encode_check(&legacy)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(FieldValue::from(addr.as_str())))
+}
+
+/// How `script` is shown: its address if it has a standard one
+fn script_address(network: BtcNetwork, script: &[u8]) -> Result<FieldValue> {
+    if let Some(addr) = standard_address(network, script)? {
+        return Ok(addr);
+    }
+    let mut out = FieldValue::from("script ");
+    let _ = out.push_str(&crate::display::hex_str::<U80>(script));
+    Ok(out)
+}
+
+impl<'a> Input<'a> {
+    fn witness_utxo(&self) -> Result<&'a [u8]> {
+        self.map
+            .get(&[IN_WITNESS_UTXO])
+            .ok_or_else(|| WalletErr::from("input has no witness UTXO"))
+    }
+
+    /// The amount and script of the output this input spends
+    fn spent_output(&self) -> Result<(u64, &'a [u8])> {
+        let mut r = Reader {
+            buf: self.witness_utxo()?,
+            pos: 0,
+        };
+        let amount = r.u64()?;
+        Ok((amount, r.var_bytes()?))
+    }
+
+    /// Whether the witness UTXO is checked against the previous transaction.
+    /// BIP143 sighashes commit to the amount of the input signed but not to
+    /// those of the others, so without it the host can lie about them and
+    /// have fees paid the user never saw (CVE-2020-14199).
+    fn verify_prev_tx(&self) -> Result<bool> {
+        let tx = match self.map.get(&[IN_NON_WITNESS_UTXO]) {
+            Some(tx) => tx,
+            None => return Ok(false),
+        };
+        let mut vout = [0u8; 4];
+        vout.copy_from_slice(&self.prevout[32..]);
+        let (txid, output) = prev_output(tx, u32::from_le_bytes(vout))?;
+        if txid[..] != self.prevout[..32] {
+            return Err(WalletErr::from("previous transaction does not match input"));
+        }
+        if output != self.witness_utxo()? {
+            return Err(WalletErr::from(
+                "witness UTXO does not match previous transaction",
+            ));
+        }
+        Ok(true)
+    }
+}
+
+/// The txid of the serialized transaction `tx`, and its output `vout` as
+/// serialized, which is how a witness UTXO has it too
+fn prev_output(tx: &[u8], vout: u32) -> Result<([u8; 32], &[u8])> {
+    let mut r = Reader { buf: tx, pos: 0 };
+    r.u32()?;
+    // The txid leaves out the segwit marker and flag, and the witnesses
+    let segwit = tx.get(4..6) == Some(&[0x00, 0x01][..]);
+    if segwit {
+        r.take(2)?;
+    }
+    let body = r.pos;
+    let inputs = r.compact_size()?;
+    for _ in 0..inputs {
+        r.take(36)?;
+        r.var_bytes()?;
+        r.u32()?;
+    }
+    let mut output = None;
+    for i in 0..r.compact_size()? {
+        let start = r.pos;
+        r.u64()?;
+        r.var_bytes()?;
+        if i == vout as usize {
+            output = Some(&tx[start..r.pos]);
+        }
+    }
+    let body = &tx[body..r.pos];
+    if segwit {
+        for _ in 0..inputs {
+            for _ in 0..r.compact_size()? {
+                r.var_bytes()?;
+            }
+        }
+    }
+    let locktime = r.take(4)?;
+    if r.pos != tx.len() {
+        return Err(malformed());
+    }
+    let first = Sha256::new()
+        .chain(&tx[..4])
+        .chain(body)
+        .chain(locktime)
+        .finalize();
+    let mut txid = [0u8; 32];
+    txid.copy_from_slice(&Sha256::digest(&first));
+    let output =
+        output.ok_or_else(|| WalletErr::from("previous transaction has no such output"))?;
+    Ok((txid, output))
+}
+
+impl<'a> Psbt<'a> {
+    pub fn parse(raw: &'a [u8]) -> Result<Self> {
+        let mut r = Reader { buf: raw, pos: 0 };
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(WalletErr::from("not a PSBT"));
+        }
+        let global = r.map()?;
+        let unsigned = global
+            .get(&[GLOBAL_UNSIGNED_TX])
+            .ok_or_else(|| WalletErr::from("PSBT has no unsigned transaction"))?;
+
+        let mut tx = Reader {
+            buf: unsigned,
+            pos: 0,
+        };
+        let version = tx.u32()?;
+        let mut inputs = Inputs::new();
+        for _ in 0..tx.compact_size()? {
+            let prevout = tx.take(36)?;
+            if !tx.var_bytes()?.is_empty() {
+                return Err(malformed());
+            }
+            let sequence = tx.u32()?;
+            let map = Map { pairs: &[], end: 0 };
+            inputs
+                .push(Input {
+                    prevout,
+                    sequence,
+                    map,
+                })
+                .map_err(|_| WalletErr::from("too many inputs"))?;
+        }
+        let mut outputs = Outputs::new();
+        for _ in 0..tx.compact_size()? {
+            let start = tx.pos;
+            let value = tx.u64()?;
+            let script = tx.var_bytes()?;
+            let output = Output {
+                value,
+                script,
+                raw: &unsigned[start..tx.pos],
+                map: Map { pairs: &[], end: 0 },
+            };
+            outputs
+                .push(output)
+                .map_err(|_| WalletErr::from("too many outputs"))?;
+        }
+        let locktime = tx.u32()?;
+        if inputs.is_empty() || outputs.is_empty() || tx.pos != unsigned.len() {
+            return Err(malformed());
+        }
+
+        for input in inputs.iter_mut() {
+            input.map = r.map()?;
+        }
+        for output in outputs.iter_mut() {
+            output.map = r.map()?;
+        }
+        if r.pos != raw.len() {
+            return Err(malformed());
+        }
+        Ok(Psbt {
+            version,
+            locktime,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// The key signing `input`, checking the input is ours and can be signed
+    fn input_key(&self, wallet: &Wallet, input: &Input) -> Result<Owned> {
+        let (_, script) = input.spent_output()?;
+        let key_types = (IN_BIP32_DERIVATION, IN_TAP_BIP32_DERIVATION);
+        let owned = owned(wallet, &input.map, key_types, script)?
+            .ok_or_else(|| WalletErr::from("input not owned by this wallet"))?;
+        // Taproot signatures with the default type commit to all of the transaction
+        let sighash = match owned.spend {
+            Spend::P2wpkh | Spend::Multisig(_) => SIGHASH_ALL,
+            Spend::P2tr => SIGHASH_DEFAULT,
+        };
+        match input.map.get(&[IN_SIGHASH_TYPE]) {
+            None => Ok(owned),
+            Some(t) if t == sighash.to_le_bytes() => Ok(owned),
+            Some(_) => Err(WalletErr::from("unsupported sighash type")),
+        }
+    }
+
+    /// What the user confirms: every output not returning to this wallet,
+    /// then change and fee. Fails unless every input can be signed.
+    pub fn summary(&self, wallet: &Wallet) -> Result<Fields> {
+        let mut fields = Fields::new();
+        let mut push = |label, value| {
+            fields
+                .push(Field { label, value })
+                .map_err(|_| WalletErr::from("too many outputs to display"))
+        };
+
+        let mut total_in = 0u64;
+        let mut unverified = false;
+        for input in self.inputs.iter() {
+            let owned = self.input_key(wallet, input)?;
+            if !matches!(owned.spend, Spend::P2tr) && !input.verify_prev_tx()? {
+                unverified = true;
+            }
+            let (amount, _) = input.spent_output()?;
+            total_in = total_in
+                .checked_add(amount)
+                .ok_or_else(|| WalletErr::from("input amounts overflow"))?;
+        }
+
+        if unverified {
+            push(
+                "Warning",
+                FieldValue::from("input amounts unverified, fee may be higher"),
+            )?;
+        }
+
+        let mut total_out = 0u64;
+        let mut change = 0u64;
+        for output in self.outputs.iter() {
+            total_out = total_out
+                .checked_add(output.value)
+                .ok_or_else(|| WalletErr::from("output amounts overflow"))?;
+            let key_types = (OUT_BIP32_DERIVATION, OUT_TAP_BIP32_DERIVATION);
+            let ours = owned(wallet, &output.map, key_types, output.script)?;
+            match ours {
+                Some(o) if o.change => change += output.value,
+                _ => {
+                    push("Send", btc_amount(output.value))?;
+                    push("To", script_address(wallet.network, output.script)?)?;
+                }
+            }
+        }
+        if change > 0 {
+            push("Change", btc_amount(change))?;
+        }
+        let fee = total_in
+            .checked_sub(total_out)
+            .ok_or_else(|| WalletErr::from("outputs exceed inputs"))?;
+        push("Fee", btc_amount(fee))?;
+        Ok(fields)
+    }
+
+    /// Whether an output pays a script with no standard address, which is
+    /// only shown as hex
+    pub fn is_blind(&self, network: BtcNetwork) -> Result<bool> {
+        for output in self.outputs.iter() {
+            if standard_address(network, output.script)?.is_none() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// A hasher finishing to the BIP143 sighash of spending `input` with
+    /// `script_code`
+    fn segwit_v0_sighash(&self, input: &Input, script_code: &[u8]) -> Result<Sha256> {
+        let (amount, _) = input.spent_output()?;
+        let mut prevouts = Sha256::new();
+        let mut sequences = Sha256::new();
+        let mut outputs = Sha256::new();
+        for i in self.inputs.iter() {
+            prevouts.update(i.prevout);
+            sequences.update(i.sequence.to_le_bytes());
+        }
+        for o in self.outputs.iter() {
+            outputs.update(o.raw);
+        }
+        let hash = |h: Sha256| Sha256::digest(&h.finalize());
+
+        let preimage = Sha256::new()
+            .chain(self.version.to_le_bytes())
+            .chain(hash(prevouts))
+            .chain(hash(sequences))
+            .chain(input.prevout)
+            .chain(compact_size(script_code.len()))
+            .chain(script_code)
+            .chain(amount.to_le_bytes())
+            .chain(input.sequence.to_le_bytes())
+            .chain(hash(outputs))
+            .chain(self.locktime.to_le_bytes())
+            .chain(SIGHASH_ALL.to_le_bytes());
+        Ok(Sha256::new().chain(preimage.finalize()))
+    }
+
+    /// The BIP143 sighash of input `index` with `script_code`, as `sign`
+    /// computes it, to check signatures against
+    pub fn input_sighash(&self, index: usize, script_code: &[u8]) -> Result<Sha256> {
+        let input = self
+            .inputs
+            .get(index)
+            .ok_or_else(|| WalletErr::from("no such input"))?;
+        self.segwit_v0_sighash(input, script_code)
+    }
+
+    /// BIP341 sighash of a key path spend of input `index`, with
+    /// `SIGHASH_DEFAULT` and no annex
+    pub fn taproot_sighash(&self, index: usize) -> Result<[u8; 32]> {
+        let mut prevouts = Sha256::new();
+        let mut amounts = Sha256::new();
+        let mut scripts = Sha256::new();
+        let mut sequences = Sha256::new();
+        let mut outputs = Sha256::new();
+        for i in self.inputs.iter() {
+            let (amount, script) = i.spent_output()?;
+            prevouts.update(i.prevout);
+            amounts.update(amount.to_le_bytes());
+            scripts.update(compact_size(script.len()));
+            scripts.update(script);
+            sequences.update(i.sequence.to_le_bytes());
+        }
+        for o in self.outputs.iter() {
+            outputs.update(o.raw);
+        }
+
+        Ok(schnorr::tagged_hash(
+            "TapSighash",
+            &[
+                // Epoch, then the sighash type
+                &[0x00, SIGHASH_DEFAULT as u8],
+                &self.version.to_le_bytes(),
+                &self.locktime.to_le_bytes(),
+                &prevouts.finalize(),
+                &amounts.finalize(),
+                &scripts.finalize(),
+                &sequences.finalize(),
+                &outputs.finalize(),
+                // Spend type: key path, no annex
+                &[0x00],
+                &(index as u32).to_le_bytes(),
+            ],
+        ))
+    }
+
+    /// Signs every input this device hasn't signed yet, to be added to the
+    /// PSBT with `add_signatures`. Fails if there are none. `aux` is fresh
+    /// randomness for the Schnorr signatures.
+    pub fn sign(&self, wallet: &Wallet, aux: &[u8; 32]) -> Result<Vec<PartialSig, U16>> {
+        let mut sigs = Vec::new();
+        for (index, input) in self.inputs.iter().enumerate() {
+            let owned = self.input_key(wallet, input)?;
+            let pubkey = owned.key.public_key();
+            let mut key: Vec<u8, U34> = Vec::new();
+            match owned.spend {
+                Spend::P2wpkh | Spend::Multisig(_) => {
+                    let _ = key.push(IN_PARTIAL_SIG);
+                    let _ = key.extend_from_slice(&pubkey);
+                }
+                Spend::P2tr => {
+                    let _ = key.push(IN_TAP_KEY_SIG);
+                }
+            }
+            // A key may appear only once in a map
+            if input.map.get(&key).is_some() {
+                continue;
+            }
+            let mut value = Vec::new();
+            match owned.spend {
+                Spend::P2wpkh | Spend::Multisig(_) => {
+                    // P2WPKH's scriptCode is the P2PKH script of the key,
+                    // P2WSH's the witness script
+                    let mut p2pkh = [0u8; 25];
+                    let script_code = match &owned.spend {
+                        Spend::Multisig(ws) => &ws[..],
+                        _ => {
+                            p2pkh[..3].copy_from_slice(&[0x76, 0xa9, 0x14]);
+                            p2pkh[3..23].copy_from_slice(&hash160(&pubkey));
+                            p2pkh[23..].copy_from_slice(&[0x88, 0xac]);
+                            &p2pkh[..]
+                        }
+                    };
+                    let signer = SigningKey::from_bytes(&owned.key.secret_bytes())?;
+                    let sighash = self.segwit_v0_sighash(input, script_code)?;
+                    let sig: Signature = signer.try_sign_digest(sighash)?;
+                    value = der_signature(&sig);
+                    let _ = value.push(SIGHASH_ALL as u8);
+                }
+                Spend::P2tr => {
+                    let secret = schnorr::taproot_tweak_secret(&owned.key.secret_bytes())?;
+                    let sighash = self.taproot_sighash(index)?;
+                    // Different for each input
+                    let aux = schnorr::tagged_hash("novus/aux", &[aux, &sighash]);
+                    let sig = schnorr::sign(&secret, &sighash, &aux)?;
+                    let _ = value.extend_from_slice(&sig);
+                }
+            }
+            let _ = sigs.push(PartialSig {
+                at: input.map.end,
+                key,
+                value,
+            });
+        }
+        if sigs.is_empty() {
+            return Err(WalletErr::from("PSBT already signed"));
+        }
+        Ok(sigs)
+    }
+}
+
+fn btc_amount(sats: u64) -> FieldValue {
+    format_units(&sats.to_be_bytes(), 8, "BTC")
+}
+
+/// Inserts `sigs` into the PSBT in `buf` as partial signature entries
+pub fn add_signatures(buf: &mut Buffer, sigs: &[PartialSig]) -> Result<()> {
+    // Back to front, so the offsets of the remaining signatures stay valid
+    for sig in sigs.iter().rev() {
+        let mut entry: Vec<u8, U112> = Vec::new();
+        let _ = entry.push(sig.key.len() as u8);
+        let _ = entry.extend_from_slice(&sig.key);
+        let _ = entry.push(sig.value.len() as u8);
+        let _ = entry.extend_from_slice(&sig.value);
+
+        let len = buf.len();
+        buf.resize(len + entry.len(), 0)
+            .map_err(|_| WalletErr::from("signed PSBT too large"))?;
+        buf.copy_within(sig.at..len, sig.at + entry.len());
+        buf[sig.at..sig.at + entry.len()].copy_from_slice(&entry);
+    }
+    Ok(())
+}
@@ -0,0 +1,490 @@
+//! A minimal QR code encoder (byte mode, error correction level M) without
+//! heap allocation. Versions are capped so a code always fits on the 64 pixel
+//! tall display. Follows the structure of Nayuki's reference implementation.
+use crate::{error::WalletErr, Result};
+
+/// Largest version drawn, 57x57 modules
+pub const MAX_VERSION: usize = 10;
+const MAX_SIZE: usize = MAX_VERSION * 4 + 17;
+const BITMAP_LEN: usize = (MAX_SIZE * MAX_SIZE + 7) / 8;
+/// Total codewords in a version 10 code
+const MAX_CODEWORDS: usize = 346;
+const MAX_BLOCKS: usize = 5;
+const MAX_BLOCK_ECC: usize = 26;
+
+// Level M only, indexed by version (index 0 is unused)
+const ECC_CODEWORDS_PER_BLOCK: [usize; MAX_VERSION + 1] =
+    [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26];
+const NUM_BLOCKS: [usize; MAX_VERSION + 1] = [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5];
+/// Format bits for level M
+const ECC_FORMAT_BITS: u32 = 0;
+
+const PENALTY_N1: i32 = 3;
+const PENALTY_N2: i32 = 3;
+const PENALTY_N3: i32 = 40;
+const PENALTY_N4: i32 = 10;
+
+#[derive(Clone)]
+struct Bitmap([u8; BITMAP_LEN]);
+
+impl Bitmap {
+    fn get(&self, i: usize) -> bool {
+        self.0[i / 8] >> (i % 8) & 1 == 1
+    }
+
+    fn set(&mut self, i: usize, v: bool) {
+        if v {
+            self.0[i / 8] |= 1 << (i % 8);
+        } else {
+            self.0[i / 8] &= !(1 << (i % 8));
+        }
+    }
+}
+
+pub struct QrCode {
+    version: usize,
+    size: i32,
+    modules: Bitmap,
+    is_function: Bitmap,
+}
+
+fn raw_data_modules(ver: usize) -> usize {
+    let mut result = (16 * ver + 128) * ver + 64;
+    if ver >= 2 {
+        let num_align = ver / 7 + 2;
+        result -= (25 * num_align - 10) * num_align - 55;
+        if ver >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn data_codewords(ver: usize) -> usize {
+    raw_data_modules(ver) / 8 - ECC_CODEWORDS_PER_BLOCK[ver] * NUM_BLOCKS[ver]
+}
+
+fn char_count_bits(ver: usize) -> usize {
+    if ver < 10 {
+        8
+    } else {
+        16
+    }
+}
+
+/// Multiplication in GF(2^8/0x11D)
+fn rs_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1D);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+fn rs_divisor(divisor: &mut [u8]) {
+    let degree = divisor.len();
+    divisor.iter_mut().for_each(|d| *d = 0);
+    divisor[degree - 1] = 1;
+    let mut root: u8 = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            divisor[j] = rs_multiply(divisor[j], root);
+            if j + 1 < degree {
+                divisor[j] ^= divisor[j + 1];
+            }
+        }
+        root = rs_multiply(root, 0x02);
+    }
+}
+
+fn rs_remainder(data: &[u8], divisor: &[u8], rem: &mut [u8]) {
+    rem.iter_mut().for_each(|r| *r = 0);
+    for b in data {
+        let factor = b ^ rem[0];
+        rem.copy_within(1.., 0);
+        rem[rem.len() - 1] = 0;
+        for (r, d) in rem.iter_mut().zip(divisor) {
+            *r ^= rs_multiply(*d, factor);
+        }
+    }
+}
+
+/// Splits `data` into blocks, computes each block's ECC and interleaves it
+/// all into `out`. Returns the number of codewords written.
+fn add_ecc_and_interleave(ver: usize, data: &[u8], out: &mut [u8]) -> usize {
+    let num_blocks = NUM_BLOCKS[ver];
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[ver];
+    let raw_codewords = raw_data_modules(ver) / 8;
+    let num_short = num_blocks - raw_codewords % num_blocks;
+    let short_data_len = raw_codewords / num_blocks - ecc_len;
+    let block_len = |b: usize| short_data_len + (b >= num_short) as usize;
+
+    let mut divisor = [0u8; MAX_BLOCK_ECC];
+    rs_divisor(&mut divisor[..ecc_len]);
+    let mut ecc = [[0u8; MAX_BLOCK_ECC]; MAX_BLOCKS];
+    let mut starts = [0usize; MAX_BLOCKS];
+    let mut k = 0;
+    for b in 0..num_blocks {
+        starts[b] = k;
+        rs_remainder(
+            &data[k..k + block_len(b)],
+            &divisor[..ecc_len],
+            &mut ecc[b][..ecc_len],
+        );
+        k += block_len(b);
+    }
+
+    let mut n = 0;
+    for i in 0..=short_data_len {
+        for b in (0..num_blocks).filter(|b| i < block_len(*b)) {
+            out[n] = data[starts[b] + i];
+            n += 1;
+        }
+    }
+    for i in 0..ecc_len {
+        for block_ecc in ecc[..num_blocks].iter() {
+            out[n] = block_ecc[i];
+            n += 1;
+        }
+    }
+    n
+}
+
+/// Appends the low `len` bits of `val` to `buf`, starting at bit `*pos`
+fn append_bits(buf: &mut [u8], pos: &mut usize, val: u32, len: usize) {
+    for i in (0..len).rev() {
+        if (val >> i) & 1 == 1 {
+            buf[*pos / 8] |= 0x80 >> (*pos % 8);
+        }
+        *pos += 1;
+    }
+}
+
+impl QrCode {
+    /// Encodes `data` with the smallest version it fits in
+    pub fn encode(data: &[u8]) -> Result<Self> {
+        let ver = (1..=MAX_VERSION)
+            .find(|v| 4 + char_count_bits(*v) + data.len() * 8 <= data_codewords(*v) * 8)
+            .ok_or_else(|| WalletErr::from("too much data for a QR code"))?;
+
+        // Mode indicator, length, data, terminator, then pad bytes
+        let capacity = data_codewords(ver);
+        let mut codewords = [0u8; MAX_CODEWORDS];
+        let mut pos = 0;
+        append_bits(&mut codewords, &mut pos, 0x4, 4);
+        append_bits(
+            &mut codewords,
+            &mut pos,
+            data.len() as u32,
+            char_count_bits(ver),
+        );
+        for b in data {
+            append_bits(&mut codewords, &mut pos, *b as u32, 8);
+        }
+        pos = (pos + 4).min(capacity * 8);
+        pos = (pos + 7) / 8;
+        for (i, c) in codewords[pos..capacity].iter_mut().enumerate() {
+            *c = if i % 2 == 0 { 0xEC } else { 0x11 };
+        }
+
+        let mut all_codewords = [0u8; MAX_CODEWORDS];
+        let n = add_ecc_and_interleave(ver, &codewords[..capacity], &mut all_codewords);
+
+        let mut qr = QrCode {
+            version: ver,
+            size: (ver * 4 + 17) as i32,
+            modules: Bitmap([0; BITMAP_LEN]),
+            is_function: Bitmap([0; BITMAP_LEN]),
+        };
+        qr.draw_function_patterns();
+        qr.draw_codewords(&all_codewords[..n]);
+
+        // Pick the mask with the lowest penalty
+        let mut best = (i32::MAX, 0);
+        for mask in 0..8 {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(mask);
+            let penalty = qr.penalty_score();
+            if penalty < best.0 {
+                best = (penalty, mask);
+            }
+            // Applying a mask twice undoes it
+            qr.apply_mask(mask);
+        }
+        qr.apply_mask(best.1);
+        qr.draw_format_bits(best.1);
+        Ok(qr)
+    }
+
+    /// Width and height in modules
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    /// Whether the module at (x, y) is dark
+    pub fn module(&self, x: i32, y: i32) -> bool {
+        self.modules.get((y * self.size + x) as usize)
+    }
+
+    fn set_function_module(&mut self, x: i32, y: i32, dark: bool) {
+        let i = (y * self.size + x) as usize;
+        self.modules.set(i, dark);
+        self.is_function.set(i, true);
+    }
+
+    fn draw_function_patterns(&mut self) {
+        let size = self.size;
+        // Timing patterns
+        for i in 0..size {
+            self.set_function_module(6, i, i % 2 == 0);
+            self.set_function_module(i, 6, i % 2 == 0);
+        }
+
+        self.draw_finder_pattern(3, 3);
+        self.draw_finder_pattern(size - 4, 3);
+        self.draw_finder_pattern(3, size - 4);
+
+        // Alignment patterns, except where they'd overlap the finders
+        let (positions, num_align) = self.alignment_pattern_positions();
+        for i in 0..num_align {
+            for j in 0..num_align {
+                let corner =
+                    (i == 0 && (j == 0 || j == num_align - 1)) || (i == num_align - 1 && j == 0);
+                if !corner {
+                    self.draw_alignment_pattern(positions[i], positions[j]);
+                }
+            }
+        }
+
+        // Reserve the format bits, they're redrawn once the mask is chosen
+        self.draw_format_bits(0);
+        self.draw_version();
+    }
+
+    fn alignment_pattern_positions(&self) -> ([i32; 7], usize) {
+        let mut positions = [0i32; 7];
+        if self.version == 1 {
+            return (positions, 0);
+        }
+        let num_align = self.version / 7 + 2;
+        let step = ((self.version * 4 + num_align * 2 + 1) / (num_align * 2 - 2) * 2) as i32;
+        positions[0] = 6;
+        for i in 1..num_align {
+            positions[num_align - i] = self.size - 7 - (i as i32 - 1) * step;
+        }
+        (positions, num_align)
+    }
+
+    fn draw_format_bits(&mut self, mask: u32) {
+        let data = ECC_FORMAT_BITS << 3 | mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = (data << 10 | rem) ^ 0x5412;
+        let bit = |i: i32| (bits >> i) & 1 == 1;
+
+        for i in 0..6 {
+            self.set_function_module(8, i, bit(i));
+        }
+        self.set_function_module(8, 7, bit(6));
+        self.set_function_module(8, 8, bit(7));
+        self.set_function_module(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function_module(14 - i, 8, bit(i));
+        }
+
+        let size = self.size;
+        for i in 0..8 {
+            self.set_function_module(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function_module(8, size - 15 + i, bit(i));
+        }
+        self.set_function_module(8, size - 8, true);
+    }
+
+    fn draw_version(&mut self) {
+        if self.version < 7 {
+            return;
+        }
+        let data = self.version as u32;
+        let mut rem = data;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+        }
+        let bits = data << 12 | rem;
+
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 == 1;
+            let a = self.size - 11 + i % 3;
+            let b = i / 3;
+            self.set_function_module(a, b, dark);
+            self.set_function_module(b, a, dark);
+        }
+    }
+
+    fn draw_finder_pattern(&mut self, x: i32, y: i32) {
+        for dy in -4..=4 {
+            for dx in -4..=4 {
+                let (xx, yy) = (x + dx, y + dy);
+                if (0..self.size).contains(&xx) && (0..self.size).contains(&yy) {
+                    let dist = dx.abs().max(dy.abs());
+                    self.set_function_module(xx, yy, dist != 2 && dist != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment_pattern(&mut self, x: i32, y: i32) {
+        for dy in -2..=2 {
+            for dx in -2..=2 {
+                self.set_function_module(x + dx, y + dy, dx.abs().max(dy.abs()) != 1);
+            }
+        }
+    }
+
+    /// Fills the data area with `data` in the zigzag order
+    fn draw_codewords(&mut self, data: &[u8]) {
+        let mut i = 0;
+        let mut right = self.size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..self.size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { self.size - 1 - vert } else { vert };
+                    let idx = (y * self.size + x) as usize;
+                    if !self.is_function.get(idx) && i < data.len() * 8 {
+                        self.modules
+                            .set(idx, (data[i >> 3] >> (7 - (i & 7))) & 1 == 1);
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let idx = (y * self.size + x) as usize;
+                if invert && !self.is_function.get(idx) {
+                    self.modules.set(idx, !self.modules.get(idx));
+                }
+            }
+        }
+    }
+
+    fn penalty_score(&self) -> i32 {
+        let size = self.size;
+        let mut result = 0;
+
+        // Runs of the same color and finder-like patterns, in rows then columns
+        for transpose in [false, true].iter() {
+            for a in 0..size {
+                let mut run_color = false;
+                let mut run_len = 0;
+                let mut history = FinderPenalty::new(size);
+                for b in 0..size {
+                    let dark = if *transpose {
+                        self.module(a, b)
+                    } else {
+                        self.module(b, a)
+                    };
+                    if dark == run_color {
+                        run_len += 1;
+                        if run_len == 5 {
+                            result += PENALTY_N1;
+                        } else if run_len > 5 {
+                            result += 1;
+                        }
+                    } else {
+                        history.add_history(run_len);
+                        if !run_color {
+                            result += history.count_patterns() * PENALTY_N3;
+                        }
+                        run_color = dark;
+                        run_len = 1;
+                    }
+                }
+                result += history.terminate_and_count(run_color, run_len) * PENALTY_N3;
+            }
+        }
+
+        // 2x2 blocks of the same color
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = self.module(x, y);
+                if c == self.module(x + 1, y)
+                    && c == self.module(x, y + 1)
+                    && c == self.module(x + 1, y + 1)
+                {
+                    result += PENALTY_N2;
+                }
+            }
+        }
+
+        // Balance of dark and light modules
+        let total = size * size;
+        let dark = (0..total as usize).filter(|i| self.modules.get(*i)).count() as i32;
+        let k = ((dark * 20 - total * 10).abs() + total - 1) / total - 1;
+        result + k * PENALTY_N4
+    }
+}
+
+struct FinderPenalty {
+    size: i32,
+    run_history: [i32; 7],
+}
+
+impl FinderPenalty {
+    fn new(size: i32) -> Self {
+        FinderPenalty {
+            size,
+            run_history: [0; 7],
+        }
+    }
+
+    fn add_history(&mut self, mut run_len: i32) {
+        if self.run_history[0] == 0 {
+            // Add the light border to the initial run
+            run_len += self.size;
+        }
+        self.run_history.copy_within(0..6, 1);
+        self.run_history[0] = run_len;
+    }
+
+    fn count_patterns(&self) -> i32 {
+        let rh = &self.run_history;
+        let n = rh[1];
+        let core = n > 0 && rh[2] == n && rh[3] == n * 3 && rh[4] == n && rh[5] == n;
+        (core && rh[0] >= n * 4 && rh[6] >= n) as i32
+            + (core && rh[6] >= n * 4 && rh[0] >= n) as i32
+    }
+
+    fn terminate_and_count(mut self, run_color: bool, mut run_len: i32) -> i32 {
+        if run_color {
+            self.add_history(run_len);
+            run_len = 0;
+        }
+        run_len += self.size;
+        self.add_history(run_len);
+        self.count_patterns()
+    }
+}
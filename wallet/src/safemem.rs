@@ -9,6 +9,23 @@ where
     B: ArrayLength<u8>,
 {
     fn drop(&mut self) {
-        self.0.iter_mut().for_each(|b| *b = 0xFF)
+        wipe(&mut self.0)
+    }
+}
+
+/// Zeroes key material that's about to be dropped. Volatile, so the writes
+/// aren't optimized away.
+pub fn wipe(bytes: &mut [u8]) {
+    for b in bytes.iter_mut() {
+        unsafe { core::ptr::write_volatile(b, 0) };
+    }
+}
+
+/// A BIP39 seed, zeroed when dropped
+pub struct SeedBytes(pub [u8; 64]);
+
+impl Drop for SeedBytes {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
     }
 }
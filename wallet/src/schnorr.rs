@@ -0,0 +1,138 @@
+//! BIP340 Schnorr signatures and the BIP341 Taproot key tweak, built from
+//! k256's curve arithmetic, which has no Schnorr support of its own.
+use crate::{error::WalletErr, Result};
+
+use k256::{
+    elliptic_curve::{
+        ff::PrimeField,
+        sec1::{FromEncodedPoint, ToEncodedPoint},
+    },
+    AffinePoint, EncodedPoint, FieldBytes, ProjectivePoint, Scalar,
+};
+use sha2::{Digest, Sha256};
+
+/// A public key as its x coordinate only, the y coordinate being even
+pub type XOnly = [u8; 32];
+pub type Signature = [u8; 64];
+
+/// SHA256(SHA256(tag) || SHA256(tag) || parts...)
+pub fn tagged_hash(tag: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let tag = Sha256::digest(tag.as_bytes());
+    let mut h = Sha256::new().chain(tag).chain(tag);
+    for p in parts {
+        h.update(p);
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&h.finalize());
+    out
+}
+
+fn secret_scalar(secret: &[u8; 32]) -> Result<Scalar> {
+    Scalar::from_repr(*FieldBytes::from_slice(secret))
+        .filter(|d| !bool::from(d.is_zero()))
+        .ok_or_else(|| WalletErr::from("invalid secret key"))
+}
+
+/// A hash as a scalar, as BIP340 uses them
+fn reduced(hash: &[u8; 32]) -> Scalar {
+    Scalar::from_bytes_reduced(FieldBytes::from_slice(hash))
+}
+
+/// The x coordinate of `p` and whether its y coordinate is odd.
+/// `p` must not be the point at infinity.
+fn x_and_parity(p: &ProjectivePoint) -> (XOnly, bool) {
+    let encoded = p.to_affine().to_encoded_point(true);
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&encoded.as_bytes()[1..]);
+    (x, encoded.as_bytes()[0] == 0x03)
+}
+
+/// The point with x coordinate `x` and an even y coordinate
+fn lift_x(x: &XOnly) -> Result<ProjectivePoint> {
+    let mut compressed = [0x02; 33];
+    compressed[1..].copy_from_slice(x);
+    EncodedPoint::from_bytes(&compressed[..])
+        .ok()
+        .and_then(|p| AffinePoint::from_encoded_point(&p))
+        .map(ProjectivePoint::from)
+        .ok_or_else(|| WalletErr::from("invalid public key"))
+}
+
+pub fn public_key(secret: &[u8; 32]) -> Result<XOnly> {
+    let d = secret_scalar(secret)?;
+    Ok(x_and_parity(&(ProjectivePoint::generator() * d)).0)
+}
+
+/// Signs the 32 byte `msg`. `aux` is fresh randomness when available; the
+/// signature is secure, merely deterministic, without it.
+pub fn sign(secret: &[u8; 32], msg: &[u8; 32], aux: &[u8; 32]) -> Result<Signature> {
+    let g = ProjectivePoint::generator();
+    let d = secret_scalar(secret)?;
+    let (px, p_odd) = x_and_parity(&(g * d));
+    let d = if p_odd { -d } else { d };
+
+    let mut t = tagged_hash("BIP0340/aux", &[aux]);
+    t.iter_mut().zip(d.to_bytes()).for_each(|(t, d)| *t ^= d);
+    let k = reduced(&tagged_hash("BIP0340/nonce", &[&t, &px, msg]));
+    if bool::from(k.is_zero()) {
+        return Err(WalletErr::from("invalid nonce"));
+    }
+    let (rx, r_odd) = x_and_parity(&(g * k));
+    let k = if r_odd { -k } else { k };
+    let e = reduced(&tagged_hash("BIP0340/challenge", &[&rx, &px, msg]));
+
+    let mut sig = [0u8; 64];
+    sig[..32].copy_from_slice(&rx);
+    sig[32..].copy_from_slice(&(k + e * d).to_bytes());
+    // Catches faults in the computation before the signature leaks the key
+    verify(&px, msg, &sig)?;
+    Ok(sig)
+}
+
+pub fn verify(pubkey: &XOnly, msg: &[u8; 32], sig: &Signature) -> Result<()> {
+    let invalid = || WalletErr::from("invalid signature");
+    let p = lift_x(pubkey)?;
+    let s = Scalar::from_repr(*FieldBytes::from_slice(&sig[32..])).ok_or_else(invalid)?;
+    let e = reduced(&tagged_hash(
+        "BIP0340/challenge",
+        &[&sig[..32], pubkey, msg],
+    ));
+    let r = ProjectivePoint::generator() * s - p * e;
+    if bool::from(r.to_affine().is_identity()) {
+        return Err(invalid());
+    }
+    match x_and_parity(&r) {
+        (rx, false) if rx[..] == sig[..32] => Ok(()),
+        _ => Err(invalid()),
+    }
+}
+
+/// BIP341 tweak of an internal key committing to no script tree, as BIP86
+/// outputs do
+fn tap_tweak(internal: &XOnly) -> Result<Scalar> {
+    let t = tagged_hash("TapTweak", &[internal]);
+    Scalar::from_repr(*FieldBytes::from_slice(&t)).ok_or_else(|| WalletErr::from("invalid tweak"))
+}
+
+/// The output key a BIP86 P2TR output with `internal` key pays to
+pub fn taproot_output_key(internal: &XOnly) -> Result<XOnly> {
+    let q = lift_x(internal)? + ProjectivePoint::generator() * tap_tweak(internal)?;
+    if bool::from(q.to_affine().is_identity()) {
+        return Err(WalletErr::from("invalid tweak"));
+    }
+    Ok(x_and_parity(&q).0)
+}
+
+/// The secret key of `taproot_output_key(public_key(secret))`, for key path spends
+pub fn taproot_tweak_secret(secret: &[u8; 32]) -> Result<[u8; 32]> {
+    let d = secret_scalar(secret)?;
+    let (px, p_odd) = x_and_parity(&(ProjectivePoint::generator() * d));
+    let d = if p_odd { -d } else { d };
+    let tweaked = d + tap_tweak(&px)?;
+    if bool::from(tweaked.is_zero()) {
+        return Err(WalletErr::from("invalid tweak"));
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&tweaked.to_bytes());
+    Ok(out)
+}
@@ -0,0 +1,100 @@
+//! The device's configurable behaviour, kept in the key/value store as
+//! postcard. Settings that don't parse, e.g. after a format change, fall
+//! back to the defaults.
+use crate::{
+    display::{Field, FieldValue},
+    error::WalletErr,
+    kvstore::{self, Flash, Store},
+    presence, Result,
+};
+
+use heapless::{consts::*, Vec};
+use numtoa::NumToA;
+use protocol::{Settings, LABEL_LEN};
+
+pub const DEFAULT: Settings<'static> = Settings {
+    confirm_timeout_s: (presence::DEFAULT_TIMEOUT_MS / 1000) as u16,
+    confirm_exports: false,
+    auto_lock_min: 0,
+    brightness: MAX_BRIGHTNESS,
+    blind_signing: false,
+    label: "",
+};
+
+pub const MAX_BRIGHTNESS: u8 = 5;
+const MIN_CONFIRM_TIMEOUT_S: u16 = 5;
+const MAX_CONFIRM_TIMEOUT_S: u16 = 600;
+/// A day
+const MAX_AUTO_LOCK_MIN: u16 = 24 * 60;
+
+pub type Fields = Vec<Field, U6>;
+
+/// The stored settings, or the defaults. `buf` holds the bytes they
+/// borrow from.
+pub fn load<'a, F: Flash>(kv: &Store<F>, buf: &'a mut kvstore::Value) -> Settings<'a> {
+    *buf = kv.get(kvstore::keys::SETTINGS).unwrap_or_default();
+    postcard::from_bytes(buf).unwrap_or(DEFAULT)
+}
+
+pub fn validate(s: &Settings) -> Result<()> {
+    if s.confirm_timeout_s < MIN_CONFIRM_TIMEOUT_S || s.confirm_timeout_s > MAX_CONFIRM_TIMEOUT_S {
+        return Err(WalletErr::from("confirm timeout must be 5 to 600 seconds"));
+    }
+    if s.auto_lock_min > MAX_AUTO_LOCK_MIN {
+        return Err(WalletErr::from("auto-lock must be at most a day"));
+    }
+    if s.brightness < 1 || s.brightness > MAX_BRIGHTNESS {
+        return Err(WalletErr::from("brightness must be 1 to 5"));
+    }
+    if s.label.len() > LABEL_LEN {
+        return Err(WalletErr::from("device label too long"));
+    }
+    if !s.label.bytes().all(|b| b == b' ' || b.is_ascii_graphic()) {
+        return Err(WalletErr::from("device label must be printable ASCII"));
+    }
+    Ok(())
+}
+
+pub fn save<F: Flash>(kv: &mut Store<F>, s: &Settings) -> Result<()> {
+    validate(s)?;
+    let bytes = postcard::to_vec::<U64, _>(s)?;
+    kv.set(kvstore::keys::SETTINGS, &bytes)
+}
+
+/// What the user confirms before `s` is applied
+pub fn summary(s: &Settings) -> Result<Fields> {
+    fn number(n: u16, unit: &str) -> FieldValue {
+        let mut buf = [0u8; 5];
+        let mut out = FieldValue::new();
+        let _ = out.push_str(n.numtoa_str(10, &mut buf));
+        let _ = out.push_str(unit);
+        out
+    }
+    let yes_no = |b: bool| FieldValue::from(if b { "yes" } else { "no" });
+
+    let mut fields = Fields::new();
+    let mut push = |label, value| {
+        fields
+            .push(Field { label, value })
+            .map_err(|_| WalletErr::from("too many fields"))
+    };
+    push("Confirm within", number(s.confirm_timeout_s, " s"))?;
+    push("Confirm exports", yes_no(s.confirm_exports))?;
+    push(
+        "Auto-lock",
+        match s.auto_lock_min {
+            0 => FieldValue::from("never"),
+            min => number(min, " min"),
+        },
+    )?;
+    push("Brightness", number(s.brightness as u16, " of 5"))?;
+    push("Blind signing", yes_no(s.blind_signing))?;
+    push(
+        "Label",
+        match s.label {
+            "" => FieldValue::from("none"),
+            label => FieldValue::from(label),
+        },
+    )?;
+    Ok(fields)
+}
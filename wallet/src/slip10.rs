@@ -0,0 +1,70 @@
+//! SLIP-10 key derivation for Ed25519, the curve family next to BIP32's
+//! secp256k1. Ed25519 keys only have hardened children.
+use crate::{
+    bip32::{hmac_sha512, HARDENED},
+    error::WalletErr,
+    safemem::wipe,
+    Result,
+};
+
+use ed25519_dalek::{ExpandedSecretKey, PublicKey, SecretKey};
+
+pub struct Ed25519Key {
+    secret: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl Ed25519Key {
+    pub fn master(seed: &[u8]) -> Self {
+        Self::from_hmac(hmac_sha512(b"ed25519 seed", &[seed]))
+    }
+
+    /// Derives along `path`, every index of which must be hardened
+    pub fn derive(seed: &[u8], path: &[u32]) -> Result<Self> {
+        path.iter()
+            .try_fold(Self::master(seed), |key, idx| key.child(*idx))
+    }
+
+    pub fn child(&self, index: u32) -> Result<Self> {
+        if index < HARDENED {
+            return Err(WalletErr::from("ed25519 keys only have hardened children"));
+        }
+        Ok(Self::from_hmac(hmac_sha512(
+            &self.chain_code,
+            &[&[0], &self.secret, &index.to_be_bytes()],
+        )))
+    }
+
+    fn from_hmac(i: [u8; 64]) -> Self {
+        let mut key = Ed25519Key {
+            secret: [0; 32],
+            chain_code: [0; 32],
+        };
+        key.secret.copy_from_slice(&i[..32]);
+        key.chain_code.copy_from_slice(&i[32..]);
+        key
+    }
+
+    fn expanded(&self) -> Result<(ExpandedSecretKey, PublicKey)> {
+        let secret = SecretKey::from_bytes(&self.secret)
+            .map_err(|_| WalletErr::from("invalid ed25519 key"))?;
+        let expanded = ExpandedSecretKey::from(&secret);
+        let public = PublicKey::from(&expanded);
+        Ok((expanded, public))
+    }
+
+    pub fn public_key(&self) -> Result<[u8; 32]> {
+        Ok(self.expanded()?.1.to_bytes())
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> Result<[u8; 64]> {
+        let (expanded, public) = self.expanded()?;
+        Ok(expanded.sign(msg, &public).to_bytes())
+    }
+}
+
+impl Drop for Ed25519Key {
+    fn drop(&mut self) {
+        wipe(&mut self.secret);
+    }
+}
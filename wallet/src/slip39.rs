@@ -0,0 +1,692 @@
+//! SLIP-39 Shamir backups. What gets split is the entropy of the device's
+//! BIP39 mnemonic, so recovering the shares gives back the same mnemonic and
+//! the same keys. (Wallets that use a SLIP-39 master secret as the BIP32
+//! seed directly derive other keys from the same shares.)
+//!
+//! The secret is encrypted with an empty passphrase, split into groups any
+//! `group_threshold` of which recover it, and each group share split again
+//! among the group's members.
+use crate::{
+    display::{Field, FieldValue},
+    error::WalletErr,
+    safemem::wipe,
+    Result,
+};
+
+use heapless::{consts::*, String, Vec};
+use hmac::{Hmac, Mac, NewMac};
+use numtoa::NumToA;
+use sha2::Sha256;
+
+pub const WORDLIST: &str = include_str!("../slip39-english.txt");
+const RADIX_BITS: usize = 10;
+const HEADER_WORDS: usize = 4;
+const CHECKSUM_WORDS: usize = 3;
+/// 128 bit secrets are the shortest, 256 bit ones the longest we store
+const MIN_WORDS: usize = HEADER_WORDS + 13 + CHECKSUM_WORDS;
+const MAX_WORDS: usize = HEADER_WORDS + 26 + CHECKSUM_WORDS;
+/// Words in a share of a 128, 192 or 256 bit secret, those of BIP39 seeds
+pub const SHARE_WORDS: [usize; 3] = [20, 27, 33];
+const MAX_SHARES: u8 = 16;
+const DIGEST_INDEX: u8 = 254;
+const SECRET_INDEX: u8 = 255;
+const DIGEST_LEN: usize = 4;
+/// Each of the 4 Feistel rounds iterates PBKDF2 `BASE_ITERATIONS << exponent` times
+const BASE_ITERATIONS: u32 = 2500;
+const ITERATION_EXPONENT: u8 = 1;
+/// The largest exponent a share may ask for. The header allows up to 15,
+/// which would keep the device in PBKDF2 for hours.
+const MAX_ITERATION_EXPONENT: u8 = 3;
+
+const GROUP_LABELS: [&str; 16] = [
+    "Group 1", "Group 2", "Group 3", "Group 4", "Group 5", "Group 6", "Group 7", "Group 8",
+    "Group 9", "Group 10", "Group 11", "Group 12", "Group 13", "Group 14", "Group 15", "Group 16",
+];
+
+pub type Value = Vec<u8, U32>;
+/// A share as words, at most 33 of at most 8 letters
+pub type Words = String<U320>;
+pub type Fields = Vec<Field, U18>;
+
+fn invalid() -> WalletErr {
+    WalletErr::from("invalid share")
+}
+
+/// A member's threshold and count of shares
+#[derive(Clone, Copy)]
+pub struct Group {
+    pub threshold: u8,
+    pub count: u8,
+}
+
+pub struct Share {
+    pub id: u16,
+    pub extendable: bool,
+    pub exponent: u8,
+    pub group_index: u8,
+    pub group_threshold: u8,
+    pub group_count: u8,
+    pub member_index: u8,
+    pub member_threshold: u8,
+    pub value: Value,
+}
+
+/// What the encryption of the master secret depends on
+pub struct Header {
+    pub id: u16,
+    pub extendable: bool,
+    pub exponent: u8,
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        wipe(&mut self.value);
+    }
+}
+
+fn customization(extendable: bool) -> &'static [u8] {
+    if extendable {
+        b"shamir_extendable"
+    } else {
+        b"shamir"
+    }
+}
+
+/// The RS1024 checksum polynomial
+fn polymod(values: impl Iterator<Item = u16>) -> u32 {
+    const GENERATOR: [u32; 10] = [
+        0x00e0_e040,
+        0x01c1_c080,
+        0x0383_8100,
+        0x0707_0200,
+        0x0e0e_0009,
+        0x1c0c_2412,
+        0x3808_6c24,
+        0x3090_fc48,
+        0x21b1_f890,
+        0x03f3_f120,
+    ];
+    values.fold(1, |chk, v| {
+        let top = chk >> 20;
+        let chk = ((chk & 0x000f_ffff) << 10) ^ v as u32;
+        GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold(chk, |chk, (_, g)| chk ^ g)
+    })
+}
+
+fn word_index(word: &str) -> Option<u16> {
+    WORDLIST.lines().position(|w| w == word).map(|i| i as u16)
+}
+
+impl Share {
+    fn header(&self) -> Header {
+        Header {
+            id: self.id,
+            extendable: self.extendable,
+            exponent: self.exponent,
+        }
+    }
+
+    pub fn parse(words: &str) -> Result<Self> {
+        let mut idx: Vec<u16, U33> = Vec::new();
+        for word in words.split_whitespace() {
+            let i = word_index(word).ok_or_else(|| WalletErr::from("unknown share word"))?;
+            idx.push(i).map_err(|_| invalid())?;
+        }
+        if idx.len() < MIN_WORDS || idx.len() > MAX_WORDS {
+            return Err(WalletErr::from("wrong number of share words"));
+        }
+
+        let header = idx[..HEADER_WORDS]
+            .iter()
+            .fold(0u64, |h, w| (h << RADIX_BITS) | *w as u64);
+        let field = |shift: u32| ((header >> shift) & 0xf) as u8;
+        let extendable = (header >> 24) & 1 == 1;
+        let cs = customization(extendable).iter().map(|b| *b as u16);
+        if polymod(cs.chain(idx.iter().copied())) != 1 {
+            return Err(WalletErr::from("share checksum mismatch"));
+        }
+
+        // The value is big endian, padded at the front with fewer than 8 zero bits
+        let value_words = &idx[HEADER_WORDS..idx.len() - CHECKSUM_WORDS];
+        let padding = value_words.len() * RADIX_BITS % 16;
+        if padding > 8 {
+            return Err(invalid());
+        }
+        let mut value = Value::new();
+        let (mut acc, mut bits, mut skip) = (0u32, 0, padding);
+        for w in value_words {
+            acc = (acc << RADIX_BITS) | *w as u32;
+            bits += RADIX_BITS;
+            if skip > 0 {
+                bits -= skip;
+                if acc >> bits != 0 {
+                    return Err(WalletErr::from("invalid share padding"));
+                }
+                skip = 0;
+            }
+            while bits >= 8 {
+                bits -= 8;
+                value.push((acc >> bits) as u8).map_err(|_| invalid())?;
+                acc &= (1 << bits) - 1;
+            }
+        }
+
+        let share = Share {
+            id: (header >> 25) as u16,
+            extendable,
+            exponent: field(20),
+            group_index: field(16),
+            group_threshold: field(12) + 1,
+            group_count: field(8) + 1,
+            member_index: field(4),
+            member_threshold: field(0) + 1,
+            value,
+        };
+        if share.group_threshold > share.group_count {
+            return Err(invalid());
+        }
+        if share.exponent > MAX_ITERATION_EXPONENT {
+            return Err(WalletErr::from("share iteration exponent too high"));
+        }
+        Ok(share)
+    }
+
+    pub fn words(&self) -> Result<Words> {
+        let header = (self.id as u64) << 25
+            | (self.extendable as u64) << 24
+            | (self.exponent as u64) << 20
+            | (self.group_index as u64) << 16
+            | ((self.group_threshold - 1) as u64) << 12
+            | ((self.group_count - 1) as u64) << 8
+            | (self.member_index as u64) << 4
+            | (self.member_threshold - 1) as u64;
+        let mut idx: Vec<u16, U33> = Vec::new();
+        for i in (0..HEADER_WORDS).rev() {
+            let _ = idx.push(((header >> (RADIX_BITS * i)) & 0x3ff) as u16);
+        }
+        let padding = (RADIX_BITS - self.value.len() * 8 % RADIX_BITS) % RADIX_BITS;
+        let (mut acc, mut bits) = (0u32, padding);
+        for b in &self.value {
+            acc = (acc << 8) | *b as u32;
+            bits += 8;
+            while bits >= RADIX_BITS {
+                bits -= RADIX_BITS;
+                idx.push(((acc >> bits) & 0x3ff) as u16)
+                    .map_err(|_| invalid())?;
+                acc &= (1 << bits) - 1;
+            }
+        }
+        let cs = customization(self.extendable).iter().map(|b| *b as u16);
+        let chk = polymod(
+            cs.chain(idx.iter().copied())
+                .chain([0, 0, 0].iter().copied()),
+        ) ^ 1;
+        for i in (0..CHECKSUM_WORDS).rev() {
+            idx.push(((chk >> (RADIX_BITS * i)) & 0x3ff) as u16)
+                .map_err(|_| invalid())?;
+        }
+
+        let mut out = Words::new();
+        for (n, i) in idx.iter().enumerate() {
+            if n > 0 {
+                let _ = out.push(' ');
+            }
+            let word = WORDLIST.lines().nth(*i as usize).ok_or_else(invalid)?;
+            out.push_str(word).map_err(|_| invalid())?;
+        }
+        Ok(out)
+    }
+}
+
+/// Multiplication in GF(256) with the Rijndael polynomial, in constant time
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut p = 0;
+    for _ in 0..8 {
+        p ^= a & 0u8.wrapping_sub(b & 1);
+        a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(a >> 7));
+        b >>= 1;
+    }
+    p
+}
+
+/// a^254, which is a^-1 for a != 0
+fn inv(a: u8) -> u8 {
+    let a2 = mul(a, a);
+    let a3 = mul(a2, a);
+    let a6 = mul(a3, a3);
+    let a12 = mul(a6, a6);
+    let a15 = mul(a12, a3);
+    let a30 = mul(a15, a15);
+    let a60 = mul(a30, a30);
+    let a120 = mul(a60, a60);
+    let a127 = mul(mul(a120, a6), a);
+    mul(a127, a127)
+}
+
+/// The value at `x` of the polynomials through `points`, byte by byte
+fn interpolate(points: &[(u8, &[u8])], x: u8) -> Result<Value> {
+    let len = points.first().ok_or_else(invalid)?.1.len();
+    let mut out = Value::new();
+    for k in 0..len {
+        let mut y = 0;
+        for (i, (xi, yi)) in points.iter().enumerate() {
+            let mut basis = 1;
+            for (j, (xj, _)) in points.iter().enumerate() {
+                if i != j {
+                    basis = mul(basis, mul(x ^ xj, inv(xi ^ xj)));
+                }
+            }
+            y ^= mul(yi[k], basis);
+        }
+        out.push(y).map_err(|_| invalid())?;
+    }
+    Ok(out)
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_varkey(key).unwrap();
+    for p in parts {
+        mac.update(p);
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&mac.finalize().into_bytes());
+    out
+}
+
+/// Randomness for splitting: HMAC-SHA256 in counter mode, keyed by the
+/// secret, the device's randomness and the host's, so no share set is ever
+/// repeated, nothing about the secret can be learned without it and neither
+/// side alone chooses the shares
+struct Drbg {
+    key: [u8; 32],
+    counter: u32,
+}
+
+impl Drbg {
+    fn fill(&mut self, out: &mut [u8]) {
+        for chunk in out.chunks_mut(32) {
+            let block = hmac_sha256(&self.key, &[&self.counter.to_be_bytes()]);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+            self.counter += 1;
+        }
+    }
+}
+
+impl Drop for Drbg {
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+/// `count` points (x, y) of which any `threshold` recover `secret`
+fn split_secret(
+    threshold: u8,
+    count: u8,
+    secret: &[u8],
+    rng: &mut Drbg,
+    mut f: impl FnMut(u8, &[u8]) -> Result<()>,
+) -> Result<()> {
+    if threshold == 1 {
+        return (0..count).try_for_each(|x| f(x, secret));
+    }
+    // threshold - 2 random points, the digest point and the secret point
+    // fix the polynomial
+    let mut random: Vec<Value, U14> = Vec::new();
+    for _ in 0..threshold - 2 {
+        let mut v = Value::new();
+        let _ = v.resize_default(secret.len());
+        rng.fill(&mut v);
+        let _ = random.push(v);
+    }
+    let mut digest = Value::new();
+    let _ = digest.resize_default(secret.len());
+    rng.fill(&mut digest[DIGEST_LEN..]);
+    let d = hmac_sha256(&digest[DIGEST_LEN..], &[secret]);
+    digest[..DIGEST_LEN].copy_from_slice(&d[..DIGEST_LEN]);
+
+    let mut points: Vec<(u8, &[u8]), U16> = Vec::new();
+    for (x, v) in random.iter().enumerate() {
+        let _ = points.push((x as u8, v));
+    }
+    let _ = points.push((DIGEST_INDEX, &digest));
+    let _ = points.push((SECRET_INDEX, secret));
+    for x in 0..count {
+        let mut y = interpolate(&points, x)?;
+        let result = f(x, &y);
+        wipe(&mut y);
+        result?;
+    }
+    drop(points);
+    for v in random.iter_mut() {
+        wipe(v);
+    }
+    wipe(&mut digest);
+    Ok(())
+}
+
+fn recover_secret(threshold: u8, points: &[(u8, &[u8])]) -> Result<Value> {
+    if threshold == 1 {
+        let (_, y) = points.first().ok_or_else(invalid)?;
+        return Value::from_slice(y).map_err(|_| invalid());
+    }
+    let secret = interpolate(points, SECRET_INDEX)?;
+    let mut digest = interpolate(points, DIGEST_INDEX)?;
+    let d = hmac_sha256(&digest[DIGEST_LEN..], &[&secret]);
+    let ok = d[..DIGEST_LEN] == digest[..DIGEST_LEN];
+    wipe(&mut digest);
+    if !ok {
+        return Err(WalletErr::from("share digest mismatch"));
+    }
+    Ok(secret)
+}
+
+/// The 4 round Feistel network keyed by PBKDF2 that turns the master secret
+/// into the encrypted master secret that gets split, and back
+fn feistel(secret: &mut [u8], passphrase: &[u8], share: &Header, decrypt: bool) {
+    let half = secret.len() / 2;
+    let mut salt: Vec<u8, U8> = Vec::new();
+    if !share.extendable {
+        let _ = salt.extend_from_slice(b"shamir");
+        let _ = salt.extend_from_slice(&share.id.to_be_bytes());
+    }
+    let iterations = BASE_ITERATIONS << share.exponent;
+    let (mut l, mut r) = ([0u8; 16], [0u8; 16]);
+    l[..half].copy_from_slice(&secret[..half]);
+    r[..half].copy_from_slice(&secret[half..]);
+    for round in 0..4u8 {
+        let round = if decrypt { 3 - round } else { round };
+        // PBKDF2-HMAC-SHA256 with [round] || passphrase as the password,
+        // whose first block is long enough
+        let mut password: Vec<u8, U64> = Vec::new();
+        let _ = password.push(round);
+        let _ = password.extend_from_slice(passphrase);
+        let mac = Hmac::<Sha256>::new_varkey(&password).unwrap();
+        let mut u = mac.clone();
+        u.update(&salt);
+        u.update(&r[..half]);
+        u.update(&1u32.to_be_bytes());
+        let mut block = u.finalize().into_bytes();
+        let mut f = block;
+        for _ in 1..iterations {
+            let mut u = mac.clone();
+            u.update(&block);
+            block = u.finalize().into_bytes();
+            f.iter_mut().zip(block.iter()).for_each(|(f, b)| *f ^= b);
+        }
+        for (l, f) in l[..half].iter_mut().zip(f.iter()) {
+            *l ^= f;
+        }
+        core::mem::swap(&mut l, &mut r);
+        wipe(&mut f);
+        wipe(&mut block);
+        wipe(&mut password);
+    }
+    secret[..half].copy_from_slice(&r[..half]);
+    secret[half..].copy_from_slice(&l[..half]);
+    wipe(&mut l);
+    wipe(&mut r);
+}
+
+/// Checks the layout of a new backup
+fn check_groups(group_threshold: u8, groups: &[Group]) -> Result<()> {
+    if groups.is_empty()
+        || groups.len() > MAX_SHARES as usize
+        || group_threshold == 0
+        || group_threshold as usize > groups.len()
+    {
+        return Err(WalletErr::from("invalid group threshold"));
+    }
+    for g in groups {
+        if g.threshold == 0 || g.threshold > g.count || g.count > MAX_SHARES {
+            return Err(WalletErr::from("invalid member threshold"));
+        }
+        if g.threshold == 1 && g.count > 1 {
+            return Err(WalletErr::from("use a 1-of-1 group instead of 1-of-n"));
+        }
+    }
+    Ok(())
+}
+
+/// `groups` from pairs of bytes, (member threshold, member count)
+pub fn groups(pairs: &[u8]) -> Result<Vec<Group, U16>> {
+    if pairs.len() % 2 != 0 {
+        return Err(WalletErr::from("invalid groups"));
+    }
+    let mut groups = Vec::new();
+    for p in pairs.chunks(2) {
+        groups
+            .push(Group {
+                threshold: p[0],
+                count: p[1],
+            })
+            .map_err(|_| WalletErr::from("too many groups"))?;
+    }
+    Ok(groups)
+}
+
+fn of(threshold: u8, count: u8) -> FieldValue {
+    let mut buf = [0u8; 3];
+    let mut out = FieldValue::from(threshold.numtoa_str(10, &mut buf));
+    let _ = out.push_str(" of ");
+    let _ = out.push_str(count.numtoa_str(10, &mut buf));
+    out
+}
+
+/// What the user checks before a backup is created, starting with the
+/// warning that only this device's firmware restores the same keys
+pub fn summary(group_threshold: u8, groups: &[Group]) -> Result<Fields> {
+    check_groups(group_threshold, groups)?;
+    let mut fields = Fields::new();
+    let _ = fields.push(Field {
+        label: "Warning",
+        value: FieldValue::from("not for other SLIP-39 wallets, they restore other keys"),
+    });
+    let _ = fields.push(Field {
+        label: "Groups",
+        value: of(group_threshold, groups.len() as u8),
+    });
+    for (g, label) in groups.iter().zip(GROUP_LABELS.iter()) {
+        let _ = fields.push(Field {
+            label,
+            value: of(g.threshold, g.count),
+        });
+    }
+    Ok(fields)
+}
+
+/// Splits `secret` with the `device` and `host` randomness and hands every
+/// share to `f`, group by group, so that they needn't all be in memory at once
+pub fn split(
+    secret: &[u8],
+    group_threshold: u8,
+    groups: &[Group],
+    device: &[u8; 32],
+    host: &[u8],
+    mut f: impl FnMut(&Share) -> Result<()>,
+) -> Result<()> {
+    check_groups(group_threshold, groups)?;
+    if secret.len() < 16 || secret.len() > 32 || secret.len() % 2 != 0 {
+        return Err(WalletErr::from("unsupported secret length"));
+    }
+    let mut rng = Drbg {
+        key: hmac_sha256(secret, &[b"slip39 split", device, host]),
+        counter: 0,
+    };
+    let mut id = [0u8; 2];
+    rng.fill(&mut id);
+    let header = Header {
+        id: u16::from_be_bytes(id) & 0x7fff,
+        extendable: false,
+        exponent: ITERATION_EXPONENT,
+    };
+    let mut encrypted = Value::from_slice(secret).map_err(|_| invalid())?;
+    feistel(&mut encrypted, b"", &header, false);
+
+    let mut group_values: Vec<Value, U16> = Vec::new();
+    let result = split_secret(
+        group_threshold,
+        groups.len() as u8,
+        &encrypted,
+        &mut rng,
+        |_, value| {
+            let _ = group_values.push(Value::from_slice(value).map_err(|_| invalid())?);
+            Ok(())
+        },
+    )
+    .and_then(|_| {
+        for ((group_index, group), value) in groups.iter().enumerate().zip(&group_values) {
+            split_secret(
+                group.threshold,
+                group.count,
+                value,
+                &mut rng,
+                |member_index, value| {
+                    let share = Share {
+                        id: header.id,
+                        extendable: header.extendable,
+                        exponent: header.exponent,
+                        group_index: group_index as u8,
+                        group_threshold,
+                        group_count: groups.len() as u8,
+                        member_index,
+                        member_threshold: group.threshold,
+                        value: Value::from_slice(value).map_err(|_| invalid())?,
+                    };
+                    f(&share)
+                },
+            )?;
+        }
+        Ok(())
+    });
+    wipe(&mut encrypted);
+    for v in group_values.iter_mut() {
+        wipe(v);
+    }
+    result
+}
+
+/// Shares collected towards recovering a backup. Shares of groups that are
+/// already complete aren't kept.
+pub struct Recovery {
+    shares: Vec<Share, U32>,
+}
+
+impl Default for Recovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recovery {
+    pub fn new() -> Self {
+        Recovery { shares: Vec::new() }
+    }
+
+    fn members(&self, group_index: u8) -> impl Iterator<Item = &Share> {
+        self.shares
+            .iter()
+            .filter(move |s| s.group_index == group_index)
+    }
+
+    fn complete(&self, group_index: u8) -> bool {
+        match self.members(group_index).next() {
+            Some(first) => self.members(group_index).count() >= first.member_threshold as usize,
+            None => false,
+        }
+    }
+
+    fn complete_groups(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..MAX_SHARES).filter(move |g| self.complete(*g))
+    }
+
+    /// Adds `share` and returns how many groups are complete and how many
+    /// are needed
+    pub fn add(&mut self, share: Share) -> Result<(u8, u8)> {
+        if let Some(first) = self.shares.first() {
+            if share.id != first.id
+                || share.extendable != first.extendable
+                || share.exponent != first.exponent
+                || share.group_threshold != first.group_threshold
+                || share.group_count != first.group_count
+                || share.value.len() != first.value.len()
+            {
+                return Err(WalletErr::from("share is from another backup"));
+            }
+        }
+        if share.group_index >= share.group_count {
+            return Err(invalid());
+        }
+        if let Some(first) = self.members(share.group_index).next() {
+            if share.member_threshold != first.member_threshold {
+                return Err(WalletErr::from("share is from another backup"));
+            }
+        }
+        let duplicate = self
+            .members(share.group_index)
+            .any(|s| s.member_index == share.member_index);
+        if !duplicate && !self.complete(share.group_index) {
+            self.shares
+                .push(share)
+                .map_err(|_| WalletErr::from("too many shares"))?;
+        }
+        Ok(self.progress())
+    }
+
+    /// Complete groups and the groups needed, or (0, 0) before any share
+    pub fn progress(&self) -> (u8, u8) {
+        let needed = self.shares.first().map_or(0, |s| s.group_threshold);
+        (self.complete_groups().count() as u8, needed)
+    }
+
+    /// The master secret, once enough shares are in
+    pub fn secret(&self) -> Result<Value> {
+        self.decrypt(b"")
+    }
+
+    /// The master secret of shares encrypted with `passphrase`, as SLIP-39's
+    /// test vectors are. The device's own backups have none.
+    pub fn decrypt(&self, passphrase: &[u8]) -> Result<Value> {
+        let first = self
+            .shares
+            .first()
+            .ok_or_else(|| WalletErr::from("no shares"))?;
+        let mut group_values: Vec<(u8, Value), U16> = Vec::new();
+        let mut result = Ok(());
+        for g in self.complete_groups().take(first.group_threshold as usize) {
+            let threshold = self.members(g).next().map_or(0, |s| s.member_threshold);
+            let points: Vec<(u8, &[u8]), U16> = self
+                .members(g)
+                .take(threshold as usize)
+                .map(|s| (s.member_index, &s.value[..]))
+                .collect();
+            match recover_secret(threshold, &points) {
+                Ok(value) => {
+                    let _ = group_values.push((g, value));
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        let secret = result.and_then(|_| {
+            if group_values.len() < first.group_threshold as usize {
+                return Err(WalletErr::from("not enough shares"));
+            }
+            let points: Vec<(u8, &[u8]), U16> =
+                group_values.iter().map(|(g, v)| (*g, &v[..])).collect();
+            let mut secret = recover_secret(first.group_threshold, &points)?;
+            feistel(&mut secret, passphrase, &first.header(), true);
+            Ok(secret)
+        });
+        for (_, v) in group_values.iter_mut() {
+            wipe(v);
+        }
+        secret
+    }
+}
@@ -0,0 +1,248 @@
+//! Solana accounts: SLIP-10 Ed25519 keys at `m/44'/501'/account'/0'`,
+//! base58 addresses, and transaction messages reviewed before signing.
+use crate::{
+    base58,
+    bip32::HARDENED,
+    display::{Field, FieldValue},
+    error::WalletErr,
+    eth::format_units,
+    slip10::Ed25519Key,
+    Result,
+};
+
+use heapless::{consts::*, String, Vec};
+use numtoa::NumToA;
+
+const COIN_TYPE: u32 = 501;
+const SYSTEM_PROGRAM: [u8; 32] = [0; 32];
+/// `ComputeBudget111111111111111111111111111111`
+const COMPUTE_BUDGET_PROGRAM: [u8; 32] = [
+    0x03, 0x06, 0x46, 0x6f, 0xe5, 0x21, 0x17, 0x32, 0xff, 0xec, 0xad, 0xba, 0x72, 0xc3, 0x9b, 0xe7,
+    0xbc, 0x8c, 0xe5, 0xbb, 0xc5, 0xf7, 0x12, 0x6b, 0x2c, 0x43, 0x9b, 0x3a, 0x40, 0x00, 0x00, 0x00,
+];
+/// System program instructions that are shown; the others are refused, as
+/// they may move funds or hand over accounts in ways not worth reviewing
+const SYSTEM_CREATE_ACCOUNT: u32 = 0;
+const SYSTEM_ASSIGN: u32 = 1;
+const SYSTEM_TRANSFER: u32 = 2;
+const SYSTEM_ADVANCE_NONCE: u32 = 4;
+const SYSTEM_WITHDRAW_NONCE: u32 = 5;
+const SYSTEM_AUTHORIZE_NONCE: u32 = 7;
+const SYSTEM_ALLOCATE: u32 = 8;
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+
+/// Base58 of a 32 byte key is at most 44 characters
+pub type Address = String<U44>;
+pub type Fields = Vec<Field, U16>;
+type Keys<'a> = Vec<&'a [u8], U32>;
+
+pub fn path(account: u32) -> Result<[u32; 4]> {
+    if account >= HARDENED {
+        return Err(WalletErr::from("account out of range"));
+    }
+    Ok([
+        44 | HARDENED,
+        COIN_TYPE | HARDENED,
+        account | HARDENED,
+        HARDENED,
+    ])
+}
+
+pub fn key(seed: &[u8], account: u32) -> Result<Ed25519Key> {
+    Ed25519Key::derive(seed, &path(account)?)
+}
+
+pub fn address(pubkey: &[u8]) -> Address {
+    base58::encode(pubkey)
+}
+
+fn malformed() -> WalletErr {
+    WalletErr::from("malformed Solana transaction")
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).ok_or_else(malformed)?;
+        let out = self.buf.get(self.pos..end).ok_or_else(malformed)?;
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Solana's "compact-u16": 7 bits per byte, at most 3 bytes
+    fn compact_u16(&mut self) -> Result<usize> {
+        let mut n = 0;
+        for i in 0..3 {
+            let b = self.u8()?;
+            n |= ((b & 0x7f) as usize) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(malformed())
+    }
+
+    fn vec(&mut self) -> Result<&'a [u8]> {
+        let len = self.compact_u16()?;
+        self.take(len)
+    }
+}
+
+fn u32_le(data: &[u8]) -> Option<u32> {
+    let b = data.get(..4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u64_le(data: &[u8]) -> Option<u64> {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(data.get(..8)?);
+    Some(u64::from_le_bytes(b))
+}
+
+/// The arguments of a System instruction after its number, which must be
+/// `len` bytes
+fn system_args(data: &[u8], len: usize) -> Result<&[u8]> {
+    match data.get(4..) {
+        Some(args) if args.len() == len => Ok(args),
+        _ => Err(malformed()),
+    }
+}
+
+fn sol(lamports: &[u8]) -> Result<FieldValue> {
+    let lamports = u64_le(lamports).ok_or_else(malformed)?;
+    Ok(format_units(&lamports.to_be_bytes(), 9, "SOL"))
+}
+
+fn push(fields: &mut Fields, label: &'static str, value: &str) -> Result<()> {
+    let too_large = || WalletErr::from("transaction too large to show");
+    let mut v = FieldValue::new();
+    v.push_str(value).map_err(|_| too_large())?;
+    fields
+        .push(Field { label, value: v })
+        .map_err(|_| too_large())
+}
+
+/// What the user checks before `ours` signs the transaction message `msg`,
+/// and whether it calls programs that can only be named, not shown
+pub fn summary(msg: &[u8], ours: &[u8; 32]) -> Result<(Fields, bool)> {
+    let mut r = Reader { buf: msg, pos: 0 };
+    // Versioned messages start with 0x80 | version, legacy ones don't
+    let versioned = msg.first().map_or(false, |b| b & 0x80 != 0);
+    if versioned && r.u8()? != 0x80 {
+        return Err(WalletErr::from("unsupported Solana message version"));
+    }
+    let signers = r.u8()? as usize;
+    r.take(2)?;
+    let mut keys = Keys::new();
+    for _ in 0..r.compact_u16()? {
+        keys.push(r.take(32)?).map_err(|_| malformed())?;
+    }
+    if signers == 0 || signers > keys.len() {
+        return Err(malformed());
+    }
+    if !keys[..signers].contains(&&ours[..]) {
+        return Err(WalletErr::from("not a signer of this transaction"));
+    }
+    // Recent blockhash
+    r.take(32)?;
+
+    let mut fields = Fields::new();
+    let mut blind = false;
+    if keys[0] != ours {
+        push(&mut fields, "Fee payer", &address(keys[0]))?;
+    }
+    for _ in 0..r.compact_u16()? {
+        let program = *keys.get(r.u8()? as usize).ok_or_else(malformed)?;
+        let accounts = r.vec()?;
+        let data = r.vec()?;
+        let account = |i: usize| -> Result<Address> {
+            let idx = *accounts.get(i).ok_or_else(malformed)? as usize;
+            Ok(address(keys.get(idx).ok_or_else(malformed)?))
+        };
+        let mut buf = [0u8; 20];
+        if program == SYSTEM_PROGRAM {
+            match u32_le(data).ok_or_else(malformed)? {
+                SYSTEM_TRANSFER => {
+                    let lamports = system_args(data, 8)?;
+                    let from = account(0)?;
+                    if from != address(ours) {
+                        push(&mut fields, "From", &from)?;
+                    }
+                    push(&mut fields, "Send", &sol(lamports)?)?;
+                    push(&mut fields, "To", &account(1)?)?;
+                }
+                SYSTEM_CREATE_ACCOUNT => {
+                    // Lamports, space, owner
+                    let args = system_args(data, 48)?;
+                    push(&mut fields, "Create account", &account(1)?)?;
+                    push(&mut fields, "Fund", &sol(args)?)?;
+                    push(&mut fields, "Owner", &address(&args[16..]))?;
+                }
+                SYSTEM_ASSIGN => {
+                    let owner = system_args(data, 32)?;
+                    push(&mut fields, "Assign", &account(0)?)?;
+                    push(&mut fields, "To program", &address(owner))?;
+                }
+                SYSTEM_ALLOCATE => {
+                    let space = u64_le(system_args(data, 8)?).ok_or_else(malformed)?;
+                    push(&mut fields, "Allocate", &account(0)?)?;
+                    push(&mut fields, "Bytes", space.numtoa_str(10, &mut buf))?;
+                }
+                SYSTEM_ADVANCE_NONCE => {
+                    system_args(data, 0)?;
+                    push(&mut fields, "Advance nonce", &account(0)?)?;
+                }
+                SYSTEM_WITHDRAW_NONCE => {
+                    let lamports = system_args(data, 8)?;
+                    push(&mut fields, "Withdraw", &sol(lamports)?)?;
+                    push(&mut fields, "From nonce", &account(0)?)?;
+                    push(&mut fields, "To", &account(3)?)?;
+                }
+                SYSTEM_AUTHORIZE_NONCE => {
+                    let authority = system_args(data, 32)?;
+                    push(&mut fields, "Nonce account", &account(0)?)?;
+                    push(&mut fields, "New authority", &address(authority))?;
+                }
+                _ => return Err(WalletErr::from("unsupported System instruction")),
+            }
+        } else if program == COMPUTE_BUDGET_PROGRAM {
+            match data.split_first() {
+                Some((&SET_COMPUTE_UNIT_LIMIT, rest)) => {
+                    let units = u32_le(rest).ok_or_else(malformed)?;
+                    push(&mut fields, "Compute units", units.numtoa_str(10, &mut buf))?;
+                }
+                Some((&SET_COMPUTE_UNIT_PRICE, rest)) => {
+                    let price = u64_le(rest).ok_or_else(malformed)?;
+                    let mut value = FieldValue::new();
+                    let _ = value.push_str(price.numtoa_str(10, &mut buf));
+                    let _ = value.push_str(" micro-lamports");
+                    push(&mut fields, "Unit price", &value)?;
+                }
+                _ => {
+                    blind = true;
+                    push(&mut fields, "Program", &address(program))?;
+                }
+            }
+        } else {
+            blind = true;
+            push(&mut fields, "Program", &address(program))?;
+        }
+    }
+
+    if versioned && r.compact_u16()? != 0 {
+        return Err(WalletErr::from("address lookup tables not supported"));
+    }
+    if r.pos != msg.len() {
+        return Err(malformed());
+    }
+    Ok((fields, blind))
+}
@@ -0,0 +1,64 @@
+//! The person holding the device: what they are shown and what they decide
+use crate::{
+    display::{self, Screen},
+    error::WalletErr,
+    presence::{Decision, UserPresence},
+    Result,
+};
+
+/// Everything used to talk to the person holding the device
+pub struct Ui<D, U> {
+    pub disp: D,
+    pub user: U,
+}
+
+impl<D: Screen, U: UserPresence> Ui<D, U> {
+    /// Pages through `fields`, the user has to approve every page
+    pub fn confirm_fields(
+        &mut self,
+        heading: &str,
+        fields: &[display::Field],
+        timeout_ms: u32,
+    ) -> Result<()> {
+        let mut start = 0;
+        while start < fields.len() {
+            start += display::fields(&mut self.disp, heading, &fields[start..])?;
+            self.confirm(timeout_ms)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the user to approve whatever is on the screen
+    pub fn confirm(&mut self, timeout_ms: u32) -> Result<()> {
+        let (status, res) = match self.user.confirm(timeout_ms) {
+            Decision::Approved => ("Approved", Ok(())),
+            Decision::Rejected => ("Rejected", Err(protocol::ERR_USER_REJECTED)),
+            Decision::TimedOut => ("Timed out", Err(protocol::ERR_USER_TIMEOUT)),
+        };
+        display::status(&mut self.disp, status)?;
+        res.map_err(WalletErr::from)
+    }
+
+    /// Offers `options` one at a time: reject steps to the next, wrapping
+    /// around, and confirm picks the one shown. Returns its index.
+    pub fn choose(
+        &mut self,
+        heading: &str,
+        prompt: &str,
+        options: &[&str],
+        timeout_ms: u32,
+    ) -> Result<usize> {
+        let mut i = 0;
+        loop {
+            display::choice(&mut self.disp, heading, prompt, options[i])?;
+            match self.user.confirm(timeout_ms) {
+                Decision::Approved => return Ok(i),
+                Decision::Rejected => i = (i + 1) % options.len(),
+                Decision::TimedOut => {
+                    display::status(&mut self.disp, "Timed out")?;
+                    return Err(WalletErr::from(protocol::ERR_USER_TIMEOUT));
+                }
+            }
+        }
+    }
+}
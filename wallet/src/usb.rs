@@ -0,0 +1,30 @@
+//! The USB CDC serial port behind `link::Serial`
+use crate::{link::Serial, Result};
+
+use usb_device::{bus::UsbBus, device::UsbDevice, UsbError};
+use usbd_serial::SerialPort;
+
+pub struct Usb<'a, B: UsbBus> {
+    pub dev: UsbDevice<'a, B>,
+    pub serial: SerialPort<'a, B>,
+}
+
+impl<B: UsbBus> Serial for Usb<'_, B> {
+    fn poll(&mut self) -> bool {
+        self.dev.poll(&mut [&mut self.serial])
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self.serial.read(buf) {
+            Err(UsbError::WouldBlock) => Ok(0),
+            res => Ok(res?),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        match self.serial.write(data) {
+            Err(UsbError::WouldBlock) => Ok(0),
+            res => Ok(res?),
+        }
+    }
+}